@@ -0,0 +1,257 @@
+// 在训练大模型时，梯度偶尔会突然变得很大（梯度爆炸），甚至出现NaN或Inf。
+// 在这个练习中，我们将实现梯度裁剪和梯度统计，用于稳定训练和跳过异常的更新步。
+
+use std::fmt;
+
+// 计算多个梯度缓冲区的全局L2范数
+// 全局范数 = sqrt(所有缓冲区中所有元素的平方和)
+// 平方和使用f64累加，避免大量元素时的累积误差
+fn global_l2_norm(grads: &[Vec<f32>]) -> f32 {
+    grads.iter()
+        .flat_map(|g| g.iter())
+        .map(|&x| (x as f64) * (x as f64))
+        .sum::<f64>()
+        .sqrt() as f32
+}
+
+// 按全局L2范数裁剪梯度
+// 如果全局范数超过max_norm，所有缓冲区统一乘以 max_norm / 全局范数，
+// 这样裁剪后各参数梯度的方向保持不变，全局范数恰好等于max_norm
+// 返回：裁剪前的全局范数（便于记录日志）
+// 注意：范数为NaN或Inf时不做任何修改，调用方应根据返回值跳过这一步更新
+fn clip_grad_norm(grads: &mut [Vec<f32>], max_norm: f32) -> f32 {
+    assert!(max_norm > 0.0, "max_norm必须为正数");
+    let total_norm = global_l2_norm(grads);
+
+    if total_norm.is_finite() && total_norm > max_norm {
+        let scale = max_norm / total_norm;
+        for g in grads.iter_mut() {
+            for x in g.iter_mut() {
+                *x *= scale;
+            }
+        }
+    }
+    total_norm
+}
+
+// 按数值裁剪梯度
+// 将每个元素限制在 [-clip_value, clip_value] 范围内
+// NaN保持不变，以便后续的梯度统计仍然能发现它
+fn clip_grad_value(grads: &mut [Vec<f32>], clip_value: f32) {
+    assert!(clip_value > 0.0, "clip_value必须为正数");
+    for g in grads.iter_mut() {
+        for x in g.iter_mut() {
+            *x = x.clamp(-clip_value, clip_value);
+        }
+    }
+}
+
+// 单个参数的梯度统计
+#[derive(Debug, Clone, PartialEq)]
+struct GradStats {
+    name: String,
+    l2_norm: f32,
+    max_abs: f32,
+    nan_count: usize,
+    inf_count: usize,
+}
+
+impl GradStats {
+    fn is_finite(&self) -> bool {
+        self.nan_count == 0 && self.inf_count == 0
+    }
+}
+
+// 所有参数的梯度统计报告
+#[derive(Debug, Clone, PartialEq)]
+struct GradReport {
+    params: Vec<GradStats>,
+    global_norm: f32,
+}
+
+impl GradReport {
+    // 所有梯度都是有限值时返回true
+    fn is_finite(&self) -> bool {
+        self.params.iter().all(|p| p.is_finite())
+    }
+
+    // 判断是否应该跳过这一步更新：
+    // 出现NaN/Inf，或者全局范数超过给定的上限
+    fn should_skip_step(&self, max_global_norm: Option<f32>) -> bool {
+        if !self.is_finite() {
+            return true;
+        }
+        match max_global_norm {
+            Some(limit) => self.global_norm > limit,
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for GradReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "global_norm={:.6e}", self.global_norm)?;
+        for p in &self.params {
+            writeln!(
+                f,
+                "  {}: l2_norm={:.6e} max_abs={:.6e} nan={} inf={}",
+                p.name, p.l2_norm, p.max_abs, p.nan_count, p.inf_count
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// 计算单个梯度缓冲区的统计信息
+// 范数和最大绝对值只统计有限值，NaN/Inf单独计数
+fn compute_grad_stats(name: &str, grad: &[f32]) -> GradStats {
+    let mut sum_sq = 0.0f64;
+    let mut max_abs = 0.0f32;
+    let mut nan_count = 0;
+    let mut inf_count = 0;
+
+    for &x in grad {
+        if x.is_nan() {
+            nan_count += 1;
+        } else if x.is_infinite() {
+            inf_count += 1;
+        } else {
+            sum_sq += (x as f64) * (x as f64);
+            max_abs = max_abs.max(x.abs());
+        }
+    }
+
+    GradStats {
+        name: name.to_string(),
+        l2_norm: sum_sq.sqrt() as f32,
+        max_abs,
+        nan_count,
+        inf_count,
+    }
+}
+
+// 生成多个命名梯度缓冲区的统计报告
+// 全局范数由各参数的范数合成：sqrt(sum(norm_i^2))
+// 只要存在NaN/Inf，全局范数就记为NaN，避免把异常的一步当成正常值记录
+fn grad_report(named_grads: &[(&str, &[f32])]) -> GradReport {
+    let params: Vec<GradStats> = named_grads.iter()
+        .map(|&(name, grad)| compute_grad_stats(name, grad))
+        .collect();
+
+    let global_norm = if params.iter().all(|p| p.is_finite()) {
+        params.iter()
+            .map(|p| (p.l2_norm as f64).powi(2))
+            .sum::<f64>()
+            .sqrt() as f32
+    } else {
+        f32::NAN
+    };
+
+    GradReport { params, global_norm }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-6;
+
+    #[test]
+    fn test_global_l2_norm() {
+        // 单个缓冲区: [3, 4] 的范数为5
+        let grads = vec![vec![3.0, 4.0]];
+        assert!((global_l2_norm(&grads) - 5.0).abs() < EPSILON, "全局范数计算错误");
+
+        // 多个缓冲区: [1, 2] 和 [2] 的全局范数为 sqrt(1 + 4 + 4) = 3
+        let grads = vec![vec![1.0, 2.0], vec![2.0]];
+        assert!((global_l2_norm(&grads) - 3.0).abs() < EPSILON, "跨缓冲区的全局范数计算错误");
+
+        // 空缓冲区的范数为0
+        let grads: Vec<Vec<f32>> = vec![vec![], vec![]];
+        assert_eq!(global_l2_norm(&grads), 0.0, "空梯度的范数应该是0");
+    }
+
+    #[test]
+    fn test_clip_grad_norm() {
+        // 全局范数为 sqrt(9 + 16 + 144) = 13，裁剪到 max_norm = 1.3
+        let mut grads = vec![vec![3.0, 4.0], vec![12.0]];
+        let total = clip_grad_norm(&mut grads, 1.3);
+        assert!((total - 13.0).abs() < EPSILON, "应该返回裁剪前的全局范数");
+
+        // 统一缩放0.1倍
+        let expected = [vec![0.3, 0.4], vec![1.2]];
+        for (g, e) in grads.iter().zip(expected.iter()) {
+            for (a, b) in g.iter().zip(e.iter()) {
+                assert!((a - b).abs() < EPSILON, "裁剪后的梯度错误: {} != {}", a, b);
+            }
+        }
+        assert!((global_l2_norm(&grads) - 1.3).abs() < EPSILON, "裁剪后全局范数应该等于max_norm");
+    }
+
+    #[test]
+    fn test_clip_grad_norm_no_op() {
+        // 范数小于上限时梯度保持不变
+        let mut grads = vec![vec![0.3, 0.4]];
+        let total = clip_grad_norm(&mut grads, 1.0);
+        assert!((total - 0.5).abs() < EPSILON);
+        assert_eq!(grads, vec![vec![0.3, 0.4]], "未超过上限时不应该修改梯度");
+
+        // 范数为NaN时不修改梯度
+        let mut grads = vec![vec![f32::NAN, 1.0]];
+        let total = clip_grad_norm(&mut grads, 1.0);
+        assert!(total.is_nan(), "存在NaN时应该返回NaN范数");
+        assert_eq!(grads[0][1], 1.0, "范数为NaN时不应该修改梯度");
+    }
+
+    #[test]
+    fn test_clip_grad_value() {
+        let mut grads = vec![vec![-3.0, 0.5, 2.0], vec![f32::INFINITY, f32::NEG_INFINITY]];
+        clip_grad_value(&mut grads, 1.0);
+        assert_eq!(grads, vec![vec![-1.0, 0.5, 1.0], vec![1.0, -1.0]], "按数值裁剪错误");
+
+        // NaN保持不变
+        let mut grads = vec![vec![f32::NAN]];
+        clip_grad_value(&mut grads, 1.0);
+        assert!(grads[0][0].is_nan(), "NaN应该保留下来以便检测");
+    }
+
+    #[test]
+    fn test_grad_report() {
+        let w = vec![3.0, -4.0];
+        let b = vec![0.0, 12.0];
+        let report = grad_report(&[("w", &w), ("b", &b)]);
+
+        assert_eq!(report.params.len(), 2);
+        assert_eq!(report.params[0].name, "w");
+        assert!((report.params[0].l2_norm - 5.0).abs() < EPSILON);
+        assert!((report.params[0].max_abs - 4.0).abs() < EPSILON);
+        assert!((report.params[1].l2_norm - 12.0).abs() < EPSILON);
+        assert!((report.global_norm - 13.0).abs() < EPSILON, "全局范数应该是13");
+
+        assert!(report.is_finite());
+        assert!(!report.should_skip_step(None));
+        assert!(!report.should_skip_step(Some(20.0)));
+        assert!(report.should_skip_step(Some(10.0)), "全局范数超过上限时应该跳过");
+    }
+
+    #[test]
+    fn test_grad_report_non_finite() {
+        let w = vec![1.0, f32::NAN, f32::INFINITY, f32::NEG_INFINITY];
+        let b = vec![2.0];
+        let report = grad_report(&[("w", &w), ("b", &b)]);
+
+        assert_eq!(report.params[0].nan_count, 1);
+        assert_eq!(report.params[0].inf_count, 2);
+        assert!((report.params[0].l2_norm - 1.0).abs() < EPSILON, "范数只统计有限值");
+        assert!(report.params[1].is_finite());
+
+        assert!(!report.is_finite());
+        assert!(report.global_norm.is_nan());
+        assert!(report.should_skip_step(None), "出现NaN/Inf时应该跳过这一步");
+
+        // 日志输出中包含每个参数的统计
+        let log = report.to_string();
+        assert!(log.contains("w: "));
+        assert!(log.contains("nan=1 inf=2"));
+    }
+}
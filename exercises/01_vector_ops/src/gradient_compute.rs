@@ -36,6 +36,68 @@ fn relu_backward(upstream_grad: &[f32], x: &[f32]) -> Vec<f32> {
 }
 
 
+// 实现Sigmoid激活函数的前向传播
+// f(x) = 1 / (1 + exp(-x))
+fn sigmoid_forward(x: &[f32]) -> Vec<f32> {
+    x.iter()
+        .map(|&x| 1.0 / (1.0 + (-x).exp()))
+        .collect()
+}
+
+// 实现Sigmoid激活函数的反向传播
+// f'(x) = f(x) * (1 - f(x))
+fn sigmoid_backward(upstream_grad: &[f32], x: &[f32]) -> Vec<f32> {
+    assert_eq!(upstream_grad.len(), x.len(), "向量长度必须相同");
+    upstream_grad.iter()
+        .zip(sigmoid_forward(x).iter())
+        .map(|(&grad, &s)| grad * s * (1.0 - s))
+        .collect()
+}
+
+// 实现SiLU（Swish）激活函数的前向传播，Llama的前馈网络使用它
+// f(x) = x * sigmoid(x)
+fn silu_forward(x: &[f32]) -> Vec<f32> {
+    x.iter()
+        .zip(sigmoid_forward(x).iter())
+        .map(|(&x, &s)| x * s)
+        .collect()
+}
+
+// 实现SiLU激活函数的反向传播
+// f'(x) = sigmoid(x) * (1 + x * (1 - sigmoid(x)))
+fn silu_backward(upstream_grad: &[f32], x: &[f32]) -> Vec<f32> {
+    assert_eq!(upstream_grad.len(), x.len(), "向量长度必须相同");
+    upstream_grad.iter()
+        .zip(x.iter().zip(sigmoid_forward(x).iter()))
+        .map(|(&grad, (&x, &s))| grad * s * (1.0 + x * (1.0 - s)))
+        .collect()
+}
+
+// 实现GELU激活函数的前向传播（tanh近似，GPT-2使用的版本）
+// f(x) = 0.5 * x * (1 + tanh(sqrt(2/π) * (x + 0.044715 * x^3)))
+fn gelu_forward(x: &[f32]) -> Vec<f32> {
+    let k = (2.0 / std::f32::consts::PI).sqrt();
+    x.iter()
+        .map(|&x| 0.5 * x * (1.0 + (k * (x + 0.044715 * x.powi(3))).tanh()))
+        .collect()
+}
+
+// 实现GELU激活函数的反向传播
+// 记 t = tanh(sqrt(2/π) * (x + 0.044715 * x^3))，则
+// f'(x) = 0.5 * (1 + t) + 0.5 * x * (1 - t^2) * sqrt(2/π) * (1 + 3 * 0.044715 * x^2)
+fn gelu_backward(upstream_grad: &[f32], x: &[f32]) -> Vec<f32> {
+    assert_eq!(upstream_grad.len(), x.len(), "向量长度必须相同");
+    let k = (2.0 / std::f32::consts::PI).sqrt();
+    upstream_grad.iter()
+        .zip(x.iter())
+        .map(|(&grad, &x)| {
+            let t = (k * (x + 0.044715 * x.powi(3))).tanh();
+            let d = 0.5 * (1.0 + t) + 0.5 * x * (1.0 - t * t) * k * (1.0 + 3.0 * 0.044715 * x * x);
+            grad * d
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -92,4 +154,50 @@ mod tests {
             assert!((a - b).abs() < EPSILON, "ReLU反向传播计算错误");
         }
     }
+
+    #[test]
+    fn test_smooth_activations_forward() {
+        let x = vec![-1.0, 0.0, 2.0];
+
+        let sigmoid = sigmoid_forward(&x);
+        let expected = [0.268_941_4, 0.5, 0.880_797_1];
+        for (a, b) in sigmoid.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < EPSILON, "Sigmoid前向传播计算错误");
+        }
+
+        let silu = silu_forward(&x);
+        let expected = [-0.268_941_4, 0.0, 1.761_594_2];
+        for (a, b) in silu.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < EPSILON, "SiLU前向传播计算错误");
+        }
+
+        let gelu = gelu_forward(&x);
+        let expected = [-0.158_808, 0.0, 1.954_597_7];
+        for (a, b) in gelu.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < EPSILON, "GELU前向传播计算错误");
+        }
+    }
+
+    // 使用中心差分验证解析梯度
+    fn check_activation_backward(
+        forward: fn(&[f32]) -> Vec<f32>,
+        backward: fn(&[f32], &[f32]) -> Vec<f32>
+    ) {
+        let x = vec![-2.0, -0.5, 0.0, 0.3, 1.5];
+        let upstream_grad = vec![1.0; x.len()];
+        let h = 1e-3;
+
+        let grad = backward(&upstream_grad, &x);
+        for i in 0..x.len() {
+            let numeric = (forward(&[x[i] + h])[0] - forward(&[x[i] - h])[0]) / (2.0 * h);
+            assert!((grad[i] - numeric).abs() < 1e-3, "激活函数反向传播计算错误");
+        }
+    }
+
+    #[test]
+    fn test_smooth_activations_backward() {
+        check_activation_backward(sigmoid_forward, sigmoid_backward);
+        check_activation_backward(silu_forward, silu_backward);
+        check_activation_backward(gelu_forward, gelu_backward);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,242 @@
+// Dropout是训练Transformer时最常用的正则化方法。
+// 在这个练习中，我们将实现带固定随机种子的Dropout前向/反向传播，保证结果可以复现。
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Dropout掩码
+// keep[i]为true表示第i个元素被保留，scale是保留元素需要乘上的系数
+// 前向传播时保存下来，反向传播时原样复用
+#[derive(Debug, Clone, PartialEq)]
+struct DropoutMask {
+    keep: Vec<bool>,
+    scale: f32,
+}
+
+// Dropout层
+// p: 丢弃概率，范围[0, 1)
+// training: 训练模式下随机丢弃，评估模式下直接返回输入
+// rng: 使用种子初始化的随机数生成器，相同种子产生相同的掩码序列
+struct Dropout {
+    p: f32,
+    training: bool,
+    rng: StdRng,
+}
+
+impl Dropout {
+    fn new(p: f32, seed: u64) -> Self {
+        assert!((0.0..1.0).contains(&p), "丢弃概率必须在[0, 1)范围内");
+        Dropout {
+            p,
+            training: true,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn train(&mut self) {
+        self.training = true;
+    }
+
+    fn eval(&mut self) {
+        self.training = false;
+    }
+
+    // 生成长度为len的掩码
+    // 使用inverted dropout：保留的元素乘以 1 / (1 - p)，
+    // 这样训练时输出的期望与输入相同，评估时不需要再做缩放
+    fn sample_mask(&mut self, len: usize) -> DropoutMask {
+        if !self.training || self.p == 0.0 {
+            return DropoutMask { keep: vec![true; len], scale: 1.0 };
+        }
+
+        let keep = (0..len)
+            .map(|_| self.rng.gen::<f32>() >= self.p)
+            .collect();
+        DropoutMask { keep, scale: 1.0 / (1.0 - self.p) }
+    }
+
+    // 前向传播
+    // 返回：输出和本次使用的掩码
+    fn forward(&mut self, x: &[f32]) -> (Vec<f32>, DropoutMask) {
+        let mask = self.sample_mask(x.len());
+        (apply_mask(x, &mask), mask)
+    }
+
+    // 对注意力概率做Dropout
+    // 输入形状: [batch_size, num_heads, seq_len, seq_len]，即batch_matmul（再经过softmax）的输出
+    // 掩码按行优先顺序覆盖整个张量
+    fn forward_attention(
+        &mut self,
+        probs: &[Vec<Vec<Vec<f32>>>]
+    ) -> (Vec<Vec<Vec<Vec<f32>>>>, DropoutMask) {
+        let len = probs.iter()
+            .flat_map(|b| b.iter())
+            .flat_map(|h| h.iter())
+            .map(|row| row.len())
+            .sum();
+        let mask = self.sample_mask(len);
+        (apply_mask_4d(probs, &mask), mask)
+    }
+}
+
+// 将掩码应用到向量上
+fn apply_mask(x: &[f32], mask: &DropoutMask) -> Vec<f32> {
+    assert_eq!(x.len(), mask.keep.len(), "掩码长度必须与输入相同");
+    x.iter()
+        .zip(mask.keep.iter())
+        .map(|(&v, &keep)| if keep { v * mask.scale } else { 0.0 })
+        .collect()
+}
+
+// 将掩码应用到4D张量上，掩码按行优先顺序展开
+fn apply_mask_4d(x: &[Vec<Vec<Vec<f32>>>], mask: &DropoutMask) -> Vec<Vec<Vec<Vec<f32>>>> {
+    let mut offset = 0;
+    x.iter()
+        .map(|batch| {
+            batch.iter()
+                .map(|head| {
+                    head.iter()
+                        .map(|row| {
+                            let sub = DropoutMask {
+                                keep: mask.keep[offset..offset + row.len()].to_vec(),
+                                scale: mask.scale,
+                            };
+                            offset += row.len();
+                            apply_mask(row, &sub)
+                        })
+                        .collect()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Dropout的反向传播
+// 被丢弃的位置梯度为0，保留的位置梯度乘以相同的缩放系数
+fn dropout_backward(upstream_grad: &[f32], mask: &DropoutMask) -> Vec<f32> {
+    apply_mask(upstream_grad, mask)
+}
+
+// 注意力概率Dropout的反向传播
+fn dropout_backward_attention(
+    upstream_grad: &[Vec<Vec<Vec<f32>>>],
+    mask: &DropoutMask
+) -> Vec<Vec<Vec<Vec<f32>>>> {
+    apply_mask_4d(upstream_grad, mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-6;
+
+    #[test]
+    fn test_dropout_preserves_expectation() {
+        // 大量样本下，inverted dropout输出的均值应该接近输入的均值
+        let mut dropout = Dropout::new(0.3, 42);
+        let x = vec![1.0; 100_000];
+        let (y, mask) = dropout.forward(&x);
+
+        let mean = y.iter().sum::<f32>() / y.len() as f32;
+        assert!((mean - 1.0).abs() < 0.01, "Dropout后的期望应该保持不变，实际均值是{}", mean);
+
+        // 丢弃比例应该接近p
+        let dropped = mask.keep.iter().filter(|&&k| !k).count() as f32 / x.len() as f32;
+        assert!((dropped - 0.3).abs() < 0.01, "丢弃比例应该接近0.3，实际是{}", dropped);
+
+        // 保留的元素被放大 1 / (1 - p) 倍
+        for (&v, &keep) in y.iter().zip(mask.keep.iter()) {
+            let expected = if keep { 1.0 / 0.7 } else { 0.0 };
+            assert!((v - expected).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn test_dropout_seed_reproducible() {
+        let x: Vec<f32> = (0..64).map(|i| i as f32).collect();
+
+        let (y1, mask1) = Dropout::new(0.5, 7).forward(&x);
+        let (y2, mask2) = Dropout::new(0.5, 7).forward(&x);
+        assert_eq!(mask1, mask2, "相同的种子应该产生相同的掩码");
+        assert_eq!(y1, y2, "相同的种子应该产生相同的输出");
+
+        let (_, mask3) = Dropout::new(0.5, 8).forward(&x);
+        assert_ne!(mask1, mask3, "不同的种子应该产生不同的掩码");
+
+        // 同一个Dropout层连续调用会产生不同的掩码
+        let mut dropout = Dropout::new(0.5, 7);
+        let (_, first) = dropout.forward(&x);
+        let (_, second) = dropout.forward(&x);
+        assert_eq!(first, mask1);
+        assert_ne!(first, second, "连续调用应该产生新的掩码");
+    }
+
+    #[test]
+    fn test_dropout_eval_mode() {
+        let mut dropout = Dropout::new(0.5, 0);
+        dropout.eval();
+        let x = vec![1.0, -2.0, 3.0];
+        let (y, mask) = dropout.forward(&x);
+        assert_eq!(y, x, "评估模式下应该直接返回输入");
+        assert!(mask.keep.iter().all(|&k| k));
+
+        dropout.train();
+        let (_, mask) = dropout.forward(&vec![1.0; 1000]);
+        assert!(mask.keep.iter().any(|&k| !k), "训练模式下应该有元素被丢弃");
+    }
+
+    #[test]
+    fn test_dropout_backward() {
+        let mut dropout = Dropout::new(0.5, 3);
+        let x = vec![2.0; 16];
+        let (y, mask) = dropout.forward(&x);
+
+        let upstream_grad = vec![1.0; 16];
+        let grad = dropout_backward(&upstream_grad, &mask);
+        for i in 0..16 {
+            if mask.keep[i] {
+                assert!((grad[i] - 2.0).abs() < EPSILON, "保留位置的梯度应该乘以缩放系数");
+                assert!((y[i] - 4.0).abs() < EPSILON);
+            } else {
+                assert_eq!(grad[i], 0.0, "丢弃位置的梯度应该为0");
+                assert_eq!(y[i], 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_attention_dropout() {
+        // 形状: [batch_size=2, num_heads=2, seq_len=3, seq_len=3]
+        let probs = vec![vec![vec![vec![1.0 / 3.0; 3]; 3]; 2]; 2];
+        let mut dropout = Dropout::new(0.25, 11);
+        let (out, mask) = dropout.forward_attention(&probs);
+
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].len(), 2);
+        assert_eq!(out[0][0].len(), 3);
+        assert_eq!(out[0][0][0].len(), 3);
+        assert_eq!(mask.keep.len(), 36);
+
+        // 掩码按行优先顺序对应到每个元素
+        let flat: Vec<f32> = out.iter()
+            .flat_map(|b| b.iter())
+            .flat_map(|h| h.iter())
+            .flat_map(|r| r.iter().copied())
+            .collect();
+        for (&v, &keep) in flat.iter().zip(mask.keep.iter()) {
+            let expected = if keep { 1.0 / 3.0 / 0.75 } else { 0.0 };
+            assert!((v - expected).abs() < EPSILON);
+        }
+
+        // 反向传播复用同一个掩码
+        let upstream_grad = vec![vec![vec![vec![1.0; 3]; 3]; 2]; 2];
+        let grad = dropout_backward_attention(&upstream_grad, &mask);
+        let flat_grad: Vec<f32> = grad.iter()
+            .flat_map(|b| b.iter())
+            .flat_map(|h| h.iter())
+            .flat_map(|r| r.iter().copied())
+            .collect();
+        assert_eq!(flat_grad, dropout_backward(&[1.0; 36], &mask));
+    }
+}
@@ -0,0 +1,412 @@
+// 模型的输入是token ID，第一步是通过词嵌入表把每个ID映射为一个向量。
+// Transformer本身不区分token的顺序，还需要加上位置信息：
+// - 正弦位置编码（原始Transformer）：固定的sin/cos函数，不需要训练
+// - 可学习的位置嵌入（GPT-2）：每个位置一个可训练的向量，长度不能超过max_len
+// 在这个练习中，我们将实现词嵌入的查表和反向传播，以及这两种位置编码。
+// 输出形状为 [batch_size, seq_len, hidden_size]，可以直接送入 reshape_for_attention。
+
+use rand::Rng;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// 可共享的权重矩阵
+// 注意：这个类型与 linear.rs 中的定义相同，已经提供
+type SharedWeight = Rc<RefCell<Vec<Vec<f32>>>>;
+
+// 辅助函数：使用Box-Muller变换生成标准正态分布的随机数
+// 注意：这个函数与 linear.rs 中的实现相同，已经提供
+fn sample_standard_normal(rng: &mut impl Rng) -> f32 {
+    let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+    let u2: f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+// GPT-2使用标准差为0.02的正态分布初始化嵌入
+const EMBEDDING_INIT_STD: f32 = 0.02;
+
+fn init_normal(rows: usize, cols: usize, rng: &mut impl Rng) -> Vec<Vec<f32>> {
+    (0..rows)
+        .map(|_| (0..cols).map(|_| EMBEDDING_INIT_STD * sample_standard_normal(rng)).collect())
+        .collect()
+}
+
+// 词嵌入层
+// weight形状: [vocab_size, hidden_size]，使用SharedWeight以便与LM head绑定
+struct Embedding {
+    weight: SharedWeight,
+}
+
+impl Embedding {
+    fn new(vocab_size: usize, hidden_size: usize, rng: &mut impl Rng) -> Self {
+        assert!(vocab_size > 0 && hidden_size > 0, "词表大小和隐藏维度必须大于0");
+        Embedding { weight: Rc::new(RefCell::new(init_normal(vocab_size, hidden_size, rng))) }
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.weight.borrow().len()
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.weight.borrow()[0].len()
+    }
+
+    // 前向传播：查表
+    // 输入形状: [batch_size, seq_len]
+    // 输出形状: [batch_size, seq_len, hidden_size]
+    // token ID超出词表大小时返回错误
+    fn forward(&self, ids: &[Vec<u32>]) -> Result<Vec<Vec<Vec<f32>>>, String> {
+        let weight = self.weight.borrow();
+        ids.iter()
+            .enumerate()
+            .map(|(b, seq)| {
+                seq.iter()
+                    .enumerate()
+                    .map(|(s, &id)| {
+                        weight.get(id as usize).cloned().ok_or_else(|| {
+                            format!("位置[{}][{}]的token ID {}超出词表大小{}", b, s, id, weight.len())
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // 反向传播：查表的梯度是scatter-add
+    // 每个位置的上游梯度累加到对应token的那一行，同一个token出现多次时梯度相加，没有出现的token梯度为0
+    // 返回grad_weight，形状: [vocab_size, hidden_size]
+    // token ID超出词表大小，或者上游梯度的形状与 [batch_size, seq_len, hidden_size] 不一致时返回错误
+    fn backward(&self, ids: &[Vec<u32>], upstream_grad: &[Vec<Vec<f32>>]) -> Result<Vec<Vec<f32>>, String> {
+        let (vocab_size, hidden_size) = (self.vocab_size(), self.hidden_size());
+        if ids.len() != upstream_grad.len() {
+            return Err(format!("上游梯度的batch_size {}与输入{}不一致", upstream_grad.len(), ids.len()));
+        }
+        let mut grad = vec![vec![0.0; hidden_size]; vocab_size];
+        for (b, (seq, grad_seq)) in ids.iter().zip(upstream_grad.iter()).enumerate() {
+            if seq.len() != grad_seq.len() {
+                return Err(format!("第{}个序列的上游梯度长度{}与输入长度{}不一致", b, grad_seq.len(), seq.len()));
+            }
+            for (s, (&id, g)) in seq.iter().zip(grad_seq.iter()).enumerate() {
+                if g.len() != hidden_size {
+                    return Err(format!("位置[{}][{}]的上游梯度维度{}与hidden_size {}不一致", b, s, g.len(), hidden_size));
+                }
+                let row = grad.get_mut(id as usize).ok_or_else(|| {
+                    format!("位置[{}][{}]的token ID {}超出词表大小{}", b, s, id, vocab_size)
+                })?;
+                for (gw, &gv) in row.iter_mut().zip(g.iter()) {
+                    *gw += gv;
+                }
+            }
+        }
+        Ok(grad)
+    }
+}
+
+// 正弦位置编码
+// PE[pos][2i]   = sin(pos / 10000^(2i / d))
+// PE[pos][2i+1] = cos(pos / 10000^(2i / d))
+// 返回形状: [max_len, hidden_size]
+fn sinusoidal_positional_encoding(max_len: usize, hidden_size: usize) -> Vec<Vec<f32>> {
+    (0..max_len)
+        .map(|pos| {
+            (0..hidden_size)
+                .map(|j| {
+                    let i = (j / 2) as f32;
+                    let angle = pos as f32 / 10000f32.powf(2.0 * i / hidden_size as f32);
+                    if j % 2 == 0 { angle.sin() } else { angle.cos() }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// 位置编码，与词嵌入相加
+// Sinusoidal: 预先计算好的固定表，没有参数
+// Learned: 可训练的位置嵌入，weight形状: [max_len, hidden_size]
+enum PositionalEncoding {
+    Sinusoidal(Vec<Vec<f32>>),
+    Learned(Vec<Vec<f32>>),
+}
+
+impl PositionalEncoding {
+    fn sinusoidal(max_len: usize, hidden_size: usize) -> Self {
+        PositionalEncoding::Sinusoidal(sinusoidal_positional_encoding(max_len, hidden_size))
+    }
+
+    fn learned(max_len: usize, hidden_size: usize, rng: &mut impl Rng) -> Self {
+        PositionalEncoding::Learned(init_normal(max_len, hidden_size, rng))
+    }
+
+    fn table(&self) -> &Vec<Vec<f32>> {
+        match self {
+            PositionalEncoding::Sinusoidal(table) | PositionalEncoding::Learned(table) => table,
+        }
+    }
+
+    fn max_len(&self) -> usize {
+        self.table().len()
+    }
+
+    // 前向传播：x[b][s] += PE[start_pos + s]
+    // start_pos是第一个token的位置，增量生成时不为0
+    // 输入/输出形状: [batch_size, seq_len, hidden_size]
+    // 检查batch中所有序列长度相同，且位置start_pos..start_pos+seq_len都在表内，返回seq_len
+    fn check_positions(&self, x: &[Vec<Vec<f32>>], start_pos: usize) -> Result<usize, String> {
+        let seq_len = x.first().map_or(0, |seq| seq.len());
+        if let Some((b, seq)) = x.iter().enumerate().find(|(_, seq)| seq.len() != seq_len) {
+            return Err(format!("第{}个序列长度{}与第一个序列长度{}不一致", b, seq.len(), seq_len));
+        }
+        if start_pos + seq_len > self.max_len() {
+            return Err(format!("位置{}超出最大长度{}", start_pos + seq_len - 1, self.max_len()));
+        }
+        Ok(seq_len)
+    }
+
+    fn forward(&self, x: &[Vec<Vec<f32>>], start_pos: usize) -> Result<Vec<Vec<Vec<f32>>>, String> {
+        self.check_positions(x, start_pos)?;
+        let table = self.table();
+        Ok(x.iter()
+            .map(|seq| {
+                seq.iter()
+                    .zip(&table[start_pos..])
+                    .map(|(row, pe)| {
+                        assert_eq!(row.len(), pe.len(), "输入维度与位置编码不匹配");
+                        row.iter().zip(pe.iter()).map(|(&a, &b)| a + b).collect()
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
+    // 反向传播
+    // 加法对输入的梯度就是上游梯度本身，这里只返回位置嵌入的梯度：
+    // 每个位置的梯度是所有batch在该位置的上游梯度之和。正弦编码没有参数，返回None
+    // 与前向传播一样，序列长度不一致或位置超出最大长度时返回错误
+    fn backward(&self, upstream_grad: &[Vec<Vec<f32>>], start_pos: usize) -> Result<Option<Vec<Vec<f32>>>, String> {
+        self.check_positions(upstream_grad, start_pos)?;
+        let PositionalEncoding::Learned(weight) = self else { return Ok(None) };
+        let mut grad = vec![vec![0.0; weight[0].len()]; weight.len()];
+        for seq in upstream_grad {
+            for (g_row, g) in grad[start_pos..].iter_mut().zip(seq.iter()) {
+                for (gw, &gv) in g_row.iter_mut().zip(g.iter()) {
+                    *gw += gv;
+                }
+            }
+        }
+        Ok(Some(grad))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const EPSILON: f32 = 1e-6;
+
+    // 辅助函数：多头注意力的头部分割
+    // 注意：这个函数与 multi_head_attention.rs 中的实现相同，已经提供
+    fn reshape_for_attention(
+        input: &[Vec<Vec<f32>>],
+        num_heads: usize
+    ) -> Vec<Vec<Vec<Vec<f32>>>> {
+        let batch_size = input.len();
+        let seq_len = input[0].len();
+        let hidden_size = input[0][0].len();
+        let head_size = hidden_size / num_heads;
+        (0..batch_size)
+            .map(|b| {
+                (0..num_heads)
+                    .map(|h| {
+                        (0..seq_len)
+                            .map(|s| input[b][s][h * head_size..(h + 1) * head_size].to_vec())
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn fixed_embedding() -> Embedding {
+        // 词嵌入矩阵形状: [vocab_size=4, hidden_size=2]
+        Embedding {
+            weight: Rc::new(RefCell::new(vec![
+                vec![0.0, 0.1],
+                vec![1.0, 1.1],
+                vec![2.0, 2.1],
+                vec![3.0, 3.1],
+            ])),
+        }
+    }
+
+    #[test]
+    fn test_embedding_lookup() {
+        let emb = fixed_embedding();
+        let out = emb.forward(&[vec![3, 0, 3], vec![1, 2, 1]]).unwrap();
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0], vec![vec![3.0, 3.1], vec![0.0, 0.1], vec![3.0, 3.1]]);
+        assert_eq!(out[1][1], vec![2.0, 2.1]);
+
+        let err = emb.forward(&[vec![0, 1], vec![2, 4]]).unwrap_err();
+        assert_eq!(err, "位置[1][1]的token ID 4超出词表大小4");
+        assert_eq!(emb.forward(&[vec![]]).unwrap(), vec![Vec::<Vec<f32>>::new()]);
+    }
+
+    #[test]
+    fn test_embedding_backward_scatter_add() {
+        let emb = fixed_embedding();
+        let ids = vec![vec![3, 0, 3], vec![1, 3, 1]];
+        let upstream = vec![
+            vec![vec![1.0, 2.0], vec![0.5, 0.5], vec![10.0, 20.0]],
+            vec![vec![-1.0, 1.0], vec![100.0, 200.0], vec![2.0, 3.0]],
+        ];
+        let grad = emb.backward(&ids, &upstream).unwrap();
+        assert_eq!(grad, vec![
+            vec![0.5, 0.5],
+            vec![1.0, 4.0],
+            vec![0.0, 0.0],
+            vec![111.0, 222.0],
+        ]);
+        assert_eq!(
+            emb.backward(&[vec![0, 1, 2], vec![2, 4, 0]], &upstream).unwrap_err(),
+            "位置[1][1]的token ID 4超出词表大小4"
+        );
+        // 上游梯度的形状必须与输入一致，不能静默截断
+        assert_eq!(
+            emb.backward(&ids, &upstream[..1]).unwrap_err(),
+            "上游梯度的batch_size 1与输入2不一致"
+        );
+        assert_eq!(
+            emb.backward(&[vec![3, 0], vec![1, 3, 1]], &upstream).unwrap_err(),
+            "第0个序列的上游梯度长度3与输入长度2不一致"
+        );
+        let mut wrong_hidden = upstream.clone();
+        wrong_hidden[1][2].push(0.0);
+        assert_eq!(
+            emb.backward(&ids, &wrong_hidden).unwrap_err(),
+            "位置[1][2]的上游梯度维度3与hidden_size 2不一致"
+        );
+
+        // 与数值梯度一致：loss = sum(forward * upstream) 对权重是线性的
+        let loss = |emb: &Embedding| -> f32 {
+            emb.forward(&ids).unwrap().iter().flatten().flatten()
+                .zip(upstream.iter().flatten().flatten())
+                .map(|(&a, &b)| a * b)
+                .sum()
+        };
+        let h = 1e-2;
+        for (v, grad_row) in grad.iter().enumerate() {
+            for (i, &g) in grad_row.iter().enumerate() {
+                let original = emb.weight.borrow()[v][i];
+                emb.weight.borrow_mut()[v][i] = original + h;
+                let plus = loss(&emb);
+                emb.weight.borrow_mut()[v][i] = original - h;
+                let minus = loss(&emb);
+                emb.weight.borrow_mut()[v][i] = original;
+                let numeric = (plus - minus) / (2.0 * h);
+                assert!((numeric - g).abs() < 0.1, "dW[{}][{}]: 数值梯度{}，解析梯度{}", v, i, numeric, g);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sinusoidal_encoding() {
+        let pe = sinusoidal_positional_encoding(50, 8);
+        assert_eq!(pe.len(), 50);
+        assert_eq!(pe[0], vec![0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0]);
+        assert!((pe[1][0] - 1f32.sin()).abs() < EPSILON);
+        assert!((pe[1][1] - 1f32.cos()).abs() < EPSILON);
+        // 第2对维度的频率是 1 / 10000^(2/8) = 0.1
+        assert!((pe[7][2] - 0.7f32.sin()).abs() < EPSILON);
+        assert!((pe[7][3] - 0.7f32.cos()).abs() < EPSILON);
+        assert!(pe.iter().flatten().all(|v| v.abs() <= 1.0));
+
+        // 每对(sin, cos)的模长为1，并且两个位置的点积只取决于它们的距离
+        for row in &pe {
+            for pair in row.chunks(2) {
+                assert!((pair[0] * pair[0] + pair[1] * pair[1] - 1.0).abs() < 1e-5);
+            }
+        }
+        let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+        let d = dot(&pe[3], &pe[8]);
+        for p in [0, 10, 30] {
+            assert!((dot(&pe[p], &pe[p + 5]) - d).abs() < 1e-4);
+        }
+
+        // 奇数维度时最后一维只有sin
+        let odd = sinusoidal_positional_encoding(2, 3);
+        assert_eq!(odd[1].len(), 3);
+        assert!((odd[1][2] - (1.0 / 10000f32.powf(2.0 / 3.0)).sin()).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_positional_encoding_forward_backward() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let x = vec![vec![vec![1.0; 4]; 3]; 2];
+
+        let sin = PositionalEncoding::sinusoidal(8, 4);
+        let out = sin.forward(&x, 2).unwrap();
+        for (row, pe) in out[1].iter().zip(&sin.table()[2..]) {
+            for (&v, &p) in row.iter().zip(pe.iter()) {
+                assert!((v - (1.0 + p)).abs() < EPSILON);
+            }
+        }
+        assert!(sin.backward(&x, 2).unwrap().is_none());
+        assert_eq!(sin.forward(&x, 6).unwrap_err(), "位置8超出最大长度8");
+        assert!(sin.forward(&x, 5).is_ok());
+
+        let learned = PositionalEncoding::learned(5, 4, &mut rng);
+        assert_eq!(learned.max_len(), 5);
+        let out = learned.forward(&x, 1).unwrap();
+        assert!((out[0][2][3] - (1.0 + learned.table()[3][3])).abs() < EPSILON);
+        assert!(learned.forward(&x, 3).is_err());
+
+        // 每个位置的梯度是两个batch的和，start_pos之前和之后未使用的位置梯度为0
+        let upstream = vec![
+            vec![vec![1.0, 0.0, 0.0, 0.0], vec![0.0, 2.0, 0.0, 0.0], vec![0.0, 0.0, 3.0, 0.0]],
+            vec![vec![1.0, 0.0, 0.0, 0.0], vec![0.0, 2.0, 0.0, 0.0], vec![0.0, 0.0, 3.0, 1.0]],
+        ];
+        let grad = learned.backward(&upstream, 1).unwrap().unwrap();
+        assert_eq!(grad, vec![
+            vec![0.0; 4],
+            vec![2.0, 0.0, 0.0, 0.0],
+            vec![0.0, 4.0, 0.0, 0.0],
+            vec![0.0, 0.0, 6.0, 1.0],
+            vec![0.0; 4],
+        ]);
+
+        // 反向传播与前向传播做相同的检查，不会静默丢弃梯度
+        assert_eq!(learned.backward(&upstream, 3).unwrap_err(), "位置5超出最大长度5");
+        assert_eq!(learned.backward(&upstream, 9).unwrap_err(), "位置11超出最大长度5");
+        assert!(sin.backward(&upstream, 6).is_err());
+
+        // batch中的序列长度必须一致，后面更长的序列不能被截断
+        let ragged = vec![vec![vec![1.0; 4]; 2], vec![vec![1.0; 4]; 3]];
+        assert_eq!(learned.forward(&ragged, 0).unwrap_err(), "第1个序列长度3与第一个序列长度2不一致");
+        assert!(learned.forward(&ragged, 3).is_err());
+        assert!(sin.forward(&ragged, 0).is_err());
+        assert!(learned.backward(&ragged, 0).is_err());
+    }
+
+    #[test]
+    fn test_embedding_init_and_attention_input() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let emb = Embedding::new(1000, 64, &mut rng);
+        assert_eq!((emb.vocab_size(), emb.hidden_size()), (1000, 64));
+        let values: Vec<f32> = emb.weight.borrow().iter().flatten().copied().collect();
+        let n = values.len() as f32;
+        let mean = values.iter().sum::<f32>() / n;
+        let std = (values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / n).sqrt();
+        assert!(mean.abs() < 1e-3);
+        assert!((std / EMBEDDING_INIT_STD - 1.0).abs() < 0.02, "标准差应该接近0.02，实际是{}", std);
+
+        // 词嵌入 + 位置编码 -> [batch_size, seq_len, hidden_size] -> 分割成多头
+        let ids = vec![vec![5, 17, 999, 0, 42], vec![1, 2, 3, 4, 5]];
+        let pos = PositionalEncoding::learned(16, 64, &mut rng);
+        let hidden = pos.forward(&emb.forward(&ids).unwrap(), 0).unwrap();
+        assert_eq!((hidden.len(), hidden[0].len(), hidden[0][0].len()), (2, 5, 64));
+        let heads = reshape_for_attention(&hidden, 8);
+        assert_eq!((heads.len(), heads[0].len(), heads[0][0].len(), heads[0][0][0].len()), (2, 8, 5, 8));
+        assert_eq!(heads[1][3][4], hidden[1][4][24..32].to_vec());
+    }
+}
@@ -0,0 +1,486 @@
+// Transformer的每一层都由注意力和前馈网络（FFN）组成。
+// 在这个练习中，我们将实现几种常见的前馈网络变体：经典MLP、GLU、SwiGLU（Llama）和GeGLU，
+// 包括前向传播和反向传播。
+
+// 辅助函数：激活函数的前向/反向传播
+// 注意：这些函数与 01_vector_ops/src/gradient_compute.rs 中的实现相同，已经提供
+fn relu_forward(x: &[f32]) -> Vec<f32> {
+    x.iter()
+        .map(|&x| x.max(0.0))
+        .collect()
+}
+
+fn relu_backward(upstream_grad: &[f32], x: &[f32]) -> Vec<f32> {
+    assert_eq!(upstream_grad.len(), x.len(), "向量长度必须相同");
+    upstream_grad.iter()
+        .zip(x.iter())
+        .map(|(&grad, &input)| if input > 0.0 { grad } else { 0.0 })
+        .collect()
+}
+
+fn sigmoid_forward(x: &[f32]) -> Vec<f32> {
+    x.iter()
+        .map(|&x| 1.0 / (1.0 + (-x).exp()))
+        .collect()
+}
+
+fn sigmoid_backward(upstream_grad: &[f32], x: &[f32]) -> Vec<f32> {
+    assert_eq!(upstream_grad.len(), x.len(), "向量长度必须相同");
+    upstream_grad.iter()
+        .zip(sigmoid_forward(x).iter())
+        .map(|(&grad, &s)| grad * s * (1.0 - s))
+        .collect()
+}
+
+fn silu_forward(x: &[f32]) -> Vec<f32> {
+    x.iter()
+        .zip(sigmoid_forward(x).iter())
+        .map(|(&x, &s)| x * s)
+        .collect()
+}
+
+fn silu_backward(upstream_grad: &[f32], x: &[f32]) -> Vec<f32> {
+    assert_eq!(upstream_grad.len(), x.len(), "向量长度必须相同");
+    upstream_grad.iter()
+        .zip(x.iter().zip(sigmoid_forward(x).iter()))
+        .map(|(&grad, (&x, &s))| grad * s * (1.0 + x * (1.0 - s)))
+        .collect()
+}
+
+fn gelu_forward(x: &[f32]) -> Vec<f32> {
+    let k = (2.0 / std::f32::consts::PI).sqrt();
+    x.iter()
+        .map(|&x| 0.5 * x * (1.0 + (k * (x + 0.044715 * x.powi(3))).tanh()))
+        .collect()
+}
+
+fn gelu_backward(upstream_grad: &[f32], x: &[f32]) -> Vec<f32> {
+    assert_eq!(upstream_grad.len(), x.len(), "向量长度必须相同");
+    let k = (2.0 / std::f32::consts::PI).sqrt();
+    upstream_grad.iter()
+        .zip(x.iter())
+        .map(|(&grad, &x)| {
+            let t = (k * (x + 0.044715 * x.powi(3))).tanh();
+            let d = 0.5 * (1.0 + t) + 0.5 * x * (1.0 - t * t) * k * (1.0 + 3.0 * 0.044715 * x * x);
+            grad * d
+        })
+        .collect()
+}
+
+// 激活函数的种类
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Activation {
+    Relu,
+    Gelu,
+    Silu,
+    Sigmoid,
+}
+
+impl Activation {
+    fn forward(&self, x: &[f32]) -> Vec<f32> {
+        match self {
+            Activation::Relu => relu_forward(x),
+            Activation::Gelu => gelu_forward(x),
+            Activation::Silu => silu_forward(x),
+            Activation::Sigmoid => sigmoid_forward(x),
+        }
+    }
+
+    fn backward(&self, upstream_grad: &[f32], x: &[f32]) -> Vec<f32> {
+        match self {
+            Activation::Relu => relu_backward(upstream_grad, x),
+            Activation::Gelu => gelu_backward(upstream_grad, x),
+            Activation::Silu => silu_backward(upstream_grad, x),
+            Activation::Sigmoid => sigmoid_backward(upstream_grad, x),
+        }
+    }
+}
+
+// 前馈网络的结构
+// Mlp:   down(act(up(x)))，GPT-2使用GELU
+// Gated: down(act(gate(x)) * up(x))
+//        act = Sigmoid 即GLU，act = Silu 即SwiGLU（Llama），act = Gelu 即GeGLU
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FfnKind {
+    Mlp(Activation),
+    Gated(Activation),
+}
+
+impl FfnKind {
+    fn swiglu() -> Self {
+        FfnKind::Gated(Activation::Silu)
+    }
+
+    fn geglu() -> Self {
+        FfnKind::Gated(Activation::Gelu)
+    }
+
+    fn glu() -> Self {
+        FfnKind::Gated(Activation::Sigmoid)
+    }
+
+    fn is_gated(&self) -> bool {
+        matches!(self, FfnKind::Gated(_))
+    }
+
+    fn activation(&self) -> Activation {
+        match *self {
+            FfnKind::Mlp(act) | FfnKind::Gated(act) => act,
+        }
+    }
+}
+
+// 计算前馈网络中间层的维度
+// 1. intermediate = hidden_size * multiplier（经典Transformer中multiplier为4）
+// 2. 门控变体有三个矩阵而不是两个，为了保持参数量大致相同，乘以2/3
+// 3. 向上取整到multiple_of的整数倍，便于硬件高效计算
+// 例如Llama-7B: hidden_size=4096, multiplier=4, multiple_of=256 => 11008
+fn intermediate_size(hidden_size: usize, multiplier: f32, multiple_of: usize, gated: bool) -> usize {
+    assert!(multiple_of > 0, "multiple_of必须大于0");
+    let mut size = (hidden_size as f32 * multiplier) as usize;
+    if gated {
+        size = 2 * size / 3;
+    }
+    size.div_ceil(multiple_of) * multiple_of
+}
+
+// 计算 x * W^T
+// x形状: [rows, in_features]，w形状: [out_features, in_features]
+fn matmul_transposed(x: &[Vec<f32>], w: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    x.iter()
+        .map(|row| {
+            w.iter()
+                .map(|w_row| row.iter().zip(w_row.iter()).map(|(&a, &b)| a * b).sum())
+                .collect()
+        })
+        .collect()
+}
+
+// 计算 dY * W
+// dy形状: [rows, out_features]，w形状: [out_features, in_features]
+fn matmul(dy: &[Vec<f32>], w: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    dy.iter()
+        .map(|dy_row| {
+            let mut out = vec![0.0; w[0].len()];
+            for (&g, w_row) in dy_row.iter().zip(w.iter()) {
+                for (o, &wv) in out.iter_mut().zip(w_row.iter()) {
+                    *o += g * wv;
+                }
+            }
+            out
+        })
+        .collect()
+}
+
+// 计算权重梯度 dY^T * X
+// 结果形状: [out_features, in_features]
+fn weight_grad(dy: &[Vec<f32>], x: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    let out_features = dy[0].len();
+    let in_features = x[0].len();
+    let mut grad = vec![vec![0.0; in_features]; out_features];
+    for (dy_row, x_row) in dy.iter().zip(x.iter()) {
+        for (g_row, &g) in grad.iter_mut().zip(dy_row.iter()) {
+            for (gv, &xv) in g_row.iter_mut().zip(x_row.iter()) {
+                *gv += g * xv;
+            }
+        }
+    }
+    grad
+}
+
+// 前馈网络
+// w_up形状: [intermediate, hidden]
+// w_gate形状: [intermediate, hidden]，只有门控变体才有
+// w_down形状: [hidden, intermediate]
+// 与Llama一致，各投影层不带偏置
+struct FeedForward {
+    kind: FfnKind,
+    w_up: Vec<Vec<f32>>,
+    w_gate: Option<Vec<Vec<f32>>>,
+    w_down: Vec<Vec<f32>>,
+}
+
+// 前向传播时保存的中间结果，反向传播需要用到
+// 所有张量都已展平为 [batch_size * seq_len, features]
+struct FfnCache {
+    x: Vec<Vec<f32>>,
+    up: Vec<Vec<f32>>,
+    gate: Option<Vec<Vec<f32>>>,
+    hidden: Vec<Vec<f32>>,
+}
+
+// 前馈网络反向传播的结果
+struct FfnGrads {
+    grad_input: Vec<Vec<Vec<f32>>>,
+    grad_up: Vec<Vec<f32>>,
+    grad_gate: Option<Vec<Vec<f32>>>,
+    grad_down: Vec<Vec<f32>>,
+}
+
+impl FeedForward {
+    fn new(
+        kind: FfnKind,
+        w_up: Vec<Vec<f32>>,
+        w_gate: Option<Vec<Vec<f32>>>,
+        w_down: Vec<Vec<f32>>
+    ) -> Self {
+        assert_eq!(kind.is_gated(), w_gate.is_some(), "只有门控变体需要gate矩阵");
+        assert_eq!(w_down[0].len(), w_up.len(), "w_down的输入维度必须等于中间层维度");
+        FeedForward { kind, w_up, w_gate, w_down }
+    }
+
+    // 前向传播
+    // 输入形状: [batch_size, seq_len, hidden_size]
+    // 输出形状: [batch_size, seq_len, hidden_size]
+    fn forward(&self, x: &[Vec<Vec<f32>>]) -> (Vec<Vec<Vec<f32>>>, FfnCache) {
+        let seq_len = x[0].len();
+        let flat: Vec<Vec<f32>> = x.iter().flatten().cloned().collect();
+
+        let act = self.kind.activation();
+        let up = matmul_transposed(&flat, &self.w_up);
+        let (gate, hidden): (Option<Vec<Vec<f32>>>, Vec<Vec<f32>>) = match &self.w_gate {
+            Some(w_gate) => {
+                let gate = matmul_transposed(&flat, w_gate);
+                let hidden = gate.iter()
+                    .zip(up.iter())
+                    .map(|(g, u)| {
+                        act.forward(g).iter().zip(u.iter()).map(|(&a, &b)| a * b).collect()
+                    })
+                    .collect();
+                (Some(gate), hidden)
+            }
+            None => (None, up.iter().map(|u| act.forward(u)).collect()),
+        };
+        let out = matmul_transposed(&hidden, &self.w_down);
+
+        let out = out.chunks(seq_len).map(|c| c.to_vec()).collect();
+        (out, FfnCache { x: flat, up, gate, hidden })
+    }
+
+    // 反向传播
+    // 输入梯度形状: [batch_size, seq_len, hidden_size]
+    // 记 h 为送入w_down的中间激活：
+    //     dh = dOut * W_down，dW_down = dOut^T * h
+    // MLP:   d_up = act'(up) * dh
+    // Gated: d_up = act(gate) * dh，d_gate = act'(gate) * up * dh
+    // 最后 dX = d_up * W_up (+ d_gate * W_gate)
+    fn backward(&self, upstream_grad: &[Vec<Vec<f32>>], cache: &FfnCache) -> FfnGrads {
+        let seq_len = upstream_grad[0].len();
+        let d_out: Vec<Vec<f32>> = upstream_grad.iter().flatten().cloned().collect();
+        let act = self.kind.activation();
+
+        let grad_down = weight_grad(&d_out, &cache.hidden);
+        let d_hidden = matmul(&d_out, &self.w_down);
+
+        let (d_up, d_gate): (Vec<Vec<f32>>, Option<Vec<Vec<f32>>>) = match &cache.gate {
+            Some(gate) => {
+                let d_up = d_hidden.iter()
+                    .zip(gate.iter())
+                    .map(|(dh, g)| {
+                        act.forward(g).iter().zip(dh.iter()).map(|(&a, &d)| a * d).collect()
+                    })
+                    .collect();
+                let d_gate = d_hidden.iter()
+                    .zip(gate.iter().zip(cache.up.iter()))
+                    .map(|(dh, (g, u))| {
+                        let d_act: Vec<f32> = dh.iter().zip(u.iter()).map(|(&d, &u)| d * u).collect();
+                        act.backward(&d_act, g)
+                    })
+                    .collect();
+                (d_up, Some(d_gate))
+            }
+            None => {
+                let d_up = d_hidden.iter()
+                    .zip(cache.up.iter())
+                    .map(|(dh, u)| act.backward(dh, u))
+                    .collect();
+                (d_up, None)
+            }
+        };
+
+        let grad_up = weight_grad(&d_up, &cache.x);
+        let mut d_x = matmul(&d_up, &self.w_up);
+        let grad_gate = match (&self.w_gate, &d_gate) {
+            (Some(w_gate), Some(d_gate)) => {
+                for (dx_row, dg_row) in d_x.iter_mut().zip(matmul(d_gate, w_gate)) {
+                    for (a, b) in dx_row.iter_mut().zip(dg_row) {
+                        *a += b;
+                    }
+                }
+                Some(weight_grad(d_gate, &cache.x))
+            }
+            _ => None,
+        };
+
+        FfnGrads {
+            grad_input: d_x.chunks(seq_len).map(|c| c.to_vec()).collect(),
+            grad_up,
+            grad_gate,
+            grad_down,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const EPSILON: f32 = 1e-5;
+
+    fn random_matrix(rng: &mut StdRng, rows: usize, cols: usize) -> Vec<Vec<f32>> {
+        (0..rows).map(|_| (0..cols).map(|_| rng.gen_range(-1.0..1.0)).collect()).collect()
+    }
+
+    fn random_ffn(rng: &mut StdRng, kind: FfnKind, hidden: usize, intermediate: usize) -> FeedForward {
+        let w_up = random_matrix(rng, intermediate, hidden);
+        let w_gate = if kind.is_gated() { Some(random_matrix(rng, intermediate, hidden)) } else { None };
+        let w_down = random_matrix(rng, hidden, intermediate);
+        FeedForward::new(kind, w_up, w_gate, w_down)
+    }
+
+    #[test]
+    fn test_intermediate_size() {
+        // 经典MLP: 4倍隐藏维度
+        assert_eq!(intermediate_size(768, 4.0, 1, false), 3072);
+        // Llama-7B的SwiGLU中间维度
+        assert_eq!(intermediate_size(4096, 4.0, 256, true), 11008);
+        // 向上取整到multiple_of的整数倍
+        assert_eq!(intermediate_size(10, 4.0, 16, false), 48);
+        assert_eq!(intermediate_size(10, 4.0, 8, true), 32);
+    }
+
+    #[test]
+    fn test_mlp_forward() {
+        // hidden=2, intermediate=2，ReLU
+        let ffn = FeedForward::new(
+            FfnKind::Mlp(Activation::Relu),
+            vec![vec![1.0, 0.0], vec![0.0, -1.0]],
+            None,
+            vec![vec![1.0, 1.0], vec![2.0, 0.0]],
+        );
+        let x = vec![vec![vec![3.0, 2.0]]];
+        let (y, _) = ffn.forward(&x);
+        // up = [3, -2]，relu => [3, 0]，down => [3, 6]
+        assert!((y[0][0][0] - 3.0).abs() < EPSILON);
+        assert!((y[0][0][1] - 6.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_swiglu_forward() {
+        let ffn = FeedForward::new(
+            FfnKind::swiglu(),
+            vec![vec![2.0, 0.0]],
+            Some(vec![vec![0.0, 1.0]]),
+            vec![vec![1.0], vec![-1.0]],
+        );
+        let x = vec![vec![vec![1.5, 2.0]]];
+        let (y, _) = ffn.forward(&x);
+        // gate = 2，up = 3，silu(2) * 3 = 2 * sigmoid(2) * 3
+        let h = 2.0 / (1.0 + (-2.0f32).exp()) * 3.0;
+        assert!((y[0][0][0] - h).abs() < EPSILON);
+        assert!((y[0][0][1] + h).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_ffn_output_shape() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let ffn = random_ffn(&mut rng, FfnKind::geglu(), 4, intermediate_size(4, 4.0, 8, true));
+        assert_eq!(ffn.w_up.len(), 16);
+
+        let x: Vec<Vec<Vec<f32>>> = (0..2).map(|_| random_matrix(&mut rng, 3, 4)).collect();
+        let (y, _) = ffn.forward(&x);
+        assert_eq!(y.len(), 2);
+        assert_eq!(y[0].len(), 3);
+        assert_eq!(y[0][0].len(), 4);
+    }
+
+    // 用中心差分检查所有梯度
+    fn check_gradients(kind: FfnKind, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (hidden, intermediate) = (3, 5);
+        let mut ffn = random_ffn(&mut rng, kind, hidden, intermediate);
+        let x: Vec<Vec<Vec<f32>>> = (0..2).map(|_| random_matrix(&mut rng, 2, hidden)).collect();
+        let upstream: Vec<Vec<Vec<f32>>> = (0..2).map(|_| random_matrix(&mut rng, 2, hidden)).collect();
+
+        let loss = |ffn: &FeedForward, x: &[Vec<Vec<f32>>]| -> f32 {
+            ffn.forward(x).0.iter().flatten().flatten()
+                .zip(upstream.iter().flatten().flatten())
+                .map(|(&a, &b)| a * b)
+                .sum()
+        };
+        let (_, cache) = ffn.forward(&x);
+        let grads = ffn.backward(&upstream, &cache);
+        let h = 1e-3;
+        let tol = 2e-2;
+
+        for b in 0..2 {
+            for s in 0..2 {
+                for i in 0..hidden {
+                    let mut plus = x.clone();
+                    let mut minus = x.clone();
+                    plus[b][s][i] += h;
+                    minus[b][s][i] -= h;
+                    let numeric = (loss(&ffn, &plus) - loss(&ffn, &minus)) / (2.0 * h);
+                    assert!((numeric - grads.grad_input[b][s][i]).abs() < tol,
+                            "{:?} dX错误: 数值梯度{}，解析梯度{}", kind, numeric, grads.grad_input[b][s][i]);
+                }
+            }
+        }
+
+        for o in 0..intermediate {
+            for i in 0..hidden {
+                let original = ffn.w_up[o][i];
+                ffn.w_up[o][i] = original + h;
+                let plus = loss(&ffn, &x);
+                ffn.w_up[o][i] = original - h;
+                let minus = loss(&ffn, &x);
+                ffn.w_up[o][i] = original;
+                let numeric = (plus - minus) / (2.0 * h);
+                assert!((numeric - grads.grad_up[o][i]).abs() < tol, "{:?} dW_up错误", kind);
+
+                if let Some(grad_gate) = &grads.grad_gate {
+                    let original = ffn.w_gate.as_ref().unwrap()[o][i];
+                    ffn.w_gate.as_mut().unwrap()[o][i] = original + h;
+                    let plus = loss(&ffn, &x);
+                    ffn.w_gate.as_mut().unwrap()[o][i] = original - h;
+                    let minus = loss(&ffn, &x);
+                    ffn.w_gate.as_mut().unwrap()[o][i] = original;
+                    let numeric = (plus - minus) / (2.0 * h);
+                    assert!((numeric - grad_gate[o][i]).abs() < tol, "{:?} dW_gate错误", kind);
+                }
+            }
+        }
+
+        for o in 0..hidden {
+            for i in 0..intermediate {
+                let original = ffn.w_down[o][i];
+                ffn.w_down[o][i] = original + h;
+                let plus = loss(&ffn, &x);
+                ffn.w_down[o][i] = original - h;
+                let minus = loss(&ffn, &x);
+                ffn.w_down[o][i] = original;
+                let numeric = (plus - minus) / (2.0 * h);
+                assert!((numeric - grads.grad_down[o][i]).abs() < tol, "{:?} dW_down错误", kind);
+            }
+        }
+
+        assert_eq!(grads.grad_gate.is_some(), kind.is_gated());
+    }
+
+    #[test]
+    fn test_mlp_gradients() {
+        check_gradients(FfnKind::Mlp(Activation::Gelu), 10);
+        // ReLU在0处不可导，差分步长跨过0时数值梯度没有意义，
+        // 这个种子生成的输入中没有离0太近的中间激活
+        check_gradients(FfnKind::Mlp(Activation::Relu), 15);
+    }
+
+    #[test]
+    fn test_gated_gradients() {
+        check_gradients(FfnKind::glu(), 20);
+        check_gradients(FfnKind::swiglu(), 21);
+        check_gradients(FfnKind::geglu(), 22);
+    }
+}
@@ -0,0 +1,338 @@
+// 多头注意力中的Q/K/V投影、输出投影，以及前馈网络中的升维/降维，都是线性层。
+// 在这个练习中，我们将实现带偏置的线性层，包括批量前向传播、反向传播和权重初始化。
+
+use rand::Rng;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// 可共享的权重矩阵
+// 权重绑定（weight tying）时，词嵌入层和LM head使用同一个矩阵
+type SharedWeight = Rc<RefCell<Vec<Vec<f32>>>>;
+
+// 权重初始化方法
+// Xavier适合tanh/线性层，Kaiming（He）适合ReLU等激活函数
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Init {
+    XavierUniform,
+    XavierNormal,
+    KaimingUniform,
+    KaimingNormal,
+}
+
+// 线性层: y = x * W^T + b
+// weight形状: [out_features, in_features]
+// bias形状: [out_features]，不需要偏置时为None（例如Llama中的投影层）
+struct Linear {
+    weight: SharedWeight,
+    bias: Option<Vec<f32>>,
+}
+
+// 线性层反向传播的结果
+// grad_input形状: [batch_size, seq_len, in_features]
+// grad_weight形状: [out_features, in_features]
+// grad_bias形状: [out_features]
+struct LinearGrads {
+    grad_input: Vec<Vec<Vec<f32>>>,
+    grad_weight: Vec<Vec<f32>>,
+    grad_bias: Option<Vec<f32>>,
+}
+
+// 使用Box-Muller变换生成标准正态分布的随机数
+fn sample_standard_normal(rng: &mut impl Rng) -> f32 {
+    let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+    let u2: f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+// 按照给定的初始化方法生成权重矩阵
+// Xavier: Var(W) = 2 / (fan_in + fan_out)
+// Kaiming: Var(W) = 2 / fan_in
+// 均匀分布U(-a, a)的方差为 a^2 / 3，据此计算边界a
+fn init_weight(
+    in_features: usize,
+    out_features: usize,
+    init: Init,
+    rng: &mut impl Rng
+) -> Vec<Vec<f32>> {
+    let fan_in = in_features as f32;
+    let fan_out = out_features as f32;
+    let std = match init {
+        Init::XavierUniform | Init::XavierNormal => (2.0 / (fan_in + fan_out)).sqrt(),
+        Init::KaimingUniform | Init::KaimingNormal => (2.0 / fan_in).sqrt(),
+    };
+
+    (0..out_features)
+        .map(|_| {
+            (0..in_features)
+                .map(|_| match init {
+                    Init::XavierUniform | Init::KaimingUniform => {
+                        let bound = std * 3.0f32.sqrt();
+                        rng.gen_range(-bound..bound)
+                    }
+                    Init::XavierNormal | Init::KaimingNormal => std * sample_standard_normal(rng),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+impl Linear {
+    // 创建一个新的线性层，偏置初始化为0
+    fn new(
+        in_features: usize,
+        out_features: usize,
+        bias: bool,
+        init: Init,
+        rng: &mut impl Rng
+    ) -> Self {
+        assert!(in_features > 0 && out_features > 0, "特征维度必须大于0");
+        Linear {
+            weight: Rc::new(RefCell::new(init_weight(in_features, out_features, init, rng))),
+            bias: if bias { Some(vec![0.0; out_features]) } else { None },
+        }
+    }
+
+    // 使用已有的权重矩阵创建线性层，与其他层共享同一份权重
+    // 例如把形状为 [vocab_size, hidden_size] 的词嵌入矩阵直接作为LM head
+    fn tied(weight: SharedWeight, bias: Option<Vec<f32>>) -> Self {
+        if let Some(b) = &bias {
+            assert_eq!(b.len(), weight.borrow().len(), "偏置长度必须等于输出维度");
+        }
+        Linear { weight, bias }
+    }
+
+    fn in_features(&self) -> usize {
+        self.weight.borrow()[0].len()
+    }
+
+    fn out_features(&self) -> usize {
+        self.weight.borrow().len()
+    }
+
+    // 前向传播
+    // 输入形状: [batch_size, seq_len, in_features]
+    // 输出形状: [batch_size, seq_len, out_features]
+    fn forward(&self, x: &[Vec<Vec<f32>>]) -> Vec<Vec<Vec<f32>>> {
+        let weight = self.weight.borrow();
+        x.iter()
+            .map(|batch| {
+                batch.iter()
+                    .map(|row| {
+                        assert_eq!(row.len(), self.in_features(), "输入维度与权重不匹配");
+                        weight.iter()
+                            .enumerate()
+                            .map(|(o, w)| {
+                                let dot = row.iter()
+                                    .zip(w.iter())
+                                    .map(|(&a, &b)| a * b)
+                                    .sum::<f32>();
+                                match &self.bias {
+                                    Some(b) => dot + b[o],
+                                    None => dot,
+                                }
+                            })
+                            .collect::<Vec<f32>>()
+                    })
+                    .collect::<Vec<Vec<f32>>>()
+            })
+            .collect()
+    }
+
+    // 反向传播
+    // dX = dY * W
+    // dW = sum over (batch, seq) of dY^T * X
+    // db = sum over (batch, seq) of dY
+    fn backward(&self, upstream_grad: &[Vec<Vec<f32>>], x: &[Vec<Vec<f32>>]) -> LinearGrads {
+        let weight = self.weight.borrow();
+        let in_features = self.in_features();
+        let out_features = self.out_features();
+
+        let mut grad_weight = vec![vec![0.0; in_features]; out_features];
+        let mut grad_bias = vec![0.0; out_features];
+
+        let grad_input = upstream_grad.iter()
+            .zip(x.iter())
+            .map(|(batch_grad, batch_x)| {
+                batch_grad.iter()
+                    .zip(batch_x.iter())
+                    .map(|(dy, row)| {
+                        let mut dx = vec![0.0; in_features];
+                        for (o, &g) in dy.iter().enumerate() {
+                            grad_bias[o] += g;
+                            for i in 0..in_features {
+                                dx[i] += g * weight[o][i];
+                                grad_weight[o][i] += g * row[i];
+                            }
+                        }
+                        dx
+                    })
+                    .collect::<Vec<Vec<f32>>>()
+            })
+            .collect();
+
+        LinearGrads {
+            grad_input,
+            grad_weight,
+            grad_bias: self.bias.as_ref().map(|_| grad_bias),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn layer_from(weight: Vec<Vec<f32>>, bias: Option<Vec<f32>>) -> Linear {
+        Linear::tied(Rc::new(RefCell::new(weight)), bias)
+    }
+
+    #[test]
+    fn test_linear_forward() {
+        // W = [[1, 2], [3, 4], [5, 6]], b = [0.5, -0.5, 1.0]
+        let layer = layer_from(
+            vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]],
+            Some(vec![0.5, -0.5, 1.0]),
+        );
+        // 形状: [batch_size=2, seq_len=1, in_features=2]
+        let x = vec![vec![vec![1.0, 1.0]], vec![vec![2.0, -1.0]]];
+        let y = layer.forward(&x);
+
+        assert_eq!(y.len(), 2);
+        assert_eq!(y[0].len(), 1);
+        assert_eq!(y[0][0].len(), 3);
+
+        let expected = [[3.5, 6.5, 12.0], [0.5, 1.5, 5.0]];
+        for (b, row) in expected.iter().enumerate() {
+            for (o, &e) in row.iter().enumerate() {
+                assert!((y[b][0][o] - e).abs() < EPSILON, "线性层前向传播错误");
+            }
+        }
+
+        // 没有偏置
+        let layer = layer_from(vec![vec![1.0, 2.0]], None);
+        let y = layer.forward(&[vec![vec![3.0, 4.0]]]);
+        assert!((y[0][0][0] - 11.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_linear_backward_finite_difference() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut layer = Linear::new(3, 4, true, Init::XavierUniform, &mut rng);
+        // 使用非零偏置，避免偏置为0时掩盖错误
+        layer.bias = Some((0..4).map(|_| rng.gen_range(-1.0..1.0)).collect());
+
+        let x: Vec<Vec<Vec<f32>>> = (0..2)
+            .map(|_| (0..3).map(|_| (0..3).map(|_| rng.gen_range(-1.0..1.0)).collect()).collect())
+            .collect();
+        let upstream: Vec<Vec<Vec<f32>>> = (0..2)
+            .map(|_| (0..3).map(|_| (0..4).map(|_| rng.gen_range(-1.0..1.0)).collect()).collect())
+            .collect();
+
+        // 标量损失 L = sum(upstream * y)
+        let loss = |layer: &Linear, x: &[Vec<Vec<f32>>]| -> f32 {
+            layer.forward(x).iter().flatten().flatten()
+                .zip(upstream.iter().flatten().flatten())
+                .map(|(&a, &b)| a * b)
+                .sum()
+        };
+        let grads = layer.backward(&upstream, &x);
+        let h = 1e-2;
+
+        // 输入的梯度
+        for b in 0..2 {
+            for s in 0..3 {
+                for i in 0..3 {
+                    let mut plus = x.clone();
+                    let mut minus = x.clone();
+                    plus[b][s][i] += h;
+                    minus[b][s][i] -= h;
+                    let numeric = (loss(&layer, &plus) - loss(&layer, &minus)) / (2.0 * h);
+                    assert!((numeric - grads.grad_input[b][s][i]).abs() < 1e-2, "dX错误");
+                }
+            }
+        }
+
+        // 权重的梯度
+        for o in 0..4 {
+            for i in 0..3 {
+                let original = layer.weight.borrow()[o][i];
+                layer.weight.borrow_mut()[o][i] = original + h;
+                let plus = loss(&layer, &x);
+                layer.weight.borrow_mut()[o][i] = original - h;
+                let minus = loss(&layer, &x);
+                layer.weight.borrow_mut()[o][i] = original;
+                let numeric = (plus - minus) / (2.0 * h);
+                assert!((numeric - grads.grad_weight[o][i]).abs() < 1e-2, "dW错误");
+            }
+        }
+
+        // 偏置的梯度等于上游梯度在batch和seq维度上的和
+        let grad_bias = grads.grad_bias.unwrap();
+        for o in 0..4 {
+            let expected: f32 = upstream.iter().flatten().map(|row| row[o]).sum();
+            assert!((grad_bias[o] - expected).abs() < EPSILON, "db错误");
+        }
+
+        // 没有偏置时不返回db
+        let no_bias = Linear::tied(layer.weight.clone(), None);
+        assert!(no_bias.backward(&upstream, &x).grad_bias.is_none());
+    }
+
+    #[test]
+    fn test_weight_init_statistics() {
+        let mut rng = StdRng::seed_from_u64(123);
+        let (fan_in, fan_out) = (256, 128);
+        let cases = [
+            (Init::XavierUniform, 2.0 / (fan_in + fan_out) as f32),
+            (Init::XavierNormal, 2.0 / (fan_in + fan_out) as f32),
+            (Init::KaimingUniform, 2.0 / fan_in as f32),
+            (Init::KaimingNormal, 2.0 / fan_in as f32),
+        ];
+
+        for (init, expected_var) in cases {
+            let layer = Linear::new(fan_in, fan_out, true, init, &mut rng);
+            assert_eq!(layer.in_features(), fan_in);
+            assert_eq!(layer.out_features(), fan_out);
+
+            let weight = layer.weight.borrow();
+            let values: Vec<f32> = weight.iter().flatten().copied().collect();
+            let n = values.len() as f32;
+            let mean = values.iter().sum::<f32>() / n;
+            let var = values.iter().map(|&v| (v - mean).powi(2)).sum::<f32>() / n;
+
+            assert!(mean.abs() < 0.01, "{:?} 初始化的均值应该接近0，实际是{}", init, mean);
+            assert!((var / expected_var - 1.0).abs() < 0.05,
+                    "{:?} 初始化的方差应该接近{}，实际是{}", init, expected_var, var);
+
+            // 偏置初始化为0
+            assert!(layer.bias.as_ref().unwrap().iter().all(|&b| b == 0.0));
+        }
+    }
+
+    #[test]
+    fn test_weight_tying() {
+        // 词嵌入矩阵形状: [vocab_size=3, hidden_size=2]
+        let embedding: SharedWeight = Rc::new(RefCell::new(vec![
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+            vec![1.0, 1.0],
+        ]));
+        let lm_head = Linear::tied(Rc::clone(&embedding), None);
+        assert!(Rc::ptr_eq(&embedding, &lm_head.weight), "LM head应该与词嵌入共享权重");
+        assert_eq!(lm_head.in_features(), 2);
+        assert_eq!(lm_head.out_features(), 3);
+
+        // LM head的输出是隐藏状态与每个词向量的点积
+        let logits = lm_head.forward(&[vec![vec![2.0, 3.0]]]);
+        assert_eq!(logits[0][0], vec![2.0, 3.0, 5.0]);
+
+        // 修改词嵌入后，LM head立刻看到同样的修改
+        embedding.borrow_mut()[0][0] = 10.0;
+        let logits = lm_head.forward(&[vec![vec![2.0, 3.0]]]);
+        assert!((logits[0][0][0] - 20.0).abs() < EPSILON, "绑定的权重应该同步更新");
+    }
+}
@@ -89,9 +89,10 @@ fn batch_matmul(
                         .map(|i| {
                             (0..seq_len_k)
                                 .map(|j| {
+                                    // key已经转置，第j列是第j个key向量
                                     q[i].iter()
-                                        .zip(k[j].iter())
-                                        .map(|(&a, &b)| a * b)
+                                        .zip(k.iter())
+                                        .map(|(&a, k_row)| a * k_row[j])
                                         .sum::<f32>()
                                 })
                                 .collect::<Vec<f32>>()
@@ -103,9 +104,92 @@ fn batch_matmul(
         .collect()
 }
 
+// 实现多头合并函数，即reshape_for_attention的逆变换
+// 输入张量形状为 [batch_size, num_heads, seq_len, head_size]
+// 输出张量形状为 [batch_size, seq_len, hidden_size]
+fn merge_heads(
+    x: &[Vec<Vec<Vec<f32>>>]
+) -> Vec<Vec<Vec<f32>>> {
+    x.iter()
+        .map(|batch| {
+            let seq_len = batch[0].len();
+            (0..seq_len)
+                .map(|s| {
+                    batch.iter()
+                        .flat_map(|head| head[s].iter().copied())
+                        .collect::<Vec<f32>>()
+                })
+                .collect::<Vec<Vec<f32>>>()
+        })
+        .collect()
+}
+
+// 实现reshape_for_attention的反向传播
+// reshape只是重新排列元素，所以梯度按相反的方式排列回去即可
+// 输入梯度形状为 [batch_size, num_heads, seq_len, head_size]
+// 输出梯度形状为 [batch_size, seq_len, hidden_size]
+fn reshape_for_attention_backward(
+    upstream_grad: &[Vec<Vec<Vec<f32>>>]
+) -> Vec<Vec<Vec<f32>>> {
+    merge_heads(upstream_grad)
+}
+
+// 实现transpose_for_scores的反向传播
+// 转置最后两个维度是自身的逆变换，再转置一次即可
+// 输入梯度形状为 [batch_size, num_heads, head_size, seq_len]
+// 输出梯度形状为 [batch_size, num_heads, seq_len, head_size]
+fn transpose_for_scores_backward(
+    upstream_grad: &[Vec<Vec<Vec<f32>>>]
+) -> Vec<Vec<Vec<Vec<f32>>>> {
+    transpose_for_scores(upstream_grad)
+}
+
+// 4D张量的简写，形状为 [batch_size, num_heads, rows, cols]
+type Tensor4D = Vec<Vec<Vec<Vec<f32>>>>;
+
+// 实现batch_matmul的反向传播
+// key的形状是 [head_size, seq_len_k]，batch_matmul中输出的第(i, j)个元素是query第i行与key第j列的点积：
+//     scores[i][j] = sum_d query[i][d] * key[d][j]
+// 因此：
+//     grad_query[i][d] = sum_j upstream_grad[i][j] * key[d][j]
+//     grad_key[d][j]   = sum_i upstream_grad[i][j] * query[i][d]
+// 输入梯度形状: [batch_size, num_heads, seq_len_q, seq_len_k]
+// 返回: (query的梯度, key的梯度)，形状分别与query、key相同
+fn batch_matmul_backward(
+    upstream_grad: &[Vec<Vec<Vec<f32>>>],
+    query: &[Vec<Vec<Vec<f32>>>],
+    key: &[Vec<Vec<Vec<f32>>>]
+) -> (Tensor4D, Tensor4D) {
+    let mut grad_query: Tensor4D = query.iter()
+        .map(|b| b.iter().map(|h| h.iter().map(|r| vec![0.0; r.len()]).collect()).collect())
+        .collect();
+    let mut grad_key: Tensor4D = key.iter()
+        .map(|b| b.iter().map(|h| h.iter().map(|r| vec![0.0; r.len()]).collect()).collect())
+        .collect();
+
+    for (b, batch_grad) in upstream_grad.iter().enumerate() {
+        for (h, head_grad) in batch_grad.iter().enumerate() {
+            let q = &query[b][h];
+            let k = &key[b][h];
+            for (i, row_grad) in head_grad.iter().enumerate() {
+                for (j, &g) in row_grad.iter().enumerate() {
+                    for d in 0..q[i].len() {
+                        grad_query[b][h][i][d] += g * k[d][j];
+                        grad_key[b][h][d][j] += g * q[i][d];
+                    }
+                }
+            }
+        }
+    }
+
+    (grad_query, grad_key)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
 
     const EPSILON: f32 = 1e-5;
 
@@ -183,11 +267,12 @@ mod tests {
                 ]
             ]
         ];
+        // key形状为 [head_size, seq_len]，两个key向量 [1.0, 3.0] 和 [2.0, 4.0] 按列存放
         let key = vec![
             vec![
                 vec![
-                    vec![1.0, 3.0],
-                    vec![2.0, 4.0]
+                    vec![1.0, 2.0],
+                    vec![3.0, 4.0]
                 ]
             ]
         ];
@@ -210,4 +295,222 @@ mod tests {
         // [3.0, 4.0] · [2.0, 4.0] = 3.0 * 2.0 + 4.0 * 4.0 = 22.0
         assert!((result[0][0][1][1] - 22.0).abs() < EPSILON);
     }
+
+    fn random_tensor(rng: &mut StdRng, shape: [usize; 4]) -> Vec<Vec<Vec<Vec<f32>>>> {
+        (0..shape[0])
+            .map(|_| (0..shape[1])
+                .map(|_| (0..shape[2])
+                    .map(|_| (0..shape[3]).map(|_| rng.gen_range(-1.0..1.0)).collect())
+                    .collect())
+                .collect())
+            .collect()
+    }
+
+    // 标量损失 L = sum(grad_output * output)，它对输出的梯度就是grad_output
+    fn weighted_sum_4d(x: &[Vec<Vec<Vec<f32>>>], w: &[Vec<Vec<Vec<f32>>>]) -> f32 {
+        x.iter().flatten().flatten().flatten()
+            .zip(w.iter().flatten().flatten().flatten())
+            .map(|(&a, &b)| a * b)
+            .sum()
+    }
+
+    const FD_STEP: f32 = 1e-2;
+    const FD_TOLERANCE: f32 = 1e-2;
+
+    #[test]
+    fn test_merge_heads() {
+        let input = vec![
+            vec![
+                vec![1.0, 2.0, 3.0, 4.0],
+                vec![5.0, 6.0, 7.0, 8.0]
+            ]
+        ];
+        let heads = reshape_for_attention(&input, 2);
+        assert_eq!(merge_heads(&heads), input, "合并多头应该恢复原始张量");
+    }
+
+    #[test]
+    fn test_batch_matmul_backward_finite_difference() {
+        let mut rng = StdRng::seed_from_u64(2024);
+        // 第一组形状中head_size、seq_len_q、seq_len_k互不相等，可以发现下标写反的错误
+        let mut shapes = vec![[2, 2, 2, 3, 4]];
+        for _ in 0..5 {
+            shapes.push([
+                rng.gen_range(1..3),
+                rng.gen_range(1..3),
+                rng.gen_range(1..4),
+                rng.gen_range(1..4),
+                rng.gen_range(1..5),
+            ]);
+        }
+        for [batch_size, num_heads, seq_len_q, head_size, seq_len_k] in shapes {
+            // key已经转置，形状为 [batch_size, num_heads, head_size, seq_len_k]
+            let query = random_tensor(&mut rng, [batch_size, num_heads, seq_len_q, head_size]);
+            let key = random_tensor(&mut rng, [batch_size, num_heads, head_size, seq_len_k]);
+            let upstream = random_tensor(&mut rng, [batch_size, num_heads, seq_len_q, seq_len_k]);
+
+            let (grad_q, grad_k) = batch_matmul_backward(&upstream, &query, &key);
+            let loss = |q: &[Vec<Vec<Vec<f32>>>], k: &[Vec<Vec<Vec<f32>>>]| {
+                weighted_sum_4d(&batch_matmul(q, k), &upstream)
+            };
+
+            for b in 0..batch_size {
+                for h in 0..num_heads {
+                    for i in 0..seq_len_q {
+                        for d in 0..head_size {
+                            let mut plus = query.clone();
+                            let mut minus = query.clone();
+                            plus[b][h][i][d] += FD_STEP;
+                            minus[b][h][i][d] -= FD_STEP;
+                            let numeric = (loss(&plus, &key) - loss(&minus, &key)) / (2.0 * FD_STEP);
+                            assert!((numeric - grad_q[b][h][i][d]).abs() < FD_TOLERANCE,
+                                    "query梯度错误: 数值梯度{}，解析梯度{}", numeric, grad_q[b][h][i][d]);
+                        }
+                    }
+                    for d in 0..head_size {
+                        for j in 0..seq_len_k {
+                            let mut plus = key.clone();
+                            let mut minus = key.clone();
+                            plus[b][h][d][j] += FD_STEP;
+                            minus[b][h][d][j] -= FD_STEP;
+                            let numeric = (loss(&query, &plus) - loss(&query, &minus)) / (2.0 * FD_STEP);
+                            assert!((numeric - grad_k[b][h][d][j]).abs() < FD_TOLERANCE,
+                                    "key梯度错误: 数值梯度{}，解析梯度{}", numeric, grad_k[b][h][d][j]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_attention_scores_backward_chain_finite_difference() {
+        // 完整的注意力分数计算：
+        //     scores = batch_matmul(reshape(x_q), transpose_for_scores(reshape(x_k)))
+        // 梯度按相反的顺序经过每个反向传播函数
+        let mut rng = StdRng::seed_from_u64(31);
+        let (batch_size, seq_len_q, seq_len_k, num_heads, head_size) = (2, 3, 5, 2, 2);
+        let hidden_size = num_heads * head_size;
+        let random_3d = |rng: &mut StdRng, seq_len: usize| -> Vec<Vec<Vec<f32>>> {
+            (0..batch_size)
+                .map(|_| (0..seq_len)
+                    .map(|_| (0..hidden_size).map(|_| rng.gen_range(-1.0..1.0)).collect())
+                    .collect())
+                .collect()
+        };
+        let x_q = random_3d(&mut rng, seq_len_q);
+        let x_k = random_3d(&mut rng, seq_len_k);
+        let upstream = random_tensor(&mut rng, [batch_size, num_heads, seq_len_q, seq_len_k]);
+
+        let forward = |x_q: &[Vec<Vec<f32>>], x_k: &[Vec<Vec<f32>>]| {
+            let query = reshape_for_attention(x_q, num_heads);
+            let key = transpose_for_scores(&reshape_for_attention(x_k, num_heads));
+            (query, key)
+        };
+        let loss = |x_q: &[Vec<Vec<f32>>], x_k: &[Vec<Vec<f32>>]| {
+            let (query, key) = forward(x_q, x_k);
+            weighted_sum_4d(&batch_matmul(&query, &key), &upstream)
+        };
+
+        let (query, key) = forward(&x_q, &x_k);
+        let (grad_query, grad_key) = batch_matmul_backward(&upstream, &query, &key);
+        let grad_x_q = reshape_for_attention_backward(&grad_query);
+        let grad_x_k = reshape_for_attention_backward(&transpose_for_scores_backward(&grad_key));
+        assert_eq!((grad_x_k.len(), grad_x_k[0].len(), grad_x_k[0][0].len()), (batch_size, seq_len_k, hidden_size));
+
+        for (x, grad, is_query) in [(&x_q, &grad_x_q, true), (&x_k, &grad_x_k, false)] {
+            for b in 0..batch_size {
+                for s in 0..x[b].len() {
+                    for c in 0..hidden_size {
+                        let mut plus = x.clone();
+                        let mut minus = x.clone();
+                        plus[b][s][c] += FD_STEP;
+                        minus[b][s][c] -= FD_STEP;
+                        let numeric = if is_query {
+                            (loss(&plus, &x_k) - loss(&minus, &x_k)) / (2.0 * FD_STEP)
+                        } else {
+                            (loss(&x_q, &plus) - loss(&x_q, &minus)) / (2.0 * FD_STEP)
+                        };
+                        assert!((numeric - grad[b][s][c]).abs() < FD_TOLERANCE,
+                                "输入梯度错误: 数值梯度{}，解析梯度{}", numeric, grad[b][s][c]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_transpose_for_scores_backward_finite_difference() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..5 {
+            let shape = [
+                rng.gen_range(1..3),
+                rng.gen_range(1..3),
+                rng.gen_range(1..4),
+                rng.gen_range(1..4),
+            ];
+            let x = random_tensor(&mut rng, shape);
+            let upstream = random_tensor(&mut rng, [shape[0], shape[1], shape[3], shape[2]]);
+            let grad = transpose_for_scores_backward(&upstream);
+
+            for b in 0..shape[0] {
+                for h in 0..shape[1] {
+                    for s in 0..shape[2] {
+                        for d in 0..shape[3] {
+                            let mut plus = x.clone();
+                            let mut minus = x.clone();
+                            plus[b][h][s][d] += FD_STEP;
+                            minus[b][h][s][d] -= FD_STEP;
+                            let numeric = (weighted_sum_4d(&transpose_for_scores(&plus), &upstream)
+                                - weighted_sum_4d(&transpose_for_scores(&minus), &upstream))
+                                / (2.0 * FD_STEP);
+                            assert!((numeric - grad[b][h][s][d]).abs() < FD_TOLERANCE,
+                                    "转置的梯度错误: 数值梯度{}，解析梯度{}", numeric, grad[b][h][s][d]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reshape_for_attention_backward_finite_difference() {
+        let mut rng = StdRng::seed_from_u64(99);
+        for _ in 0..5 {
+            let batch_size = rng.gen_range(1..3);
+            let seq_len = rng.gen_range(1..4);
+            let num_heads = rng.gen_range(1..4);
+            let head_size = rng.gen_range(1..3);
+            let hidden_size = num_heads * head_size;
+
+            let x: Vec<Vec<Vec<f32>>> = (0..batch_size)
+                .map(|_| (0..seq_len)
+                    .map(|_| (0..hidden_size).map(|_| rng.gen_range(-1.0..1.0)).collect())
+                    .collect())
+                .collect();
+            let upstream = random_tensor(&mut rng, [batch_size, num_heads, seq_len, head_size]);
+            let grad = reshape_for_attention_backward(&upstream);
+
+            // 梯度形状与输入相同
+            assert_eq!(grad.len(), batch_size);
+            assert_eq!(grad[0].len(), seq_len);
+            assert_eq!(grad[0][0].len(), hidden_size);
+
+            for b in 0..batch_size {
+                for s in 0..seq_len {
+                    for c in 0..hidden_size {
+                        let mut plus = x.clone();
+                        let mut minus = x.clone();
+                        plus[b][s][c] += FD_STEP;
+                        minus[b][s][c] -= FD_STEP;
+                        let numeric = (weighted_sum_4d(&reshape_for_attention(&plus, num_heads), &upstream)
+                            - weighted_sum_4d(&reshape_for_attention(&minus, num_heads), &upstream))
+                            / (2.0 * FD_STEP);
+                        assert!((numeric - grad[b][s][c]).abs() < FD_TOLERANCE,
+                                "reshape的梯度错误: 数值梯度{}，解析梯度{}", numeric, grad[b][s][c]);
+                    }
+                }
+            }
+        }
+    }
 }
\ No newline at end of file
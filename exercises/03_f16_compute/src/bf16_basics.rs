@@ -0,0 +1,412 @@
+// 除了f16，大模型训练中更常用的是bf16（brain floating point）。
+// bf16的位模式：1位符号位，8位指数位，7位尾数位
+// 它与f32的指数位数相同，可以看作f32的高16位：表示范围和f32一样大，但精度比f16低。
+// 在这个练习中，我们将实现bf16的位操作和转换，并比较bf16与f16的范围和精度。
+
+// 辅助函数：f32到f16位模式的转换（舍入到最近值，平局时取偶数）
+// 注意：这个函数与 f16_overflow.rs 中的实现相同，已经提供
+fn f32_to_f16_bits(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xFF) as i32;
+    let man = bits & 0x7FFFFF;
+
+    if exp == 0xFF {
+        if man == 0 {
+            return sign | 0x7C00;
+        }
+        let payload = (man >> 13) as u16;
+        return sign | 0x7C00 | if payload == 0 { 0x200 } else { payload };
+    }
+
+    let e = exp - 127;
+    if e > 15 {
+        return sign | 0x7C00;
+    }
+    if e >= -14 {
+        let half_man = (man >> 13) as u16;
+        let rem = man & 0x1FFF;
+        let half_bits = sign | (((e + 15) as u16) << 10) | half_man;
+        return if rem > 0x1000 || (rem == 0x1000 && (half_man & 1) == 1) {
+            half_bits + 1
+        } else {
+            half_bits
+        };
+    }
+    if e < -25 {
+        return sign;
+    }
+
+    let m = man | 0x800000;
+    let shift = (-e - 1) as u32;
+    let half_man = (m >> shift) as u16;
+    let rem = m & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    if rem > halfway || (rem == halfway && (half_man & 1) == 1) {
+        sign | (half_man + 1)
+    } else {
+        sign | half_man
+    }
+}
+
+// 辅助函数：f16位模式到f32的转换
+// 注意：这个函数与 f16_overflow.rs 中的实现相同，已经提供
+fn f16_bits_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exp = ((bits >> 10) & 0x1F) as i32;
+    let mut frac = (bits & 0x3FF) as u32;
+
+    if exp == 0 {
+        if frac == 0 {
+            return f32::from_bits(sign);
+        }
+        let mut e = -14;
+        while frac & 0x400 == 0 {
+            frac <<= 1;
+            e -= 1;
+        }
+        frac &= 0x3FF;
+        return f32::from_bits(sign | (((e + 127) as u32) << 23) | (frac << 13));
+    } else if exp == 31 {
+        return f32::from_bits(sign | 0x7F800000 | (frac << 13));
+    }
+
+    let adjusted_exp = (exp - 15 + 127) as u32;
+    f32::from_bits(sign | (adjusted_exp << 23) | (frac << 13))
+}
+
+// 提取bf16的符号位
+// 输入：bf16的位表示（u16）
+// 输出：符号位（0表示正数，1表示负数）
+fn extract_bf16_sign(bits: u16) -> u8 {
+    (bits >> 15) as u8
+}
+
+// 提取bf16的指数位
+// 输入：bf16的位表示（u16）
+// 输出：指数位的值（8位，范围0-255，偏移量127）
+fn extract_bf16_exponent(bits: u16) -> u8 {
+    ((bits >> 7) & 0xFF) as u8
+}
+
+// 提取bf16的尾数位
+// 输入：bf16的位表示（u16）
+// 输出：尾数位的值（7位，范围0-127）
+fn extract_bf16_mantissa(bits: u16) -> u8 {
+    (bits & 0x7F) as u8
+}
+
+// 构造bf16的位表示
+// 输入：符号位、指数位、尾数位
+// 输出：bf16的完整位表示
+fn construct_bf16_bits(sign: u8, exponent: u8, mantissa: u8) -> u16 {
+    assert!(sign <= 1, "符号位只能是0或1");
+    assert!(mantissa <= 0x7F, "bf16尾数只有7位");
+    ((sign as u16) << 15) | ((exponent as u16) << 7) | (mantissa as u16)
+}
+
+// f32到bf16位模式的转换
+// bf16就是f32的高16位，所以只需要对低16位做舍入（舍入到最近值，平局时取偶数）：
+// 加上 0x7FFF + 保留部分的最低位，进位会自然地传递到指数，最大有限值向上舍入时得到无穷大
+// NaN需要单独处理：直接截断可能把载荷全部截掉而变成无穷大，所以保留高位并置上quiet位
+fn f32_to_bf16_bits(x: f32) -> u16 {
+    let bits = x.to_bits();
+    if x.is_nan() {
+        return ((bits >> 16) as u16) | 0x0040;
+    }
+    let lsb = (bits >> 16) & 1;
+    let rounded = bits.wrapping_add(0x7FFF + lsb);
+    (rounded >> 16) as u16
+}
+
+// bf16位模式到f32的转换
+// 低16位补0即可，每个bf16值都可以精确地用f32表示
+fn bf16_bits_to_f32(bits: u16) -> f32 {
+    f32::from_bits((bits as u32) << 16)
+}
+
+// 格式对应的位模式转换
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FormatKind {
+    F16,
+    BF16,
+    F32,
+}
+
+// 浮点格式的描述
+// 所有数值特性都可以由指数位数和尾数位数推导出来
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FloatFormat {
+    name: &'static str,
+    kind: FormatKind,
+    exponent_bits: u32,
+    mantissa_bits: u32,
+}
+
+const F16_FORMAT: FloatFormat = FloatFormat { name: "f16", kind: FormatKind::F16, exponent_bits: 5, mantissa_bits: 10 };
+const BF16_FORMAT: FloatFormat = FloatFormat { name: "bf16", kind: FormatKind::BF16, exponent_bits: 8, mantissa_bits: 7 };
+const F32_FORMAT: FloatFormat = FloatFormat { name: "f32", kind: FormatKind::F32, exponent_bits: 8, mantissa_bits: 23 };
+
+impl FloatFormat {
+    fn bias(&self) -> i32 {
+        (1 << (self.exponent_bits - 1)) - 1
+    }
+
+    // 最大有限值: (2 - 2^-m) * 2^bias
+    fn max_value(&self) -> f64 {
+        (2.0 - 2f64.powi(-(self.mantissa_bits as i32))) * 2f64.powi(self.bias())
+    }
+
+    // 最小正规数: 2^(1 - bias)
+    fn min_normal(&self) -> f64 {
+        2f64.powi(1 - self.bias())
+    }
+
+    // 最小非规格化数: 2^(1 - bias - m)
+    fn min_subnormal(&self) -> f64 {
+        2f64.powi(1 - self.bias() - self.mantissa_bits as i32)
+    }
+
+    // 机器精度：1.0与下一个可表示值之间的距离
+    fn epsilon(&self) -> f64 {
+        2f64.powi(-(self.mantissa_bits as i32))
+    }
+
+    // 大约能精确表示的十进制有效位数
+    fn decimal_digits(&self) -> f64 {
+        (self.mantissa_bits + 1) as f64 * 2f64.log10()
+    }
+
+    // 将f32值舍入到这个格式再转换回f32
+    fn round_trip(&self, x: f32) -> f32 {
+        match self.kind {
+            FormatKind::F16 => f16_bits_to_f32(f32_to_f16_bits(x)),
+            FormatKind::BF16 => bf16_bits_to_f32(f32_to_bf16_bits(x)),
+            FormatKind::F32 => x,
+        }
+    }
+}
+
+// 生成f16与bf16的范围和精度对比报告
+fn format_comparison_report() -> String {
+    let mut report = format!(
+        "{:<6}{:>6}{:>6}{:>14}{:>14}{:>14}{:>12}{:>8}\n",
+        "格式", "指数", "尾数", "最大值", "最小正规数", "最小非规格化", "机器精度", "位数"
+    );
+    for format in [F16_FORMAT, BF16_FORMAT] {
+        report.push_str(&format!(
+            "{:<6}{:>6}{:>6}{:>14.4e}{:>14.4e}{:>14.4e}{:>12.4e}{:>8.2}\n",
+            format.name,
+            format.exponent_bits,
+            format.mantissa_bits,
+            format.max_value(),
+            format.min_normal(),
+            format.min_subnormal(),
+            format.epsilon(),
+            format.decimal_digits(),
+        ));
+    }
+    report
+}
+
+// 一个张量在某种格式下的表示情况
+#[derive(Debug, Clone, PartialEq)]
+struct FormatFit {
+    format: FloatFormat,
+    overflow_count: usize,    // 有限值变成了无穷大
+    underflow_count: usize,   // 非零值变成了零
+    subnormal_count: usize,   // 落入非规格化数范围，精度降低
+    max_relative_error: f64,  // 不计上溢和下溢的值
+}
+
+impl FormatFit {
+    // 没有上溢和下溢时认为这个格式能容纳该张量
+    fn fits(&self) -> bool {
+        self.overflow_count == 0 && self.underflow_count == 0
+    }
+}
+
+// 检查一个张量转换到给定格式后的表示情况
+fn check_tensor_fit(x: &[f32], format: FloatFormat) -> FormatFit {
+    let mut fit = FormatFit {
+        format,
+        overflow_count: 0,
+        underflow_count: 0,
+        subnormal_count: 0,
+        max_relative_error: 0.0,
+    };
+
+    for &v in x.iter().filter(|v| v.is_finite()) {
+        let y = format.round_trip(v);
+        if y.is_infinite() {
+            fit.overflow_count += 1;
+        } else if y == 0.0 && v != 0.0 {
+            fit.underflow_count += 1;
+        } else {
+            if y != 0.0 && (y.abs() as f64) < format.min_normal() {
+                fit.subnormal_count += 1;
+            }
+            if v != 0.0 {
+                let err = ((v as f64) - (y as f64)).abs() / (v as f64).abs();
+                fit.max_relative_error = fit.max_relative_error.max(err);
+            }
+        }
+    }
+    fit
+}
+
+// 为张量推荐存储格式
+// 优先选择精度更高的f16；f16放不下但bf16可以时选择bf16；都不行则保留f32
+fn recommend_format(x: &[f32]) -> FloatFormat {
+    [F16_FORMAT, BF16_FORMAT]
+        .into_iter()
+        .find(|&format| check_tensor_fit(x, format).fits())
+        .unwrap_or(F32_FORMAT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_bf16_fields() {
+        // 1.0 = 0x3F80: 符号0，指数127，尾数0
+        assert_eq!(extract_bf16_sign(0x3F80), 0);
+        assert_eq!(extract_bf16_exponent(0x3F80), 127);
+        assert_eq!(extract_bf16_mantissa(0x3F80), 0);
+
+        // -1.5 = 0xBFC0: 符号1，指数127，尾数0b1000000
+        assert_eq!(extract_bf16_sign(0xBFC0), 1, "负数符号位应该是1");
+        assert_eq!(extract_bf16_exponent(0xBFC0), 127);
+        assert_eq!(extract_bf16_mantissa(0xBFC0), 64);
+
+        // 最大尾数和最大指数
+        assert_eq!(extract_bf16_mantissa(0x007F), 127, "尾数位提取错误");
+        assert_eq!(extract_bf16_exponent(0x7F80), 255, "指数位提取错误");
+    }
+
+    #[test]
+    fn test_construct_bf16_bits() {
+        assert_eq!(construct_bf16_bits(0, 127, 0), 0x3F80, "bf16位构造错误");
+        assert_eq!(construct_bf16_bits(1, 128, 64), 0xC040, "bf16位构造错误");
+
+        // 分解再重组
+        for bits in 0..=u16::MAX {
+            let reconstructed = construct_bf16_bits(
+                extract_bf16_sign(bits),
+                extract_bf16_exponent(bits),
+                extract_bf16_mantissa(bits),
+            );
+            assert_eq!(reconstructed, bits, "位操作综合测试失败");
+        }
+    }
+
+    #[test]
+    fn test_bf16_is_upper_half_of_f32() {
+        // 可以精确表示的值转换后就是f32的高16位
+        for &x in &[1.0f32, -2.0, 0.5, 3.0, 2f32.powi(100), -2f32.powi(-100), 0.0, -0.0, f32::INFINITY] {
+            assert_eq!(f32_to_bf16_bits(x), (x.to_bits() >> 16) as u16);
+        }
+        assert_eq!(bf16_bits_to_f32(0x3F80), 1.0);
+        assert_eq!(bf16_bits_to_f32(0xC040), -3.0);
+        assert!(bf16_bits_to_f32(0x8000).is_sign_negative(), "负零的符号应该保留");
+    }
+
+    #[test]
+    fn test_bf16_round_trip_exhaustive() {
+        for bits in 0..=u16::MAX {
+            let x = bf16_bits_to_f32(bits);
+            if x.is_nan() {
+                assert!(bf16_bits_to_f32(f32_to_bf16_bits(x)).is_nan(), "NaN应该保持为NaN");
+            } else {
+                assert_eq!(f32_to_bf16_bits(x), bits, "{:#06x}往返转换失败", bits);
+            }
+        }
+    }
+
+    #[test]
+    fn test_f32_to_bf16_round_to_nearest_even_exhaustive() {
+        // 对每一对相邻的有限bf16值，检查中点（低16位为0x8000）和它两侧的值
+        for lo in (0..0x7F80u16).chain(0x8000..0xFF80) {
+            let hi = lo + 1;
+            let mid = ((lo as u32) << 16) | 0x8000;
+            let even = if lo & 1 == 0 { lo } else { hi };
+
+            assert_eq!(f32_to_bf16_bits(f32::from_bits(mid)), even, "平局时应该舍入到偶数");
+            assert_eq!(f32_to_bf16_bits(f32::from_bits(mid + 1)), hi);
+            assert_eq!(f32_to_bf16_bits(f32::from_bits(mid - 1)), lo);
+        }
+    }
+
+    #[test]
+    fn test_bf16_special_values() {
+        // 最大有限f32值向上舍入为无穷大
+        assert_eq!(f32_to_bf16_bits(f32::MAX), 0x7F80);
+        assert_eq!(f32_to_bf16_bits(f32::MIN), 0xFF80);
+
+        // NaN保持为quiet NaN，即使载荷只在低16位
+        assert_eq!(f32_to_bf16_bits(f32::from_bits(0x7FC0_0000)), 0x7FC0);
+        assert_eq!(f32_to_bf16_bits(f32::from_bits(0x7F80_0001)), 0x7FC0, "截断后不能变成无穷大");
+        assert_eq!(f32_to_bf16_bits(f32::from_bits(0xFFA1_0000)), 0xFFE1, "NaN的符号和高位载荷应该保留");
+
+        // f32非规格化数对应bf16非规格化数
+        assert_eq!(f32_to_bf16_bits(f32::from_bits(0x0001_0000)), 0x0001);
+        assert_eq!(f32_to_bf16_bits(f32::from_bits(0x0000_8000)), 0x0000, "平局舍入到偶数0");
+    }
+
+    #[test]
+    fn test_format_properties() {
+        assert_eq!(F16_FORMAT.max_value(), 65504.0);
+        assert_eq!(F16_FORMAT.min_normal(), 2f64.powi(-14));
+        assert_eq!(F16_FORMAT.min_subnormal(), 2f64.powi(-24));
+        assert_eq!(F16_FORMAT.epsilon(), 2f64.powi(-10));
+
+        // bf16与f32的范围相同
+        assert_eq!(BF16_FORMAT.max_value() as f32, bf16_bits_to_f32(0x7F7F));
+        assert_eq!(BF16_FORMAT.min_normal() as f32, f32::MIN_POSITIVE);
+        assert_eq!(BF16_FORMAT.epsilon(), 2f64.powi(-7));
+        assert_eq!(F32_FORMAT.max_value() as f32, f32::MAX);
+        assert_eq!(F32_FORMAT.epsilon() as f32, f32::EPSILON);
+
+        // bf16范围更大，f16精度更高
+        assert!(BF16_FORMAT.max_value() > F16_FORMAT.max_value());
+        assert!(F16_FORMAT.epsilon() < BF16_FORMAT.epsilon());
+        assert!((F16_FORMAT.decimal_digits() - 3.31).abs() < 0.01);
+        assert!((BF16_FORMAT.decimal_digits() - 2.41).abs() < 0.01);
+
+        let report = format_comparison_report();
+        assert!(report.contains("f16") && report.contains("bf16"));
+        assert!(report.contains("6.5504e4"), "报告中应该包含f16的最大值");
+    }
+
+    #[test]
+    fn test_tensor_fit() {
+        // 大数值：f16上溢，bf16可以表示
+        let large = [1.0, 1e5, -3e6];
+        let f16_fit = check_tensor_fit(&large, F16_FORMAT);
+        assert_eq!(f16_fit.overflow_count, 2);
+        assert!(!f16_fit.fits());
+        assert!(check_tensor_fit(&large, BF16_FORMAT).fits());
+        assert_eq!(recommend_format(&large), BF16_FORMAT);
+
+        // 很小的数值：f16下溢或变成非规格化数
+        let tiny = [1e-9, 1e-6, 0.0];
+        let f16_fit = check_tensor_fit(&tiny, F16_FORMAT);
+        assert_eq!(f16_fit.underflow_count, 1, "1e-9在f16中下溢为0");
+        assert_eq!(f16_fit.subnormal_count, 1, "1e-6在f16中是非规格化数");
+        assert_eq!(recommend_format(&tiny), BF16_FORMAT);
+
+        // 普通数值：两种格式都能表示，f16的相对误差更小
+        let normal = [0.1, -0.2, 0.3, 1.7];
+        let f16_fit = check_tensor_fit(&normal, F16_FORMAT);
+        let bf16_fit = check_tensor_fit(&normal, BF16_FORMAT);
+        assert!(f16_fit.fits() && bf16_fit.fits());
+        assert!(f16_fit.max_relative_error <= F16_FORMAT.epsilon() / 2.0);
+        assert!(bf16_fit.max_relative_error <= BF16_FORMAT.epsilon() / 2.0);
+        assert!(f16_fit.max_relative_error < bf16_fit.max_relative_error);
+        assert_eq!(recommend_format(&normal), F16_FORMAT);
+
+        // 超出bf16范围只能保留f32
+        assert_eq!(recommend_format(&[f32::MAX]), F32_FORMAT);
+    }
+}
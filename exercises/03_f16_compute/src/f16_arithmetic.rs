@@ -0,0 +1,486 @@
+// f16_basics.rs 只做了位的提取和组合，这个练习中我们要真正用f16做运算。
+// 我们将实现一个软件的半精度类型F16，支持四则运算、取负、比较和融合乘加（FMA），不使用half库。
+// IEEE 754要求每个运算的结果都等于先精确计算、再舍入一次得到的值。
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+// 辅助函数：f32到f16位模式的转换（舍入到最近值，平局时取偶数）
+// 注意：这个函数与 f16_overflow.rs 中的实现相同，已经提供
+fn f32_to_f16_bits(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xFF) as i32;
+    let man = bits & 0x7FFFFF;
+
+    if exp == 0xFF {
+        if man == 0 {
+            return sign | 0x7C00;
+        }
+        let payload = (man >> 13) as u16;
+        return sign | 0x7C00 | if payload == 0 { 0x200 } else { payload };
+    }
+
+    let e = exp - 127;
+    if e > 15 {
+        return sign | 0x7C00;
+    }
+    if e >= -14 {
+        let half_man = (man >> 13) as u16;
+        let rem = man & 0x1FFF;
+        let half_bits = sign | (((e + 15) as u16) << 10) | half_man;
+        return if rem > 0x1000 || (rem == 0x1000 && (half_man & 1) == 1) {
+            half_bits + 1
+        } else {
+            half_bits
+        };
+    }
+    if e < -25 {
+        return sign;
+    }
+
+    let m = man | 0x800000;
+    let shift = (-e - 1) as u32;
+    let half_man = (m >> shift) as u16;
+    let rem = m & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    if rem > halfway || (rem == halfway && (half_man & 1) == 1) {
+        sign | (half_man + 1)
+    } else {
+        sign | half_man
+    }
+}
+
+// 辅助函数：f16位模式到f32的转换
+// 注意：这个函数与 f16_overflow.rs 中的实现相同，已经提供
+fn f16_bits_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exp = ((bits >> 10) & 0x1F) as i32;
+    let mut frac = (bits & 0x3FF) as u32;
+
+    if exp == 0 {
+        if frac == 0 {
+            return f32::from_bits(sign);
+        }
+        let mut e = -14;
+        while frac & 0x400 == 0 {
+            frac <<= 1;
+            e -= 1;
+        }
+        frac &= 0x3FF;
+        return f32::from_bits(sign | (((e + 127) as u32) << 23) | (frac << 13));
+    } else if exp == 31 {
+        return f32::from_bits(sign | 0x7F800000 | (frac << 13));
+    }
+
+    let adjusted_exp = (exp - 15 + 127) as u32;
+    f32::from_bits(sign | (adjusted_exp << 23) | (frac << 13))
+}
+
+// 软件实现的半精度浮点数，内部只保存f16的位模式
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+struct F16(u16);
+
+impl F16 {
+    const ZERO: F16 = F16(0x0000);
+    const ONE: F16 = F16(0x3C00);
+    const INFINITY: F16 = F16(0x7C00);
+    const NEG_INFINITY: F16 = F16(0xFC00);
+    const NAN: F16 = F16(0x7E00);
+    const MAX: F16 = F16(0x7BFF); // 65504
+    const MIN_POSITIVE: F16 = F16(0x0400); // 最小正规数 2^-14
+    const MIN_POSITIVE_SUBNORMAL: F16 = F16(0x0001); // 2^-24
+    const EPSILON: F16 = F16(0x1400); // 2^-10
+
+    fn from_bits(bits: u16) -> Self {
+        F16(bits)
+    }
+
+    fn to_bits(self) -> u16 {
+        self.0
+    }
+
+    fn from_f32(x: f32) -> Self {
+        F16(f32_to_f16_bits(x))
+    }
+
+    fn to_f32(self) -> f32 {
+        f16_bits_to_f32(self.0)
+    }
+
+    fn is_nan(self) -> bool {
+        self.0 & 0x7FFF > 0x7C00
+    }
+
+    fn is_infinite(self) -> bool {
+        self.0 & 0x7FFF == 0x7C00
+    }
+
+    fn is_finite(self) -> bool {
+        self.0 & 0x7C00 != 0x7C00
+    }
+
+    fn is_sign_negative(self) -> bool {
+        self.0 & 0x8000 != 0
+    }
+
+    fn abs(self) -> Self {
+        F16(self.0 & 0x7FFF)
+    }
+
+    // 有限的f16值都是2^-24的整数倍，且绝对值小于2^16，所以乘以2^24后是一个精确的整数
+    fn to_scaled_int(self) -> i128 {
+        debug_assert!(self.is_finite());
+        (self.to_f32() as f64 * 2f64.powi(24)) as i128
+    }
+
+    // 融合乘加：计算 self * a + b，只在最后舍入一次
+    // 先算乘积再相加会舍入两次，结果可能与正确值差一个单位，乘积上溢时还会得到错误的无穷大
+    //
+    // 精确结果是2^-48的整数倍，绝对值小于2^82，可以用i128精确表示。
+    // 先用“向奇数舍入”（截断，如果有被截掉的非零位就把最低位置1）保留24位有效位得到f32，
+    // 再用f32_to_f16_bits舍入到f16：24 >= 11 + 2，向奇数舍入保证了第二次舍入的结果与直接舍入相同
+    fn mul_add(self, a: F16, b: F16) -> F16 {
+        let (x, y, z) = (self.to_f32(), a.to_f32(), b.to_f32());
+        // 两个f16的乘积最多22位有效位，在f32中是精确的，
+        // 所以特殊值（NaN、无穷大、inf * 0）以及精确结果为0时零的符号都可以直接由f32运算得到
+        if !(x.is_finite() && y.is_finite() && z.is_finite()) {
+            return F16::from_f32(x * y + z);
+        }
+        let exact = self.to_scaled_int() * a.to_scaled_int() + (b.to_scaled_int() << 24);
+        if exact == 0 {
+            return F16::from_f32(x * y + z);
+        }
+
+        let magnitude = exact.unsigned_abs();
+        let len = 128 - magnitude.leading_zeros();
+        let (kept, shift) = if len <= 24 {
+            (magnitude, 0)
+        } else {
+            let shift = len - 24;
+            let sticky = magnitude & ((1 << shift) - 1) != 0;
+            ((magnitude >> shift) | sticky as u128, shift)
+        };
+        let value = kept as f32 * 2f32.powi(shift as i32 - 48);
+        F16::from_f32(if exact < 0 { -value } else { value })
+    }
+}
+
+// 四则运算都先转换为f32计算，再舍入到f16。
+// f32有24位有效位，24 >= 2 * 11 + 2，对于加减乘除来说，先舍入到f32再舍入到f16的结果与直接舍入到f16相同。
+// 只有两个f16的积在f32中一定是精确的（最多22位有效位）；和、差、商在f32中可能先舍入一次（例如65504 + 2^-24需要约40位），
+// 但由上面的双重舍入结论，最终舍入到f16的结果仍然正确。
+impl Add for F16 {
+    type Output = F16;
+
+    fn add(self, rhs: F16) -> F16 {
+        F16::from_f32(self.to_f32() + rhs.to_f32())
+    }
+}
+
+impl Sub for F16 {
+    type Output = F16;
+
+    fn sub(self, rhs: F16) -> F16 {
+        F16::from_f32(self.to_f32() - rhs.to_f32())
+    }
+}
+
+impl Mul for F16 {
+    type Output = F16;
+
+    fn mul(self, rhs: F16) -> F16 {
+        F16::from_f32(self.to_f32() * rhs.to_f32())
+    }
+}
+
+impl Div for F16 {
+    type Output = F16;
+
+    fn div(self, rhs: F16) -> F16 {
+        F16::from_f32(self.to_f32() / rhs.to_f32())
+    }
+}
+
+// 取负只翻转符号位，对NaN和零也是如此
+impl Neg for F16 {
+    type Output = F16;
+
+    fn neg(self) -> F16 {
+        F16(self.0 ^ 0x8000)
+    }
+}
+
+// 比较按数值进行：NaN与任何值都不相等，+0和-0相等
+impl PartialEq for F16 {
+    fn eq(&self, other: &F16) -> bool {
+        self.to_f32() == other.to_f32()
+    }
+}
+
+impl PartialOrd for F16 {
+    fn partial_cmp(&self, other: &F16) -> Option<Ordering> {
+        self.to_f32().partial_cmp(&other.to_f32())
+    }
+}
+
+impl From<f32> for F16 {
+    fn from(x: f32) -> Self {
+        F16::from_f32(x)
+    }
+}
+
+impl From<F16> for f32 {
+    fn from(x: F16) -> Self {
+        x.to_f32()
+    }
+}
+
+impl fmt::Display for F16 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.to_f32(), f)
+    }
+}
+
+impl fmt::Debug for F16 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "F16({:?}, {:#06x})", self.to_f32(), self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // 参考实现：正确舍入到f16（舍入到最近值，平局时取偶数）
+    // compare(k) 给出精确结果的绝对值与 k * 2^-25 的比较，所有f16值以及相邻f16值的中点都是2^-25的整数倍。
+    // 在所有正的f16位模式中二分查找结果所在的区间，再与区间中点比较。
+    // 把无穷大看作下一个值65536，这样上溢也按同样的规则处理。
+    fn reference_round(negative: bool, compare: impl Fn(i128) -> Ordering) -> u16 {
+        let scaled = |bits: u16| -> i128 {
+            if bits == 0x7C00 {
+                65536 << 25
+            } else {
+                (f16_bits_to_f32(bits) as f64 * 2f64.powi(25)) as i128
+            }
+        };
+        let sign = if negative { 0x8000 } else { 0 };
+
+        let (mut lo, mut hi) = (0u16, 0x7C00u16);
+        if compare(scaled(hi)) != Ordering::Less {
+            return sign | 0x7C00;
+        }
+        // 不变式：scaled(lo) <= |x| < scaled(hi)
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if compare(scaled(mid)) == Ordering::Less {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        let rounded = match compare((scaled(lo) + scaled(hi)) / 2) {
+            Ordering::Less => lo,
+            Ordering::Greater => hi,
+            Ordering::Equal => if lo & 1 == 0 { lo } else { hi },
+        };
+        sign | rounded
+    }
+
+    // 用f64计算的结果做参考：f16的和、差、积在f64中是精确的，
+    // 商在f64中舍入一次，53 >= 2 * 11 + 2，再舍入到f16不会产生双重舍入误差
+    fn reference_from_f64(x: f64) -> Option<u16> {
+        if x.is_nan() {
+            return None;
+        }
+        let magnitude = x.abs() * 2f64.powi(25);
+        Some(reference_round(x.is_sign_negative(), |k| magnitude.partial_cmp(&(k as f64)).unwrap()))
+    }
+
+    fn check_binary_ops(a: F16, b: F16) {
+        let (x, y) = (a.to_f32() as f64, b.to_f32() as f64);
+        let cases = [
+            ("+", a + b, x + y),
+            ("-", a - b, x - y),
+            ("*", a * b, x * y),
+            ("/", a / b, x / y),
+        ];
+        for (op, result, exact) in cases {
+            match reference_from_f64(exact) {
+                None => assert!(result.is_nan(), "{:?} {} {:?} 应该是NaN，实际为{:?}", a, op, b, result),
+                Some(expected) => assert_eq!(result.to_bits(), expected,
+                    "{:?} {} {:?} = {:?}，期望{:?}", a, op, b, result, F16::from_bits(expected)),
+            }
+        }
+    }
+
+    fn check_mul_add(a: F16, b: F16, c: F16) {
+        let result = a.mul_add(b, c);
+        if !(a.is_finite() && b.is_finite() && c.is_finite()) {
+            let exact = a.to_f32() as f64 * b.to_f32() as f64 + c.to_f32() as f64;
+            match reference_from_f64(exact) {
+                None => assert!(result.is_nan()),
+                Some(expected) => assert_eq!(result.to_bits(), expected),
+            }
+            return;
+        }
+
+        // 精确结果 n * 2^-48
+        let n = a.to_scaled_int() * b.to_scaled_int() + (c.to_scaled_int() << 24);
+        let negative = if n == 0 {
+            // 精确和为0：只有两个加数都是-0时结果才是-0
+            let product_is_zero = a.to_bits() & 0x7FFF == 0 || b.to_bits() & 0x7FFF == 0;
+            let product_negative = a.is_sign_negative() != b.is_sign_negative();
+            let c_is_zero = c.to_bits() & 0x7FFF == 0;
+            product_is_zero && product_negative && c_is_zero && c.is_sign_negative()
+        } else {
+            n < 0
+        };
+        let expected = reference_round(negative, |k| n.abs().cmp(&(k << 23)));
+        assert_eq!(result.to_bits(), expected,
+                   "fma({:?}, {:?}, {:?}) = {:?}，期望{:?}", a, b, c, result, F16::from_bits(expected));
+    }
+
+    fn interesting_values() -> Vec<F16> {
+        let bits = [
+            0x0000, 0x0001, 0x0002, 0x03FF, 0x0400, 0x0401, 0x1400, 0x3555, 0x3800, 0x3BFF,
+            0x3C00, 0x3C01, 0x3E00, 0x4000, 0x4200, 0x57FF, 0x5800, 0x7BFE, 0x7BFF, 0x7C00, 0x7E00,
+        ];
+        bits.iter()
+            .flat_map(|&b| [F16::from_bits(b), -F16::from_bits(b)])
+            .collect()
+    }
+
+    #[test]
+    fn test_constants() {
+        assert_eq!(F16::ONE.to_f32(), 1.0);
+        assert_eq!(F16::MAX.to_f32(), 65504.0);
+        assert_eq!(F16::MIN_POSITIVE.to_f32(), 2f32.powi(-14));
+        assert_eq!(F16::MIN_POSITIVE_SUBNORMAL.to_f32(), 2f32.powi(-24));
+        assert_eq!(F16::EPSILON.to_f32(), 2f32.powi(-10));
+        assert_eq!((F16::ONE + F16::EPSILON).to_bits(), 0x3C01, "1 + epsilon是1之后的下一个值");
+        assert_eq!(F16::INFINITY.to_f32(), f32::INFINITY);
+        assert_eq!(F16::NEG_INFINITY.to_f32(), f32::NEG_INFINITY);
+        assert!(F16::NAN.is_nan() && !F16::NAN.is_finite());
+        assert!(F16::INFINITY.is_infinite() && !F16::INFINITY.is_nan());
+        assert!(F16::MAX.is_finite());
+        assert_eq!(F16::from(0.1f32).to_bits(), 0x2E66);
+        assert_eq!(f32::from(F16::from_bits(0xC000)), -2.0);
+    }
+
+    #[test]
+    fn test_arithmetic_special_values() {
+        let values = interesting_values();
+        for &a in &values {
+            for &b in &values {
+                check_binary_ops(a, b);
+            }
+        }
+
+        assert_eq!((F16::ONE / F16::from(3.0)).to_bits(), 0x3555);
+        assert_eq!((F16::MAX + F16::MAX).to_bits(), 0x7C00, "上溢为无穷大");
+        assert_eq!((F16::MAX + F16::from(15.0)).to_bits(), 0x7BFF, "不到半个单位，仍然是最大值");
+        assert!((F16::INFINITY - F16::INFINITY).is_nan());
+        assert!((F16::ZERO / F16::ZERO).is_nan());
+        assert_eq!((F16::ONE / F16::ZERO).to_bits(), 0x7C00);
+        assert_eq!((F16::ONE / -F16::ZERO).to_bits(), 0xFC00);
+        assert_eq!((F16::ONE - F16::ONE).to_bits(), 0x0000, "x - x 是 +0");
+        assert_eq!((-F16::ZERO + -F16::ZERO).to_bits(), 0x8000);
+        // 最小非规格化数的一半：平局舍入到偶数0
+        let tiny = F16::MIN_POSITIVE_SUBNORMAL;
+        assert_eq!((tiny * F16::from(0.5)).to_bits(), 0x0000);
+        assert_eq!((tiny * F16::from(1.5)).to_bits(), 0x0002);
+    }
+
+    #[test]
+    fn test_arithmetic_random() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..100_000 {
+            let a = F16::from_bits(rng.gen());
+            let b = F16::from_bits(rng.gen());
+            check_binary_ops(a, b);
+        }
+        // 指数接近的操作数更容易触发平局和抵消
+        for _ in 0..100_000 {
+            let a: u16 = rng.gen();
+            let b = (a & 0xFC00) | (rng.gen::<u16>() & 0x83FF);
+            check_binary_ops(F16::from_bits(a), F16::from_bits(b));
+        }
+    }
+
+    #[test]
+    fn test_mul_add_single_rounding() {
+        // 3 * (1 + 2^-10) = 3 + 2^-9 + 2^-10 恰好是 3 + 2^-9 与 3 + 2^-8 的中点
+        // 再减去2^-24，精确结果略小于中点，应该向下舍入；分两步计算时在f32中减法被舍掉，平局向上舍入到偶数
+        let a = F16::from(3.0);
+        let b = F16::ONE + F16::EPSILON;
+        let c = -F16::MIN_POSITIVE_SUBNORMAL;
+        assert_eq!(a.mul_add(b, c).to_bits(), 0x4201);
+        assert_eq!((a * b + c).to_bits(), 0x4202, "分两步计算会舍入两次");
+
+        // 乘积超出f16范围，但最终结果在范围内
+        assert_eq!(F16::MAX.mul_add(F16::from(2.0), -F16::MAX).to_bits(), 0x7BFF);
+        assert!((F16::MAX * F16::from(2.0) - F16::MAX).is_infinite());
+
+        assert!(F16::INFINITY.mul_add(F16::ZERO, F16::ONE).is_nan());
+        assert!(F16::INFINITY.mul_add(F16::ONE, F16::NEG_INFINITY).is_nan());
+        assert_eq!(F16::ONE.mul_add(-F16::ZERO, -F16::ZERO).to_bits(), 0x8000);
+        assert_eq!(F16::ONE.mul_add(F16::ONE, -F16::ONE).to_bits(), 0x0000);
+    }
+
+    #[test]
+    fn test_mul_add_against_reference() {
+        let values = interesting_values();
+        for &a in &values {
+            for &b in &values {
+                for &c in &values {
+                    check_mul_add(a, b, c);
+                }
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100_000 {
+            let a = F16::from_bits(rng.gen());
+            let b = F16::from_bits(rng.gen());
+            // 让c与乘积的大小接近，这样结果的舍入才依赖于乘积的所有位
+            let product = a.to_f32() * b.to_f32();
+            let c = -F16::from(product) + F16::from_bits(rng.gen::<u16>() & 0x87FF);
+            check_mul_add(a, b, c);
+        }
+    }
+
+    #[test]
+    fn test_neg_and_comparison() {
+        assert_eq!((-F16::ONE).to_bits(), 0xBC00);
+        assert_eq!((-F16::ZERO).to_bits(), 0x8000);
+        assert!((-F16::NAN).is_nan());
+        assert_eq!(F16::from(-2.5).abs().to_f32(), 2.5);
+
+        assert_eq!(F16::ZERO, -F16::ZERO, "+0和-0相等");
+        assert_ne!(F16::NAN, F16::NAN, "NaN与自身不相等");
+        assert_eq!(F16::NAN.partial_cmp(&F16::ONE), None);
+        assert!(F16::MIN_POSITIVE_SUBNORMAL > F16::ZERO);
+        assert!(F16::NEG_INFINITY < -F16::MAX);
+        assert!(F16::from(1.5) >= F16::from(1.5));
+
+        // 负数的位模式更大，但数值更小
+        let mut values = [F16::from(2.0), F16::from(-3.0), F16::ZERO, F16::from(-0.5), F16::INFINITY];
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let sorted: Vec<f32> = values.iter().map(|&v| v.into()).collect();
+        assert_eq!(sorted, vec![-3.0, -0.5, 0.0, 2.0, f32::INFINITY]);
+    }
+
+    #[test]
+    fn test_display_and_debug() {
+        assert_eq!(format!("{}", F16::from(1.5)), "1.5");
+        assert_eq!(format!("{}", F16::from(0.1)), "0.099975586", "显示的是f16实际保存的值");
+        assert_eq!(format!("{:.2}", F16::from(1.23456)), "1.23");
+        assert_eq!(format!("{}", F16::NEG_INFINITY), "-inf");
+        assert_eq!(format!("{:?}", F16::from(1.5)), "F16(1.5, 0x3e00)");
+    }
+}
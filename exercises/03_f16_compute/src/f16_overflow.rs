@@ -5,46 +5,174 @@
 const F16_MAX: f32 = 65504.0; // f16最大正数
 const F16_MIN_NORMAL: f32 = 6.104e-5; // f16最小正规数
 
-// 辅助函数：简化的f32到f16位模式的转换（仅用于测试）
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// f32到f16转换时的舍入模式
+// NearestEven:    舍入到最近值，平局时取偶数（IEEE 754默认模式）
+// TowardZero:     向零舍入（截断）
+// TowardPositive: 向正无穷舍入
+// TowardNegative: 向负无穷舍入
+// Stochastic:     随机舍入，以与两个相邻f16值的距离成反比的概率选择其中一个，
+//                 期望值等于原值，低精度训练中用来避免小的梯度更新被舍入掉
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RoundingMode {
+    NearestEven,
+    TowardZero,
+    TowardPositive,
+    TowardNegative,
+    Stochastic,
+}
+
+// 判断截断后的值是否需要向远离零的方向进一位
+// half_bits: 截断后的f16位模式（不含符号位）
+// rem: 被截掉的低位，shift: 被截掉的位数，即被截掉部分的值为 rem / 2^shift 个单位
+// random_bits: 随机舍入使用的32位均匀随机数
+fn should_round_up(
+    mode: RoundingMode,
+    negative: bool,
+    half_bits: u16,
+    rem: u128,
+    shift: u32,
+    random_bits: u32
+) -> bool {
+    if rem == 0 {
+        return false; // 精确表示，不需要舍入
+    }
+    let halfway = 1u128 << (shift - 1);
+    match mode {
+        RoundingMode::NearestEven => rem > halfway || (rem == halfway && (half_bits & 1) == 1),
+        RoundingMode::TowardZero => false,
+        RoundingMode::TowardPositive => !negative,
+        RoundingMode::TowardNegative => negative,
+        // 以 rem / 2^shift 的概率进位：把被截掉的部分缩放到32位后与随机数比较
+        // shift不超过32时概率是精确的，更大时（远小于最小非规格化数的值）误差不超过2^-32
+        RoundingMode::Stochastic => {
+            let threshold = (rem << 32) >> shift;
+            (random_bits as u128) < threshold
+        }
+    }
+}
+
+// f32到f16位模式的转换，使用给定的舍入模式
+// random_bits只在Stochastic模式下使用
+// 超出f16范围的有限值：NearestEven和Stochastic得到无穷大，TowardZero得到±F16_MAX，
+// 有向舍入按方向得到无穷大或±F16_MAX，与IEEE 754的规定一致
+fn f32_to_f16_bits_with_mode(x: f32, mode: RoundingMode, random_bits: u32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let negative = sign != 0;
+    let exp = ((bits >> 23) & 0xFF) as i32;
+    let man = bits & 0x7FFFFF;
+
+    if exp == 0xFF {
+        if man == 0 {
+            return sign | 0x7C00; // 无穷大
+        }
+        // NaN：保留载荷的高10位，如果高10位全为0则置上quiet位，保证结果仍然是NaN
+        let payload = (man >> 13) as u16;
+        return sign | 0x7C00 | if payload == 0 { 0x200 } else { payload };
+    }
+    if exp == 0 && man == 0 {
+        return sign; // 带符号的零
+    }
+
+    // 写成 m * 2^(e - 23) 的形式，f32非规格化数没有隐含的1
+    let (m, e) = if exp == 0 {
+        (man as u128, -126)
+    } else {
+        ((man | 0x800000) as u128, exp - 127)
+    };
+
+    if e > 15 {
+        // 上溢：是否得到无穷大取决于舍入方向
+        let to_infinity = match mode {
+            RoundingMode::NearestEven | RoundingMode::Stochastic => true,
+            RoundingMode::TowardZero => false,
+            RoundingMode::TowardPositive => !negative,
+            RoundingMode::TowardNegative => negative,
+        };
+        return sign | if to_infinity { 0x7C00 } else { 0x7BFF };
+    }
+
+    let (half_bits, rem, shift) = if e >= -14 {
+        // 规格化数：保留尾数的高10位，剩下的13位用于舍入
+        let half_bits = (((e + 15) as u16) << 10) | ((m >> 13) as u16 & 0x3FF);
+        (half_bits, m & 0x1FFF, 13)
+    } else {
+        // 非规格化数：以最小非规格化数 2^-24 为单位，值为 m * 2^(e + 1)
+        let shift = (-e - 1) as u32; // 范围 14..=125
+        ((m >> shift) as u16, m & ((1 << shift) - 1), shift)
+    };
+
+    // 进位会自然地传递到指数位：非规格化数进位成最小正规数，F16_MAX进位成无穷大
+    if should_round_up(mode, negative, half_bits, rem, shift, random_bits) {
+        sign | (half_bits + 1)
+    } else {
+        sign | half_bits
+    }
+}
+
+// 辅助函数：f32到f16位模式的转换
+// 按照IEEE 754的默认规则舍入到最近值（平局时取偶数），支持：
+// - 尾数舍入进位到指数（例如舍入后超过F16_MAX时得到无穷大）
+// - 渐进下溢：小于最小正规数的值舍入为f16非规格化数，而不是直接变为0
+// - 保留零的符号和NaN的载荷（payload）
 // 注意：这个函数不需要实现，已经提供
 fn f32_to_f16_bits(x: f32) -> u16 {
-    if x == 0.0 {
-        return 0; // 零
+    f32_to_f16_bits_with_mode(x, RoundingMode::NearestEven, 0)
+}
+
+// 带舍入模式的f16转换器
+// 随机舍入使用种子初始化的随机数生成器，相同的种子得到相同的舍入结果
+struct F16Rounder {
+    mode: RoundingMode,
+    rng: StdRng,
+}
+
+impl F16Rounder {
+    fn new(mode: RoundingMode, seed: u64) -> Self {
+        F16Rounder { mode, rng: StdRng::seed_from_u64(seed) }
     }
-    
-    let bits = x.to_bits();
-    let sign = (bits >> 31) & 1;
-    let exp = ((bits >> 23) & 0xFF) as i32 - 127 + 15;
-    let frac = (bits & 0x7FFFFF) >> 13;
-    
-    if exp >= 31 {
-        return (sign << 15 | 0x7C00) as u16; // 无穷大
-    } else if exp <= 0 {
-        return (sign << 15) as u16; // 下溢到零
+
+    fn round(&mut self, x: f32) -> u16 {
+        let random_bits = if self.mode == RoundingMode::Stochastic { self.rng.gen() } else { 0 };
+        f32_to_f16_bits_with_mode(x, self.mode, random_bits)
+    }
+
+    fn round_slice(&mut self, x: &[f32]) -> Vec<u16> {
+        x.iter().map(|&v| self.round(v)).collect()
     }
-    
-    ((sign << 15) | ((exp as u32) << 10) | frac) as u16
 }
 
-// 辅助函数：简化的f16位模式到f32的转换（仅用于测试）
+// 辅助函数：f16位模式到f32的转换
+// 每个f16值（包括非规格化数）都可以精确地用f32表示，所以这个转换没有误差
+// 零的符号和NaN的载荷都会保留
 // 注意：这个函数不需要实现，已经提供
 fn f16_bits_to_f32(bits: u16) -> f32 {
-    let sign = ((bits >> 15) & 1) as u32;
+    let sign = ((bits & 0x8000) as u32) << 16;
     let exp = ((bits >> 10) & 0x1F) as i32;
-    let frac = (bits & 0x3FF) as u32;
-    
+    let mut frac = (bits & 0x3FF) as u32;
+
     if exp == 0 {
-        return 0.0; // 零或非规格化数（简化为零）
+        if frac == 0 {
+            return f32::from_bits(sign); // 带符号的零
+        }
+        // 非规格化数：左移尾数直到出现隐含的1，同时减小指数
+        let mut e = -14;
+        while frac & 0x400 == 0 {
+            frac <<= 1;
+            e -= 1;
+        }
+        frac &= 0x3FF;
+        return f32::from_bits(sign | (((e + 127) as u32) << 23) | (frac << 13));
     } else if exp == 31 {
-        return if frac == 0 {
-            if sign == 0 { f32::INFINITY } else { f32::NEG_INFINITY }
-        } else {
-            f32::NAN
-        };
+        // 无穷大（frac为0）或NaN（保留载荷）
+        return f32::from_bits(sign | 0x7F800000 | (frac << 13));
     }
-    
+
     let adjusted_exp = (exp - 15 + 127) as u32;
-    let f32_bits = (sign << 31) | (adjusted_exp << 23) | (frac << 13);
+    let f32_bits = sign | (adjusted_exp << 23) | (frac << 13);
     f32::from_bits(f32_bits)
 }
 
@@ -168,7 +296,8 @@ mod tests {
     fn test_precision_loss() {
         // 测试有显著精度损失的情况
         // 大数精度损失 - 接近最大值的数
-        assert!(check_precision_loss(F16_MAX * 0.999, 0.0001), 
+        // 65424恰好位于相邻的f16值65408和65440中间，舍入误差为16
+        assert!(check_precision_loss(F16_MAX - 80.0, 0.0001), 
                 "接近最大值的数应该有显著精度损失");
 
         // 小数精度损失 - 非2的幂次
@@ -243,4 +372,294 @@ mod tests {
         assert_eq!(f16_bits_to_f32(F16_NEG_INFINITY), f32::NEG_INFINITY, 
                   "f16负无穷大应该转换为f32负无穷大");
     }
+
+    // 参考实现：直接按照IEEE 754的定义用f64计算f16位模式表示的值
+    // 非规格化数: (-1)^s * m * 2^-24
+    // 规格化数:   (-1)^s * (1024 + m) * 2^(e - 25)
+    fn reference_f16_value(bits: u16) -> f64 {
+        let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+        let exp = ((bits >> 10) & 0x1F) as i32;
+        let man = (bits & 0x3FF) as f64;
+        match exp {
+            0 => sign * man * 2f64.powi(-24),
+            31 if man == 0.0 => sign * f64::INFINITY,
+            31 => f64::NAN,
+            _ => sign * (1024.0 + man) * 2f64.powi(exp - 25),
+        }
+    }
+
+    #[test]
+    fn test_f16_to_f32_exhaustive() {
+        for bits in 0..=u16::MAX {
+            let value = f16_bits_to_f32(bits);
+            let expected = reference_f16_value(bits);
+
+            if expected.is_nan() {
+                assert!(value.is_nan(), "{:#06x}应该转换为NaN", bits);
+                // 载荷和符号保留在f32的对应位上
+                assert_eq!((value.to_bits() >> 13) & 0x3FF, (bits & 0x3FF) as u32,
+                          "{:#06x}的NaN载荷应该保留", bits);
+                assert_eq!(value.is_sign_negative(), bits & 0x8000 != 0);
+            } else {
+                assert_eq!(value as f64, expected, "{:#06x}转换为f32的结果错误", bits);
+                assert_eq!(value.is_sign_negative(), bits & 0x8000 != 0,
+                          "{:#06x}的符号应该保留", bits);
+            }
+        }
+    }
+
+    #[test]
+    fn test_f16_round_trip_exhaustive() {
+        // 所有f16值（包括非规格化数、带符号零、无穷大和NaN载荷）都应该能精确往返
+        for bits in 0..=u16::MAX {
+            let back = f32_to_f16_bits(f16_bits_to_f32(bits));
+            assert_eq!(back, bits, "{:#06x}往返转换后变成了{:#06x}", bits, back);
+        }
+    }
+
+    #[test]
+    fn test_f32_to_f16_round_to_nearest_even_exhaustive() {
+        // 对每一对相邻的有限f16值，检查它们之间的中点及中点两侧最近的f32值
+        // f16只有11位有效数字，中点需要12位，在f32中可以精确表示
+        for bits in 0..0x7BFFu16 {
+            for sign in [0u16, 0x8000] {
+                let lo = bits | sign;
+                let hi = (bits + 1) | sign;
+                let mid = ((f16_bits_to_f32(lo) as f64 + f16_bits_to_f32(hi) as f64) / 2.0) as f32;
+                // 远离零方向的下一个f32值和靠近零方向的上一个f32值
+                let above = f32::from_bits(mid.to_bits() + 1);
+                let below = f32::from_bits(mid.to_bits() - 1);
+
+                let even = if lo & 1 == 0 { lo } else { hi };
+                assert_eq!(f32_to_f16_bits(mid), even, "中点{:e}应该舍入到偶数", mid);
+                assert_eq!(f32_to_f16_bits(above), hi, "{:e}应该舍入到{:#06x}", above, hi);
+                if bits == 0 {
+                    // 0和最小非规格化数之间，中点以下的值舍入为零
+                    assert_eq!(f32_to_f16_bits(below), sign, "{:e}应该舍入到零", below);
+                } else {
+                    assert_eq!(f32_to_f16_bits(below), lo, "{:e}应该舍入到{:#06x}", below, lo);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_f32_to_f16_boundaries() {
+        // 最大值附近：65520是F16_MAX与下一个（不存在的）值65536的中点，平局时舍入到偶数，即无穷大
+        assert_eq!(f32_to_f16_bits(F16_MAX), 0x7BFF);
+        assert_eq!(f32_to_f16_bits(65519.996), 0x7BFF, "略小于65520的值应该舍入到F16_MAX");
+        assert_eq!(f32_to_f16_bits(65520.0), F16_INFINITY, "65520应该舍入到无穷大");
+        assert_eq!(f32_to_f16_bits(-65520.0), F16_NEG_INFINITY);
+        assert_eq!(f32_to_f16_bits(f32::MAX), F16_INFINITY);
+
+        // 舍入进位到指数: 0x3FFF = 2047/1024，它与2.0的中点平局时舍入到偶数0x4000
+        assert_eq!(f32_to_f16_bits(1.9990234), 0x3FFF);
+        assert_eq!(f32_to_f16_bits(1.9995117), 0x4000, "尾数舍入进位应该增加指数");
+
+        // 渐进下溢
+        let min_subnormal = 2f32.powi(-24);
+        assert_eq!(f32_to_f16_bits(min_subnormal), 0x0001, "最小非规格化数");
+        assert_eq!(f32_to_f16_bits(2f32.powi(-25)), F16_ZERO, "最小非规格化数的一半平局舍入到0");
+        assert_eq!(f32_to_f16_bits(2f32.powi(-25) * 1.0001), 0x0001);
+        assert_eq!(f32_to_f16_bits(-2f32.powi(-26)), F16_NEG_ZERO, "负数下溢到-0");
+        assert_eq!(f32_to_f16_bits(2f32.powi(-14)), 0x0400, "最小正规数");
+        assert_eq!(f32_to_f16_bits(2f32.powi(-14) - 2f32.powi(-25)), 0x0400,
+                  "最大非规格化数与最小正规数的中点平局舍入到最小正规数（偶数）");
+        assert_eq!(f32_to_f16_bits(f32::from_bits(1)), F16_ZERO, "f32非规格化数下溢到0");
+
+        // 带符号的零
+        assert_eq!(f32_to_f16_bits(-0.0), F16_NEG_ZERO);
+        assert!(f16_bits_to_f32(F16_NEG_ZERO).is_sign_negative());
+
+        // NaN载荷
+        assert_eq!(f32_to_f16_bits(f32::from_bits(0x7FC0_0000)), 0x7E00, "quiet NaN");
+        assert_eq!(f32_to_f16_bits(f32::from_bits(0xFFA0_2000)), 0xFD01, "载荷的高10位应该保留");
+        assert_eq!(f32_to_f16_bits(f32::from_bits(0x7F80_0001)), 0x7E00,
+                  "载荷高10位为0时置上quiet位，结果仍然是NaN");
+    }
+
+    #[test]
+    fn test_rounding_mode_nearest_even_known_bits() {
+        // 已知正确的f16位模式，覆盖平局取偶、次正规数和上溢边界
+        let cases: [(f32, u16); 22] = [
+            (1.0, 0x3C00),
+            (0.1, 0x2E66),
+            (1.0 / 3.0, 0x3555),
+            (-2.5, 0xC100),
+            (F16_MAX, 0x7BFF),
+            // 1 + 2^-11 正好在0x3C00和0x3C01中间，取偶数0x3C00
+            (1.0 + 2f32.powi(-11), 0x3C00),
+            // 1 + 3 * 2^-11 在0x3C01和0x3C02中间，取偶数0x3C02
+            (1.0 + 3.0 * 2f32.powi(-11), 0x3C02),
+            // 略大于中点时向上舍入
+            (1.0 + 2f32.powi(-11) + 2f32.powi(-20), 0x3C01),
+            (-(1.0 + 3.0 * 2f32.powi(-11)), 0xBC02),
+            // 上溢边界：65520是65504与65536（无穷大）的中点，65504的尾数是奇数，所以舍入为无穷大
+            (65519.996, 0x7BFF),
+            (65520.0, F16_INFINITY),
+            (-65520.0, F16_NEG_INFINITY),
+            (f32::MAX, F16_INFINITY),
+            // 次正规数：最小的次正规数是2^-24
+            (2f32.powi(-24), 0x0001),
+            (2f32.powi(-25), F16_ZERO),
+            (-2f32.powi(-25), F16_NEG_ZERO),
+            (f32::from_bits(2f32.powi(-25).to_bits() + 1), 0x0001),
+            (3.0 * 2f32.powi(-25), 0x0002),
+            (5.0 * 2f32.powi(-25), 0x0002),
+            (1023.0 * 2f32.powi(-24), 0x03FF),
+            // 最大的次正规数与最小正规数的中点，取偶数0x0400
+            (1023.5 * 2f32.powi(-24), 0x0400),
+            (2f32.powi(-26), F16_ZERO),
+        ];
+        let mut rounder = F16Rounder::new(RoundingMode::NearestEven, 0);
+        for (x, expected) in cases {
+            assert_eq!(f32_to_f16_bits_with_mode(x, RoundingMode::NearestEven, 0), expected, "{:e}", x);
+            assert_eq!(rounder.round(x), expected, "{:e}", x);
+            assert_eq!(f32_to_f16_bits(x), expected, "{:e}", x);
+        }
+
+        // 相邻两个有限f16值的中点取尾数为偶数的那个，中点两侧各取较近的那个
+        for bits in 0..0x7BFFu16 {
+            let lo = f16_bits_to_f32(bits);
+            let hi = f16_bits_to_f32(bits + 1);
+            let mid = ((lo as f64 + hi as f64) / 2.0) as f32;
+            let even = if bits % 2 == 0 { bits } else { bits + 1 };
+            assert_eq!(rounder.round(mid), even, "{:e}", mid);
+            assert_eq!(rounder.round(f32::from_bits(mid.to_bits() - 1)), bits);
+            assert_eq!(rounder.round(f32::from_bits(mid.to_bits() + 1)), bits + 1);
+            assert_eq!(rounder.round(-mid), even | 0x8000);
+        }
+    }
+
+    #[test]
+    fn test_directed_rounding_exhaustive() {
+        // 对每一对相邻的有限f16值，取它们之间的中点以及中点两侧的f32值
+        // 有向舍入只取决于方向，与离哪个值更近无关
+        for bits in 0..0x7BFFu16 {
+            let lo = f16_bits_to_f32(bits);
+            let hi = f16_bits_to_f32(bits + 1);
+            let mid = ((lo as f64 + hi as f64) / 2.0) as f32;
+            let candidates = [
+                mid,
+                f32::from_bits(mid.to_bits() - 1),
+                f32::from_bits(mid.to_bits() + 1),
+            ];
+
+            for x in candidates {
+                let round = |v: f32, mode| f32_to_f16_bits_with_mode(v, mode, 0);
+                assert_eq!(round(x, RoundingMode::TowardZero), bits);
+                assert_eq!(round(x, RoundingMode::TowardPositive), bits + 1);
+                assert_eq!(round(x, RoundingMode::TowardNegative), bits);
+
+                assert_eq!(round(-x, RoundingMode::TowardZero), bits | 0x8000);
+                assert_eq!(round(-x, RoundingMode::TowardPositive), bits | 0x8000);
+                assert_eq!(round(-x, RoundingMode::TowardNegative), (bits + 1) | 0x8000);
+            }
+
+            // 可以精确表示的值在所有模式下都不变
+            for mode in [
+                RoundingMode::NearestEven,
+                RoundingMode::TowardZero,
+                RoundingMode::TowardPositive,
+                RoundingMode::TowardNegative,
+                RoundingMode::Stochastic,
+            ] {
+                assert_eq!(f32_to_f16_bits_with_mode(lo, mode, u32::MAX), bits);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rounding_mode_boundaries() {
+        let round = |x: f32, mode| f32_to_f16_bits_with_mode(x, mode, 0);
+        const MAX_BITS: u16 = 0x7BFF;
+
+        // F16_MAX本身可以精确表示
+        for mode in [RoundingMode::NearestEven, RoundingMode::TowardZero,
+                     RoundingMode::TowardPositive, RoundingMode::TowardNegative] {
+            assert_eq!(round(F16_MAX, mode), MAX_BITS, "{:?}下F16_MAX应该保持不变", mode);
+            assert_eq!(round(-F16_MAX, mode), MAX_BITS | 0x8000);
+        }
+
+        // F16_MAX与65536之间的值
+        assert_eq!(round(65505.0, RoundingMode::NearestEven), MAX_BITS);
+        assert_eq!(round(65505.0, RoundingMode::TowardZero), MAX_BITS);
+        assert_eq!(round(65505.0, RoundingMode::TowardPositive), F16_INFINITY, "向正无穷舍入超过F16_MAX的值得到无穷大");
+        assert_eq!(round(65505.0, RoundingMode::TowardNegative), MAX_BITS);
+        assert_eq!(round(-65505.0, RoundingMode::TowardPositive), MAX_BITS | 0x8000);
+        assert_eq!(round(-65505.0, RoundingMode::TowardNegative), F16_NEG_INFINITY);
+
+        // 远超范围的有限值
+        assert_eq!(round(1e6, RoundingMode::NearestEven), F16_INFINITY);
+        assert_eq!(round(1e6, RoundingMode::TowardZero), MAX_BITS, "向零舍入不会产生无穷大");
+        assert_eq!(round(1e6, RoundingMode::TowardPositive), F16_INFINITY);
+        assert_eq!(round(1e6, RoundingMode::TowardNegative), MAX_BITS);
+        assert_eq!(round(-1e6, RoundingMode::TowardZero), MAX_BITS | 0x8000);
+        assert_eq!(round(-1e6, RoundingMode::TowardPositive), MAX_BITS | 0x8000);
+        assert_eq!(round(-1e6, RoundingMode::TowardNegative), F16_NEG_INFINITY);
+
+        // 无穷大本身在任何模式下都保持不变
+        assert_eq!(round(f32::INFINITY, RoundingMode::TowardZero), F16_INFINITY);
+        assert_eq!(round(f32::NEG_INFINITY, RoundingMode::TowardPositive), F16_NEG_INFINITY);
+
+        // 极小的值：只有向远离零的方向舍入时才得到最小非规格化数
+        let tiny = 1e-10;
+        assert_eq!(round(tiny, RoundingMode::NearestEven), F16_ZERO);
+        assert_eq!(round(tiny, RoundingMode::TowardZero), F16_ZERO);
+        assert_eq!(round(tiny, RoundingMode::TowardPositive), 0x0001);
+        assert_eq!(round(tiny, RoundingMode::TowardNegative), F16_ZERO);
+        assert_eq!(round(-tiny, RoundingMode::TowardPositive), F16_NEG_ZERO);
+        assert_eq!(round(-tiny, RoundingMode::TowardNegative), 0x8001);
+        assert_eq!(round(f32::from_bits(1), RoundingMode::TowardPositive), 0x0001,
+                  "f32非规格化数向正无穷舍入得到最小非规格化数");
+    }
+
+    #[test]
+    fn test_stochastic_rounding_unbiased() {
+        // 取几个落在两个相邻f16值之间不同位置的值，包括非规格化数
+        let values = [
+            1.0 + 1.0 / 4096.0,          // 1.0与下一个值之间的1/4处
+            -3.3,
+            1000.3,
+            2f32.powi(-24) * 0.3,        // 0与最小非规格化数之间
+            F16_MIN_NORMAL * 0.77,
+        ];
+        let samples = 200_000;
+
+        for &x in &values {
+            let mut rounder = F16Rounder::new(RoundingMode::Stochastic, 1234);
+            let lo = f32_to_f16_bits_with_mode(x, RoundingMode::TowardZero, 0);
+            let hi = lo + 1;
+            let (lo_value, hi_value) = (f16_bits_to_f32(lo) as f64, f16_bits_to_f32(hi) as f64);
+
+            let mut sum = 0.0f64;
+            for _ in 0..samples {
+                let bits = rounder.round(x);
+                assert!(bits == lo || bits == hi, "随机舍入只能得到两个相邻的f16值之一");
+                sum += f16_bits_to_f32(bits) as f64;
+            }
+            let mean = sum / samples as f64;
+
+            // 每个样本是两点分布，均值的标准差为 gap * sqrt(p(1-p)/n) <= gap / (2 * sqrt(n))
+            let gap = (hi_value - lo_value).abs();
+            let tolerance = 5.0 * gap / (2.0 * (samples as f64).sqrt());
+            assert!((mean - x as f64).abs() < tolerance,
+                    "随机舍入应该是无偏的: x={}, 均值={}", x, mean);
+        }
+    }
+
+    #[test]
+    fn test_stochastic_rounding_seeded() {
+        let x: Vec<f32> = (0..256).map(|i| i as f32 * 0.0137).collect();
+        let a = F16Rounder::new(RoundingMode::Stochastic, 42).round_slice(&x);
+        let b = F16Rounder::new(RoundingMode::Stochastic, 42).round_slice(&x);
+        let c = F16Rounder::new(RoundingMode::Stochastic, 43).round_slice(&x);
+        assert_eq!(a, b, "相同的种子应该得到相同的舍入结果");
+        assert_ne!(a, c, "不同的种子应该得到不同的舍入结果");
+
+        // 确定性模式不受种子影响
+        let d = F16Rounder::new(RoundingMode::NearestEven, 1).round_slice(&x);
+        let e = F16Rounder::new(RoundingMode::NearestEven, 2).round_slice(&x);
+        assert_eq!(d, e);
+    }
 }
\ No newline at end of file
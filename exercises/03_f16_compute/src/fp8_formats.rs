@@ -0,0 +1,432 @@
+// FP8（8位浮点数）已经用于大模型的推理和训练，显存和带宽只有f16的一半。
+// 在这个练习中，我们将实现OCP规范中的两种FP8格式：
+// E4M3: 1位符号位，4位指数位（偏移7），3位尾数位。没有无穷大，只有S.1111.111表示NaN，最大值448
+// E5M2: 1位符号位，5位指数位（偏移15），2位尾数位。与IEEE 754规则一致，有无穷大，最大值57344
+// 由于FP8的表示范围很小，实际使用时需要给每个张量乘上一个缩放因子。
+
+use std::collections::VecDeque;
+
+// FP8格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Fp8Format {
+    E4M3,
+    E5M2,
+}
+
+// 上溢处理方式
+// Saturate:    超出范围的值（包括无穷大）截断为±最大值，训练中常用
+// NonSaturate: E5M2上溢为±无穷大，E4M3没有无穷大所以上溢为NaN
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OverflowMode {
+    Saturate,
+    NonSaturate,
+}
+
+impl Fp8Format {
+    fn exponent_bits(&self) -> u32 {
+        match self {
+            Fp8Format::E4M3 => 4,
+            Fp8Format::E5M2 => 5,
+        }
+    }
+
+    fn mantissa_bits(&self) -> u32 {
+        match self {
+            Fp8Format::E4M3 => 3,
+            Fp8Format::E5M2 => 2,
+        }
+    }
+
+    fn bias(&self) -> i32 {
+        (1 << (self.exponent_bits() - 1)) - 1
+    }
+
+    // 最大有限值的位模式（不含符号位）
+    // E4M3: 0.1111.110，因为0.1111.111是NaN
+    // E5M2: 0.11110.11，因为指数全1用于无穷大和NaN
+    fn max_bits(&self) -> u8 {
+        match self {
+            Fp8Format::E4M3 => 0x7E,
+            Fp8Format::E5M2 => 0x7B,
+        }
+    }
+
+    // 规范的NaN位模式（不含符号位）
+    fn nan_bits(&self) -> u8 {
+        match self {
+            Fp8Format::E4M3 => 0x7F,
+            Fp8Format::E5M2 => 0x7E,
+        }
+    }
+
+    fn max_value(&self) -> f32 {
+        fp8_to_f32(self.max_bits(), *self)
+    }
+
+    fn is_nan(&self, bits: u8) -> bool {
+        let magnitude = bits & 0x7F;
+        match self {
+            Fp8Format::E4M3 => magnitude == 0x7F,
+            Fp8Format::E5M2 => magnitude > 0x7C,
+        }
+    }
+}
+
+// FP8位模式到f32的转换
+// 每个FP8值都可以精确地用f32表示
+fn fp8_to_f32(bits: u8, format: Fp8Format) -> f32 {
+    let negative = bits & 0x80 != 0;
+    let mantissa_bits = format.mantissa_bits();
+    let exp = ((bits & 0x7F) >> mantissa_bits) as i32;
+    let man = (bits & ((1 << mantissa_bits) - 1)) as f32;
+    let max_exp = (1 << format.exponent_bits()) - 1;
+    let scale = (1u32 << mantissa_bits) as f32;
+
+    let magnitude = if format.is_nan(bits) {
+        return f32::NAN;
+    } else if format == Fp8Format::E5M2 && exp == max_exp {
+        f32::INFINITY
+    } else if exp == 0 {
+        // 非规格化数: m / 2^mb * 2^(1 - bias)
+        man / scale * 2f32.powi(1 - format.bias())
+    } else {
+        (1.0 + man / scale) * 2f32.powi(exp - format.bias())
+    };
+
+    if negative { -magnitude } else { magnitude }
+}
+
+// f32到FP8位模式的转换（舍入到最近值，平局时取偶数）
+fn f32_to_fp8(x: f32, format: Fp8Format, mode: OverflowMode) -> u8 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 24) & 0x80) as u8;
+
+    if x.is_nan() {
+        return sign | format.nan_bits();
+    }
+
+    // 上溢时的结果
+    let overflow = match (mode, format) {
+        (OverflowMode::Saturate, _) => sign | format.max_bits(),
+        (OverflowMode::NonSaturate, Fp8Format::E5M2) => sign | 0x7C,
+        (OverflowMode::NonSaturate, Fp8Format::E4M3) => sign | 0x7F,
+    };
+    if x.is_infinite() {
+        return overflow;
+    }
+
+    let exp = ((bits >> 23) & 0xFF) as i32;
+    let man = bits & 0x7FFFFF;
+    if exp == 0 && man == 0 {
+        return sign; // 带符号的零
+    }
+
+    // 写成 m * 2^(e - 23) 的形式
+    let (m, e) = if exp == 0 {
+        (man as u64, -126)
+    } else {
+        ((man | 0x800000) as u64, exp - 127)
+    };
+
+    let mantissa_bits = format.mantissa_bits() as i32;
+    let min_exp = 1 - format.bias();
+    if e > format.bias() + 1 {
+        return overflow; // 远超范围，不需要再舍入
+    }
+
+    // 以目标格式在当前指数下的最小单位表示，得到截断后的位模式和被截掉的低位
+    let (truncated, rem, shift) = if e >= min_exp {
+        let shift = (23 - mantissa_bits) as u32;
+        let biased = (e + format.bias()) as u64;
+        let truncated = (biased << mantissa_bits) | ((m >> shift) & ((1 << mantissa_bits) - 1));
+        (truncated, m & ((1 << shift) - 1), shift)
+    } else {
+        // 非规格化数，最小单位是 2^(min_exp - mantissa_bits)
+        let shift = (23 + min_exp - mantissa_bits - e) as u32;
+        if shift >= 64 {
+            return sign; // 远小于最小非规格化数的一半
+        }
+        (m >> shift, m & ((1 << shift) - 1), shift)
+    };
+
+    let halfway = 1u64 << (shift - 1);
+    let rounded = if rem > halfway || (rem == halfway && truncated & 1 == 1) {
+        truncated + 1
+    } else {
+        truncated
+    };
+
+    if rounded > format.max_bits() as u64 {
+        overflow
+    } else {
+        sign | rounded as u8
+    }
+}
+
+// 带缩放因子的FP8张量
+// 存储的是 x * scale 的FP8表示，反量化时再除以scale
+#[derive(Debug, Clone, PartialEq)]
+struct Fp8Tensor {
+    data: Vec<u8>,
+    scale: f32,
+    format: Fp8Format,
+}
+
+impl Fp8Tensor {
+    fn quantize(x: &[f32], scale: f32, format: Fp8Format, mode: OverflowMode) -> Self {
+        assert!(scale > 0.0 && scale.is_finite(), "缩放因子必须是正的有限值");
+        let data = x.iter()
+            .map(|&v| f32_to_fp8(v * scale, format, mode))
+            .collect();
+        Fp8Tensor { data, scale, format }
+    }
+
+    fn dequantize(&self) -> Vec<f32> {
+        self.data.iter()
+            .map(|&b| fp8_to_f32(b, self.format) / self.scale)
+            .collect()
+    }
+}
+
+// 计算张量的最大绝对值（amax），忽略NaN和无穷大
+fn compute_amax(x: &[f32]) -> f32 {
+    x.iter()
+        .filter(|v| v.is_finite())
+        .fold(0.0f32, |acc, &v| acc.max(v.abs()))
+}
+
+// 根据amax计算缩放因子，使 amax * scale 恰好对应FP8的最大值
+// margin用于留出余量：每增加1，缩放因子减半
+fn compute_scale(amax: f32, format: Fp8Format, margin: i32) -> f32 {
+    if amax == 0.0 || !amax.is_finite() {
+        return 1.0;
+    }
+    format.max_value() / amax / 2f32.powi(margin)
+}
+
+// 延迟缩放（delayed scaling）
+// 当前张量的amax要等计算完成后才知道，所以使用之前若干步amax的最大值来计算这一步的缩放因子，
+// 计算完成后再把这一步的amax加入历史记录
+struct DelayedScaler {
+    format: Fp8Format,
+    margin: i32,
+    history_len: usize,
+    amax_history: VecDeque<f32>,
+    scale: f32,
+}
+
+impl DelayedScaler {
+    fn new(format: Fp8Format, history_len: usize, margin: i32) -> Self {
+        assert!(history_len > 0, "历史长度必须大于0");
+        DelayedScaler {
+            format,
+            margin,
+            history_len,
+            amax_history: VecDeque::with_capacity(history_len),
+            scale: 1.0,
+        }
+    }
+
+    // 使用当前的缩放因子量化张量，然后更新amax历史和缩放因子
+    fn quantize(&mut self, x: &[f32]) -> Fp8Tensor {
+        let tensor = Fp8Tensor::quantize(x, self.scale, self.format, OverflowMode::Saturate);
+
+        if self.amax_history.len() == self.history_len {
+            self.amax_history.pop_front();
+        }
+        self.amax_history.push_back(compute_amax(x));
+        let amax = self.amax_history.iter().fold(0.0f32, |acc, &v| acc.max(v));
+        self.scale = compute_scale(amax, self.format, self.margin);
+
+        tensor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [Fp8Format; 2] = [Fp8Format::E4M3, Fp8Format::E5M2];
+
+    // 参考实现：按定义用f64计算FP8位模式表示的值
+    fn reference_value(bits: u8, format: Fp8Format) -> Option<f64> {
+        let (eb, mb, bias) = match format {
+            Fp8Format::E4M3 => (4, 3, 7),
+            Fp8Format::E5M2 => (5, 2, 15),
+        };
+        let sign = if bits & 0x80 != 0 { -1.0 } else { 1.0 };
+        let exp = ((bits & 0x7F) >> mb) as i32;
+        let man = (bits & ((1 << mb) - 1)) as f64;
+        let max_exp = (1 << eb) - 1;
+
+        match format {
+            Fp8Format::E4M3 if exp == max_exp && man == 7.0 => None,
+            Fp8Format::E5M2 if exp == max_exp && man != 0.0 => None,
+            Fp8Format::E5M2 if exp == max_exp => Some(sign * f64::INFINITY),
+            _ if exp == 0 => Some(sign * man * 2f64.powi(1 - bias - mb)),
+            _ => Some(sign * (1.0 + man / 2f64.powi(mb)) * 2f64.powi(exp - bias)),
+        }
+    }
+
+    #[test]
+    fn test_decode_all_values() {
+        for format in FORMATS {
+            let mut nan_count = 0;
+            for bits in 0..=u8::MAX {
+                let value = fp8_to_f32(bits, format);
+                match reference_value(bits, format) {
+                    None => {
+                        assert!(value.is_nan(), "{:?} {:#04x}应该是NaN", format, bits);
+                        assert!(format.is_nan(bits));
+                        nan_count += 1;
+                    }
+                    Some(expected) => {
+                        assert_eq!(value as f64, expected, "{:?} {:#04x}解码错误", format, bits);
+                        assert_eq!(value.is_sign_negative(), bits & 0x80 != 0);
+                    }
+                }
+            }
+            let expected_nan = match format {
+                Fp8Format::E4M3 => 2, // 0x7F和0xFF
+                Fp8Format::E5M2 => 6, // 指数全1且尾数非0
+            };
+            assert_eq!(nan_count, expected_nan, "{:?}的NaN数量错误", format);
+        }
+    }
+
+    #[test]
+    fn test_format_constants() {
+        assert_eq!(Fp8Format::E4M3.max_value(), 448.0);
+        assert_eq!(Fp8Format::E5M2.max_value(), 57344.0);
+        assert_eq!(fp8_to_f32(0x01, Fp8Format::E4M3), 2f32.powi(-9), "E4M3最小非规格化数");
+        assert_eq!(fp8_to_f32(0x01, Fp8Format::E5M2), 2f32.powi(-16), "E5M2最小非规格化数");
+        assert_eq!(fp8_to_f32(0x08, Fp8Format::E4M3), 2f32.powi(-6), "E4M3最小正规数");
+        assert_eq!(fp8_to_f32(0x04, Fp8Format::E5M2), 2f32.powi(-14), "E5M2最小正规数");
+        assert_eq!(fp8_to_f32(0x7C, Fp8Format::E5M2), f32::INFINITY);
+        assert_eq!(fp8_to_f32(0xFC, Fp8Format::E5M2), f32::NEG_INFINITY);
+        // E4M3中0x78是正常的数值256，而不是无穷大
+        assert_eq!(fp8_to_f32(0x78, Fp8Format::E4M3), 256.0);
+    }
+
+    #[test]
+    fn test_round_trip_all_values() {
+        for format in FORMATS {
+            for mode in [OverflowMode::Saturate, OverflowMode::NonSaturate] {
+                for bits in 0..=u8::MAX {
+                    let value = fp8_to_f32(bits, format);
+                    let back = f32_to_fp8(value, format, mode);
+                    if value.is_nan() {
+                        assert!(format.is_nan(back), "NaN应该保持为NaN");
+                    } else if value.is_infinite() && mode == OverflowMode::Saturate {
+                        assert_eq!(back, (bits & 0x80) | format.max_bits(), "饱和模式下无穷大变为最大值");
+                    } else {
+                        assert_eq!(back, bits, "{:?} {:#04x}往返转换失败", format, bits);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_to_nearest_even_all_values() {
+        // 对每一对相邻的有限值，检查中点以及中点两侧的值
+        for format in FORMATS {
+            for lo in 0..format.max_bits() {
+                let hi = lo + 1;
+                let mid = (fp8_to_f32(lo, format) + fp8_to_f32(hi, format)) / 2.0;
+                let even = if lo & 1 == 0 { lo } else { hi };
+                let above = f32::from_bits(mid.to_bits() + 1);
+                let below = f32::from_bits(mid.to_bits() - 1);
+
+                for sign in [0u8, 0x80] {
+                    let s = if sign == 0 { 1.0 } else { -1.0 };
+                    let mode = OverflowMode::NonSaturate;
+                    assert_eq!(f32_to_fp8(s * mid, format, mode), sign | even,
+                              "{:?} 中点{}应该舍入到偶数", format, mid);
+                    assert_eq!(f32_to_fp8(s * above, format, mode), sign | hi);
+                    let expected_below = if lo == 0 { sign } else { sign | lo };
+                    assert_eq!(f32_to_fp8(s * below, format, mode), expected_below);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_overflow_modes() {
+        // E4M3: 448与下一个（不存在的）值480的中点是464，平局舍入到偶数448
+        let e4m3 = Fp8Format::E4M3;
+        assert_eq!(f32_to_fp8(464.0, e4m3, OverflowMode::NonSaturate), 0x7E);
+        assert_eq!(f32_to_fp8(465.0, e4m3, OverflowMode::NonSaturate), 0x7F, "E4M3非饱和上溢为NaN");
+        assert_eq!(f32_to_fp8(465.0, e4m3, OverflowMode::Saturate), 0x7E, "饱和模式截断为448");
+        assert_eq!(f32_to_fp8(-1e9, e4m3, OverflowMode::Saturate), 0xFE);
+        assert_eq!(f32_to_fp8(f32::INFINITY, e4m3, OverflowMode::NonSaturate), 0x7F);
+        assert_eq!(f32_to_fp8(f32::NEG_INFINITY, e4m3, OverflowMode::Saturate), 0xFE);
+
+        // E5M2: 57344与下一个（不存在的）值65536的中点是61440，平局舍入到偶数即无穷大
+        let e5m2 = Fp8Format::E5M2;
+        assert_eq!(f32_to_fp8(61439.0, e5m2, OverflowMode::NonSaturate), 0x7B);
+        assert_eq!(f32_to_fp8(61440.0, e5m2, OverflowMode::NonSaturate), 0x7C, "E5M2非饱和上溢为无穷大");
+        assert_eq!(f32_to_fp8(61440.0, e5m2, OverflowMode::Saturate), 0x7B);
+        assert_eq!(f32_to_fp8(-1e9, e5m2, OverflowMode::NonSaturate), 0xFC);
+        assert_eq!(f32_to_fp8(f32::INFINITY, e5m2, OverflowMode::Saturate), 0x7B);
+
+        // NaN在两种模式下都是NaN
+        for format in FORMATS {
+            for mode in [OverflowMode::Saturate, OverflowMode::NonSaturate] {
+                assert!(format.is_nan(f32_to_fp8(f32::NAN, format, mode)));
+            }
+        }
+
+        // 下溢：小于最小非规格化数一半的值变为带符号的零
+        assert_eq!(f32_to_fp8(2f32.powi(-11), e4m3, OverflowMode::Saturate), 0x00);
+        assert_eq!(f32_to_fp8(-2f32.powi(-11), e4m3, OverflowMode::Saturate), 0x80);
+        assert_eq!(f32_to_fp8(f32::from_bits(1), e5m2, OverflowMode::Saturate), 0x00);
+    }
+
+    #[test]
+    fn test_per_tensor_scaling() {
+        // 数值很小的张量直接转换会下溢，乘上缩放因子后可以保留精度
+        let x = [1e-4, -2e-4, 3e-4, 5e-5];
+        let unscaled = Fp8Tensor::quantize(&x, 1.0, Fp8Format::E4M3, OverflowMode::Saturate);
+        assert!(unscaled.dequantize().iter().all(|&v| v.abs() < 1e-6), "不缩放时全部下溢");
+
+        let amax = compute_amax(&x);
+        assert_eq!(amax, 3e-4);
+        let scale = compute_scale(amax, Fp8Format::E4M3, 0);
+        let scaled = Fp8Tensor::quantize(&x, scale, Fp8Format::E4M3, OverflowMode::Saturate);
+        // amax正好映射到最大值448
+        assert_eq!(scaled.data[2], 0x7E);
+        for (&a, &b) in x.iter().zip(scaled.dequantize().iter()) {
+            assert!((a - b).abs() / a.abs() <= 1.0 / 16.0, "缩放后相对误差应该不超过半个单位: {} vs {}", a, b);
+        }
+
+        // amax为0时缩放因子为1
+        assert_eq!(compute_scale(0.0, Fp8Format::E5M2, 0), 1.0);
+        // margin为1时缩放因子减半
+        assert_eq!(compute_scale(448.0, Fp8Format::E4M3, 1), 0.5);
+    }
+
+    #[test]
+    fn test_delayed_scaling() {
+        let mut scaler = DelayedScaler::new(Fp8Format::E4M3, 2, 0);
+        assert_eq!(scaler.scale, 1.0);
+
+        // 第1步：使用初始缩放因子1.0，之后根据amax=4更新
+        let t1 = scaler.quantize(&[1.0, -4.0]);
+        assert_eq!(t1.scale, 1.0);
+        assert_eq!(scaler.scale, 112.0, "448 / 4 = 112");
+
+        // 第2步：amax=8超出了上一步的估计，使用的缩放因子是112，8 * 112 = 896被饱和截断为448
+        let t2 = scaler.quantize(&[8.0]);
+        assert_eq!(t2.scale, 112.0);
+        assert_eq!(t2.dequantize()[0], 4.0, "饱和后反量化得到 448 / 112 = 4");
+        assert_eq!(scaler.scale, 56.0, "历史[4, 8]的最大值为8");
+
+        // 第3步：历史长度为2，amax=4被挤出窗口后只剩[8, 2]
+        scaler.quantize(&[2.0]);
+        assert_eq!(scaler.scale, 56.0);
+        scaler.quantize(&[2.0]);
+        assert_eq!(scaler.scale, 224.0, "历史[2, 2]的最大值为2");
+        assert_eq!(scaler.amax_history, VecDeque::from(vec![2.0, 2.0]));
+    }
+}
@@ -0,0 +1,381 @@
+// 混合精度训练中，梯度用f16保存。很小的梯度在f16中会下溢为0，所以反向传播前先把损失乘以一个较大的缩放因子，
+// 更新参数前再把梯度除回去。缩放因子太大时梯度会上溢为Inf/NaN，这时必须跳过这一步并减小缩放因子。
+// 在这个练习中，我们将实现动态损失缩放（dynamic loss scaling）：
+// - 出现上溢时跳过这一步，缩放因子减半
+// - 连续growth_interval步都没有上溢时，缩放因子加倍
+// 这样缩放因子会自动保持在“刚好不上溢”的最大值附近。
+
+// f16的常量定义
+const F16_MAX: f32 = 65504.0; // f16最大正数
+
+// 辅助函数：f32到f16位模式的转换（舍入到最近值，平局时取偶数）
+// 注意：这个函数与 f16_overflow.rs 中的实现相同，已经提供
+fn f32_to_f16_bits(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xFF) as i32;
+    let man = bits & 0x7FFFFF;
+
+    if exp == 0xFF {
+        if man == 0 {
+            return sign | 0x7C00;
+        }
+        let payload = (man >> 13) as u16;
+        return sign | 0x7C00 | if payload == 0 { 0x200 } else { payload };
+    }
+
+    let e = exp - 127;
+    if e > 15 {
+        return sign | 0x7C00;
+    }
+    if e >= -14 {
+        let half_man = (man >> 13) as u16;
+        let rem = man & 0x1FFF;
+        let half_bits = sign | (((e + 15) as u16) << 10) | half_man;
+        return if rem > 0x1000 || (rem == 0x1000 && (half_man & 1) == 1) {
+            half_bits + 1
+        } else {
+            half_bits
+        };
+    }
+    if e < -25 {
+        return sign;
+    }
+
+    let m = man | 0x800000;
+    let shift = (-e - 1) as u32;
+    let half_man = (m >> shift) as u16;
+    let rem = m & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    if rem > halfway || (rem == halfway && (half_man & 1) == 1) {
+        sign | (half_man + 1)
+    } else {
+        sign | half_man
+    }
+}
+
+// 判断f16位模式是否为Inf或NaN（指数位全1）
+fn is_f16_inf_or_nan(bits: u16) -> bool {
+    bits & 0x7C00 == 0x7C00
+}
+
+// 检查梯度缓冲区中是否有值在f16中会变成Inf或NaN
+// 有限的f32在舍入后超出f16范围时，转换结果就是Inf，所以直接检查转换后的指数位即可
+fn grads_overflow(grads: &[Vec<f32>]) -> bool {
+    grads.iter()
+        .flat_map(|g| g.iter())
+        .any(|&x| is_f16_inf_or_nan(f32_to_f16_bits(x)))
+}
+
+// 一次step的结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StepOutcome {
+    Applied,
+    Skipped,
+}
+
+// 动态损失缩放器
+#[derive(Debug, Clone, PartialEq)]
+struct DynamicLossScaler {
+    scale: f32,
+    growth_factor: f32,
+    backoff_factor: f32,
+    growth_interval: usize,
+    min_scale: f32,
+    max_scale: f32,
+    good_steps: usize, // 距离上一次上溢或增长已经连续成功的步数
+    skipped_steps: usize,
+    total_steps: usize,
+}
+
+impl DynamicLossScaler {
+    // 默认每次增长为2倍、回退为一半，与常见框架的默认设置一致
+    fn new(init_scale: f32, growth_interval: usize) -> Self {
+        assert!(init_scale > 0.0 && init_scale.is_finite(), "初始缩放因子必须是正的有限值");
+        assert!(growth_interval > 0, "growth_interval必须大于0");
+        DynamicLossScaler {
+            scale: init_scale,
+            growth_factor: 2.0,
+            backoff_factor: 0.5,
+            growth_interval,
+            min_scale: 1.0,
+            max_scale: 2f32.powi(24),
+            good_steps: 0,
+            skipped_steps: 0,
+            total_steps: 0,
+        }
+    }
+
+    fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    fn scale_loss(&self, loss: f32) -> f32 {
+        loss * self.scale
+    }
+
+    // 检查缩放后的梯度并更新缩放因子
+    // 没有上溢时把梯度原地除以这一步使用的缩放因子，返回Applied，调用方可以用这些梯度更新参数；
+    // 出现上溢时梯度保持不变，返回Skipped，调用方应该丢弃这些梯度
+    fn step(&mut self, scaled_grads: &mut [Vec<f32>]) -> StepOutcome {
+        self.total_steps += 1;
+
+        if grads_overflow(scaled_grads) {
+            self.scale = (self.scale * self.backoff_factor).max(self.min_scale);
+            self.good_steps = 0;
+            self.skipped_steps += 1;
+            return StepOutcome::Skipped;
+        }
+
+        let inv_scale = 1.0 / self.scale;
+        for g in scaled_grads.iter_mut() {
+            for x in g.iter_mut() {
+                *x *= inv_scale;
+            }
+        }
+
+        self.good_steps += 1;
+        if self.good_steps == self.growth_interval {
+            self.scale = (self.scale * self.growth_factor).min(self.max_scale);
+            self.good_steps = 0;
+        }
+        StepOutcome::Applied
+    }
+
+    // 将状态序列化为每行一个 key=value 的文本，用于保存检查点
+    // f32的Display输出是能精确还原该值的最短十进制表示，所以解析后得到的状态完全相同
+    fn state_dict(&self) -> String {
+        format!(
+            "scale={}\ngrowth_factor={}\nbackoff_factor={}\ngrowth_interval={}\nmin_scale={}\nmax_scale={}\ngood_steps={}\nskipped_steps={}\ntotal_steps={}\n",
+            self.scale,
+            self.growth_factor,
+            self.backoff_factor,
+            self.growth_interval,
+            self.min_scale,
+            self.max_scale,
+            self.good_steps,
+            self.skipped_steps,
+            self.total_steps,
+        )
+    }
+
+    // 从state_dict的输出恢复缩放器，缺少、重复或未知的字段，数值格式错误，
+    // 以及会让缩放器无法正常工作的取值都返回错误信息
+    fn from_state_dict(text: &str) -> Result<Self, String> {
+        let mut scaler = DynamicLossScaler::new(1.0, 1);
+        let mut seen = Vec::new();
+
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| format!("无效的行: {}", line))?;
+            if seen.contains(&key) {
+                return Err(format!("重复的字段: {}", key));
+            }
+            let parse_f32 = || value.parse::<f32>().map_err(|_| format!("{}的值无效: {}", key, value));
+            let parse_usize = || value.parse::<usize>().map_err(|_| format!("{}的值无效: {}", key, value));
+            match key {
+                "scale" => scaler.scale = parse_f32()?,
+                "growth_factor" => scaler.growth_factor = parse_f32()?,
+                "backoff_factor" => scaler.backoff_factor = parse_f32()?,
+                "growth_interval" => scaler.growth_interval = parse_usize()?,
+                "min_scale" => scaler.min_scale = parse_f32()?,
+                "max_scale" => scaler.max_scale = parse_f32()?,
+                "good_steps" => scaler.good_steps = parse_usize()?,
+                "skipped_steps" => scaler.skipped_steps = parse_usize()?,
+                "total_steps" => scaler.total_steps = parse_usize()?,
+                _ => return Err(format!("未知字段: {}", key)),
+            }
+            seen.push(key);
+        }
+
+        let required = [
+            "scale", "growth_factor", "backoff_factor", "growth_interval", "min_scale",
+            "max_scale", "good_steps", "skipped_steps", "total_steps",
+        ];
+        if let Some(missing) = required.iter().find(|k| !seen.contains(k)) {
+            return Err(format!("缺少字段: {}", missing));
+        }
+        if !(scaler.scale > 0.0 && scaler.scale.is_finite()) || scaler.growth_interval == 0 {
+            return Err("缩放因子必须是正的有限值，growth_interval必须大于0".to_string());
+        }
+        if !(scaler.growth_factor > 1.0 && scaler.growth_factor.is_finite()) {
+            return Err(format!("growth_factor必须大于1，实际为{}", scaler.growth_factor));
+        }
+        if !(scaler.backoff_factor > 0.0 && scaler.backoff_factor < 1.0) {
+            return Err(format!("backoff_factor必须在(0, 1)之间，实际为{}", scaler.backoff_factor));
+        }
+        if !(scaler.min_scale > 0.0 && scaler.min_scale <= scaler.max_scale && scaler.max_scale.is_finite()) {
+            return Err(format!(
+                "缩放因子的范围无效: min_scale={}，max_scale={}", scaler.min_scale, scaler.max_scale
+            ));
+        }
+        // step中只在good_steps恰好等于growth_interval时增长
+        if scaler.good_steps >= scaler.growth_interval {
+            return Err(format!(
+                "good_steps {}必须小于growth_interval {}", scaler.good_steps, scaler.growth_interval
+            ));
+        }
+        Ok(scaler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 模拟一次反向传播：真实梯度乘以缩放因子，得到f16中保存的缩放后梯度
+    fn scaled_grads(scaler: &DynamicLossScaler, grads: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        grads.iter()
+            .map(|g| g.iter().map(|&x| scaler.scale_loss(x)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_overflow_detection() {
+        assert!(!grads_overflow(&[vec![1.0, -2.0], vec![F16_MAX, -F16_MAX]]));
+        assert!(grads_overflow(&[vec![1.0], vec![70000.0]]), "超过F16_MAX的值会上溢");
+        assert!(grads_overflow(&[vec![-1e10]]));
+        assert!(grads_overflow(&[vec![f32::INFINITY]]));
+        assert!(grads_overflow(&[vec![0.0, f32::NAN]]), "NaN也需要跳过这一步");
+        assert!(!grads_overflow(&[vec![1e-10]]), "下溢不算上溢");
+        assert!(!grads_overflow(&[]));
+    }
+
+    #[test]
+    fn test_scale_loss_and_unscale() {
+        let mut scaler = DynamicLossScaler::new(1024.0, 100);
+        assert_eq!(scaler.scale_loss(0.5), 512.0);
+
+        // 1e-6在f16中几乎完全下溢，乘以1024后可以正常表示
+        let grads = vec![vec![1e-6, -3e-6], vec![2e-5]];
+        let mut scaled = scaled_grads(&scaler, &grads);
+        assert_eq!(scaler.step(&mut scaled), StepOutcome::Applied);
+        for (g, s) in grads.iter().flatten().zip(scaled.iter().flatten()) {
+            assert!((g - s).abs() <= g.abs() * 1e-6, "除以缩放因子后应该还原梯度: {} vs {}", g, s);
+        }
+    }
+
+    #[test]
+    fn test_scripted_overflow_sequence() {
+        // 按给定的上溢序列检查缩放因子的变化
+        let mut scaler = DynamicLossScaler::new(1024.0, 3);
+        let events = [false, false, false, true, false, false, true, false, false, false, true, true];
+        let expected_scales = [
+            1024.0, 1024.0, 2048.0, // 连续3步成功后加倍
+            1024.0, // 上溢，减半
+            1024.0, 1024.0, 512.0, // 上溢会清零连续成功的计数
+            512.0, 512.0, 1024.0,
+            512.0, 256.0,
+        ];
+
+        for (step, (&overflow, &expected)) in events.iter().zip(expected_scales.iter()).enumerate() {
+            let mut grads = vec![vec![if overflow { f32::INFINITY } else { 1.0 }]];
+            let outcome = scaler.step(&mut grads);
+            let expected_outcome = if overflow { StepOutcome::Skipped } else { StepOutcome::Applied };
+            assert_eq!(outcome, expected_outcome, "第{}步", step);
+            assert_eq!(scaler.scale(), expected, "第{}步后的缩放因子", step);
+        }
+        assert_eq!(scaler.total_steps, 12);
+        assert_eq!(scaler.skipped_steps, 4);
+        assert_eq!(scaler.good_steps, 0);
+    }
+
+    #[test]
+    fn test_scale_settles_below_overflow() {
+        // 真实梯度最大为100，缩放因子达到1024时 100 * 1024 > F16_MAX 会上溢，
+        // 缩放因子应该在512和1024之间来回，并且每次上溢都回到512
+        let grads = vec![vec![100.0, -0.01], vec![1e-7]];
+        let mut scaler = DynamicLossScaler::new(64.0, 2);
+        let mut trajectory = Vec::new();
+
+        for _ in 0..14 {
+            let mut scaled = scaled_grads(&scaler, &grads);
+            scaler.step(&mut scaled);
+            trajectory.push(scaler.scale());
+        }
+        assert_eq!(trajectory, [
+            64.0, 128.0, 128.0, 256.0, 256.0, 512.0, 512.0, 1024.0, 512.0, 512.0, 1024.0, 512.0, 512.0, 1024.0,
+        ]);
+        assert_eq!(scaler.skipped_steps, 2);
+    }
+
+    #[test]
+    fn test_scale_limits() {
+        let mut scaler = DynamicLossScaler::new(2.0, 1);
+        for _ in 0..5 {
+            scaler.step(&mut [vec![f32::NAN]]);
+        }
+        assert_eq!(scaler.scale(), 1.0, "缩放因子不会低于min_scale");
+
+        scaler.max_scale = 8.0;
+        for _ in 0..10 {
+            scaler.step(&mut [vec![0.0]]);
+        }
+        assert_eq!(scaler.scale(), 8.0, "缩放因子不会超过max_scale");
+    }
+
+    #[test]
+    fn test_state_dict_round_trip() {
+        let mut scaler = DynamicLossScaler::new(65536.0, 4);
+        scaler.backoff_factor = 0.25;
+        for overflow in [false, true, false, false] {
+            scaler.step(&mut [vec![if overflow { 1e9 } else { 0.1 }]]);
+        }
+
+        let text = scaler.state_dict();
+        assert!(text.contains("scale=16384\n"));
+        assert!(text.contains("good_steps=2\n"));
+        let restored = DynamicLossScaler::from_state_dict(&text).unwrap();
+        assert_eq!(restored, scaler);
+
+        // 恢复后继续训练，行为与原来的缩放器一致
+        let mut original = scaler.clone();
+        let mut resumed = restored;
+        for _ in 0..3 {
+            assert_eq!(original.step(&mut [vec![0.1]]), resumed.step(&mut [vec![0.1]]));
+        }
+        assert_eq!(original, resumed);
+        assert_eq!(resumed.scale(), 32768.0);
+    }
+
+    #[test]
+    fn test_state_dict_errors() {
+        let text = DynamicLossScaler::new(8.0, 2).state_dict();
+
+        let missing = text.replace("total_steps=0\n", "");
+        assert_eq!(DynamicLossScaler::from_state_dict(&missing).unwrap_err(), "缺少字段: total_steps");
+
+        let unknown = format!("{}foo=1\n", text);
+        assert_eq!(DynamicLossScaler::from_state_dict(&unknown).unwrap_err(), "未知字段: foo");
+
+        let bad_value = text.replace("scale=8", "scale=abc");
+        assert!(DynamicLossScaler::from_state_dict(&bad_value).is_err());
+
+        let negative = text.replace("scale=8", "scale=-8");
+        assert!(DynamicLossScaler::from_state_dict(&negative).is_err());
+
+        assert!(DynamicLossScaler::from_state_dict("scale").is_err());
+
+        let duplicate = format!("{}scale=1024\n", text);
+        assert_eq!(DynamicLossScaler::from_state_dict(&duplicate).unwrap_err(), "重复的字段: scale");
+
+        // 每个字段都合法，但组合起来会让缩放器无法正常工作
+        let invalid = [
+            ("growth_factor=2\n", "growth_factor=1\n"),
+            ("growth_factor=2\n", "growth_factor=inf\n"),
+            ("backoff_factor=0.5\n", "backoff_factor=0\n"),
+            ("backoff_factor=0.5\n", "backoff_factor=1\n"),
+            ("backoff_factor=0.5\n", "backoff_factor=1.5\n"),
+            ("min_scale=1\n", "min_scale=0\n"),
+            ("min_scale=1\n", "min_scale=1e30\n"),
+            ("max_scale=16777216\n", "max_scale=inf\n"),
+            ("good_steps=0\n", "good_steps=2\n"),
+        ];
+        for (from, to) in invalid {
+            assert!(text.contains(from), "{}", from);
+            let state = text.replace(from, to);
+            assert!(DynamicLossScaler::from_state_dict(&state).is_err(), "{}应该被拒绝", to.trim());
+        }
+        assert!(DynamicLossScaler::from_state_dict(&text.replace("good_steps=0\n", "good_steps=1\n")).is_ok());
+    }
+}
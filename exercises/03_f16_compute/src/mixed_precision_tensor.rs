@@ -0,0 +1,553 @@
+// 模型权重用f16或bf16保存，显存占用只有f32的一半。但是计算时如果也用半精度累加，
+// 大量元素的求和会很快丢失精度（例如f16中 2048 + 1 == 2048）。
+// 常见的做法是：存储用半精度，读取时转换为f32，矩阵乘法和归约都用f32累加，结果写回时再舍入到半精度。
+// 在这个练习中，我们将实现半精度存储的张量，以及f32累加的batch_matmul、均值和softmax，
+// 并与纯f32的计算结果比较精度。
+
+use std::fmt;
+
+// 辅助函数：f32到f16位模式的转换（舍入到最近值，平局时取偶数）
+// 注意：这个函数与 f16_overflow.rs 中的实现相同，已经提供
+fn f32_to_f16_bits(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xFF) as i32;
+    let man = bits & 0x7FFFFF;
+
+    if exp == 0xFF {
+        if man == 0 {
+            return sign | 0x7C00;
+        }
+        let payload = (man >> 13) as u16;
+        return sign | 0x7C00 | if payload == 0 { 0x200 } else { payload };
+    }
+
+    let e = exp - 127;
+    if e > 15 {
+        return sign | 0x7C00;
+    }
+    if e >= -14 {
+        let half_man = (man >> 13) as u16;
+        let rem = man & 0x1FFF;
+        let half_bits = sign | (((e + 15) as u16) << 10) | half_man;
+        return if rem > 0x1000 || (rem == 0x1000 && (half_man & 1) == 1) {
+            half_bits + 1
+        } else {
+            half_bits
+        };
+    }
+    if e < -25 {
+        return sign;
+    }
+
+    let m = man | 0x800000;
+    let shift = (-e - 1) as u32;
+    let half_man = (m >> shift) as u16;
+    let rem = m & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    if rem > halfway || (rem == halfway && (half_man & 1) == 1) {
+        sign | (half_man + 1)
+    } else {
+        sign | half_man
+    }
+}
+
+// 辅助函数：f16位模式到f32的转换
+// 注意：这个函数与 f16_overflow.rs 中的实现相同，已经提供
+fn f16_bits_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exp = ((bits >> 10) & 0x1F) as i32;
+    let mut frac = (bits & 0x3FF) as u32;
+
+    if exp == 0 {
+        if frac == 0 {
+            return f32::from_bits(sign);
+        }
+        let mut e = -14;
+        while frac & 0x400 == 0 {
+            frac <<= 1;
+            e -= 1;
+        }
+        frac &= 0x3FF;
+        return f32::from_bits(sign | (((e + 127) as u32) << 23) | (frac << 13));
+    } else if exp == 31 {
+        return f32::from_bits(sign | 0x7F800000 | (frac << 13));
+    }
+
+    let adjusted_exp = (exp - 15 + 127) as u32;
+    f32::from_bits(sign | (adjusted_exp << 23) | (frac << 13))
+}
+
+// 辅助函数：f32到bf16位模式的转换（舍入到最近值，平局时取偶数）
+// 注意：这个函数与 bf16_basics.rs 中的实现相同，已经提供
+fn f32_to_bf16_bits(x: f32) -> u16 {
+    let bits = x.to_bits();
+    if x.is_nan() {
+        return ((bits >> 16) as u16) | 0x0040;
+    }
+    let lsb = (bits >> 16) & 1;
+    let rounded = bits.wrapping_add(0x7FFF + lsb);
+    (rounded >> 16) as u16
+}
+
+// 辅助函数：bf16位模式到f32的转换
+// 注意：这个函数与 bf16_basics.rs 中的实现相同，已经提供
+fn bf16_bits_to_f32(bits: u16) -> f32 {
+    f32::from_bits((bits as u32) << 16)
+}
+
+// 辅助函数：纯f32的批量矩阵乘法
+// 注意：这个函数与 multi_head_attention.rs 中的实现相同，已经提供
+fn batch_matmul(
+    query: &[Vec<Vec<Vec<f32>>>],
+    key: &[Vec<Vec<Vec<f32>>>]
+) -> Vec<Vec<Vec<Vec<f32>>>> {
+    let batch_size = query.len();
+    let num_heads = query[0].len();
+    let seq_len_q = query[0][0].len();
+    let head_size = query[0][0][0].len();
+    let seq_len_k = key[0][0][0].len();
+
+    assert_eq!(head_size, key[0][0].len(), "Head size mismatch");
+
+    (0..batch_size)
+        .map(|b| {
+            (0..num_heads)
+                .map(|h| {
+                    let q = &query[b][h];
+                    let k = &key[b][h];
+
+                    (0..seq_len_q)
+                        .map(|i| {
+                            (0..seq_len_k)
+                                .map(|j| {
+                                    // key已经转置，第j列是第j个key向量
+                                    q[i].iter()
+                                        .zip(k.iter())
+                                        .map(|(&a, k_row)| a * k_row[j])
+                                        .sum::<f32>()
+                                })
+                                .collect::<Vec<f32>>()
+                        })
+                        .collect::<Vec<Vec<f32>>>()
+                })
+                .collect::<Vec<Vec<Vec<f32>>>>()
+        })
+        .collect()
+}
+
+// 辅助函数：纯f32的均值
+// 注意：这个函数与 layer_norm.rs 中的实现相同，已经提供
+fn compute_mean(x: &[f32]) -> f32 {
+    x.iter().sum::<f32>() / x.len() as f32
+}
+
+// 纯f32的softmax，先减去最大值避免exp上溢
+fn softmax(x: &[f32]) -> Vec<f32> {
+    let max = x.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+    let exps: Vec<f32> = x.iter().map(|&v| (v - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.iter().map(|&e| e / sum).collect()
+}
+
+// 半精度存储格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum HalfFormat {
+    F16,
+    BF16,
+}
+
+impl HalfFormat {
+    fn encode(self, x: f32) -> u16 {
+        match self {
+            HalfFormat::F16 => f32_to_f16_bits(x),
+            HalfFormat::BF16 => f32_to_bf16_bits(x),
+        }
+    }
+
+    fn decode(self, bits: u16) -> f32 {
+        match self {
+            HalfFormat::F16 => f16_bits_to_f32(bits),
+            HalfFormat::BF16 => bf16_bits_to_f32(bits),
+        }
+    }
+
+    // 最小正规数，比它小的值只能用非规格化数表示，相对精度逐渐降低
+    fn min_normal(self) -> f32 {
+        match self {
+            HalfFormat::F16 => 2f32.powi(-14),
+            HalfFormat::BF16 => f32::MIN_POSITIVE,
+        }
+    }
+}
+
+// 以半精度位模式存储的张量，数据按行优先（最后一维连续）排列
+#[derive(Debug, Clone, PartialEq)]
+struct HalfTensor {
+    format: HalfFormat,
+    shape: Vec<usize>,
+    data: Vec<u16>,
+}
+
+impl HalfTensor {
+    // 从f32数据创建张量，每个元素舍入到存储格式
+    fn from_f32(values: &[f32], shape: &[usize], format: HalfFormat) -> Self {
+        assert_eq!(values.len(), shape.iter().product::<usize>(), "数据长度与形状不匹配");
+        HalfTensor {
+            format,
+            shape: shape.to_vec(),
+            data: values.iter().map(|&v| format.encode(v)).collect(),
+        }
+    }
+
+    // 从 [batch_size, num_heads, rows, cols] 的嵌套Vec创建张量
+    fn from_4d(x: &[Vec<Vec<Vec<f32>>>], format: HalfFormat) -> Self {
+        let shape = [x.len(), x[0].len(), x[0][0].len(), x[0][0][0].len()];
+        let values: Vec<f32> = x.iter().flatten().flatten().flatten().copied().collect();
+        HalfTensor::from_f32(&values, &shape, format)
+    }
+
+    fn to_f32(&self) -> Vec<f32> {
+        self.data.iter().map(|&b| self.format.decode(b)).collect()
+    }
+
+    fn to_4d(&self) -> Vec<Vec<Vec<Vec<f32>>>> {
+        assert_eq!(self.shape.len(), 4, "只有4D张量才能转换为嵌套Vec");
+        let values = self.to_f32();
+        let (d1, d2, d3) = (self.shape[1], self.shape[2], self.shape[3]);
+        values.chunks(d1 * d2 * d3)
+            .map(|b| b.chunks(d2 * d3)
+                .map(|h| h.chunks(d3).map(|r| r.to_vec()).collect())
+                .collect())
+            .collect()
+    }
+
+    fn numel(&self) -> usize {
+        self.data.len()
+    }
+
+    // 数据部分占用的字节数
+    fn memory_bytes(&self) -> usize {
+        self.data.len() * std::mem::size_of::<u16>()
+    }
+
+    // 最后一维的长度，以及把最后一维替换为new_last后的形状
+    fn last_dim(&self) -> usize {
+        *self.shape.last().expect("张量至少需要一维")
+    }
+
+    fn shape_with_last(&self, new_last: usize) -> Vec<usize> {
+        let mut shape = self.shape.clone();
+        *shape.last_mut().expect("张量至少需要一维") = new_last;
+        shape
+    }
+}
+
+// 半精度存储、f32累加的批量矩阵乘法
+// 与batch_matmul相同，key已经转置：scores[i][j] = sum_d query[i][d] * key[d][j]
+// query形状: [batch_size, num_heads, seq_len_q, head_size]
+// key形状:   [batch_size, num_heads, head_size, seq_len_k]
+// 输出形状:  [batch_size, num_heads, seq_len_q, seq_len_k]，格式与输入相同
+fn half_batch_matmul(query: &HalfTensor, key: &HalfTensor) -> HalfTensor {
+    assert_eq!(query.format, key.format, "query和key的存储格式必须相同");
+    assert_eq!(query.shape.len(), 4);
+    assert_eq!(key.shape.len(), 4);
+    assert_eq!(query.shape[..2], key.shape[..2], "batch_size和num_heads必须相同");
+    assert_eq!(query.shape[3], key.shape[2], "Head size mismatch");
+
+    let format = query.format;
+    let (batch_size, num_heads, seq_len_q, head_size) =
+        (query.shape[0], query.shape[1], query.shape[2], query.shape[3]);
+    let seq_len_k = key.shape[3];
+    let q = query.to_f32();
+    let k = key.to_f32();
+
+    let mut out = Vec::with_capacity(batch_size * num_heads * seq_len_q * seq_len_k);
+    for bh in 0..batch_size * num_heads {
+        let q_block = &q[bh * seq_len_q * head_size..(bh + 1) * seq_len_q * head_size];
+        let k_block = &k[bh * head_size * seq_len_k..(bh + 1) * head_size * seq_len_k];
+        for q_row in q_block.chunks(head_size) {
+            for j in 0..seq_len_k {
+                let acc: f32 = q_row.iter()
+                    .zip(k_block[j..].iter().step_by(seq_len_k))
+                    .map(|(&a, &b)| a * b)
+                    .sum();
+                out.push(format.encode(acc));
+            }
+        }
+    }
+
+    HalfTensor {
+        format,
+        shape: vec![batch_size, num_heads, seq_len_q, seq_len_k],
+        data: out,
+    }
+}
+
+// 沿最后一维求均值，f32累加，结果舍入到存储格式
+// 输出形状与输入相同，只是最后一维变为1
+fn half_mean(x: &HalfTensor) -> HalfTensor {
+    let n = x.last_dim();
+    let data = x.to_f32()
+        .chunks(n)
+        .map(|row| x.format.encode(compute_mean(row)))
+        .collect();
+    HalfTensor { format: x.format, shape: x.shape_with_last(1), data }
+}
+
+// 沿最后一维计算softmax，max、exp和求和都在f32中进行，结果舍入到存储格式
+fn half_softmax(x: &HalfTensor) -> HalfTensor {
+    let n = x.last_dim();
+    let data = x.to_f32()
+        .chunks(n)
+        .flat_map(softmax)
+        .map(|p| x.format.encode(p))
+        .collect();
+    HalfTensor { format: x.format, shape: x.shape_with_last(n), data }
+}
+
+// 半精度计算与纯f32计算的精度对比
+#[derive(Debug, Clone, PartialEq)]
+struct AccuracyReport {
+    op: &'static str,
+    format: HalfFormat,
+    max_abs_error: f32,
+    max_rel_error: f32,    // 只统计参考值不小于最小正规数的元素
+    underflow_count: usize, // 参考值不为0，但半精度结果为0的元素个数
+    f32_bytes: usize,   // 纯f32存储输入和输出需要的字节数
+    half_bytes: usize,  // 半精度存储输入和输出需要的字节数
+}
+
+impl AccuracyReport {
+    fn new(op: &'static str, reference: &[f32], inputs: &[&HalfTensor], output: &HalfTensor) -> Self {
+        let actual = output.to_f32();
+        assert_eq!(reference.len(), actual.len(), "结果长度不匹配");
+
+        let mut max_abs_error = 0.0f32;
+        let mut max_rel_error = 0.0f32;
+        let mut underflow_count = 0;
+        for (&r, &a) in reference.iter().zip(actual.iter()) {
+            let err = (r - a).abs();
+            max_abs_error = max_abs_error.max(err);
+            if r.abs() >= output.format.min_normal() {
+                max_rel_error = max_rel_error.max(err / r.abs());
+            }
+            if r != 0.0 && a == 0.0 {
+                underflow_count += 1;
+            }
+        }
+
+        let numel: usize = inputs.iter().map(|t| t.numel()).sum::<usize>() + output.numel();
+        let half_bytes: usize = inputs.iter().map(|t| t.memory_bytes()).sum::<usize>() + output.memory_bytes();
+        AccuracyReport {
+            op,
+            format: output.format,
+            max_abs_error,
+            max_rel_error,
+            underflow_count,
+            f32_bytes: numel * std::mem::size_of::<f32>(),
+            half_bytes,
+        }
+    }
+}
+
+impl fmt::Display for AccuracyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} [{:?}]: max_abs_error={:.3e} max_rel_error={:.3e} underflow={} memory={}B (f32: {}B)",
+            self.op, self.format, self.max_abs_error, self.max_rel_error, self.underflow_count,
+            self.half_bytes, self.f32_bytes
+        )
+    }
+}
+
+// 在 multi_head_attention.rs 和 layer_norm.rs 的测试输入上比较半精度与纯f32的结果
+fn accuracy_report(format: HalfFormat) -> Vec<AccuracyReport> {
+    let mut reports = Vec::new();
+
+    // batch_matmul测试中的query和key
+    let query = vec![vec![vec![vec![1.0, 2.0], vec![3.0, 4.0]]]];
+    let key = vec![vec![vec![vec![1.0, 2.0], vec![3.0, 4.0]]]];
+    let reference: Vec<f32> = batch_matmul(&query, &key).into_iter().flatten().flatten().flatten().collect();
+    let (q, k) = (HalfTensor::from_4d(&query, format), HalfTensor::from_4d(&key, format));
+    let scores = half_batch_matmul(&q, &k);
+    reports.push(AccuracyReport::new("batch_matmul", &reference, &[&q, &k], &scores));
+
+    // batch_layer_norm测试中的输入
+    let batch = [
+        vec![1.0, 2.0, 3.0],
+        vec![10.0, 20.0, 30.0],
+        vec![100.0, 200.0, 300.0],
+    ];
+    let values: Vec<f32> = batch.iter().flatten().copied().collect();
+    let reference: Vec<f32> = batch.iter().map(|row| compute_mean(row)).collect();
+    let x = HalfTensor::from_f32(&values, &[batch.len(), batch[0].len()], format);
+    reports.push(AccuracyReport::new("compute_mean", &reference, &[&x], &half_mean(&x)));
+
+    // 对注意力分数和batch_layer_norm的输入做softmax，差距较大的logits会产生很小的概率，可能下溢
+    let scores_f32 = scores.to_f32();
+    let reference: Vec<f32> = scores_f32.chunks(2).flat_map(softmax).collect();
+    reports.push(AccuracyReport::new("softmax(scores)", &reference, &[&scores], &half_softmax(&scores)));
+
+    let reference: Vec<f32> = batch.iter().flat_map(|row| softmax(row)).collect();
+    reports.push(AccuracyReport::new("softmax", &reference, &[&x], &half_softmax(&x)));
+
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const FORMATS: [HalfFormat; 2] = [HalfFormat::F16, HalfFormat::BF16];
+
+    // 舍入到存储格式的单位舍入误差（半个ulp的相对值）
+    fn unit_roundoff(format: HalfFormat) -> f32 {
+        match format {
+            HalfFormat::F16 => 2f32.powi(-11),
+            HalfFormat::BF16 => 2f32.powi(-8),
+        }
+    }
+
+    // 反例：每次加法的结果都写回半精度
+    fn naive_half_sum(x: &HalfTensor) -> f32 {
+        x.to_f32().iter().fold(0.0, |acc, &v| x.format.decode(x.format.encode(acc + v)))
+    }
+
+    #[test]
+    fn test_storage_round_trip() {
+        let values = [1.0, -2.5, 0.1, 65504.0, 1e-3];
+        let t = HalfTensor::from_f32(&values, &[5], HalfFormat::F16);
+        assert_eq!(t.data[0], 0x3C00);
+        assert_eq!(t.to_f32()[2], 0.099975586, "存储时舍入到最近的f16值");
+        assert_eq!(t.to_f32()[3], 65504.0);
+
+        let b = HalfTensor::from_f32(&values, &[5], HalfFormat::BF16);
+        assert_eq!(b.data[0], 0x3F80);
+        assert_eq!(b.to_f32()[2], f32::from_bits(0x3DCD0000));
+
+        assert_eq!(t.memory_bytes(), 10, "每个元素2字节，是f32的一半");
+
+        let nested = vec![vec![vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]], vec![vec![vec![7.0, 8.0, 9.0], vec![0.5, 0.25, -1.0]]]];
+        let t = HalfTensor::from_4d(&nested, HalfFormat::BF16);
+        assert_eq!(t.shape, vec![2, 1, 2, 3]);
+        assert_eq!(t.to_4d(), nested, "这些值都可以精确表示");
+    }
+
+    #[test]
+    fn test_batch_matmul_existing_input() {
+        let query = vec![vec![vec![vec![1.0, 2.0], vec![3.0, 4.0]]]];
+        let key = vec![vec![vec![vec![1.0, 2.0], vec![3.0, 4.0]]]];
+        for format in FORMATS {
+            let scores = half_batch_matmul(&HalfTensor::from_4d(&query, format), &HalfTensor::from_4d(&key, format));
+            assert_eq!(scores.shape, vec![1, 1, 2, 2]);
+            assert_eq!(scores.to_4d(), batch_matmul(&query, &key), "小整数的结果可以精确表示");
+        }
+    }
+
+    #[test]
+    fn test_batch_matmul_random_error_bound() {
+        let mut rng = StdRng::seed_from_u64(3);
+        // key已经转置，形状为 [b, h, head_size, seq_len_k]，这里head_size与seq_len_k不相等
+        let (b, h, s, d, sk) = (2, 3, 5, 32, 7);
+        let query: Vec<Vec<Vec<Vec<f32>>>> = (0..b).map(|_| (0..h).map(|_| (0..s)
+            .map(|_| (0..d).map(|_| rng.gen_range(-1.0..1.0)).collect()).collect()).collect()).collect();
+        let key: Vec<Vec<Vec<Vec<f32>>>> = (0..b).map(|_| (0..h).map(|_| (0..d)
+            .map(|_| (0..sk).map(|_| rng.gen_range(-1.0..1.0)).collect()).collect()).collect()).collect();
+
+        for format in FORMATS {
+            let q = HalfTensor::from_4d(&query, format);
+            let k = HalfTensor::from_4d(&key, format);
+            let result = half_batch_matmul(&q, &k);
+            assert_eq!(result.shape, vec![b, h, s, sk]);
+            let result = result.to_4d();
+            let reference = batch_matmul(&query, &key);
+            let u = unit_roundoff(format);
+
+            for bh in 0..b * h {
+                let (bi, hi) = (bh / h, bh % h);
+                for i in 0..s {
+                    for j in 0..sk {
+                        // 每个乘积的两个因子各有一次舍入，结果写回时再舍入一次
+                        let abs_dot: f32 = query[bi][hi][i].iter().zip(&key[bi][hi]).map(|(a, k_row)| (a * k_row[j]).abs()).sum();
+                        let r = reference[bi][hi][i][j];
+                        let bound = 2.0 * u * abs_dot + u * r.abs() + 1e-5;
+                        let err = (result[bi][hi][i][j] - r).abs();
+                        assert!(err <= bound, "{:?} 误差{}超过界{}", format, err, bound);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mean_accumulates_in_f32() {
+        let ones = HalfTensor::from_f32(&vec![1.0; 4096], &[4096], HalfFormat::F16);
+        let mean = half_mean(&ones);
+        assert_eq!(mean.shape, vec![1]);
+        assert_eq!(mean.to_f32(), vec![1.0]);
+        // 在f16中累加时，和到达2048后再加1会被舍入掉
+        assert_eq!(naive_half_sum(&ones), 2048.0);
+
+        let bf = HalfTensor::from_f32(&vec![1.0; 4096], &[4096], HalfFormat::BF16);
+        assert_eq!(half_mean(&bf).to_f32(), vec![1.0]);
+        assert_eq!(naive_half_sum(&bf), 256.0, "bf16只有8位有效位，到256就停止增长");
+
+        // layer_norm测试中的输入
+        let x = HalfTensor::from_f32(&[1.0, 2.0, 3.0, 4.0, 5.0, 10.0, 20.0, 30.0, 40.0, 50.0], &[2, 5], HalfFormat::F16);
+        let mean = half_mean(&x);
+        assert_eq!(mean.shape, vec![2, 1]);
+        assert_eq!(mean.to_f32(), vec![3.0, 30.0]);
+    }
+
+    #[test]
+    fn test_softmax() {
+        for format in FORMATS {
+            // 大的logits：先减去最大值，exp不会上溢
+            let x = HalfTensor::from_f32(&[1000.0, 1001.0, 999.0, 0.0, 0.0, 0.0], &[2, 3], format);
+            let p = half_softmax(&x);
+            assert_eq!(p.shape, vec![2, 3]);
+            let values = p.to_f32();
+            let reference = softmax(&x.to_f32()[..3]);
+            for (a, r) in values[..3].iter().zip(reference.iter()) {
+                assert!((a - r).abs() <= unit_roundoff(format) * r, "{:?}: {} vs {}", format, a, r);
+            }
+            for row in values.chunks(3) {
+                let sum: f32 = row.iter().sum();
+                assert!((sum - 1.0).abs() <= 3.0 * unit_roundoff(format), "每行的和应该接近1");
+            }
+            assert!(values.iter().all(|v| v.is_finite()));
+        }
+    }
+
+    #[test]
+    fn test_accuracy_report() {
+        for format in FORMATS {
+            let reports = accuracy_report(format);
+            let ops: Vec<&str> = reports.iter().map(|r| r.op).collect();
+            assert_eq!(ops, ["batch_matmul", "compute_mean", "softmax(scores)", "softmax"]);
+
+            for report in &reports {
+                assert_eq!(report.format, format);
+                assert_eq!(report.half_bytes * 2, report.f32_bytes, "半精度存储占用一半的内存");
+                // 输入都可以精确表示，误差只来自结果写回时的一次舍入
+                assert!(report.max_rel_error <= unit_roundoff(format),
+                        "{}: 相对误差{}超过了一次舍入的误差", report, report.max_rel_error);
+                assert!(report.to_string().starts_with(report.op));
+            }
+            // 矩阵乘法和均值的结果都是精确的
+            assert_eq!(reports[0].max_abs_error, 0.0);
+            assert_eq!(reports[1].max_abs_error, 0.0);
+            // 概率不超过1，所以softmax的绝对误差不超过一次舍入
+            assert!(reports[3].max_abs_error <= unit_roundoff(format));
+        }
+
+        // softmax([10, 20, 30])中的exp(-20)约为2e-9，小于f16最小的非规格化数，在f16中下溢为0，在bf16中可以表示；
+        // softmax([100, 200, 300])中的exp(-100)约为3.7e-44，只能用f32的非规格化数表示，在两种格式中都下溢为0
+        assert_eq!(accuracy_report(HalfFormat::F16)[3].underflow_count, 2);
+        assert_eq!(accuracy_report(HalfFormat::BF16)[3].underflow_count, 1);
+    }
+}
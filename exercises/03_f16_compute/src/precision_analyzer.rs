@@ -0,0 +1,613 @@
+// check_precision_loss 只能判断单个数值的相对误差是否超过阈值。
+// 决定某一层的权重能否降低精度存储时，需要看整个张量：误差的分布、有多少值会上溢或下溢、
+// 数值的指数分布是否落在目标格式的范围内。
+// 在这个练习中，我们将实现一个精度损失分析器，把整个缓冲区转换到目标格式（f16、bf16、fp8）再转换回来，统计误差。
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+// 辅助函数：f32到f16位模式的转换（舍入到最近值，平局时取偶数）
+// 注意：这个函数与 f16_overflow.rs 中的实现相同，已经提供
+fn f32_to_f16_bits(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xFF) as i32;
+    let man = bits & 0x7FFFFF;
+
+    if exp == 0xFF {
+        if man == 0 {
+            return sign | 0x7C00;
+        }
+        let payload = (man >> 13) as u16;
+        return sign | 0x7C00 | if payload == 0 { 0x200 } else { payload };
+    }
+
+    let e = exp - 127;
+    if e > 15 {
+        return sign | 0x7C00;
+    }
+    if e >= -14 {
+        let half_man = (man >> 13) as u16;
+        let rem = man & 0x1FFF;
+        let half_bits = sign | (((e + 15) as u16) << 10) | half_man;
+        return if rem > 0x1000 || (rem == 0x1000 && (half_man & 1) == 1) {
+            half_bits + 1
+        } else {
+            half_bits
+        };
+    }
+    if e < -25 {
+        return sign;
+    }
+
+    let m = man | 0x800000;
+    let shift = (-e - 1) as u32;
+    let half_man = (m >> shift) as u16;
+    let rem = m & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    if rem > halfway || (rem == halfway && (half_man & 1) == 1) {
+        sign | (half_man + 1)
+    } else {
+        sign | half_man
+    }
+}
+
+// 辅助函数：f16位模式到f32的转换
+// 注意：这个函数与 f16_overflow.rs 中的实现相同，已经提供
+fn f16_bits_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exp = ((bits >> 10) & 0x1F) as i32;
+    let mut frac = (bits & 0x3FF) as u32;
+
+    if exp == 0 {
+        if frac == 0 {
+            return f32::from_bits(sign);
+        }
+        let mut e = -14;
+        while frac & 0x400 == 0 {
+            frac <<= 1;
+            e -= 1;
+        }
+        frac &= 0x3FF;
+        return f32::from_bits(sign | (((e + 127) as u32) << 23) | (frac << 13));
+    } else if exp == 31 {
+        return f32::from_bits(sign | 0x7F800000 | (frac << 13));
+    }
+
+    let adjusted_exp = (exp - 15 + 127) as u32;
+    f32::from_bits(sign | (adjusted_exp << 23) | (frac << 13))
+}
+
+// 辅助函数：f32到bf16位模式的转换（舍入到最近值，平局时取偶数）
+// 注意：这个函数与 bf16_basics.rs 中的实现相同，已经提供
+fn f32_to_bf16_bits(x: f32) -> u16 {
+    let bits = x.to_bits();
+    if x.is_nan() {
+        return ((bits >> 16) as u16) | 0x0040;
+    }
+    let lsb = (bits >> 16) & 1;
+    let rounded = bits.wrapping_add(0x7FFF + lsb);
+    (rounded >> 16) as u16
+}
+
+// 辅助函数：bf16位模式到f32的转换
+// 注意：这个函数与 bf16_basics.rs 中的实现相同，已经提供
+fn bf16_bits_to_f32(bits: u16) -> f32 {
+    f32::from_bits((bits as u32) << 16)
+}
+
+// 注意：以下FP8相关的类型和函数与 fp8_formats.rs 中的实现相同，已经提供
+// FP8格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Fp8Format {
+    E4M3,
+    E5M2,
+}
+
+// 上溢处理方式
+// Saturate:    超出范围的值（包括无穷大）截断为±最大值，训练中常用
+// NonSaturate: E5M2上溢为±无穷大，E4M3没有无穷大所以上溢为NaN
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OverflowMode {
+    Saturate,
+    NonSaturate,
+}
+
+impl Fp8Format {
+    fn exponent_bits(&self) -> u32 {
+        match self {
+            Fp8Format::E4M3 => 4,
+            Fp8Format::E5M2 => 5,
+        }
+    }
+
+    fn mantissa_bits(&self) -> u32 {
+        match self {
+            Fp8Format::E4M3 => 3,
+            Fp8Format::E5M2 => 2,
+        }
+    }
+
+    fn bias(&self) -> i32 {
+        (1 << (self.exponent_bits() - 1)) - 1
+    }
+
+    // 最大有限值的位模式（不含符号位）
+    // E4M3: 0.1111.110，因为0.1111.111是NaN
+    // E5M2: 0.11110.11，因为指数全1用于无穷大和NaN
+    fn max_bits(&self) -> u8 {
+        match self {
+            Fp8Format::E4M3 => 0x7E,
+            Fp8Format::E5M2 => 0x7B,
+        }
+    }
+
+    // 规范的NaN位模式（不含符号位）
+    fn nan_bits(&self) -> u8 {
+        match self {
+            Fp8Format::E4M3 => 0x7F,
+            Fp8Format::E5M2 => 0x7E,
+        }
+    }
+
+    fn max_value(&self) -> f32 {
+        fp8_to_f32(self.max_bits(), *self)
+    }
+
+    fn is_nan(&self, bits: u8) -> bool {
+        let magnitude = bits & 0x7F;
+        match self {
+            Fp8Format::E4M3 => magnitude == 0x7F,
+            Fp8Format::E5M2 => magnitude > 0x7C,
+        }
+    }
+}
+
+// FP8位模式到f32的转换
+// 每个FP8值都可以精确地用f32表示
+fn fp8_to_f32(bits: u8, format: Fp8Format) -> f32 {
+    let negative = bits & 0x80 != 0;
+    let mantissa_bits = format.mantissa_bits();
+    let exp = ((bits & 0x7F) >> mantissa_bits) as i32;
+    let man = (bits & ((1 << mantissa_bits) - 1)) as f32;
+    let max_exp = (1 << format.exponent_bits()) - 1;
+    let scale = (1u32 << mantissa_bits) as f32;
+
+    let magnitude = if format.is_nan(bits) {
+        return f32::NAN;
+    } else if format == Fp8Format::E5M2 && exp == max_exp {
+        f32::INFINITY
+    } else if exp == 0 {
+        // 非规格化数: m / 2^mb * 2^(1 - bias)
+        man / scale * 2f32.powi(1 - format.bias())
+    } else {
+        (1.0 + man / scale) * 2f32.powi(exp - format.bias())
+    };
+
+    if negative { -magnitude } else { magnitude }
+}
+
+// f32到FP8位模式的转换（舍入到最近值，平局时取偶数）
+fn f32_to_fp8(x: f32, format: Fp8Format, mode: OverflowMode) -> u8 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 24) & 0x80) as u8;
+
+    if x.is_nan() {
+        return sign | format.nan_bits();
+    }
+
+    // 上溢时的结果
+    let overflow = match (mode, format) {
+        (OverflowMode::Saturate, _) => sign | format.max_bits(),
+        (OverflowMode::NonSaturate, Fp8Format::E5M2) => sign | 0x7C,
+        (OverflowMode::NonSaturate, Fp8Format::E4M3) => sign | 0x7F,
+    };
+    if x.is_infinite() {
+        return overflow;
+    }
+
+    let exp = ((bits >> 23) & 0xFF) as i32;
+    let man = bits & 0x7FFFFF;
+    if exp == 0 && man == 0 {
+        return sign; // 带符号的零
+    }
+
+    // 写成 m * 2^(e - 23) 的形式
+    let (m, e) = if exp == 0 {
+        (man as u64, -126)
+    } else {
+        ((man | 0x800000) as u64, exp - 127)
+    };
+
+    let mantissa_bits = format.mantissa_bits() as i32;
+    let min_exp = 1 - format.bias();
+    if e > format.bias() + 1 {
+        return overflow; // 远超范围，不需要再舍入
+    }
+
+    // 以目标格式在当前指数下的最小单位表示，得到截断后的位模式和被截掉的低位
+    let (truncated, rem, shift) = if e >= min_exp {
+        let shift = (23 - mantissa_bits) as u32;
+        let biased = (e + format.bias()) as u64;
+        let truncated = (biased << mantissa_bits) | ((m >> shift) & ((1 << mantissa_bits) - 1));
+        (truncated, m & ((1 << shift) - 1), shift)
+    } else {
+        // 非规格化数，最小单位是 2^(min_exp - mantissa_bits)
+        let shift = (23 + min_exp - mantissa_bits - e) as u32;
+        if shift >= 64 {
+            return sign; // 远小于最小非规格化数的一半
+        }
+        (m >> shift, m & ((1 << shift) - 1), shift)
+    };
+
+    let halfway = 1u64 << (shift - 1);
+    let rounded = if rem > halfway || (rem == halfway && truncated & 1 == 1) {
+        truncated + 1
+    } else {
+        truncated
+    };
+
+    if rounded > format.max_bits() as u64 {
+        overflow
+    } else {
+        sign | rounded as u8
+    }
+}
+
+// 分析的目标格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TargetFormat {
+    F16,
+    BF16,
+    Fp8E4M3,
+    Fp8E5M2,
+}
+
+impl TargetFormat {
+    fn mantissa_bits(self) -> i32 {
+        match self {
+            TargetFormat::F16 => 10,
+            TargetFormat::BF16 => 7,
+            TargetFormat::Fp8E4M3 => 3,
+            TargetFormat::Fp8E5M2 => 2,
+        }
+    }
+
+    // 最小正规数的指数
+    fn min_exponent(self) -> i32 {
+        match self {
+            TargetFormat::F16 => -14,
+            TargetFormat::BF16 => -126,
+            TargetFormat::Fp8E4M3 => -6,
+            TargetFormat::Fp8E5M2 => -14,
+        }
+    }
+
+    // 最大有限值的指数
+    fn max_exponent(self) -> i32 {
+        match self {
+            TargetFormat::F16 => 15,
+            TargetFormat::BF16 => 127,
+            TargetFormat::Fp8E4M3 => 8,
+            TargetFormat::Fp8E5M2 => 15,
+        }
+    }
+
+    // 转换到目标格式再转换回来，超出范围的值得到Inf（E4M3没有无穷大，得到NaN）
+    fn round_trip(self, x: f32) -> f32 {
+        match self {
+            TargetFormat::F16 => f16_bits_to_f32(f32_to_f16_bits(x)),
+            TargetFormat::BF16 => bf16_bits_to_f32(f32_to_bf16_bits(x)),
+            TargetFormat::Fp8E4M3 => {
+                fp8_to_f32(f32_to_fp8(x, Fp8Format::E4M3, OverflowMode::NonSaturate), Fp8Format::E4M3)
+            }
+            TargetFormat::Fp8E5M2 => {
+                fp8_to_f32(f32_to_fp8(x, Fp8Format::E5M2, OverflowMode::NonSaturate), Fp8Format::E5M2)
+            }
+        }
+    }
+
+    // 目标格式在x所在区间内相邻两个值的间距（ulp），非规格化数区间的间距固定为最小非规格化数
+    // 用f64计算，因为bf16的ulp可能小于f32能表示的最小值
+    fn ulp(self, x: f32) -> f64 {
+        let e = exponent_of(x).max(self.min_exponent());
+        2f64.powi(e - self.mantissa_bits())
+    }
+
+    // 按指数判断数值在目标格式中落在哪个区间
+    fn classify_exponent(self, e: i32) -> &'static str {
+        if e > self.max_exponent() {
+            "overflow"
+        } else if e >= self.min_exponent() {
+            "normal"
+        } else if e >= self.min_exponent() - self.mantissa_bits() {
+            "subnormal"
+        } else {
+            "underflow"
+        }
+    }
+}
+
+// 非零有限f32的指数，即 floor(log2(|x|))，对f32非规格化数也成立
+fn exponent_of(x: f32) -> i32 {
+    let bits = x.to_bits();
+    let exp = ((bits >> 23) & 0xFF) as i32;
+    if exp == 0 {
+        let man = bits & 0x7FFFFF;
+        (31 - man.leading_zeros() as i32) - 149
+    } else {
+        exp - 127
+    }
+}
+
+// 整个张量的精度损失统计
+#[derive(Debug, Clone, PartialEq)]
+struct PrecisionReport {
+    format: TargetFormat,
+    count: usize,            // 有限输入值的个数
+    non_finite_count: usize, // 输入中的NaN和Inf，不参与统计
+    zero_count: usize,
+    // 误差只统计没有上溢的值，相对误差还要求输入不为0
+    max_abs_error: f32,
+    mean_abs_error: f32,
+    max_rel_error: f32,
+    mean_rel_error: f32,
+    max_ulp_error: f32, // 以目标格式的ulp为单位，正确舍入时不超过0.5
+    overflow_count: usize,
+    underflow_count: usize, // 非零值变为0
+    subnormal_count: usize, // 变为目标格式的非规格化数
+    exponent_histogram: BTreeMap<i32, usize>, // 非零有限输入的指数分布
+}
+
+impl PrecisionReport {
+    // 判断转换到目标格式是否安全：没有上溢，平均相对误差和下溢比例不超过给定的阈值
+    fn is_safe(&self, max_mean_rel_error: f32, max_underflow_fraction: f32) -> bool {
+        if self.overflow_count > 0 {
+            return false;
+        }
+        let nonzero = self.count - self.zero_count;
+        let underflow_fraction = if nonzero == 0 {
+            0.0
+        } else {
+            self.underflow_count as f32 / nonzero as f32
+        };
+        self.mean_rel_error <= max_mean_rel_error && underflow_fraction <= max_underflow_fraction
+    }
+}
+
+impl fmt::Display for PrecisionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:?}: count={} zero={} non_finite={} overflow={} underflow={} subnormal={}",
+            self.format, self.count, self.zero_count, self.non_finite_count,
+            self.overflow_count, self.underflow_count, self.subnormal_count
+        )?;
+        writeln!(
+            f,
+            "  abs_error: max={:.3e} mean={:.3e}  rel_error: max={:.3e} mean={:.3e}  max_ulp_error={:.3}",
+            self.max_abs_error, self.mean_abs_error, self.max_rel_error, self.mean_rel_error, self.max_ulp_error
+        )?;
+        for (&e, &n) in &self.exponent_histogram {
+            writeln!(f, "  2^{:<5} {:>8} {}", e, n, self.format.classify_exponent(e))?;
+        }
+        Ok(())
+    }
+}
+
+// 把整个缓冲区转换到目标格式再转换回来，统计精度损失
+// 误差使用f64累加，避免大张量的累积误差
+fn analyze_precision(x: &[f32], format: TargetFormat) -> PrecisionReport {
+    let mut report = PrecisionReport {
+        format,
+        count: 0,
+        non_finite_count: 0,
+        zero_count: 0,
+        max_abs_error: 0.0,
+        mean_abs_error: 0.0,
+        max_rel_error: 0.0,
+        mean_rel_error: 0.0,
+        max_ulp_error: 0.0,
+        overflow_count: 0,
+        underflow_count: 0,
+        subnormal_count: 0,
+        exponent_histogram: BTreeMap::new(),
+    };
+    let min_normal = 2f64.powi(format.min_exponent());
+    let (mut abs_sum, mut rel_sum) = (0.0f64, 0.0f64);
+    let (mut abs_n, mut rel_n) = (0usize, 0usize);
+    let (mut max_abs, mut max_rel, mut max_ulp) = (0.0f64, 0.0f64, 0.0f64);
+
+    for &v in x {
+        if !v.is_finite() {
+            report.non_finite_count += 1;
+            continue;
+        }
+        report.count += 1;
+        if v == 0.0 {
+            report.zero_count += 1;
+            abs_n += 1;
+            continue;
+        }
+        *report.exponent_histogram.entry(exponent_of(v)).or_insert(0) += 1;
+
+        let rt = format.round_trip(v);
+        if !rt.is_finite() {
+            report.overflow_count += 1;
+            continue;
+        }
+        if rt == 0.0 {
+            report.underflow_count += 1;
+        } else if (rt.abs() as f64) < min_normal {
+            report.subnormal_count += 1;
+        }
+
+        let abs_error = (v as f64 - rt as f64).abs();
+        let rel_error = abs_error / (v as f64).abs();
+        max_abs = max_abs.max(abs_error);
+        max_rel = max_rel.max(rel_error);
+        max_ulp = max_ulp.max(abs_error / format.ulp(v));
+        abs_sum += abs_error;
+        rel_sum += rel_error;
+        abs_n += 1;
+        rel_n += 1;
+    }
+
+    report.max_abs_error = max_abs as f32;
+    report.max_rel_error = max_rel as f32;
+    report.max_ulp_error = max_ulp as f32;
+    if abs_n > 0 {
+        report.mean_abs_error = (abs_sum / abs_n as f64) as f32;
+    }
+    if rel_n > 0 {
+        report.mean_rel_error = (rel_sum / rel_n as f64) as f32;
+    }
+    report
+}
+
+// 在候选格式中（按优先顺序）选择第一个可以安全使用的格式，都不安全时返回None，应该保持f32
+fn choose_format(
+    x: &[f32],
+    candidates: &[TargetFormat],
+    max_mean_rel_error: f32,
+    max_underflow_fraction: f32,
+) -> Option<TargetFormat> {
+    candidates.iter()
+        .copied()
+        .find(|&format| analyze_precision(x, format).is_safe(max_mean_rel_error, max_underflow_fraction))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const ALL_FORMATS: [TargetFormat; 4] = [
+        TargetFormat::F16, TargetFormat::BF16, TargetFormat::Fp8E4M3, TargetFormat::Fp8E5M2,
+    ];
+
+    // 均值为0、标准差约为std的随机权重（均匀分布）
+    fn random_weights(rng: &mut StdRng, n: usize, std: f32) -> Vec<f32> {
+        let a = std * 3f32.sqrt();
+        (0..n).map(|_| rng.gen_range(-a..a)).collect()
+    }
+
+    #[test]
+    fn test_exponent_of() {
+        assert_eq!(exponent_of(1.0), 0);
+        assert_eq!(exponent_of(1.99), 0);
+        assert_eq!(exponent_of(-0.25), -2);
+        assert_eq!(exponent_of(65504.0), 15);
+        assert_eq!(exponent_of(f32::MIN_POSITIVE), -126);
+        assert_eq!(exponent_of(f32::from_bits(1)), -149, "f32最小的非规格化数");
+        assert_eq!(exponent_of(f32::from_bits(0x00400000)), -127);
+    }
+
+    #[test]
+    fn test_exact_values() {
+        let x = [0.0, 1.0, -2.0, 0.5, 1.5, -0.0];
+        for format in ALL_FORMATS {
+            let report = analyze_precision(&x, format);
+            assert_eq!(report.count, 6);
+            assert_eq!(report.zero_count, 2);
+            assert_eq!(report.max_abs_error, 0.0, "{:?}可以精确表示这些值", format);
+            assert_eq!(report.mean_rel_error, 0.0);
+            assert!(report.is_safe(0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn test_errors_bounded_by_half_ulp() {
+        let mut rng = StdRng::seed_from_u64(11);
+        // 指数跨度很大的数值，覆盖正规数和非规格化数区间，但不超过E4M3的最大值448
+        let x: Vec<f32> = (0..20_000)
+            .map(|_| rng.gen_range(-1.0f32..1.0) * 2f32.powi(rng.gen_range(-30..8)))
+            .collect();
+
+        for format in ALL_FORMATS {
+            let report = analyze_precision(&x, format);
+            assert_eq!(report.overflow_count, 0);
+            assert!(report.max_ulp_error <= 0.5, "{:?}: 正确舍入的误差不超过半个ulp，实际{}", format, report.max_ulp_error);
+            assert!(report.mean_abs_error <= report.max_abs_error);
+        }
+
+        // 正规数区间内的相对误差不超过 2^-(mantissa_bits + 1)
+        let normal: Vec<f32> = (0..20_000).map(|_| rng.gen_range(1.0f32..2.0) * 2f32.powi(rng.gen_range(-5..5))).collect();
+        for format in ALL_FORMATS {
+            let report = analyze_precision(&normal, format);
+            let bound = 2f32.powi(-(format.mantissa_bits() + 1));
+            assert!(report.max_rel_error <= bound, "{:?}: {} > {}", format, report.max_rel_error, bound);
+            assert!(report.mean_rel_error < bound / 2.0, "平均相对误差应该明显小于最大值");
+            assert_eq!(report.subnormal_count, 0);
+        }
+    }
+
+    #[test]
+    fn test_overflow_underflow_and_subnormal_counts() {
+        let x = [70000.0, 500.0, 1.0, 1e-5, 1e-9, f32::NAN, f32::INFINITY, 0.0];
+
+        let f16 = analyze_precision(&x, TargetFormat::F16);
+        assert_eq!(f16.count, 6);
+        assert_eq!(f16.non_finite_count, 2);
+        assert_eq!(f16.overflow_count, 1, "70000超出f16范围");
+        assert_eq!(f16.underflow_count, 1, "1e-9小于f16最小非规格化数的一半");
+        assert_eq!(f16.subnormal_count, 1, "1e-5是f16的非规格化数");
+        assert!(!f16.is_safe(1.0, 1.0), "有上溢时不安全");
+
+        let bf16 = analyze_precision(&x, TargetFormat::BF16);
+        assert_eq!((bf16.overflow_count, bf16.underflow_count, bf16.subnormal_count), (0, 0, 0));
+        assert!(bf16.is_safe(1e-2, 0.0));
+
+        let e4m3 = analyze_precision(&x, TargetFormat::Fp8E4M3);
+        assert_eq!(e4m3.overflow_count, 2, "E4M3最大值是448");
+        assert_eq!(e4m3.underflow_count, 2);
+
+        let e5m2 = analyze_precision(&x, TargetFormat::Fp8E5M2);
+        assert_eq!(e5m2.overflow_count, 1);
+        assert_eq!(e5m2.underflow_count, 1);
+        assert_eq!(e5m2.subnormal_count, 1);
+    }
+
+    #[test]
+    fn test_exponent_histogram() {
+        let x = [1.0, 1.5, 0.25, 0.0, -3.0, 1e-6, f32::NAN];
+        let report = analyze_precision(&x, TargetFormat::F16);
+        let expected: BTreeMap<i32, usize> = [(-20, 1), (-2, 1), (0, 2), (1, 1)].into_iter().collect();
+        assert_eq!(report.exponent_histogram, expected);
+
+        let text = report.to_string();
+        assert!(text.contains("overflow=0"));
+        assert!(text.contains("2^-20"), "{}", text);
+        assert!(text.lines().any(|l| l.contains("2^-20") && l.ends_with("subnormal")));
+        assert!(text.lines().any(|l| l.contains("2^0 ") && l.ends_with("normal")));
+
+        assert_eq!(TargetFormat::F16.classify_exponent(16), "overflow");
+        assert_eq!(TargetFormat::F16.classify_exponent(-24), "subnormal");
+        assert_eq!(TargetFormat::F16.classify_exponent(-25), "underflow");
+        assert_eq!(TargetFormat::Fp8E4M3.classify_exponent(8), "normal");
+    }
+
+    #[test]
+    fn test_choose_format_per_layer() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let candidates = [TargetFormat::Fp8E4M3, TargetFormat::F16, TargetFormat::BF16];
+
+        // 普通的权重：很多值落在E4M3的非规格化数区间，平均相对误差约8%，即使允许5%的误差也不能直接用fp8
+        let weights = random_weights(&mut rng, 4096, 0.02);
+        assert_eq!(choose_format(&weights, &candidates, 1e-2, 1e-3), Some(TargetFormat::F16));
+        assert_eq!(choose_format(&weights, &candidates, 5e-2, 1e-3), Some(TargetFormat::F16));
+        // 按amax缩放到E4M3的最大值附近后，平均相对误差约2%，可以使用fp8
+        let amax = weights.iter().fold(0.0f32, |a, &b| a.max(b.abs()));
+        let scaled: Vec<f32> = weights.iter().map(|&w| w * 448.0 / amax).collect();
+        assert_eq!(choose_format(&scaled, &candidates, 5e-2, 1e-3), Some(TargetFormat::Fp8E4M3));
+
+        // 有离群值的激活超出f16范围，只能用bf16
+        let mut activations = random_weights(&mut rng, 4096, 10.0);
+        activations[17] = 1e5;
+        assert_eq!(choose_format(&activations, &candidates, 1e-2, 1e-3), Some(TargetFormat::BF16));
+
+        // 要求的误差比bf16的精度还小时，只能保持f32
+        assert_eq!(choose_format(&activations, &candidates, 1e-4, 0.0), None);
+    }
+}
@@ -0,0 +1,562 @@
+// llama.cpp使用的GGUF文件中，权重以“块”为单位量化：每32个（或256个）权重共用一个f16缩放因子。
+// 在这个练习中，我们将实现与GGML完全相同的三种块格式：
+// Q8_0: 32个权重一块，f16的d + 32个int8，x = q * d，每个权重8.5位
+// Q4_0: 32个权重一块，f16的d + 16字节（每字节两个4位值），x = (q - 8) * d，每个权重4.5位
+// Q4_K: 256个权重的超级块，分为8个32个权重的子块。超级块有f16的d和dmin，
+//       每个子块有6位的scale和min（共12字节），x = d * scale * q - dmin * min，每个权重4.5位
+// 多字节数值都是小端序。每种格式都需要反量化，以及直接在量化数据上与f32激活做点积（不需要先反量化整行）。
+
+// 辅助函数：f32到f16位模式的转换（舍入到最近值，平局时取偶数）
+// 注意：这个函数与 f16_overflow.rs 中的实现相同，已经提供
+fn f32_to_f16_bits(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xFF) as i32;
+    let man = bits & 0x7FFFFF;
+
+    if exp == 0xFF {
+        if man == 0 {
+            return sign | 0x7C00;
+        }
+        let payload = (man >> 13) as u16;
+        return sign | 0x7C00 | if payload == 0 { 0x200 } else { payload };
+    }
+
+    let e = exp - 127;
+    if e > 15 {
+        return sign | 0x7C00;
+    }
+    if e >= -14 {
+        let half_man = (man >> 13) as u16;
+        let rem = man & 0x1FFF;
+        let half_bits = sign | (((e + 15) as u16) << 10) | half_man;
+        return if rem > 0x1000 || (rem == 0x1000 && (half_man & 1) == 1) {
+            half_bits + 1
+        } else {
+            half_bits
+        };
+    }
+    if e < -25 {
+        return sign;
+    }
+
+    let m = man | 0x800000;
+    let shift = (-e - 1) as u32;
+    let half_man = (m >> shift) as u16;
+    let rem = m & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    if rem > halfway || (rem == halfway && (half_man & 1) == 1) {
+        sign | (half_man + 1)
+    } else {
+        sign | half_man
+    }
+}
+
+// 辅助函数：f16位模式到f32的转换
+// 注意：这个函数与 f16_overflow.rs 中的实现相同，已经提供
+fn f16_bits_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exp = ((bits >> 10) & 0x1F) as i32;
+    let mut frac = (bits & 0x3FF) as u32;
+
+    if exp == 0 {
+        if frac == 0 {
+            return f32::from_bits(sign);
+        }
+        let mut e = -14;
+        while frac & 0x400 == 0 {
+            frac <<= 1;
+            e -= 1;
+        }
+        frac &= 0x3FF;
+        return f32::from_bits(sign | (((e + 127) as u32) << 23) | (frac << 13));
+    } else if exp == 31 {
+        return f32::from_bits(sign | 0x7F800000 | (frac << 13));
+    }
+
+    let adjusted_exp = (exp - 15 + 127) as u32;
+    f32::from_bits(sign | (adjusted_exp << 23) | (frac << 13))
+}
+
+const QK4_0: usize = 32;
+const QK8_0: usize = 32;
+const QK_K: usize = 256;
+const K_SCALE_SIZE: usize = 12;
+
+// 块量化格式，名称与GGML一致
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlockFormat {
+    Q4_0,
+    Q8_0,
+    Q4_K,
+}
+
+impl BlockFormat {
+    // 每块的权重个数
+    fn block_size(self) -> usize {
+        match self {
+            BlockFormat::Q4_0 => QK4_0,
+            BlockFormat::Q8_0 => QK8_0,
+            BlockFormat::Q4_K => QK_K,
+        }
+    }
+
+    // 每块占用的字节数
+    fn type_size(self) -> usize {
+        match self {
+            BlockFormat::Q4_0 => 2 + QK4_0 / 2,
+            BlockFormat::Q8_0 => 2 + QK8_0,
+            BlockFormat::Q4_K => 2 + 2 + K_SCALE_SIZE + QK_K / 2,
+        }
+    }
+
+    fn bits_per_weight(self) -> f32 {
+        (self.type_size() * 8) as f32 / self.block_size() as f32
+    }
+
+    // n个权重占用的字节数，n必须是块大小的整数倍
+    fn row_bytes(self, n: usize) -> usize {
+        assert_eq!(n % self.block_size(), 0, "权重个数必须是块大小的整数倍");
+        n / self.block_size() * self.type_size()
+    }
+}
+
+fn read_f16(bytes: &[u8], offset: usize) -> f32 {
+    f16_bits_to_f32(u16::from_le_bytes([bytes[offset], bytes[offset + 1]]))
+}
+
+// 对于Q4_0和Q8_0，与GGML一样用未舍入的d计算倒数，d本身以f16保存
+fn quantize_row_q4_0(x: &[f32]) -> Vec<u8> {
+    assert_eq!(x.len() % QK4_0, 0, "权重个数必须是32的整数倍");
+    let mut out = Vec::with_capacity(BlockFormat::Q4_0.row_bytes(x.len()));
+
+    for block in x.chunks(QK4_0) {
+        // 绝对值最大的数（保留符号）映射到-8，这样它可以被精确表示
+        let mut amax = 0.0f32;
+        let mut max = 0.0f32;
+        for &v in block {
+            if amax < v.abs() {
+                amax = v.abs();
+                max = v;
+            }
+        }
+        let d = max / -8.0;
+        let id = if d != 0.0 { 1.0 / d } else { 0.0 };
+
+        out.extend_from_slice(&f32_to_f16_bits(d).to_le_bytes());
+        for j in 0..QK4_0 / 2 {
+            // 与C代码中的 (int8_t)(x + 8.5f) 相同：加8.5后向零截断
+            let q0 = ((block[j] * id + 8.5) as u8).min(15);
+            let q1 = ((block[j + QK4_0 / 2] * id + 8.5) as u8).min(15);
+            out.push(q0 | (q1 << 4));
+        }
+    }
+    out
+}
+
+// 每个字节的低4位是块的前16个权重，高4位是后16个权重
+fn dequantize_row_q4_0(bytes: &[u8]) -> Vec<f32> {
+    let type_size = BlockFormat::Q4_0.type_size();
+    assert_eq!(bytes.len() % type_size, 0, "数据长度必须是块大小的整数倍");
+    let mut out = vec![0.0; bytes.len() / type_size * QK4_0];
+
+    for (block, y) in bytes.chunks(type_size).zip(out.chunks_mut(QK4_0)) {
+        let d = read_f16(block, 0);
+        for (j, &q) in block[2..].iter().enumerate() {
+            y[j] = ((q & 0x0F) as i32 - 8) as f32 * d;
+            y[j + QK4_0 / 2] = ((q >> 4) as i32 - 8) as f32 * d;
+        }
+    }
+    out
+}
+
+// 点积：每块内先累加 (q - 8) * x，最后乘以一次d
+fn vec_dot_q4_0(bytes: &[u8], x: &[f32]) -> f32 {
+    let type_size = BlockFormat::Q4_0.type_size();
+    assert_eq!(bytes.len() / type_size * QK4_0, x.len(), "激活长度与权重个数不匹配");
+
+    bytes.chunks(type_size)
+        .zip(x.chunks(QK4_0))
+        .map(|(block, xs)| {
+            let sum: f32 = block[2..].iter()
+                .enumerate()
+                .map(|(j, &q)| {
+                    ((q & 0x0F) as i32 - 8) as f32 * xs[j] + ((q >> 4) as i32 - 8) as f32 * xs[j + QK4_0 / 2]
+                })
+                .sum();
+            read_f16(block, 0) * sum
+        })
+        .sum()
+}
+
+fn quantize_row_q8_0(x: &[f32]) -> Vec<u8> {
+    assert_eq!(x.len() % QK8_0, 0, "权重个数必须是32的整数倍");
+    let mut out = Vec::with_capacity(BlockFormat::Q8_0.row_bytes(x.len()));
+
+    for block in x.chunks(QK8_0) {
+        let amax = block.iter().fold(0.0f32, |a, &v| a.max(v.abs()));
+        let d = amax / 127.0;
+        let id = if d != 0.0 { 1.0 / d } else { 0.0 };
+
+        out.extend_from_slice(&f32_to_f16_bits(d).to_le_bytes());
+        // f32::round与C的roundf一样，平局时远离0
+        out.extend(block.iter().map(|&v| ((v * id).round() as i8) as u8));
+    }
+    out
+}
+
+fn dequantize_row_q8_0(bytes: &[u8]) -> Vec<f32> {
+    let type_size = BlockFormat::Q8_0.type_size();
+    assert_eq!(bytes.len() % type_size, 0, "数据长度必须是块大小的整数倍");
+
+    bytes.chunks(type_size)
+        .flat_map(|block| {
+            let d = read_f16(block, 0);
+            block[2..].iter().map(move |&q| q as i8 as f32 * d)
+        })
+        .collect()
+}
+
+fn vec_dot_q8_0(bytes: &[u8], x: &[f32]) -> f32 {
+    let type_size = BlockFormat::Q8_0.type_size();
+    assert_eq!(bytes.len() / type_size * QK8_0, x.len(), "激活长度与权重个数不匹配");
+
+    bytes.chunks(type_size)
+        .zip(x.chunks(QK8_0))
+        .map(|(block, xs)| {
+            let sum: f32 = block[2..].iter().zip(xs).map(|(&q, &v)| q as i8 as f32 * v).sum();
+            read_f16(block, 0) * sum
+        })
+        .sum()
+}
+
+// 从12字节中取出第j个子块的6位scale和min（与GGML的get_scale_min_k4相同）
+// 前4个子块：scales[j]和scales[j + 4]的低6位
+// 后4个子块：低4位在scales[j + 4]的低/高半字节中，高2位在scales[j - 4]和scales[j]的最高2位中
+fn get_scale_min_k4(j: usize, scales: &[u8]) -> (u8, u8) {
+    if j < 4 {
+        (scales[j] & 63, scales[j + 4] & 63)
+    } else {
+        let sc = (scales[j + 4] & 0x0F) | ((scales[j - 4] >> 6) << 4);
+        let m = (scales[j + 4] >> 4) | ((scales[j] >> 6) << 4);
+        (sc, m)
+    }
+}
+
+// get_scale_min_k4的逆操作
+fn pack_scale_min_k4(sc: &[u8; 8], m: &[u8; 8]) -> [u8; K_SCALE_SIZE] {
+    let mut scales = [0u8; K_SCALE_SIZE];
+    for j in 0..8 {
+        assert!(sc[j] < 64 && m[j] < 64, "scale和min只有6位");
+        if j < 4 {
+            scales[j] = sc[j];
+            scales[j + 4] = m[j];
+        } else {
+            scales[j + 4] = (sc[j] & 0x0F) | ((m[j] & 0x0F) << 4);
+            scales[j - 4] |= (sc[j] >> 4) << 6;
+            scales[j] |= (m[j] >> 4) << 6;
+        }
+    }
+    scales
+}
+
+// Q4_K的量化
+// GGML会对每个子块迭代搜索最优的scale和min，这里使用直接的最小/最大值：
+// 子块的范围 [min(x, 0), max(x)] 映射到 [0, 15]，子块的scale和-min再分别用超级块的d和dmin量化为6位。
+// 输出的字节布局与GGML完全相同，可以被llama.cpp读取。
+fn quantize_row_q4_k(x: &[f32]) -> Vec<u8> {
+    assert_eq!(x.len() % QK_K, 0, "权重个数必须是256的整数倍");
+    let mut out = Vec::with_capacity(BlockFormat::Q4_K.row_bytes(x.len()));
+
+    for block in x.chunks(QK_K) {
+        let mut sub_scales = [0.0f32; 8];
+        let mut sub_mins = [0.0f32; 8];
+        for (j, sub) in block.chunks(32).enumerate() {
+            let min = sub.iter().fold(0.0f32, |a, &v| a.min(v));
+            let max = sub.iter().fold(f32::NEG_INFINITY, |a, &v| a.max(v));
+            sub_scales[j] = (max - min) / 15.0;
+            sub_mins[j] = -min;
+        }
+
+        let max_scale = sub_scales.iter().fold(0.0f32, |a, &v| a.max(v));
+        let max_min = sub_mins.iter().fold(0.0f32, |a, &v| a.max(v));
+        let inv_scale = if max_scale > 0.0 { 63.0 / max_scale } else { 0.0 };
+        let inv_min = if max_min > 0.0 { 63.0 / max_min } else { 0.0 };
+
+        let mut sc = [0u8; 8];
+        let mut m = [0u8; 8];
+        for j in 0..8 {
+            sc[j] = (inv_scale * sub_scales[j]).round().min(63.0) as u8;
+            m[j] = (inv_min * sub_mins[j]).round().min(63.0) as u8;
+        }
+        let d_bits = f32_to_f16_bits(max_scale / 63.0);
+        let dmin_bits = f32_to_f16_bits(max_min / 63.0);
+        let scales = pack_scale_min_k4(&sc, &m);
+
+        // 使用f16舍入之后的d和dmin计算4位的值，与反量化时完全一致
+        let d = f16_bits_to_f32(d_bits);
+        let dmin = f16_bits_to_f32(dmin_bits);
+        let mut levels = [0u8; QK_K];
+        for (j, sub) in block.chunks(32).enumerate() {
+            let (sub_sc, sub_m) = get_scale_min_k4(j, &scales);
+            let dq = d * sub_sc as f32;
+            let dm = dmin * sub_m as f32;
+            for (l, &v) in sub.iter().enumerate() {
+                levels[32 * j + l] = if dq > 0.0 {
+                    ((v + dm) / dq).round().clamp(0.0, 15.0) as u8
+                } else {
+                    0
+                };
+            }
+        }
+
+        out.extend_from_slice(&d_bits.to_le_bytes());
+        out.extend_from_slice(&dmin_bits.to_le_bytes());
+        out.extend_from_slice(&scales);
+        // 每64个权重占32字节：低4位是前32个权重，高4位是后32个权重
+        for chunk in levels.chunks(64) {
+            out.extend((0..32).map(|l| chunk[l] | (chunk[l + 32] << 4)));
+        }
+    }
+    out
+}
+
+fn dequantize_row_q4_k(bytes: &[u8]) -> Vec<f32> {
+    let type_size = BlockFormat::Q4_K.type_size();
+    assert_eq!(bytes.len() % type_size, 0, "数据长度必须是块大小的整数倍");
+    let mut out = Vec::with_capacity(bytes.len() / type_size * QK_K);
+
+    for block in bytes.chunks(type_size) {
+        let d = read_f16(block, 0);
+        let dmin = read_f16(block, 2);
+        let scales = &block[4..4 + K_SCALE_SIZE];
+        let qs = &block[4 + K_SCALE_SIZE..];
+
+        for (chunk, q) in qs.chunks(32).enumerate() {
+            let (sc1, m1) = get_scale_min_k4(2 * chunk, scales);
+            let (sc2, m2) = get_scale_min_k4(2 * chunk + 1, scales);
+            let (d1, min1) = (d * sc1 as f32, dmin * m1 as f32);
+            let (d2, min2) = (d * sc2 as f32, dmin * m2 as f32);
+            out.extend(q.iter().map(|&b| d1 * (b & 0x0F) as f32 - min1));
+            out.extend(q.iter().map(|&b| d2 * (b >> 4) as f32 - min2));
+        }
+    }
+    out
+}
+
+// 点积：每个子块内 sum((d1 * q - m1) * x) = d1 * sum(q * x) - m1 * sum(x)
+fn vec_dot_q4_k(bytes: &[u8], x: &[f32]) -> f32 {
+    let type_size = BlockFormat::Q4_K.type_size();
+    assert_eq!(bytes.len() / type_size * QK_K, x.len(), "激活长度与权重个数不匹配");
+
+    bytes.chunks(type_size)
+        .zip(x.chunks(QK_K))
+        .map(|(block, xs)| {
+            let d = read_f16(block, 0);
+            let dmin = read_f16(block, 2);
+            let scales = &block[4..4 + K_SCALE_SIZE];
+            let qs = &block[4 + K_SCALE_SIZE..];
+
+            let mut total = 0.0f32;
+            for (chunk, (q, x64)) in qs.chunks(32).zip(xs.chunks(64)).enumerate() {
+                let (lo_x, hi_x) = x64.split_at(32);
+                for (j, half_x, shift) in [(2 * chunk, lo_x, 0), (2 * chunk + 1, hi_x, 4)] {
+                    let (sc, m) = get_scale_min_k4(j, scales);
+                    let qx: f32 = q.iter().zip(half_x).map(|(&b, &v)| ((b >> shift) & 0x0F) as f32 * v).sum();
+                    let sx: f32 = half_x.iter().sum();
+                    total += d * sc as f32 * qx - dmin * m as f32 * sx;
+                }
+            }
+            total
+        })
+        .sum()
+}
+
+fn quantize_row(format: BlockFormat, x: &[f32]) -> Vec<u8> {
+    match format {
+        BlockFormat::Q4_0 => quantize_row_q4_0(x),
+        BlockFormat::Q8_0 => quantize_row_q8_0(x),
+        BlockFormat::Q4_K => quantize_row_q4_k(x),
+    }
+}
+
+fn dequantize_row(format: BlockFormat, bytes: &[u8]) -> Vec<f32> {
+    match format {
+        BlockFormat::Q4_0 => dequantize_row_q4_0(bytes),
+        BlockFormat::Q8_0 => dequantize_row_q8_0(bytes),
+        BlockFormat::Q4_K => dequantize_row_q4_k(bytes),
+    }
+}
+
+fn vec_dot(format: BlockFormat, bytes: &[u8], x: &[f32]) -> f32 {
+    match format {
+        BlockFormat::Q4_0 => vec_dot_q4_0(bytes, x),
+        BlockFormat::Q8_0 => vec_dot_q8_0(bytes, x),
+        BlockFormat::Q4_K => vec_dot_q4_k(bytes, x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const FORMATS: [BlockFormat; 3] = [BlockFormat::Q4_0, BlockFormat::Q8_0, BlockFormat::Q4_K];
+
+    fn relative_rmse(reference: &[f32], result: &[f32]) -> f32 {
+        let err: f32 = reference.iter().zip(result).map(|(a, b)| (a - b) * (a - b)).sum();
+        let norm: f32 = reference.iter().map(|a| a * a).sum();
+        (err / norm).sqrt()
+    }
+
+    #[test]
+    fn test_block_sizes() {
+        assert_eq!(BlockFormat::Q4_0.type_size(), 18);
+        assert_eq!(BlockFormat::Q8_0.type_size(), 34);
+        assert_eq!(BlockFormat::Q4_K.type_size(), 144);
+        assert_eq!(BlockFormat::Q4_0.bits_per_weight(), 4.5);
+        assert_eq!(BlockFormat::Q8_0.bits_per_weight(), 8.5);
+        assert_eq!(BlockFormat::Q4_K.bits_per_weight(), 4.5);
+        assert_eq!(BlockFormat::Q4_K.row_bytes(4096), 16 * 144);
+    }
+
+    #[test]
+    fn test_q4_0_known_block() {
+        // d = 0.5 (f16 0x3800)
+        let mut block = vec![0x00, 0x38];
+        block.extend_from_slice(&[0x88; 16]);
+        block[2] = 0x9F; // 第0个: 15 - 8 = 7，第16个: 9 - 8 = 1
+        block[3] = 0x07; // 第1个: 7 - 8 = -1，第17个: 0 - 8 = -8
+        let y = dequantize_row_q4_0(&block);
+        assert_eq!(y.len(), 32);
+        assert_eq!((y[0], y[16]), (3.5, 0.5));
+        assert_eq!((y[1], y[17]), (-0.5, -4.0));
+        assert!(y.iter().enumerate().filter(|&(i, _)| ![0, 1, 16, 17].contains(&i)).all(|(_, &v)| v == 0.0));
+    }
+
+    #[test]
+    fn test_q4_0_known_quantization() {
+        // 前16个和后16个都是 -8..=7，绝对值最大的是-8，所以d = 1，q = x + 8
+        let x: Vec<f32> = (0..32).map(|i| (i % 16) as f32 - 8.0).collect();
+        let bytes = quantize_row_q4_0(&x);
+        let mut expected = vec![0x00, 0x3C];
+        expected.extend((0..16u8).map(|j| j | (j << 4)));
+        assert_eq!(bytes, expected);
+        assert_eq!(dequantize_row_q4_0(&bytes), x);
+
+        // 绝对值最大的数为正数时，d为负数
+        let mut y = vec![0.0f32; 32];
+        y[5] = 4.0;
+        let bytes = quantize_row_q4_0(&y);
+        assert_eq!(read_f16(&bytes, 0), -0.5);
+        assert_eq!(bytes[2 + 5] & 0x0F, 0, "4.0 = (0 - 8) * -0.5");
+        assert_eq!(dequantize_row_q4_0(&bytes), y);
+    }
+
+    #[test]
+    fn test_q8_0_known_block() {
+        // d = 0.5，qs = [-128, 1, 127, -1, 0, ...]
+        let mut block = vec![0x00, 0x38, 0x80, 0x01, 0x7F, 0xFF];
+        block.extend_from_slice(&[0; 28]);
+        let y = dequantize_row_q8_0(&block);
+        assert_eq!(&y[..5], &[-64.0, 0.5, 63.5, -0.5, 0.0]);
+
+        // 0..32 的最大值31映射到127
+        let x: Vec<f32> = (0..32).map(|i| i as f32).collect();
+        let bytes = quantize_row_q8_0(&x);
+        assert_eq!(u16::from_le_bytes([bytes[0], bytes[1]]), f32_to_f16_bits(31.0 / 127.0));
+        assert_eq!(bytes[2 + 31], 127);
+        assert_eq!(bytes[2 + 1], 4, "1 / (31 / 127) = 4.097，舍入为4");
+    }
+
+    #[test]
+    fn test_q4_k_scale_packing() {
+        let sc = [1, 2, 3, 4, 44, 45, 46, 47];
+        let m = [0, 1, 2, 3, 24, 25, 26, 27];
+        let packed = pack_scale_min_k4(&sc, &m);
+        assert_eq!(packed, [0x81, 0x82, 0x83, 0x84, 0x40, 0x41, 0x42, 0x43, 0x8C, 0x9D, 0xAE, 0xBF]);
+        for j in 0..8 {
+            assert_eq!(get_scale_min_k4(j, &packed), (sc[j], m[j]));
+        }
+    }
+
+    #[test]
+    fn test_q4_k_known_block() {
+        // d = 1.0, dmin = 0.5，子块的scale和min与test_q4_k_scale_packing相同
+        let mut block = vec![0x00, 0x3C, 0x00, 0x38];
+        block.extend_from_slice(&[0x81, 0x82, 0x83, 0x84, 0x40, 0x41, 0x42, 0x43, 0x8C, 0x9D, 0xAE, 0xBF]);
+        block.extend_from_slice(&[0; 128]);
+        block[16] = 0x21; // 第0个权重: q = 1 (子块0)，第32个权重: q = 2 (子块1)
+        block[16 + 96] = 0xF0; // 第192个权重: q = 0 (子块6)，第224个权重: q = 15 (子块7)
+        let y = dequantize_row_q4_k(&block);
+        assert_eq!(y.len(), 256);
+        assert_eq!(y[0], 1.0, "1 * 1 * 1 - 0.5 * 0");
+        assert_eq!(y[32], 3.5, "1 * 2 * 2 - 0.5 * 1");
+        assert_eq!(y[192], -13.0, "1 * 46 * 0 - 0.5 * 26");
+        assert_eq!(y[224], 691.5, "1 * 47 * 15 - 0.5 * 27");
+        // 其它q = 0的权重都等于 -dmin * min
+        assert_eq!(y[1], 0.0);
+        assert_eq!(y[33], -0.5);
+        assert_eq!(y[160], -12.5, "子块5: -0.5 * 25");
+        assert_eq!(y[255], -13.5);
+    }
+
+    #[test]
+    fn test_round_trip_error() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let x: Vec<f32> = (0..4096).map(|_| rng.gen_range(-1.0f32..1.0) * 0.05).collect();
+
+        for (format, tolerance) in [(BlockFormat::Q4_0, 0.12), (BlockFormat::Q8_0, 0.01), (BlockFormat::Q4_K, 0.12)] {
+            let bytes = quantize_row(format, &x);
+            assert_eq!(bytes.len(), format.row_bytes(x.len()));
+            let y = dequantize_row(format, &bytes);
+            let err = relative_rmse(&x, &y);
+            assert!(err < tolerance, "{:?}: 相对误差{}", format, err);
+        }
+
+        // Q8_0每个元素的误差不超过半个d（再加上d本身的f16舍入误差）
+        let y = dequantize_row_q8_0(&quantize_row_q8_0(&x));
+        for (block_x, block_y) in x.chunks(32).zip(y.chunks(32)) {
+            let d = block_x.iter().fold(0.0f32, |a, &v| a.max(v.abs())) / 127.0;
+            for (a, b) in block_x.iter().zip(block_y) {
+                assert!((a - b).abs() <= d * 0.5 + a.abs() * 1e-3, "{} vs {}", a, b);
+            }
+        }
+
+        // 全为0的块
+        for format in FORMATS {
+            let zeros = vec![0.0; format.block_size()];
+            assert_eq!(dequantize_row(format, &quantize_row(format, &zeros)), zeros);
+        }
+    }
+
+    #[test]
+    fn test_q4_k_non_negative_block() {
+        // 全为正数的子块：min为0，范围从0开始
+        let x: Vec<f32> = (0..256).map(|i| (i % 32) as f32 * 0.1).collect();
+        let y = dequantize_row_q4_k(&quantize_row_q4_k(&x));
+        let err = relative_rmse(&x, &y);
+        assert!(err < 0.05, "相对误差{}", err);
+        assert_eq!(y[0], 0.0);
+    }
+
+    #[test]
+    fn test_vec_dot_matches_dequantized() {
+        let mut rng = StdRng::seed_from_u64(10);
+        let w: Vec<f32> = (0..1024).map(|_| rng.gen_range(-1.0f32..1.0)).collect();
+        let x: Vec<f32> = (0..1024).map(|_| rng.gen_range(-1.0f32..1.0)).collect();
+        let exact: f32 = w.iter().zip(&x).map(|(a, b)| a * b).sum();
+
+        for format in FORMATS {
+            let bytes = quantize_row(format, &w);
+            let fused = vec_dot(format, &bytes, &x);
+            let reference: f32 = dequantize_row(format, &bytes).iter().zip(&x).map(|(a, b)| a * b).sum();
+            assert!((fused - reference).abs() <= 1e-3 * reference.abs().max(1.0),
+                    "{:?}: 融合点积{}与反量化后的点积{}不一致", format, fused, reference);
+            let norm: f32 = w.iter().map(|v| v * v).sum::<f32>().sqrt() * x.iter().map(|v| v * v).sum::<f32>().sqrt();
+            assert!((fused - exact).abs() < 0.05 * norm, "{:?}: {} vs {}", format, fused, exact);
+        }
+    }
+}
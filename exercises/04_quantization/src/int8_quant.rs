@@ -0,0 +1,589 @@
+// 除了降低浮点精度，大模型部署中更常用的是整数量化：用int8保存权重和激活，再用一个缩放因子还原为实数。
+// 在这个练习中，我们将实现int8量化：
+// - 对称量化：x ≈ q * scale，q ∈ [-127, 127]，零点为0
+// - 非对称量化：x ≈ (q - zero_point) * scale，q ∈ [-128, 127]，适合全为正数的激活（例如ReLU之后）
+// - 量化粒度：整个张量共用一组参数、每个输出通道（每行）一组、或每行中每group_size个元素一组
+// - 校准：用最小/最大值确定范围，或者用百分位数裁剪掉离群值
+// 最后实现int8 x int8 -> i32的矩阵乘法，替代f32的batch_matmul，并统计与f32结果的误差。
+
+use std::fmt;
+
+// 4D张量的简写，形状为 [batch_size, num_heads, rows, cols]
+type Tensor4D = Vec<Vec<Vec<Vec<f32>>>>;
+
+// 辅助函数：f32的批量矩阵乘法，key已经转置，scores[i][j] = sum_d query[i][d] * key[d][j]
+// 注意：这个函数与 multi_head_attention.rs 中的实现相同，已经提供
+fn batch_matmul(
+    query: &[Vec<Vec<Vec<f32>>>],
+    key: &[Vec<Vec<Vec<f32>>>]
+) -> Vec<Vec<Vec<Vec<f32>>>> {
+    let batch_size = query.len();
+    let num_heads = query[0].len();
+    let seq_len_q = query[0][0].len();
+    let head_size = query[0][0][0].len();
+    let seq_len_k = key[0][0][0].len();
+
+    assert_eq!(head_size, key[0][0].len(), "Head size mismatch");
+
+    (0..batch_size)
+        .map(|b| {
+            (0..num_heads)
+                .map(|h| {
+                    let q = &query[b][h];
+                    let k = &key[b][h];
+
+                    (0..seq_len_q)
+                        .map(|i| {
+                            (0..seq_len_k)
+                                .map(|j| {
+                                    // key已经转置，第j列是第j个key向量
+                                    q[i].iter()
+                                        .zip(k.iter())
+                                        .map(|(&a, k_row)| a * k_row[j])
+                                        .sum::<f32>()
+                                })
+                                .collect::<Vec<f32>>()
+                        })
+                        .collect::<Vec<Vec<f32>>>()
+                })
+                .collect::<Vec<Vec<Vec<f32>>>>()
+        })
+        .collect()
+}
+
+// 量化方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QuantScheme {
+    Symmetric,
+    Asymmetric,
+}
+
+// 量化粒度
+// Tensor:  整个张量一组参数（per-tensor）
+// Channel: 每个输出通道（矩阵的每一行）一组参数（per-channel）
+// Group:   每行中每group_size个连续元素一组参数（per-group），常用128
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Granularity {
+    Tensor,
+    Channel,
+    Group(usize),
+}
+
+// 校准方法
+// MinMax:         使用最小值和最大值，所有值都不会被裁剪，但一个离群值就会让其它值的精度变差
+// Percentile(p):  对称量化使用|x|的第p百分位数作为范围，非对称量化使用第(100-p)和第p百分位数
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Calibration {
+    MinMax,
+    Percentile(f32),
+}
+
+// 一组量化参数
+// 记录量化方式而不是根据zero_point推断：非对称量化的零点也可能恰好为0，这时仍然可以使用-128
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct QuantParams {
+    scale: f32,
+    zero_point: i32,
+    scheme: QuantScheme,
+}
+
+impl QuantParams {
+    // 对称量化：[-amax, amax] 映射到 [-127, 127]
+    // 不使用-128，这样正负范围对称，也避免 -128 * -128 在累加时多出来的一个单位
+    fn symmetric(amax: f32) -> Self {
+        let scale = if amax > 0.0 { amax / 127.0 } else { 1.0 };
+        QuantParams { scale, zero_point: 0, scheme: QuantScheme::Symmetric }
+    }
+
+    // 非对称量化：[min, max] 映射到 [-128, 127]
+    // 范围总是包含0，并且0可以被精确表示（padding和ReLU的输出中有大量的0）
+    fn asymmetric(min: f32, max: f32) -> Self {
+        let (min, max) = (min.min(0.0), max.max(0.0));
+        if max == min {
+            return QuantParams { scale: 1.0, zero_point: 0, scheme: QuantScheme::Asymmetric };
+        }
+        let scale = (max - min) / 255.0;
+        let zero_point = (-128.0 - min / scale).round().clamp(-128.0, 127.0) as i32;
+        QuantParams { scale, zero_point, scheme: QuantScheme::Asymmetric }
+    }
+
+    fn quantize(&self, x: f32) -> i8 {
+        let q = (x / self.scale).round() as i32 + self.zero_point;
+        let min = match self.scheme {
+            QuantScheme::Symmetric => -127,
+            QuantScheme::Asymmetric => -128,
+        };
+        q.clamp(min, 127) as i8
+    }
+
+    fn dequantize(&self, q: i8) -> f32 {
+        (q as i32 - self.zero_point) as f32 * self.scale
+    }
+}
+
+// 已排序数据的第p百分位数（p ∈ [0, 100]），相邻两个值之间线性插值
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    assert!(!sorted.is_empty(), "数据不能为空");
+    assert!((0.0..=100.0).contains(&p), "百分位数必须在[0, 100]范围内");
+    let pos = p / 100.0 * (sorted.len() - 1) as f32;
+    let lo = pos.floor() as usize;
+    let hi = pos.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (pos - lo as f32)
+}
+
+// 根据一组数据计算量化参数
+fn calibrate(values: &[f32], scheme: QuantScheme, calibration: Calibration) -> QuantParams {
+    match (scheme, calibration) {
+        (QuantScheme::Symmetric, Calibration::MinMax) => {
+            QuantParams::symmetric(values.iter().fold(0.0f32, |a, &v| a.max(v.abs())))
+        }
+        (QuantScheme::Symmetric, Calibration::Percentile(p)) => {
+            let mut abs: Vec<f32> = values.iter().map(|v| v.abs()).collect();
+            abs.sort_by(|a, b| a.total_cmp(b));
+            QuantParams::symmetric(percentile(&abs, p))
+        }
+        (QuantScheme::Asymmetric, Calibration::MinMax) => {
+            let min = values.iter().fold(f32::INFINITY, |a, &v| a.min(v));
+            let max = values.iter().fold(f32::NEG_INFINITY, |a, &v| a.max(v));
+            QuantParams::asymmetric(min, max)
+        }
+        (QuantScheme::Asymmetric, Calibration::Percentile(p)) => {
+            let mut sorted = values.to_vec();
+            sorted.sort_by(|a, b| a.total_cmp(b));
+            QuantParams::asymmetric(percentile(&sorted, 100.0 - p), percentile(&sorted, p))
+        }
+    }
+}
+
+// 量化后的2D张量（形状为 [rows, cols] 的矩阵，行是输出通道）
+// params按行优先排列：Tensor只有1组，Channel每行1组，Group每行 ceil(cols / group_size) 组
+#[derive(Debug, Clone, PartialEq)]
+struct QuantizedTensor {
+    data: Vec<i8>,
+    rows: usize,
+    cols: usize,
+    granularity: Granularity,
+    params: Vec<QuantParams>,
+}
+
+impl QuantizedTensor {
+    fn quantize(
+        x: &[Vec<f32>],
+        scheme: QuantScheme,
+        granularity: Granularity,
+        calibration: Calibration,
+    ) -> Self {
+        let rows = x.len();
+        let cols = x[0].len();
+        assert!(x.iter().all(|r| r.len() == cols), "每行的长度必须相同");
+        if let Granularity::Group(g) = granularity {
+            assert!(g > 0, "group_size必须大于0");
+        }
+
+        let params = match granularity {
+            Granularity::Tensor => {
+                let all: Vec<f32> = x.iter().flatten().copied().collect();
+                vec![calibrate(&all, scheme, calibration)]
+            }
+            Granularity::Channel => x.iter().map(|r| calibrate(r, scheme, calibration)).collect(),
+            Granularity::Group(g) => x.iter()
+                .flat_map(|r| r.chunks(g).map(|c| calibrate(c, scheme, calibration)))
+                .collect(),
+        };
+
+        let mut tensor = QuantizedTensor { data: Vec::with_capacity(rows * cols), rows, cols, granularity, params };
+        for (i, row) in x.iter().enumerate() {
+            for (j, &v) in row.iter().enumerate() {
+                let q = tensor.params_at(i, j).quantize(v);
+                tensor.data.push(q);
+            }
+        }
+        tensor
+    }
+
+    // 同一组参数覆盖的连续元素个数
+    fn group_size(&self) -> usize {
+        match self.granularity {
+            Granularity::Tensor | Granularity::Channel => self.cols,
+            Granularity::Group(g) => g.min(self.cols),
+        }
+    }
+
+    // 第row行第col列元素使用的量化参数
+    fn params_at(&self, row: usize, col: usize) -> &QuantParams {
+        match self.granularity {
+            Granularity::Tensor => &self.params[0],
+            Granularity::Channel => &self.params[row],
+            Granularity::Group(g) => &self.params[row * self.cols.div_ceil(g) + col / g],
+        }
+    }
+
+    fn get(&self, row: usize, col: usize) -> i8 {
+        self.data[row * self.cols + col]
+    }
+
+    fn dequantize(&self) -> Vec<Vec<f32>> {
+        (0..self.rows)
+            .map(|i| (0..self.cols).map(|j| self.params_at(i, j).dequantize(self.get(i, j))).collect())
+            .collect()
+    }
+
+    // 数据和参数占用的字节数（每组参数为f32的scale和i32的zero_point）
+    fn memory_bytes(&self) -> usize {
+        self.data.len() + self.params.len() * 8
+    }
+}
+
+// int8矩阵乘法：out[i][j] = sum_k a[i][k] * b[j][k]，a形状 [m, k]，b形状 [n, k]
+// 在每组参数覆盖的范围内用i32精确累加 (qa - za) * (qb - zb)，再乘以两组的scale转换为f32
+// 两个int8之差最多255，乘积最多65025，i32可以累加三万多项而不溢出
+fn int8_matmul(a: &QuantizedTensor, b: &QuantizedTensor) -> Vec<Vec<f32>> {
+    assert_eq!(a.cols, b.cols, "内积维度不匹配");
+    assert_eq!(a.group_size(), b.group_size(), "两个矩阵的分组必须对齐");
+    let group = a.group_size();
+
+    (0..a.rows)
+        .map(|i| {
+            (0..b.rows)
+                .map(|j| {
+                    (0..a.cols).step_by(group)
+                        .map(|start| {
+                            let pa = a.params_at(i, start);
+                            let pb = b.params_at(j, start);
+                            let end = (start + group).min(a.cols);
+                            let acc: i32 = (start..end)
+                                .map(|k| (a.get(i, k) as i32 - pa.zero_point) * (b.get(j, k) as i32 - pb.zero_point))
+                                .sum();
+                            acc as f32 * pa.scale * pb.scale
+                        })
+                        .sum::<f32>()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// 矩阵转置，[rows, cols] -> [cols, rows]
+fn transpose(m: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    let cols = m.first().map_or(0, |r| r.len());
+    (0..cols).map(|j| m.iter().map(|r| r[j]).collect()).collect()
+}
+
+// 用int8矩阵乘法代替batch_matmul，输入形状与batch_matmul相同：
+// query形状: [batch_size, num_heads, seq_len_q, head_size]
+// key形状:   [batch_size, num_heads, head_size, seq_len_k]
+// int8_matmul要求两个矩阵都按内积维度存放，所以key先转置回 [seq_len_k, head_size]，
+// 这样per-channel时每个key向量各有一组参数
+// 对每个(batch, head)，query和key分别按给定的方式动态量化（每次调用时根据当前数据校准）
+fn int8_batch_matmul(
+    query: &[Vec<Vec<Vec<f32>>>],
+    key: &[Vec<Vec<Vec<f32>>>],
+    scheme: QuantScheme,
+    granularity: Granularity,
+) -> Tensor4D {
+    query.iter()
+        .zip(key.iter())
+        .map(|(qb, kb)| {
+            qb.iter()
+                .zip(kb.iter())
+                .map(|(q, k)| {
+                    let qq = QuantizedTensor::quantize(q, scheme, granularity, Calibration::MinMax);
+                    let qk = QuantizedTensor::quantize(&transpose(k), scheme, granularity, Calibration::MinMax);
+                    int8_matmul(&qq, &qk)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// 量化结果与f32结果的误差
+#[derive(Debug, Clone, PartialEq)]
+struct QuantErrorReport {
+    max_abs_error: f32,
+    mean_abs_error: f32,
+    relative_error: f32, // ||result - reference|| / ||reference||（所有元素的L2范数）
+}
+
+impl QuantErrorReport {
+    fn new(reference: &[f32], result: &[f32]) -> Self {
+        assert_eq!(reference.len(), result.len(), "结果长度不匹配");
+        let mut max_abs = 0.0f64;
+        let mut sum_abs = 0.0f64;
+        let mut err_sq = 0.0f64;
+        let mut ref_sq = 0.0f64;
+        for (&r, &x) in reference.iter().zip(result.iter()) {
+            let e = (r as f64 - x as f64).abs();
+            max_abs = max_abs.max(e);
+            sum_abs += e;
+            err_sq += e * e;
+            ref_sq += (r as f64) * (r as f64);
+        }
+        QuantErrorReport {
+            max_abs_error: max_abs as f32,
+            mean_abs_error: if reference.is_empty() { 0.0 } else { (sum_abs / reference.len() as f64) as f32 },
+            relative_error: if ref_sq > 0.0 { (err_sq / ref_sq).sqrt() as f32 } else { err_sq.sqrt() as f32 },
+        }
+    }
+}
+
+impl fmt::Display for QuantErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "max_abs_error={:.3e} mean_abs_error={:.3e} relative_error={:.3e}",
+            self.max_abs_error, self.mean_abs_error, self.relative_error
+        )
+    }
+}
+
+// 比较int8_batch_matmul与f32的batch_matmul
+fn int8_batch_matmul_error(
+    query: &[Vec<Vec<Vec<f32>>>],
+    key: &[Vec<Vec<Vec<f32>>>],
+    scheme: QuantScheme,
+    granularity: Granularity,
+) -> QuantErrorReport {
+    let reference: Vec<f32> = batch_matmul(query, key).into_iter().flatten().flatten().flatten().collect();
+    let result: Vec<f32> = int8_batch_matmul(query, key, scheme, granularity)
+        .into_iter().flatten().flatten().flatten().collect();
+    QuantErrorReport::new(&reference, &result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_matrix(rng: &mut StdRng, rows: usize, cols: usize, range: f32) -> Vec<Vec<f32>> {
+        (0..rows).map(|_| (0..cols).map(|_| rng.gen_range(-range..range)).collect()).collect()
+    }
+
+    fn flatten(x: &[Vec<f32>]) -> Vec<f32> {
+        x.iter().flatten().copied().collect()
+    }
+
+    fn f32_matmul(a: &[Vec<f32>], b: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        a.iter()
+            .map(|ra| b.iter().map(|rb| ra.iter().zip(rb).map(|(x, y)| x * y).sum()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_symmetric_params() {
+        let p = QuantParams::symmetric(1.27);
+        assert_eq!(p.zero_point, 0);
+        assert!((p.scale - 0.01).abs() < 1e-7);
+        assert_eq!(p.quantize(0.5), 50);
+        assert_eq!(p.quantize(-1.27), -127);
+        assert_eq!(p.quantize(2.0), 127, "超出范围的值被截断");
+        assert_eq!(p.quantize(-2.0), -127, "对称量化不使用-128");
+        assert!((p.dequantize(50) - 0.5).abs() < 1e-6);
+
+        // 全为0时不能除以0
+        let zero = QuantParams::symmetric(0.0);
+        assert_eq!(zero.quantize(0.0), 0);
+        assert_eq!(zero.dequantize(0), 0.0);
+    }
+
+    #[test]
+    fn test_asymmetric_params() {
+        // 全为正数的激活：[0, 2.55] 映射到 [-128, 127]
+        let p = QuantParams::asymmetric(0.0, 2.55);
+        assert!((p.scale - 0.01).abs() < 1e-7);
+        assert_eq!(p.zero_point, -128);
+        assert_eq!(p.quantize(0.0), -128);
+        assert_eq!(p.quantize(2.55), 127);
+        assert_eq!(p.dequantize(p.quantize(0.0)), 0.0, "0必须被精确表示");
+
+        // 范围不包含0时会扩展到包含0
+        let p = QuantParams::asymmetric(1.0, 3.0);
+        assert_eq!(p.dequantize(p.quantize(0.0)), 0.0);
+        assert!((p.dequantize(p.quantize(3.0)) - 3.0).abs() <= p.scale / 2.0);
+
+        // 零点恰好为0的非对称量化仍然使用-128这个量化值
+        let p = QuantParams::asymmetric(-128.0, 127.0);
+        assert_eq!((p.scale, p.zero_point), (1.0, 0));
+        assert_eq!(p.quantize(-128.0), -128);
+        assert_eq!(p.dequantize(p.quantize(-128.0)), -128.0);
+
+        // 非对称范围：[-1, 3]
+        let p = QuantParams::asymmetric(-1.0, 3.0);
+        for x in [-1.0, -0.3, 0.0, 1.7, 3.0] {
+            assert!((p.dequantize(p.quantize(x)) - x).abs() <= p.scale / 2.0 + 1e-6, "{}的量化误差应该不超过半个scale", x);
+        }
+    }
+
+    #[test]
+    fn test_percentile() {
+        let sorted = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 50.0), 3.0);
+        assert_eq!(percentile(&sorted, 100.0), 5.0);
+        assert_eq!(percentile(&sorted, 62.5), 3.5);
+    }
+
+    #[test]
+    fn test_granularity_layout() {
+        let x = vec![vec![1.0, -2.0, 3.0, 0.5, 0.25], vec![100.0, 50.0, -25.0, 10.0, 1.0]];
+        let t = QuantizedTensor::quantize(&x, QuantScheme::Symmetric, Granularity::Tensor, Calibration::MinMax);
+        assert_eq!(t.params.len(), 1);
+        let c = QuantizedTensor::quantize(&x, QuantScheme::Symmetric, Granularity::Channel, Calibration::MinMax);
+        assert_eq!(c.params.len(), 2);
+        assert_eq!(c.get(0, 2), 127, "每行的最大绝对值映射到127");
+        assert_eq!(c.get(1, 0), 127);
+        let g = QuantizedTensor::quantize(&x, QuantScheme::Symmetric, Granularity::Group(2), Calibration::MinMax);
+        assert_eq!(g.params.len(), 6, "每行5个元素分为3组，最后一组只有1个元素");
+        assert_eq!(g.params_at(1, 4), &g.params[5]);
+        assert_eq!(g.get(0, 4), 127);
+        assert_eq!(g.memory_bytes(), 10 + 6 * 8);
+    }
+
+    #[test]
+    fn test_finer_granularity_reduces_error() {
+        let mut rng = StdRng::seed_from_u64(1);
+        // 每行的数值范围相差很大，这在真实权重中很常见
+        let mut x = random_matrix(&mut rng, 8, 256, 1.0);
+        for (i, row) in x.iter_mut().enumerate() {
+            let s = 10f32.powi(i as i32 % 4 - 2);
+            row.iter_mut().for_each(|v| *v *= s);
+        }
+        // 第0行的第一个组里有一个离群值
+        x[0][3] = 0.5;
+
+        // 各行相对误差的最大值，数值小的行不会被数值大的行掩盖
+        let error = |granularity| {
+            let q = QuantizedTensor::quantize(&x, QuantScheme::Symmetric, granularity, Calibration::MinMax);
+            x.iter()
+                .zip(q.dequantize().iter())
+                .map(|(r, d)| QuantErrorReport::new(r, d).relative_error)
+                .fold(0.0f32, f32::max)
+        };
+        let per_tensor = error(Granularity::Tensor);
+        let per_channel = error(Granularity::Channel);
+        let per_group = error(Granularity::Group(128));
+        assert!(per_channel < per_tensor / 2.0, "per-channel {} vs per-tensor {}", per_channel, per_tensor);
+        assert!(per_group < per_channel, "per-group {} vs per-channel {}", per_group, per_channel);
+
+        // 离群值只影响它所在的组：第0行第二个组的参数与离群值无关
+        let q = QuantizedTensor::quantize(&x, QuantScheme::Symmetric, Granularity::Group(128), Calibration::MinMax);
+        assert_eq!(q.params_at(0, 3).scale, 0.5 / 127.0);
+        assert!(q.params_at(0, 200).scale < 0.01 / 127.0 + 1e-9);
+    }
+
+    #[test]
+    fn test_percentile_calibration_clips_outliers() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut x = random_matrix(&mut rng, 1, 4096, 1.0);
+        x[0][100] = 50.0;
+        x[0][200] = -80.0;
+
+        let bulk_error = |calibration| {
+            let q = QuantizedTensor::quantize(&x, QuantScheme::Symmetric, Granularity::Tensor, calibration);
+            let deq = q.dequantize();
+            // 只统计正常值的误差
+            let (reference, result): (Vec<f32>, Vec<f32>) = x[0].iter().zip(deq[0].iter())
+                .filter(|(v, _)| v.abs() <= 1.0)
+                .map(|(&a, &b)| (a, b))
+                .unzip();
+            QuantErrorReport::new(&reference, &result)
+        };
+        let minmax = bulk_error(Calibration::MinMax);
+        let clipped = bulk_error(Calibration::Percentile(99.9));
+        assert!(clipped.mean_abs_error < minmax.mean_abs_error / 10.0,
+                "裁剪离群值后，正常值的误差应该小得多: {} vs {}", clipped, minmax);
+
+        // 被裁剪的离群值饱和到范围边界
+        let q = QuantizedTensor::quantize(&x, QuantScheme::Symmetric, Granularity::Tensor, Calibration::Percentile(99.9));
+        assert_eq!(q.get(0, 100), 127);
+        assert_eq!(q.get(0, 200), -127);
+
+        // 非对称量化的百分位数校准
+        let relu: Vec<Vec<f32>> = vec![x[0].iter().map(|v| v.max(0.0)).collect()];
+        let q = QuantizedTensor::quantize(&relu, QuantScheme::Asymmetric, Granularity::Tensor, Calibration::Percentile(99.9));
+        assert_eq!(q.params[0].zero_point, -128, "最小值为0时零点在-128");
+        assert!(q.params[0].scale < 1.01 / 255.0);
+    }
+
+    #[test]
+    fn test_int8_matmul_matches_dequantized_f32() {
+        // i32累加是精确的，所以结果应该等于先反量化再做f32矩阵乘法（只差f32的舍入误差）
+        let mut rng = StdRng::seed_from_u64(3);
+        let a = random_matrix(&mut rng, 6, 96, 2.0);
+        let b: Vec<Vec<f32>> = random_matrix(&mut rng, 5, 96, 1.0).into_iter()
+            .map(|r| r.into_iter().map(|v| v + 0.5).collect())
+            .collect();
+
+        for scheme in [QuantScheme::Symmetric, QuantScheme::Asymmetric] {
+            for granularity in [Granularity::Tensor, Granularity::Channel, Granularity::Group(32)] {
+                let qa = QuantizedTensor::quantize(&a, scheme, granularity, Calibration::MinMax);
+                let qb = QuantizedTensor::quantize(&b, scheme, granularity, Calibration::MinMax);
+                let result = int8_matmul(&qa, &qb);
+                let expected = f32_matmul(&qa.dequantize(), &qb.dequantize());
+                let report = QuantErrorReport::new(&flatten(&expected), &flatten(&result));
+                assert!(report.relative_error < 1e-5, "{:?} {:?}: {}", scheme, granularity, report);
+
+                // 与原始f32结果相比，误差来自量化
+                let report = QuantErrorReport::new(&flatten(&f32_matmul(&a, &b)), &flatten(&result));
+                assert!(report.relative_error < 2e-2, "{:?} {:?}: {}", scheme, granularity, report);
+            }
+        }
+    }
+
+    #[test]
+    fn test_int8_batch_matmul_existing_input() {
+        let query = vec![vec![vec![vec![1.0, 2.0], vec![3.0, 4.0]]]];
+        let key = vec![vec![vec![vec![1.0, 2.0], vec![3.0, 4.0]]]];
+        let result = int8_batch_matmul(&query, &key, QuantScheme::Symmetric, Granularity::Channel);
+        let expected = [[7.0, 10.0], [15.0, 22.0]];
+        for i in 0..2 {
+            for j in 0..2 {
+                assert!((result[0][0][i][j] - expected[i][j]).abs() < 0.1,
+                        "result[{}][{}] = {}，期望约为{}", i, j, result[0][0][i][j], expected[i][j]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_int8_batch_matmul_error_report() {
+        let mut rng = StdRng::seed_from_u64(4);
+        // key已经转置，形状为 [b, h, head_size, seq_len_k]
+        let (b, h, s, d, sk) = (2, 4, 8, 32, 12);
+        let query: Tensor4D = (0..b).map(|_| (0..h).map(|_| random_matrix(&mut rng, s, d, 1.0)).collect()).collect();
+        let key: Tensor4D = (0..b).map(|_| (0..h).map(|_| random_matrix(&mut rng, d, sk, 1.0)).collect()).collect();
+
+        let per_tensor = int8_batch_matmul_error(&query, &key, QuantScheme::Symmetric, Granularity::Tensor);
+        let per_channel = int8_batch_matmul_error(&query, &key, QuantScheme::Symmetric, Granularity::Channel);
+        assert!(per_tensor.relative_error < 2e-2, "{}", per_tensor);
+        assert!(per_channel.relative_error <= per_tensor.relative_error, "{} vs {}", per_channel, per_tensor);
+        assert!(per_channel.mean_abs_error <= per_channel.max_abs_error);
+        assert!(per_channel.to_string().starts_with("max_abs_error="));
+
+        let asymmetric = int8_batch_matmul_error(&query, &key, QuantScheme::Asymmetric, Granularity::Channel);
+        assert!(asymmetric.relative_error < 2e-2, "{}", asymmetric);
+    }
+
+    #[test]
+    fn test_int8_batch_matmul_non_square_key() {
+        // head_size=3，seq_len_k=4，与batch_matmul的结果逐个比较
+        let query = vec![vec![vec![vec![1.0, -2.0, 0.5], vec![0.0, 3.0, -1.0]]]];
+        let key = vec![vec![vec![
+            vec![1.0, 0.0, -1.0, 2.0],
+            vec![0.5, 1.0, 0.0, -0.5],
+            vec![-2.0, 1.5, 1.0, 0.0],
+        ]]];
+        let reference = batch_matmul(&query, &key);
+        assert_eq!((reference[0][0].len(), reference[0][0][0].len()), (2, 4));
+        for scheme in [QuantScheme::Symmetric, QuantScheme::Asymmetric] {
+            for granularity in [Granularity::Tensor, Granularity::Channel, Granularity::Group(2)] {
+                let result = int8_batch_matmul(&query, &key, scheme, granularity);
+                assert_eq!((result[0][0].len(), result[0][0][0].len()), (2, 4));
+                for (row, ref_row) in result[0][0].iter().zip(&reference[0][0]) {
+                    for (&x, &r) in row.iter().zip(ref_row) {
+                        assert!((x - r).abs() < 0.1, "{:?} {:?}: {} vs {}", scheme, granularity, x, r);
+                    }
+                }
+            }
+        }
+    }
+}
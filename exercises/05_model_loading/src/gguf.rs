@@ -0,0 +1,1221 @@
+// llama.cpp使用GGUF格式保存模型。与safetensors只保存张量不同，GGUF把超参数、分词器词表和（量化的）张量放在同一个文件中。
+// GGUF v3的结构（所有数值都是小端序）：
+// - 头部：魔数"GGUF"、版本号u32、张量个数u64、键值对个数u64
+// - 键值对：键（字符串）、值类型u32、值。字符串是u64长度 + UTF-8字节，数组是元素类型u32 + u64长度 + 元素
+// - 张量信息表：名称、维数u32、各维大小u64（ne[0]是最内层，即每行的元素个数）、GGML类型u32、数据偏移u64
+// - 填充到general.alignment（默认32字节）的整数倍后是数据区，每个张量的偏移都相对数据区起点并且对齐
+// 在这个练习中，我们将实现GGUF的读取：解析元数据、取出模型超参数和分词器词表，并以类型化的视图访问张量数据。
+// 文件来自外部，任何损坏都应该返回描述清楚的错误，而不是panic。
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// 辅助函数：f16位模式到f32的转换
+// 注意：这个函数与 f16_overflow.rs 中的实现相同，已经提供
+fn f16_bits_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exp = ((bits >> 10) & 0x1F) as i32;
+    let mut frac = (bits & 0x3FF) as u32;
+
+    if exp == 0 {
+        if frac == 0 {
+            return f32::from_bits(sign);
+        }
+        let mut e = -14;
+        while frac & 0x400 == 0 {
+            frac <<= 1;
+            e -= 1;
+        }
+        frac &= 0x3FF;
+        return f32::from_bits(sign | (((e + 127) as u32) << 23) | (frac << 13));
+    } else if exp == 31 {
+        return f32::from_bits(sign | 0x7F800000 | (frac << 13));
+    }
+
+    let adjusted_exp = (exp - 15 + 127) as u32;
+    f32::from_bits(sign | (adjusted_exp << 23) | (frac << 13))
+}
+
+const QK4_0: usize = 32;
+const QK8_0: usize = 32;
+const QK_K: usize = 256;
+const K_SCALE_SIZE: usize = 12;
+
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+const GGUF_DEFAULT_ALIGNMENT: u64 = 32;
+const GGML_MAX_DIMS: usize = 4;
+// 数组嵌套的最大深度，防止恶意文件导致栈溢出
+const MAX_ARRAY_DEPTH: usize = 8;
+// 读取数组时最多预分配的元素个数
+const MAX_ARRAY_PREALLOC: usize = 1024;
+
+// 读取GGUF时可能出现的错误
+#[derive(Debug, Clone, PartialEq)]
+enum GgufError {
+    Io(String),
+    InvalidMagic([u8; 4]),
+    UnsupportedVersion(u32),
+    UnexpectedEof { offset: usize, context: String },
+    InvalidUtf8 { offset: usize },
+    InvalidValueType { key: String, value_type: u32 },
+    InvalidValue { key: String, message: String },
+    DuplicateKey(String),
+    DuplicateTensor(String),
+    UnknownTensorType { name: String, type_id: u32 },
+    InvalidTensor { name: String, message: String },
+    MissingKey(String),
+    WrongValueType { key: String, expected: &'static str, actual: &'static str },
+    TensorNotFound(String),
+    UnsupportedConversion { name: String, ggml_type: GgmlType },
+}
+
+impl fmt::Display for GgufError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GgufError::Io(msg) => write!(f, "IO错误: {}", msg),
+            GgufError::InvalidMagic(magic) => write!(f, "不是GGUF文件，魔数是{:?}", magic),
+            GgufError::UnsupportedVersion(v) => write!(f, "不支持的GGUF版本{}，只支持2和3", v),
+            GgufError::UnexpectedEof { offset, context } => {
+                write!(f, "读取{}时文件意外结束（偏移{}）", context, offset)
+            }
+            GgufError::InvalidUtf8 { offset } => write!(f, "偏移{}处的字符串不是有效的UTF-8", offset),
+            GgufError::InvalidValueType { key, value_type } => {
+                write!(f, "键{}的值类型{}无效", key, value_type)
+            }
+            GgufError::InvalidValue { key, message } => write!(f, "键{}的值无效: {}", key, message),
+            GgufError::DuplicateKey(key) => write!(f, "键{}重复出现", key),
+            GgufError::DuplicateTensor(name) => write!(f, "张量{}重复出现", name),
+            GgufError::UnknownTensorType { name, type_id } => {
+                write!(f, "张量{}的GGML类型{}未知", name, type_id)
+            }
+            GgufError::InvalidTensor { name, message } => write!(f, "张量{}无效: {}", name, message),
+            GgufError::MissingKey(key) => write!(f, "缺少键{}", key),
+            GgufError::WrongValueType { key, expected, actual } => {
+                write!(f, "键{}应该是{}，实际是{}", key, expected, actual)
+            }
+            GgufError::TensorNotFound(name) => write!(f, "找不到张量{}", name),
+            GgufError::UnsupportedConversion { name, ggml_type } => {
+                write!(f, "张量{}的类型{}不能转换为f32", name, ggml_type.name())
+            }
+        }
+    }
+}
+
+// GGML张量类型，名称和编号与ggml.h一致
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GgmlType {
+    F32,
+    F16,
+    Q4_0,
+    Q4_1,
+    Q5_0,
+    Q5_1,
+    Q8_0,
+    Q8_1,
+    Q2_K,
+    Q3_K,
+    Q4_K,
+    Q5_K,
+    Q6_K,
+    Q8_K,
+    BF16,
+}
+
+impl GgmlType {
+    fn from_id(id: u32) -> Option<GgmlType> {
+        match id {
+            0 => Some(GgmlType::F32),
+            1 => Some(GgmlType::F16),
+            2 => Some(GgmlType::Q4_0),
+            3 => Some(GgmlType::Q4_1),
+            6 => Some(GgmlType::Q5_0),
+            7 => Some(GgmlType::Q5_1),
+            8 => Some(GgmlType::Q8_0),
+            9 => Some(GgmlType::Q8_1),
+            10 => Some(GgmlType::Q2_K),
+            11 => Some(GgmlType::Q3_K),
+            12 => Some(GgmlType::Q4_K),
+            13 => Some(GgmlType::Q5_K),
+            14 => Some(GgmlType::Q6_K),
+            15 => Some(GgmlType::Q8_K),
+            30 => Some(GgmlType::BF16),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            GgmlType::F32 => "F32",
+            GgmlType::F16 => "F16",
+            GgmlType::Q4_0 => "Q4_0",
+            GgmlType::Q4_1 => "Q4_1",
+            GgmlType::Q5_0 => "Q5_0",
+            GgmlType::Q5_1 => "Q5_1",
+            GgmlType::Q8_0 => "Q8_0",
+            GgmlType::Q8_1 => "Q8_1",
+            GgmlType::Q2_K => "Q2_K",
+            GgmlType::Q3_K => "Q3_K",
+            GgmlType::Q4_K => "Q4_K",
+            GgmlType::Q5_K => "Q5_K",
+            GgmlType::Q6_K => "Q6_K",
+            GgmlType::Q8_K => "Q8_K",
+            GgmlType::BF16 => "BF16",
+        }
+    }
+
+    // 每块的元素个数，非量化类型为1
+    fn block_size(self) -> usize {
+        match self {
+            GgmlType::F32 | GgmlType::F16 | GgmlType::BF16 => 1,
+            GgmlType::Q4_0 | GgmlType::Q4_1 | GgmlType::Q5_0 | GgmlType::Q5_1 | GgmlType::Q8_0 | GgmlType::Q8_1 => 32,
+            _ => QK_K,
+        }
+    }
+
+    // 每块占用的字节数
+    fn type_size(self) -> usize {
+        match self {
+            GgmlType::F32 => 4,
+            GgmlType::F16 | GgmlType::BF16 => 2,
+            GgmlType::Q4_0 => 2 + QK4_0 / 2,
+            GgmlType::Q4_1 => 4 + QK4_0 / 2,
+            GgmlType::Q5_0 => 2 + 4 + QK4_0 / 2,
+            GgmlType::Q5_1 => 4 + 4 + QK4_0 / 2,
+            GgmlType::Q8_0 => 2 + QK8_0,
+            GgmlType::Q8_1 => 4 + QK8_0,
+            GgmlType::Q2_K => QK_K / 16 + QK_K / 4 + 4,
+            GgmlType::Q3_K => QK_K / 8 + QK_K / 4 + 12 + 2,
+            GgmlType::Q4_K => 2 + 2 + K_SCALE_SIZE + QK_K / 2,
+            GgmlType::Q5_K => 2 + 2 + K_SCALE_SIZE + QK_K / 8 + QK_K / 2,
+            GgmlType::Q6_K => QK_K / 2 + QK_K / 4 + QK_K / 16 + 2,
+            GgmlType::Q8_K => 4 + QK_K + QK_K / 16 * 2,
+        }
+    }
+
+    fn is_quantized(self) -> bool {
+        self.block_size() > 1
+    }
+}
+
+fn read_f16(bytes: &[u8], offset: usize) -> f32 {
+    f16_bits_to_f32(u16::from_le_bytes([bytes[offset], bytes[offset + 1]]))
+}
+
+// 辅助函数：Q4_0、Q8_0和Q4_K的反量化
+// 注意：这些函数与 block_quant.rs 中的实现相同，已经提供
+fn dequantize_row_q4_0(bytes: &[u8]) -> Vec<f32> {
+    let type_size = GgmlType::Q4_0.type_size();
+    assert_eq!(bytes.len() % type_size, 0, "数据长度必须是块大小的整数倍");
+    let mut out = vec![0.0; bytes.len() / type_size * QK4_0];
+
+    for (block, y) in bytes.chunks(type_size).zip(out.chunks_mut(QK4_0)) {
+        let d = read_f16(block, 0);
+        for (j, &q) in block[2..].iter().enumerate() {
+            y[j] = ((q & 0x0F) as i32 - 8) as f32 * d;
+            y[j + QK4_0 / 2] = ((q >> 4) as i32 - 8) as f32 * d;
+        }
+    }
+    out
+}
+
+fn dequantize_row_q8_0(bytes: &[u8]) -> Vec<f32> {
+    let type_size = GgmlType::Q8_0.type_size();
+    assert_eq!(bytes.len() % type_size, 0, "数据长度必须是块大小的整数倍");
+
+    bytes.chunks(type_size)
+        .flat_map(|block| {
+            let d = read_f16(block, 0);
+            block[2..].iter().map(move |&q| q as i8 as f32 * d)
+        })
+        .collect()
+}
+
+// 从12字节中取出第j个子块的6位scale和min（与GGML的get_scale_min_k4相同）
+// 前4个子块：scales[j]和scales[j + 4]的低6位
+// 后4个子块：低4位在scales[j + 4]的低/高半字节中，高2位在scales[j - 4]和scales[j]的最高2位中
+fn get_scale_min_k4(j: usize, scales: &[u8]) -> (u8, u8) {
+    if j < 4 {
+        (scales[j] & 63, scales[j + 4] & 63)
+    } else {
+        let sc = (scales[j + 4] & 0x0F) | ((scales[j - 4] >> 6) << 4);
+        let m = (scales[j + 4] >> 4) | ((scales[j] >> 6) << 4);
+        (sc, m)
+    }
+}
+
+fn dequantize_row_q4_k(bytes: &[u8]) -> Vec<f32> {
+    let type_size = GgmlType::Q4_K.type_size();
+    assert_eq!(bytes.len() % type_size, 0, "数据长度必须是块大小的整数倍");
+    let mut out = Vec::with_capacity(bytes.len() / type_size * QK_K);
+
+    for block in bytes.chunks(type_size) {
+        let d = read_f16(block, 0);
+        let dmin = read_f16(block, 2);
+        let scales = &block[4..4 + K_SCALE_SIZE];
+        let qs = &block[4 + K_SCALE_SIZE..];
+
+        for (chunk, q) in qs.chunks(32).enumerate() {
+            let (sc1, m1) = get_scale_min_k4(2 * chunk, scales);
+            let (sc2, m2) = get_scale_min_k4(2 * chunk + 1, scales);
+            let (d1, min1) = (d * sc1 as f32, dmin * m1 as f32);
+            let (d2, min2) = (d * sc2 as f32, dmin * m2 as f32);
+            out.extend(q.iter().map(|&b| d1 * (b & 0x0F) as f32 - min1));
+            out.extend(q.iter().map(|&b| d2 * (b >> 4) as f32 - min2));
+        }
+    }
+    out
+}
+
+// 元数据的值
+#[derive(Debug, Clone, PartialEq)]
+enum GgufValue {
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    F32(f32),
+    Bool(bool),
+    String(String),
+    Array(Vec<GgufValue>),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+}
+
+impl GgufValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            GgufValue::U8(_) => "u8",
+            GgufValue::I8(_) => "i8",
+            GgufValue::U16(_) => "u16",
+            GgufValue::I16(_) => "i16",
+            GgufValue::U32(_) => "u32",
+            GgufValue::I32(_) => "i32",
+            GgufValue::F32(_) => "f32",
+            GgufValue::Bool(_) => "bool",
+            GgufValue::String(_) => "字符串",
+            GgufValue::Array(_) => "数组",
+            GgufValue::U64(_) => "u64",
+            GgufValue::I64(_) => "i64",
+            GgufValue::F64(_) => "f64",
+        }
+    }
+
+    // 任意非负整数都可以转换为u64（不同的转换工具会用不同宽度的整数保存同一个超参数）
+    fn as_u64(&self) -> Option<u64> {
+        match *self {
+            GgufValue::U8(v) => Some(v as u64),
+            GgufValue::U16(v) => Some(v as u64),
+            GgufValue::U32(v) => Some(v as u64),
+            GgufValue::U64(v) => Some(v),
+            GgufValue::I8(v) => u64::try_from(v).ok(),
+            GgufValue::I16(v) => u64::try_from(v).ok(),
+            GgufValue::I32(v) => u64::try_from(v).ok(),
+            GgufValue::I64(v) => u64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+
+    fn as_i64(&self) -> Option<i64> {
+        match *self {
+            GgufValue::I8(v) => Some(v as i64),
+            GgufValue::I16(v) => Some(v as i64),
+            GgufValue::I32(v) => Some(v as i64),
+            GgufValue::I64(v) => Some(v),
+            _ => self.as_u64().and_then(|v| i64::try_from(v).ok()),
+        }
+    }
+
+    fn as_f32(&self) -> Option<f32> {
+        match *self {
+            GgufValue::F32(v) => Some(v),
+            GgufValue::F64(v) => Some(v as f32),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            GgufValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&[GgufValue]> {
+        match self {
+            GgufValue::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+// 带边界检查的顺序读取器，所有越界都转换为UnexpectedEof
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn take(&mut self, n: usize, context: &str) -> Result<&'a [u8], GgufError> {
+        if n > self.remaining() {
+            return Err(GgufError::UnexpectedEof { offset: self.pos, context: context.to_string() });
+        }
+        let slice = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+
+    fn read_bytes<const N: usize>(&mut self, context: &str) -> Result<[u8; N], GgufError> {
+        Ok(self.take(N, context)?.try_into().unwrap())
+    }
+
+    fn read_u32(&mut self, context: &str) -> Result<u32, GgufError> {
+        Ok(u32::from_le_bytes(self.read_bytes(context)?))
+    }
+
+    fn read_u64(&mut self, context: &str) -> Result<u64, GgufError> {
+        Ok(u64::from_le_bytes(self.read_bytes(context)?))
+    }
+
+    // 读取长度（元素个数或字节数），每个元素至少占min_size字节，超过剩余字节数说明文件已损坏
+    fn read_len(&mut self, min_size: usize, context: &str) -> Result<usize, GgufError> {
+        let offset = self.pos;
+        let len = self.read_u64(context)?;
+        if len.saturating_mul(min_size as u64) > self.remaining() as u64 {
+            return Err(GgufError::UnexpectedEof { offset, context: context.to_string() });
+        }
+        Ok(len as usize)
+    }
+
+    fn read_string(&mut self, context: &str) -> Result<String, GgufError> {
+        let len = self.read_len(1, context)?;
+        let offset = self.pos;
+        let bytes = self.take(len, context)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| GgufError::InvalidUtf8 { offset })
+    }
+
+    // 一个值在文件中至少占用的字节数，用来在分配之前检查数组长度
+    // 字符串至少有8字节的长度，数组至少有4字节的类型和8字节的长度，未知类型在读取时再报错
+    fn min_value_size(value_type: u32) -> usize {
+        match value_type {
+            0 | 1 | 7 => 1,
+            2 | 3 => 2,
+            4..=6 => 4,
+            8 | 10..=12 => 8,
+            9 => 12,
+            _ => 1,
+        }
+    }
+
+    fn read_value(&mut self, value_type: u32, key: &str, context: &str, depth: usize) -> Result<GgufValue, GgufError> {
+        let value = match value_type {
+            0 => GgufValue::U8(self.read_bytes::<1>(context)?[0]),
+            1 => GgufValue::I8(self.read_bytes::<1>(context)?[0] as i8),
+            2 => GgufValue::U16(u16::from_le_bytes(self.read_bytes(context)?)),
+            3 => GgufValue::I16(i16::from_le_bytes(self.read_bytes(context)?)),
+            4 => GgufValue::U32(u32::from_le_bytes(self.read_bytes(context)?)),
+            5 => GgufValue::I32(i32::from_le_bytes(self.read_bytes(context)?)),
+            6 => GgufValue::F32(f32::from_le_bytes(self.read_bytes(context)?)),
+            7 => match self.read_bytes::<1>(context)?[0] {
+                0 => GgufValue::Bool(false),
+                1 => GgufValue::Bool(true),
+                b => {
+                    return Err(GgufError::InvalidValue { key: key.to_string(), message: format!("bool的值必须是0或1，实际是{}", b) });
+                }
+            },
+            8 => GgufValue::String(self.read_string(context)?),
+            9 => {
+                if depth >= MAX_ARRAY_DEPTH {
+                    return Err(GgufError::InvalidValue { key: key.to_string(), message: "数组嵌套过深".to_string() });
+                }
+                let item_type = self.read_u32(context)?;
+                let len = self.read_len(Self::min_value_size(item_type), context)?;
+                // 元素个数已经受文件大小限制，但预分配仍然只取一个上限，避免为损坏的文件分配过多内存
+                let mut items = Vec::with_capacity(len.min(MAX_ARRAY_PREALLOC));
+                for _ in 0..len {
+                    items.push(self.read_value(item_type, key, context, depth + 1)?);
+                }
+                GgufValue::Array(items)
+            }
+            10 => GgufValue::U64(u64::from_le_bytes(self.read_bytes(context)?)),
+            11 => GgufValue::I64(i64::from_le_bytes(self.read_bytes(context)?)),
+            12 => GgufValue::F64(f64::from_le_bytes(self.read_bytes(context)?)),
+            _ => return Err(GgufError::InvalidValueType { key: key.to_string(), value_type }),
+        };
+        Ok(value)
+    }
+}
+
+// 张量信息表中的一项
+#[derive(Debug, Clone, PartialEq)]
+struct GgufTensorInfo {
+    name: String,
+    dims: Vec<u64>, // dims[0]是最内层
+    ggml_type: GgmlType,
+    offset: u64,    // 相对数据区起点
+}
+
+impl GgufTensorInfo {
+    fn numel(&self) -> u64 {
+        self.dims.iter().product()
+    }
+
+    // 行数 = 除最内层以外所有维的乘积，溢出时返回None
+    fn row_count(&self) -> Option<u64> {
+        self.dims[1..].iter().try_fold(1u64, |acc, &d| acc.checked_mul(d))
+    }
+
+    // 数据的字节数，溢出时返回None
+    fn byte_size(&self) -> Option<u64> {
+        let blocks_per_row = self.dims[0] / self.ggml_type.block_size() as u64;
+        let row_bytes = blocks_per_row.checked_mul(self.ggml_type.type_size() as u64)?;
+        row_bytes.checked_mul(self.row_count()?)
+    }
+}
+
+// 从GGUF元数据中读取的模型超参数
+#[derive(Debug, Clone, PartialEq)]
+struct GgufModelConfig {
+    architecture: String,
+    context_length: usize,
+    embedding_length: usize,
+    block_count: usize,
+    head_count: usize,
+    head_count_kv: usize,
+    rope_freq_base: f32,
+    rms_norm_eps: Option<f32>,
+}
+
+// GGUF中嵌入的分词器词表
+#[derive(Debug, Clone, PartialEq)]
+struct GgufVocab {
+    model: String,
+    tokens: Vec<String>,
+    scores: Option<Vec<f32>>,
+    token_types: Option<Vec<i32>>,
+    merges: Vec<(String, String)>,
+    bos_token_id: Option<u32>,
+    eos_token_id: Option<u32>,
+}
+
+// 解析后的GGUF文件
+#[derive(Debug)]
+struct GgufFile {
+    version: u32,
+    metadata: Vec<(String, GgufValue)>,
+    tensors: Vec<GgufTensorInfo>,
+    alignment: u64,
+    data_start: usize,
+    bytes: Vec<u8>,
+}
+
+impl GgufFile {
+    fn load<P: AsRef<Path>>(path: P) -> Result<Self, GgufError> {
+        let bytes = fs::read(path).map_err(|e| GgufError::Io(e.to_string()))?;
+        GgufFile::from_bytes(bytes)
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, GgufError> {
+        let mut r = ByteReader { bytes: &bytes, pos: 0 };
+
+        let magic: [u8; 4] = r.read_bytes("魔数")?;
+        if &magic != GGUF_MAGIC {
+            return Err(GgufError::InvalidMagic(magic));
+        }
+        // v2与v3的布局相同（v3只是增加了大端序文件的支持）
+        let version = r.read_u32("版本号")?;
+        if version != 2 && version != 3 {
+            return Err(GgufError::UnsupportedVersion(version));
+        }
+        // 每个张量信息至少24字节，每个键值对至少12字节
+        let tensor_count = r.read_len(24, "张量个数")?;
+        let kv_count = r.read_len(12, "键值对个数")?;
+
+        let mut metadata: Vec<(String, GgufValue)> = Vec::with_capacity(kv_count);
+        for i in 0..kv_count {
+            let key = r.read_string(&format!("第{}个键", i))?;
+            let context = format!("键{}的值", key);
+            let value_type = r.read_u32(&context)?;
+            let value = r.read_value(value_type, &key, &context, 0)?;
+            if metadata.iter().any(|(k, _)| *k == key) {
+                return Err(GgufError::DuplicateKey(key));
+            }
+            metadata.push((key, value));
+        }
+
+        let alignment = match metadata.iter().find(|(k, _)| k == "general.alignment") {
+            None => GGUF_DEFAULT_ALIGNMENT,
+            Some((key, GgufValue::U32(a))) if a.is_power_of_two() => *a as u64,
+            Some((key, value)) => {
+                return Err(GgufError::InvalidValue {
+                    key: key.clone(),
+                    message: format!("对齐必须是u32类型的2的幂，实际是{:?}", value),
+                });
+            }
+        };
+
+        let mut tensors: Vec<GgufTensorInfo> = Vec::with_capacity(tensor_count);
+        for i in 0..tensor_count {
+            let name = r.read_string(&format!("第{}个张量的名称", i))?;
+            let context = format!("张量{}的信息", name);
+            let invalid = |message: String| GgufError::InvalidTensor { name: name.clone(), message };
+
+            let n_dims = r.read_u32(&context)? as usize;
+            if n_dims == 0 || n_dims > GGML_MAX_DIMS {
+                return Err(invalid(format!("维数必须在1到{}之间，实际是{}", GGML_MAX_DIMS, n_dims)));
+            }
+            let mut dims = Vec::with_capacity(n_dims);
+            for _ in 0..n_dims {
+                dims.push(r.read_u64(&context)?);
+            }
+            let type_id = r.read_u32(&context)?;
+            let ggml_type = GgmlType::from_id(type_id)
+                .ok_or_else(|| GgufError::UnknownTensorType { name: name.clone(), type_id })?;
+            let offset = r.read_u64(&context)?;
+
+            // 长度为0的维度会让张量不占任何字节，其它维度再大也能通过大小检查
+            if dims.contains(&0) {
+                return Err(invalid(format!("维度不能为0，实际是{:?}", dims)));
+            }
+            if dims[0] % ggml_type.block_size() as u64 != 0 {
+                return Err(invalid(format!(
+                    "行长度{}不是{}的块大小{}的整数倍", dims[0], ggml_type.name(), ggml_type.block_size()
+                )));
+            }
+            if offset % alignment != 0 {
+                return Err(invalid(format!("偏移{}没有按{}字节对齐", offset, alignment)));
+            }
+            if tensors.iter().any(|t| t.name == name) {
+                return Err(GgufError::DuplicateTensor(name));
+            }
+            tensors.push(GgufTensorInfo { name, dims, ggml_type, offset });
+        }
+
+        // 张量信息表之后填充到对齐边界，数据区从这里开始
+        let data_start = (r.pos as u64).div_ceil(alignment) as usize * alignment as usize;
+        let data_len = bytes.len().saturating_sub(data_start) as u64;
+
+        // 每个张量都必须完整地位于数据区内，并且互不重叠
+        let mut ranges = Vec::with_capacity(tensors.len());
+        for t in &tensors {
+            let size = t.byte_size().ok_or_else(|| GgufError::InvalidTensor {
+                name: t.name.clone(),
+                message: "张量大小溢出".to_string(),
+            })?;
+            match t.offset.checked_add(size) {
+                Some(end) if end <= data_len => ranges.push((t.offset, end, &t.name)),
+                _ => {
+                    return Err(GgufError::UnexpectedEof {
+                        offset: bytes.len(),
+                        context: format!("张量{}的数据（偏移{}，{}字节）", t.name, t.offset, size),
+                    });
+                }
+            }
+        }
+        ranges.sort();
+        for pair in ranges.windows(2) {
+            if pair[1].0 < pair[0].1 {
+                return Err(GgufError::InvalidTensor {
+                    name: pair[1].2.clone(),
+                    message: format!("数据与张量{}重叠", pair[0].2),
+                });
+            }
+        }
+
+        Ok(GgufFile { version, metadata, tensors, alignment, data_start, bytes })
+    }
+
+    fn get(&self, key: &str) -> Option<&GgufValue> {
+        self.metadata.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    fn require(&self, key: &str) -> Result<&GgufValue, GgufError> {
+        self.get(key).ok_or_else(|| GgufError::MissingKey(key.to_string()))
+    }
+
+    fn get_usize(&self, key: &str) -> Result<Option<usize>, GgufError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(v) => v.as_u64().map(|n| Some(n as usize)).ok_or_else(|| GgufError::WrongValueType {
+                key: key.to_string(),
+                expected: "非负整数",
+                actual: v.type_name(),
+            }),
+        }
+    }
+
+    fn get_f32(&self, key: &str) -> Result<Option<f32>, GgufError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(v) => v.as_f32().map(Some).ok_or_else(|| GgufError::WrongValueType {
+                key: key.to_string(),
+                expected: "浮点数",
+                actual: v.type_name(),
+            }),
+        }
+    }
+
+    fn get_str(&self, key: &str) -> Result<&str, GgufError> {
+        let v = self.require(key)?;
+        v.as_str().ok_or_else(|| GgufError::WrongValueType { key: key.to_string(), expected: "字符串", actual: v.type_name() })
+    }
+
+    fn require_usize(&self, key: &str) -> Result<usize, GgufError> {
+        self.get_usize(key)?.ok_or_else(|| GgufError::MissingKey(key.to_string()))
+    }
+
+    // 读取数组，并用convert转换每个元素
+    fn get_array<T>(
+        &self,
+        key: &str,
+        expected: &'static str,
+        convert: impl Fn(&GgufValue) -> Option<T>,
+    ) -> Result<Option<Vec<T>>, GgufError> {
+        let value = match self.get(key) {
+            None => return Ok(None),
+            Some(v) => v,
+        };
+        let wrong = |actual: &'static str| GgufError::WrongValueType { key: key.to_string(), expected, actual };
+        let items = value.as_array().ok_or_else(|| wrong(value.type_name()))?;
+        items.iter()
+            .map(|item| convert(item).ok_or_else(|| wrong(item.type_name())))
+            .collect::<Result<Vec<T>, GgufError>>()
+            .map(Some)
+    }
+
+    // 超参数的键以架构名为前缀，例如llama.context_length
+    fn model_config(&self) -> Result<GgufModelConfig, GgufError> {
+        let arch = self.get_str("general.architecture")?.to_string();
+        let key = |name: &str| format!("{}.{}", arch, name);
+        let head_count = self.require_usize(&key("attention.head_count"))?;
+        Ok(GgufModelConfig {
+            context_length: self.require_usize(&key("context_length"))?,
+            embedding_length: self.require_usize(&key("embedding_length"))?,
+            block_count: self.require_usize(&key("block_count"))?,
+            head_count,
+            // 没有分组查询注意力时，KV头数等于查询头数
+            head_count_kv: self.get_usize(&key("attention.head_count_kv"))?.unwrap_or(head_count),
+            rope_freq_base: self.get_f32(&key("rope.freq_base"))?.unwrap_or(10000.0),
+            rms_norm_eps: self.get_f32(&key("attention.layer_norm_rms_epsilon"))?,
+            architecture: arch,
+        })
+    }
+
+    fn vocab(&self) -> Result<GgufVocab, GgufError> {
+        let model = self.get_str("tokenizer.ggml.model")?.to_string();
+        let to_string = |v: &GgufValue| v.as_str().map(str::to_string);
+        let tokens = self.get_array("tokenizer.ggml.tokens", "字符串数组", to_string)?
+            .ok_or_else(|| GgufError::MissingKey("tokenizer.ggml.tokens".to_string()))?;
+        let scores = self.get_array("tokenizer.ggml.scores", "f32数组", GgufValue::as_f32)?;
+        let token_types = self.get_array("tokenizer.ggml.token_type", "i32数组", |v| {
+            v.as_i64().and_then(|n| i32::try_from(n).ok())
+        })?;
+
+        for (key, len) in [
+            ("tokenizer.ggml.scores", scores.as_ref().map(Vec::len)),
+            ("tokenizer.ggml.token_type", token_types.as_ref().map(Vec::len)),
+        ] {
+            if let Some(len) = len.filter(|&len| len != tokens.len()) {
+                return Err(GgufError::InvalidValue {
+                    key: key.to_string(),
+                    message: format!("长度{}与词表大小{}不一致", len, tokens.len()),
+                });
+            }
+        }
+
+        // 每条合并规则是用空格分隔的两个token，例如"Ġ t"
+        let merges = self.get_array("tokenizer.ggml.merges", "字符串数组", to_string)?.unwrap_or_default();
+        let merges = merges.into_iter()
+            .map(|m| match m.split_once(' ') {
+                Some((a, b)) => Ok((a.to_string(), b.to_string())),
+                None => Err(GgufError::InvalidValue {
+                    key: "tokenizer.ggml.merges".to_string(),
+                    message: format!("合并规则{:?}中没有空格", m),
+                }),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let token_id = |key: &str| -> Result<Option<u32>, GgufError> {
+            match self.get_usize(key)? {
+                Some(id) if id >= tokens.len() => Err(GgufError::InvalidValue {
+                    key: key.to_string(),
+                    message: format!("token id {}超出词表大小{}", id, tokens.len()),
+                }),
+                id => Ok(id.map(|id| id as u32)),
+            }
+        };
+        let bos_token_id = token_id("tokenizer.ggml.bos_token_id")?;
+        let eos_token_id = token_id("tokenizer.ggml.eos_token_id")?;
+
+        Ok(GgufVocab { model, tokens, scores, token_types, merges, bos_token_id, eos_token_id })
+    }
+
+    fn tensor_names(&self) -> Vec<&str> {
+        self.tensors.iter().map(|t| t.name.as_str()).collect()
+    }
+
+    fn tensor(&self, name: &str) -> Result<GgufTensorView<'_>, GgufError> {
+        let info = self.tensors.iter()
+            .find(|t| t.name == name)
+            .ok_or_else(|| GgufError::TensorNotFound(name.to_string()))?;
+        // 加载时已经检查过范围，这里不会越界
+        let begin = self.data_start + info.offset as usize;
+        let end = begin + info.byte_size().unwrap() as usize;
+        Ok(GgufTensorView { info, data: &self.bytes[begin..end] })
+    }
+}
+
+// 张量数据按类型解码后的结果，量化张量保留原始的块数据（每行一个切片），可以直接用于块量化的点积
+#[derive(Debug, Clone, PartialEq)]
+enum TypedTensor<'a> {
+    F32(Vec<f32>),
+    F16(Vec<u16>),
+    BF16(Vec<u16>),
+    Quantized { ggml_type: GgmlType, rows: Vec<&'a [u8]> },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GgufTensorView<'a> {
+    info: &'a GgufTensorInfo,
+    data: &'a [u8],
+}
+
+impl<'a> GgufTensorView<'a> {
+    // 行数 = 除最内层以外所有维的乘积，加载时已经检查过不会溢出
+    fn row_count(self) -> usize {
+        self.info.row_count().expect("行数在加载时已经检查过") as usize
+    }
+
+    // 加载时保证了每一维都不为0，所以每行至少有一个块
+    fn rows(self) -> Vec<&'a [u8]> {
+        let row_bytes = self.info.dims[0] as usize / self.info.ggml_type.block_size() * self.info.ggml_type.type_size();
+        self.data.chunks(row_bytes).collect()
+    }
+
+    fn typed(self) -> TypedTensor<'a> {
+        let u16s = || self.data.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect();
+        match self.info.ggml_type {
+            GgmlType::F32 => TypedTensor::F32(
+                self.data.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect(),
+            ),
+            GgmlType::F16 => TypedTensor::F16(u16s()),
+            GgmlType::BF16 => TypedTensor::BF16(u16s()),
+            ggml_type => TypedTensor::Quantized { ggml_type, rows: self.rows() },
+        }
+    }
+
+    // 解码为f32，支持F32、F16、BF16以及Q4_0、Q8_0和Q4_K
+    fn to_f32(self) -> Result<Vec<f32>, GgufError> {
+        let values = match self.info.ggml_type {
+            GgmlType::F32 | GgmlType::F16 | GgmlType::BF16 => match self.typed() {
+                TypedTensor::F32(v) => v,
+                TypedTensor::F16(v) => v.into_iter().map(f16_bits_to_f32).collect(),
+                TypedTensor::BF16(v) => v.into_iter().map(|b| f32::from_bits((b as u32) << 16)).collect(),
+                TypedTensor::Quantized { .. } => unreachable!(),
+            },
+            GgmlType::Q4_0 => dequantize_row_q4_0(self.data),
+            GgmlType::Q8_0 => dequantize_row_q8_0(self.data),
+            GgmlType::Q4_K => dequantize_row_q4_k(self.data),
+            ggml_type => {
+                return Err(GgufError::UnsupportedConversion { name: self.info.name.clone(), ggml_type });
+            }
+        };
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 在测试中构造GGUF文件
+    struct GgufBuilder {
+        version: u32,
+        kv: Vec<u8>,
+        kv_count: u64,
+        tensors: Vec<(String, Vec<u64>, u32, Vec<u8>)>,
+        alignment: usize,
+    }
+
+    fn gguf_string(s: &str) -> Vec<u8> {
+        let mut out = (s.len() as u64).to_le_bytes().to_vec();
+        out.extend_from_slice(s.as_bytes());
+        out
+    }
+
+    impl GgufBuilder {
+        fn new() -> Self {
+            GgufBuilder { version: 3, kv: Vec::new(), kv_count: 0, tensors: Vec::new(), alignment: 32 }
+        }
+
+        // 写入键值对，value是值类型之后的原始字节
+        fn kv_raw(mut self, key: &str, value_type: u32, value: &[u8]) -> Self {
+            self.kv.extend(gguf_string(key));
+            self.kv.extend(value_type.to_le_bytes());
+            self.kv.extend_from_slice(value);
+            self.kv_count += 1;
+            self
+        }
+
+        fn kv_u32(self, key: &str, v: u32) -> Self {
+            self.kv_raw(key, 4, &v.to_le_bytes())
+        }
+
+        fn kv_f32(self, key: &str, v: f32) -> Self {
+            self.kv_raw(key, 6, &v.to_le_bytes())
+        }
+
+        fn kv_string(self, key: &str, v: &str) -> Self {
+            self.kv_raw(key, 8, &gguf_string(v))
+        }
+
+        fn kv_array(self, key: &str, item_type: u32, items: &[Vec<u8>]) -> Self {
+            let mut value = item_type.to_le_bytes().to_vec();
+            value.extend((items.len() as u64).to_le_bytes());
+            for item in items {
+                value.extend_from_slice(item);
+            }
+            self.kv_raw(key, 9, &value)
+        }
+
+        fn kv_strings(self, key: &str, items: &[&str]) -> Self {
+            let items: Vec<Vec<u8>> = items.iter().map(|s| gguf_string(s)).collect();
+            self.kv_array(key, 8, &items)
+        }
+
+        fn tensor(mut self, name: &str, dims: &[u64], type_id: u32, data: Vec<u8>) -> Self {
+            self.tensors.push((name.to_string(), dims.to_vec(), type_id, data));
+            self
+        }
+
+        // 张量数据之间按对齐要求填充
+        fn build(self) -> Vec<u8> {
+            let mut out = GGUF_MAGIC.to_vec();
+            out.extend(self.version.to_le_bytes());
+            out.extend((self.tensors.len() as u64).to_le_bytes());
+            out.extend(self.kv_count.to_le_bytes());
+            out.extend_from_slice(&self.kv);
+
+            let mut offset = 0;
+            let mut data = Vec::new();
+            for (i, (name, dims, type_id, bytes)) in self.tensors.iter().enumerate() {
+                out.extend(gguf_string(name));
+                out.extend((dims.len() as u32).to_le_bytes());
+                for d in dims {
+                    out.extend(d.to_le_bytes());
+                }
+                out.extend(type_id.to_le_bytes());
+                out.extend((offset as u64).to_le_bytes());
+                data.extend_from_slice(bytes);
+                offset += bytes.len();
+                // 最后一个张量之后不填充，文件正好在数据末尾结束
+                if i + 1 < self.tensors.len() {
+                    offset = offset.div_ceil(self.alignment) * self.alignment;
+                    data.resize(offset, 0);
+                }
+            }
+            if !self.tensors.is_empty() {
+                out.resize(out.len().div_ceil(self.alignment) * self.alignment, 0);
+            }
+            out.extend(data);
+            out
+        }
+    }
+
+    fn f32_bytes(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn llama_builder() -> GgufBuilder {
+        GgufBuilder::new()
+            .kv_string("general.architecture", "llama")
+            .kv_string("general.name", "tiny-llama")
+            .kv_u32("llama.context_length", 2048)
+            .kv_u32("llama.embedding_length", 64)
+            .kv_u32("llama.block_count", 2)
+            .kv_u32("llama.attention.head_count", 8)
+            .kv_u32("llama.attention.head_count_kv", 2)
+            .kv_f32("llama.rope.freq_base", 500000.0)
+            .kv_f32("llama.attention.layer_norm_rms_epsilon", 1e-5)
+    }
+
+    #[test]
+    fn test_metadata_and_model_config() {
+        let bytes = llama_builder()
+            .kv_raw("general.file_type", 10, &7u64.to_le_bytes())
+            .kv_raw("general.quantized", 7, &[1])
+            .kv_array("nested", 9, &[[5u32.to_le_bytes().to_vec(), 1u64.to_le_bytes().to_vec(), 42i32.to_le_bytes().to_vec()].concat()])
+            .build();
+        let file = GgufFile::from_bytes(bytes).unwrap();
+
+        assert_eq!(file.version, 3);
+        assert_eq!(file.alignment, 32);
+        assert!(file.tensors.is_empty());
+        assert_eq!(file.get("general.name").and_then(GgufValue::as_str), Some("tiny-llama"));
+        assert_eq!(file.get("general.file_type"), Some(&GgufValue::U64(7)));
+        assert_eq!(file.get("general.quantized"), Some(&GgufValue::Bool(true)));
+        assert_eq!(file.get("nested"), Some(&GgufValue::Array(vec![GgufValue::Array(vec![GgufValue::I32(42)])])));
+
+        let config = file.model_config().unwrap();
+        assert_eq!(config, GgufModelConfig {
+            architecture: "llama".to_string(),
+            context_length: 2048,
+            embedding_length: 64,
+            block_count: 2,
+            head_count: 8,
+            head_count_kv: 2,
+            rope_freq_base: 500000.0,
+            rms_norm_eps: Some(1e-5),
+        });
+
+        // 可选的超参数使用默认值
+        let minimal = GgufBuilder::new()
+            .kv_string("general.architecture", "gpt2")
+            .kv_raw("gpt2.context_length", 10, &1024u64.to_le_bytes())
+            .kv_u32("gpt2.embedding_length", 768)
+            .kv_u32("gpt2.block_count", 12)
+            .kv_u32("gpt2.attention.head_count", 12)
+            .build();
+        let config = GgufFile::from_bytes(minimal).unwrap().model_config().unwrap();
+        assert_eq!((config.context_length, config.head_count_kv, config.rope_freq_base), (1024, 12, 10000.0));
+        assert_eq!(config.rms_norm_eps, None);
+
+        // 缺少或类型错误的超参数
+        let missing = GgufBuilder::new().kv_string("general.architecture", "llama").build();
+        assert_eq!(
+            GgufFile::from_bytes(missing).unwrap().model_config().unwrap_err(),
+            GgufError::MissingKey("llama.attention.head_count".to_string())
+        );
+        let wrong = GgufBuilder::new()
+            .kv_string("general.architecture", "llama")
+            .kv_u32("llama.context_length", 2048)
+            .kv_u32("llama.embedding_length", 64)
+            .kv_string("llama.block_count", "two")
+            .kv_u32("llama.attention.head_count", 8)
+            .build();
+        let err = GgufFile::from_bytes(wrong).unwrap().model_config().unwrap_err();
+        assert_eq!(err, GgufError::WrongValueType {
+            key: "llama.block_count".to_string(),
+            expected: "非负整数",
+            actual: "字符串",
+        });
+        let wrong = GgufBuilder::new()
+            .kv_string("general.architecture", "llama")
+            .kv_f32("llama.attention.head_count", 8.0)
+            .build();
+        let err = GgufFile::from_bytes(wrong).unwrap().model_config().unwrap_err();
+        assert_eq!(err, GgufError::WrongValueType {
+            key: "llama.attention.head_count".to_string(),
+            expected: "非负整数",
+            actual: "f32",
+        });
+    }
+
+    #[test]
+    fn test_vocab() {
+        let tokens = ["<unk>", "<s>", "</s>", "Ġ", "t", "Ġt", "你好"];
+        let scores: Vec<Vec<u8>> = (0..tokens.len()).map(|i| (-(i as f32)).to_le_bytes().to_vec()).collect();
+        let types: Vec<Vec<u8>> = [2i32, 3, 3, 1, 1, 1, 1].iter().map(|t| t.to_le_bytes().to_vec()).collect();
+        let bytes = GgufBuilder::new()
+            .kv_string("tokenizer.ggml.model", "gpt2")
+            .kv_strings("tokenizer.ggml.tokens", &tokens)
+            .kv_array("tokenizer.ggml.scores", 6, &scores)
+            .kv_array("tokenizer.ggml.token_type", 5, &types)
+            .kv_strings("tokenizer.ggml.merges", &["Ġ t"])
+            .kv_u32("tokenizer.ggml.bos_token_id", 1)
+            .kv_u32("tokenizer.ggml.eos_token_id", 2)
+            .build();
+        let vocab = GgufFile::from_bytes(bytes).unwrap().vocab().unwrap();
+
+        assert_eq!(vocab.model, "gpt2");
+        assert_eq!(vocab.tokens, tokens);
+        assert_eq!(vocab.scores.unwrap()[3], -3.0);
+        assert_eq!(vocab.token_types.unwrap(), vec![2, 3, 3, 1, 1, 1, 1]);
+        assert_eq!(vocab.merges, vec![("Ġ".to_string(), "t".to_string())]);
+        assert_eq!((vocab.bos_token_id, vocab.eos_token_id), (Some(1), Some(2)));
+
+        let base = || GgufBuilder::new().kv_string("tokenizer.ggml.model", "llama").kv_strings("tokenizer.ggml.tokens", &["a", "b"]);
+        let vocab = |b: GgufBuilder| GgufFile::from_bytes(b.build()).unwrap().vocab();
+        let minimal = vocab(base()).unwrap();
+        assert_eq!((minimal.scores, minimal.merges.len(), minimal.bos_token_id), (None, 0, None));
+
+        assert!(matches!(vocab(base().kv_strings("tokenizer.ggml.merges", &["ab"])), Err(GgufError::InvalidValue { .. })));
+        assert!(matches!(vocab(base().kv_u32("tokenizer.ggml.eos_token_id", 2)), Err(GgufError::InvalidValue { .. })));
+        assert!(matches!(
+            vocab(base().kv_array("tokenizer.ggml.scores", 6, &[0f32.to_le_bytes().to_vec()])),
+            Err(GgufError::InvalidValue { .. })
+        ));
+        let err = vocab(base().kv_array("tokenizer.ggml.scores", 4, &[vec![0; 4], vec![0; 4]])).unwrap_err();
+        assert_eq!(err, GgufError::WrongValueType { key: "tokenizer.ggml.scores".to_string(), expected: "f32数组", actual: "u32" });
+        let no_tokens = GgufBuilder::new().kv_string("tokenizer.ggml.model", "llama").build();
+        assert_eq!(
+            GgufFile::from_bytes(no_tokens).unwrap().vocab().unwrap_err(),
+            GgufError::MissingKey("tokenizer.ggml.tokens".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tensor_views() {
+        // Q8_0：d = 0.5，q = -16..16
+        let mut q8 = 0x3800u16.to_le_bytes().to_vec();
+        q8.extend((0..32).map(|i| (i - 16) as i8 as u8));
+        // Q4_0：d = 2.0，每个字节的低4位是3（-5），高4位是12（+4）
+        let mut q4 = 0x4000u16.to_le_bytes().to_vec();
+        q4.extend([0xC3u8; 16]);
+        // Q4_K：d = 1.0，dmin = 0.5，所有子块scale = 1，min = 2，q的低4位是1、高4位是15
+        let mut q4k = [0x3C00u16.to_le_bytes(), 0x3800u16.to_le_bytes()].concat();
+        q4k.extend(get_scales_bytes());
+        q4k.extend([0xF1u8; QK_K / 2]);
+        // Q6_K只能以原始块访问
+        let q6k = vec![0u8; GgmlType::Q6_K.type_size() * 2];
+
+        let f16: Vec<u8> = [0x3C00u16, 0xC000, 0x3555].iter().flat_map(|b| b.to_le_bytes()).collect();
+        let bytes = llama_builder()
+            .tensor("token_embd.weight", &[3, 2], 0, f32_bytes(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]))
+            .tensor("output_norm.weight", &[3], 1, f16)
+            .tensor("bf16", &[2], 30, vec![0x80, 0x3F, 0x00, 0xC0])
+            .tensor("blk.0.attn_q.weight", &[32, 1], 8, q8)
+            .tensor("blk.0.attn_k.weight", &[32], 2, q4)
+            .tensor("blk.0.ffn_up.weight", &[256], 12, q4k)
+            .tensor("blk.0.ffn_down.weight", &[256, 2], 14, q6k)
+            .build();
+        let file = GgufFile::from_bytes(bytes).unwrap();
+
+        assert_eq!(file.tensor_names().len(), 7);
+        assert_eq!(file.data_start % 32, 0);
+        assert!(file.tensors.iter().all(|t| t.offset % 32 == 0));
+
+        let embd = file.tensor("token_embd.weight").unwrap();
+        assert_eq!((embd.info.numel(), embd.row_count()), (6, 2));
+        assert_eq!(embd.typed(), TypedTensor::F32(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        assert_eq!(embd.rows().len(), 2);
+
+        let norm = file.tensor("output_norm.weight").unwrap();
+        assert_eq!(norm.typed(), TypedTensor::F16(vec![0x3C00, 0xC000, 0x3555]));
+        assert_eq!(norm.to_f32().unwrap()[..2], [1.0, -2.0]);
+        assert_eq!(file.tensor("bf16").unwrap().to_f32().unwrap(), vec![1.0, -2.0]);
+
+        let q = file.tensor("blk.0.attn_q.weight").unwrap();
+        let expected: Vec<f32> = (0..32).map(|i| (i - 16) as f32 * 0.5).collect();
+        assert_eq!(q.to_f32().unwrap(), expected);
+        assert!(matches!(q.typed(), TypedTensor::Quantized { ggml_type: GgmlType::Q8_0, ref rows } if rows.len() == 1 && rows[0].len() == 34));
+
+        let k = file.tensor("blk.0.attn_k.weight").unwrap().to_f32().unwrap();
+        assert!(k[..16].iter().all(|&v| v == -10.0) && k[16..].iter().all(|&v| v == 8.0));
+
+        // x = d * scale * q - dmin * min = q - 1
+        let up = file.tensor("blk.0.ffn_up.weight").unwrap().to_f32().unwrap();
+        assert_eq!((up[0], up[31], up[32], up[255]), (0.0, 0.0, 14.0, 14.0));
+
+        let down = file.tensor("blk.0.ffn_down.weight").unwrap();
+        assert!(matches!(down.typed(), TypedTensor::Quantized { ggml_type: GgmlType::Q6_K, ref rows } if rows.len() == 2));
+        assert!(matches!(down.to_f32(), Err(GgufError::UnsupportedConversion { ggml_type: GgmlType::Q6_K, .. })));
+        assert_eq!(file.tensor("missing").unwrap_err(), GgufError::TensorNotFound("missing".to_string()));
+
+        // 自定义对齐
+        let mut builder = GgufBuilder::new().kv_u32("general.alignment", 64)
+            .tensor("a", &[3], 0, f32_bytes(&[1.0, 2.0, 3.0]))
+            .tensor("b", &[1], 0, f32_bytes(&[4.0]));
+        builder.alignment = 64;
+        let file = GgufFile::from_bytes(builder.build()).unwrap();
+        assert_eq!((file.alignment, file.data_start % 64, file.tensors[1].offset), (64, 0, 64));
+        assert_eq!(file.tensor("b").unwrap().to_f32().unwrap(), vec![4.0]);
+    }
+
+    // 8个子块scale = 1、min = 2时的12字节
+    fn get_scales_bytes() -> [u8; K_SCALE_SIZE] {
+        let mut scales = [0u8; K_SCALE_SIZE];
+        scales[..4].fill(1);
+        scales[4..8].fill(2);
+        scales[8..].fill(0x21);
+        for j in 0..8 {
+            assert_eq!(get_scale_min_k4(j, &scales), (1, 2));
+        }
+        scales
+    }
+
+    #[test]
+    fn test_malformed_files() {
+        let load = |bytes: Vec<u8>| GgufFile::from_bytes(bytes).unwrap_err();
+        let valid = llama_builder()
+            .kv_strings("tokenizer.ggml.tokens", &["a", "b"])
+            .tensor("w", &[2, 2], 0, f32_bytes(&[1.0, 2.0, 3.0, 4.0]))
+            .tensor("v", &[32], 8, vec![0; 34])
+            .build();
+        assert!(GgufFile::from_bytes(valid.clone()).is_ok());
+
+        // 任意位置截断都返回错误而不是panic
+        for len in 0..valid.len() {
+            assert!(GgufFile::from_bytes(valid[..len].to_vec()).is_err(), "截断到{}字节应该失败", len);
+        }
+        assert!(matches!(load(valid[..30].to_vec()), GgufError::UnexpectedEof { .. }));
+
+        let mut bad_magic = valid.clone();
+        bad_magic[..4].copy_from_slice(b"GGML");
+        assert_eq!(load(bad_magic), GgufError::InvalidMagic(*b"GGML"));
+        let mut builder = GgufBuilder::new();
+        builder.version = 1;
+        assert_eq!(load(builder.build()), GgufError::UnsupportedVersion(1));
+
+        // 值的错误
+        assert_eq!(
+            load(GgufBuilder::new().kv_raw("x", 13, &[]).build()),
+            GgufError::InvalidValueType { key: "x".to_string(), value_type: 13 }
+        );
+        assert!(matches!(load(GgufBuilder::new().kv_raw("x", 7, &[2]).build()), GgufError::InvalidValue { .. }));
+        assert!(matches!(load(GgufBuilder::new().kv_raw("x", 8, &[2, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFE]).build()), GgufError::InvalidUtf8 { .. }));
+        let huge_array = [4u32.to_le_bytes().to_vec(), u64::MAX.to_le_bytes().to_vec()].concat();
+        assert!(matches!(load(GgufBuilder::new().kv_raw("x", 9, &huge_array).build()), GgufError::UnexpectedEof { .. }));
+        // 元素个数不超过剩余字节数，但按元素的实际大小（f64为8字节）超出了文件，分配之前就返回错误
+        let f64_array = [12u32.to_le_bytes().to_vec(), 4096u64.to_le_bytes().to_vec()].concat();
+        let err = load(GgufBuilder::new().kv_raw("x", 9, &f64_array).tensor("w", &[1024], 0, vec![0; 4096]).build());
+        assert!(matches!(err, GgufError::UnexpectedEof { offset, ref context } if offset < 64 && context.contains('x')),
+                "应该在读取数组长度时就失败: {:?}", err);
+        let mut deep = 0u32.to_le_bytes().to_vec();
+        deep.extend(1u64.to_le_bytes());
+        deep.push(0);
+        for _ in 0..MAX_ARRAY_DEPTH {
+            deep = [9u32.to_le_bytes().to_vec(), 1u64.to_le_bytes().to_vec(), deep].concat();
+        }
+        assert!(matches!(load(GgufBuilder::new().kv_raw("x", 9, &deep).build()), GgufError::InvalidValue { .. }));
+        assert_eq!(load(GgufBuilder::new().kv_u32("x", 1).kv_u32("x", 2).build()), GgufError::DuplicateKey("x".to_string()));
+        assert!(matches!(load(GgufBuilder::new().kv_u32("general.alignment", 24).build()), GgufError::InvalidValue { .. }));
+
+        // 张量信息的错误
+        let tensor = |name: &str, dims: &[u64], type_id: u32, data: Vec<u8>| GgufBuilder::new().tensor(name, dims, type_id, data).build();
+        assert_eq!(load(tensor("w", &[4], 16, vec![0; 16])), GgufError::UnknownTensorType { name: "w".to_string(), type_id: 16 });
+        assert!(matches!(load(tensor("w", &[16], 8, vec![0; 34])), GgufError::InvalidTensor { .. }));
+        assert!(matches!(load(tensor("w", &[1, 1, 1, 1, 1], 0, vec![0; 4])), GgufError::InvalidTensor { .. }));
+        assert!(matches!(load(tensor("w", &[], 0, vec![])), GgufError::InvalidTensor { .. }));
+        assert!(matches!(load(tensor("w", &[u64::MAX, 2], 0, vec![])), GgufError::InvalidTensor { .. }));
+        // 最内层为0时行的字节数为0，外层维度的乘积也不能溢出
+        assert!(matches!(load(tensor("w", &[0, u64::MAX, 2], 0, vec![])), GgufError::InvalidTensor { .. }));
+        assert!(matches!(load(tensor("w", &[4, 0], 0, vec![])), GgufError::InvalidTensor { .. }));
+        assert!(matches!(load(tensor("w", &[8], 0, vec![0; 16])), GgufError::UnexpectedEof { .. }));
+        let duplicate = GgufBuilder::new().tensor("w", &[1], 0, vec![0; 4]).tensor("w", &[1], 0, vec![0; 4]).build();
+        assert_eq!(load(duplicate), GgufError::DuplicateTensor("w".to_string()));
+
+        // 手动修改偏移：未对齐、重叠
+        let two = GgufBuilder::new().tensor("a", &[8], 0, vec![0; 32]).tensor("b", &[8], 0, vec![0; 32]).build();
+        let b_offset = two.windows(2).rposition(|w| w == b"b\x01").unwrap() + 1 + 4 + 8 + 4;
+        let mut misaligned = two.clone();
+        misaligned[b_offset..b_offset + 8].copy_from_slice(&16u64.to_le_bytes());
+        assert!(matches!(load(misaligned), GgufError::InvalidTensor { ref name, .. } if name == "b"));
+        let mut overlap = two.clone();
+        overlap[b_offset..b_offset + 8].copy_from_slice(&0u64.to_le_bytes());
+        overlap.truncate(overlap.len() - 32);
+        let err = load(overlap);
+        assert!(matches!(err, GgufError::InvalidTensor { .. }), "{:?}", err);
+        assert!(err.to_string().contains("重叠"), "{}", err);
+
+        assert!(GgufFile::load("/nonexistent/model.gguf").unwrap_err().to_string().starts_with("IO错误"));
+    }
+}
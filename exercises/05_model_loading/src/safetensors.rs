@@ -0,0 +1,930 @@
+// 要加载真实的模型权重，首先要能读取权重文件。safetensors是Hugging Face使用的格式，结构非常简单：
+// - 8字节小端序u64：JSON头部的长度N
+// - N字节UTF-8编码的JSON头部，例如
+//   {"__metadata__":{"format":"pt"},"w":{"dtype":"F16","shape":[2,3],"data_offsets":[0,12]}}
+//   data_offsets是张量数据在数据区中的 [起始, 结束) 字节偏移
+// - 数据区：所有张量的原始字节（小端序，行优先），依次紧密排列
+// 在这个练习中，我们将实现safetensors的读取（包括一个小型的JSON解析器和完整的校验）和写入，
+// F16/BF16张量通过我们自己的半精度转换函数解码为f32。
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// 辅助函数：f32到f16位模式的转换（舍入到最近值，平局时取偶数）
+// 注意：这个函数与 f16_overflow.rs 中的实现相同，已经提供
+fn f32_to_f16_bits(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xFF) as i32;
+    let man = bits & 0x7FFFFF;
+
+    if exp == 0xFF {
+        if man == 0 {
+            return sign | 0x7C00;
+        }
+        let payload = (man >> 13) as u16;
+        return sign | 0x7C00 | if payload == 0 { 0x200 } else { payload };
+    }
+
+    let e = exp - 127;
+    if e > 15 {
+        return sign | 0x7C00;
+    }
+    if e >= -14 {
+        let half_man = (man >> 13) as u16;
+        let rem = man & 0x1FFF;
+        let half_bits = sign | (((e + 15) as u16) << 10) | half_man;
+        return if rem > 0x1000 || (rem == 0x1000 && (half_man & 1) == 1) {
+            half_bits + 1
+        } else {
+            half_bits
+        };
+    }
+    if e < -25 {
+        return sign;
+    }
+
+    let m = man | 0x800000;
+    let shift = (-e - 1) as u32;
+    let half_man = (m >> shift) as u16;
+    let rem = m & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    if rem > halfway || (rem == halfway && (half_man & 1) == 1) {
+        sign | (half_man + 1)
+    } else {
+        sign | half_man
+    }
+}
+
+// 辅助函数：f16位模式到f32的转换
+// 注意：这个函数与 f16_overflow.rs 中的实现相同，已经提供
+fn f16_bits_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exp = ((bits >> 10) & 0x1F) as i32;
+    let mut frac = (bits & 0x3FF) as u32;
+
+    if exp == 0 {
+        if frac == 0 {
+            return f32::from_bits(sign);
+        }
+        let mut e = -14;
+        while frac & 0x400 == 0 {
+            frac <<= 1;
+            e -= 1;
+        }
+        frac &= 0x3FF;
+        return f32::from_bits(sign | (((e + 127) as u32) << 23) | (frac << 13));
+    } else if exp == 31 {
+        return f32::from_bits(sign | 0x7F800000 | (frac << 13));
+    }
+
+    let adjusted_exp = (exp - 15 + 127) as u32;
+    f32::from_bits(sign | (adjusted_exp << 23) | (frac << 13))
+}
+
+// 辅助函数：f32到bf16位模式的转换（舍入到最近值，平局时取偶数）
+// 注意：这个函数与 bf16_basics.rs 中的实现相同，已经提供
+fn f32_to_bf16_bits(x: f32) -> u16 {
+    let bits = x.to_bits();
+    if x.is_nan() {
+        return ((bits >> 16) as u16) | 0x0040;
+    }
+    let lsb = (bits >> 16) & 1;
+    let rounded = bits.wrapping_add(0x7FFF + lsb);
+    (rounded >> 16) as u16
+}
+
+// 辅助函数：bf16位模式到f32的转换
+// 注意：这个函数与 bf16_basics.rs 中的实现相同，已经提供
+fn bf16_bits_to_f32(bits: u16) -> f32 {
+    f32::from_bits((bits as u32) << 16)
+}
+
+// 头部长度的上限，防止损坏的文件导致分配过大的内存
+const MAX_HEADER_SIZE: u64 = 100_000_000;
+// JSON嵌套的最大深度，防止恶意头部导致栈溢出
+const MAX_JSON_DEPTH: usize = 64;
+
+// 读取和写入safetensors时可能出现的错误
+#[derive(Debug, Clone, PartialEq)]
+enum SafetensorsError {
+    Io(String),
+    HeaderTooShort,
+    HeaderTooLarge(u64),
+    InvalidUtf8,
+    InvalidJson { position: usize, message: String },
+    InvalidHeader(String),
+    UnknownDtype(String),
+    DuplicateTensor(String),
+    InvalidOffsets { name: String, message: String },
+    DataLengthMismatch { expected: usize, actual: usize },
+    TensorNotFound(String),
+    UnsupportedConversion { name: String, dtype: Dtype },
+    ShapeMismatch { shape: Vec<usize>, len: usize },
+}
+
+impl fmt::Display for SafetensorsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SafetensorsError::Io(msg) => write!(f, "IO错误: {}", msg),
+            SafetensorsError::HeaderTooShort => write!(f, "文件不足8字节，无法读取头部长度"),
+            SafetensorsError::HeaderTooLarge(n) => write!(f, "头部长度{}超过了文件长度或上限", n),
+            SafetensorsError::InvalidUtf8 => write!(f, "头部不是有效的UTF-8"),
+            SafetensorsError::InvalidJson { position, message } => {
+                write!(f, "JSON解析错误（位置{}）: {}", position, message)
+            }
+            SafetensorsError::InvalidHeader(msg) => write!(f, "头部格式错误: {}", msg),
+            SafetensorsError::UnknownDtype(dtype) => write!(f, "未知的数据类型: {}", dtype),
+            SafetensorsError::DuplicateTensor(name) => write!(f, "张量{}重复出现", name),
+            SafetensorsError::InvalidOffsets { name, message } => {
+                write!(f, "张量{}的data_offsets无效: {}", name, message)
+            }
+            SafetensorsError::DataLengthMismatch { expected, actual } => {
+                write!(f, "数据区长度为{}字节，但张量总共需要{}字节", actual, expected)
+            }
+            SafetensorsError::TensorNotFound(name) => write!(f, "找不到张量{}", name),
+            SafetensorsError::UnsupportedConversion { name, dtype } => {
+                write!(f, "张量{}的数据类型{}不能转换为f32", name, dtype.name())
+            }
+            SafetensorsError::ShapeMismatch { shape, len } => {
+                write!(f, "{}个元素与形状{:?}不匹配", len, shape)
+            }
+        }
+    }
+}
+
+// safetensors支持的数据类型
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dtype {
+    Bool,
+    U8,
+    I8,
+    I16,
+    U16,
+    F16,
+    BF16,
+    I32,
+    U32,
+    F32,
+    F64,
+    I64,
+    U64,
+}
+
+impl Dtype {
+    const ALL: [Dtype; 13] = [
+        Dtype::Bool, Dtype::U8, Dtype::I8, Dtype::I16, Dtype::U16, Dtype::F16, Dtype::BF16,
+        Dtype::I32, Dtype::U32, Dtype::F32, Dtype::F64, Dtype::I64, Dtype::U64,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Dtype::Bool => "BOOL",
+            Dtype::U8 => "U8",
+            Dtype::I8 => "I8",
+            Dtype::I16 => "I16",
+            Dtype::U16 => "U16",
+            Dtype::F16 => "F16",
+            Dtype::BF16 => "BF16",
+            Dtype::I32 => "I32",
+            Dtype::U32 => "U32",
+            Dtype::F32 => "F32",
+            Dtype::F64 => "F64",
+            Dtype::I64 => "I64",
+            Dtype::U64 => "U64",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Dtype> {
+        Dtype::ALL.iter().copied().find(|d| d.name() == name)
+    }
+
+    // 每个元素的字节数
+    fn size(self) -> usize {
+        match self {
+            Dtype::Bool | Dtype::U8 | Dtype::I8 => 1,
+            Dtype::I16 | Dtype::U16 | Dtype::F16 | Dtype::BF16 => 2,
+            Dtype::I32 | Dtype::U32 | Dtype::F32 => 4,
+            Dtype::F64 | Dtype::I64 | Dtype::U64 => 8,
+        }
+    }
+}
+
+// JSON值。对象保留键的原始顺序
+#[derive(Debug, Clone, PartialEq)]
+enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+// 递归下降的JSON解析器，只需要支持safetensors头部用到的标准JSON
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize, // 当前所在的对象/数组嵌套层数
+}
+
+impl<'a> JsonParser<'a> {
+    fn error<T>(&self, message: &str) -> Result<T, SafetensorsError> {
+        Err(SafetensorsError::InvalidJson { position: self.pos, message: message.to_string() })
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.bytes.len() && matches!(self.bytes[self.pos], b' ' | b'\t' | b'\n' | b'\r') {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), SafetensorsError> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(&format!("应该是'{}'", byte as char))
+        }
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: JsonValue) -> Result<JsonValue, SafetensorsError> {
+        if self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(value)
+        } else {
+            self.error("无效的字面量")
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, SafetensorsError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{' | b'[') => {
+                if self.depth >= MAX_JSON_DEPTH {
+                    return self.error("嵌套过深");
+                }
+                self.depth += 1;
+                let value = if self.peek() == Some(b'{') { self.parse_object() } else { self.parse_array() };
+                self.depth -= 1;
+                value
+            }
+            Some(b'"') => Ok(JsonValue::String(self.parse_string()?)),
+            Some(b't') => self.parse_literal("true", JsonValue::Bool(true)),
+            Some(b'f') => self.parse_literal("false", JsonValue::Bool(false)),
+            Some(b'n') => self.parse_literal("null", JsonValue::Null),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(_) => self.error("意外的字符"),
+            None => self.error("意外的结尾"),
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, SafetensorsError> {
+        self.expect(b'{')?;
+        let mut entries = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(JsonValue::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            let value = self.parse_value()?;
+            entries.push((key, value));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(entries));
+                }
+                _ => return self.error("对象中应该是','或'}'"),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, SafetensorsError> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            items.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(items));
+                }
+                _ => return self.error("数组中应该是','或']'"),
+            }
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, SafetensorsError> {
+        let hex = self.bytes.get(self.pos..self.pos + 4)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u32::from_str_radix(h, 16).ok());
+        match hex {
+            Some(v) => {
+                self.pos += 4;
+                Ok(v)
+            }
+            None => self.error("\\u后面应该是4个十六进制数字"),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, SafetensorsError> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        loop {
+            // 连续的普通字符整段复制，输入是合法的UTF-8，在ASCII字符处切分不会破坏多字节字符
+            let start = self.pos;
+            while self.pos < self.bytes.len() && !matches!(self.bytes[self.pos], b'"' | b'\\' | 0x00..=0x1F) {
+                self.pos += 1;
+            }
+            out.push_str(std::str::from_utf8(&self.bytes[start..self.pos]).expect("输入是合法的UTF-8"));
+
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escape = self.peek();
+                    self.pos += 1;
+                    match escape {
+                        Some(b'"') => out.push('"'),
+                        Some(b'\\') => out.push('\\'),
+                        Some(b'/') => out.push('/'),
+                        Some(b'b') => out.push('\u{8}'),
+                        Some(b'f') => out.push('\u{c}'),
+                        Some(b'n') => out.push('\n'),
+                        Some(b'r') => out.push('\r'),
+                        Some(b't') => out.push('\t'),
+                        Some(b'u') => {
+                            let mut code = self.parse_hex4()?;
+                            // UTF-16代理对
+                            if (0xD800..0xDC00).contains(&code) {
+                                if !self.bytes[self.pos..].starts_with(b"\\u") {
+                                    return self.error("缺少低位代理");
+                                }
+                                self.pos += 2;
+                                let low = self.parse_hex4()?;
+                                if !(0xDC00..0xE000).contains(&low) {
+                                    return self.error("无效的低位代理");
+                                }
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            }
+                            match char::from_u32(code) {
+                                Some(c) => out.push(c),
+                                None => return self.error("无效的Unicode码点"),
+                            }
+                        }
+                        _ => {
+                            self.pos -= 1;
+                            return self.error("无效的转义字符");
+                        }
+                    }
+                }
+                Some(_) => return self.error("字符串中不能有未转义的控制字符"),
+                None => return self.error("字符串没有结束"),
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, SafetensorsError> {
+        let start = self.pos;
+        while self.pos < self.bytes.len() && matches!(self.bytes[self.pos], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).expect("数字只包含ASCII字符");
+        match text.parse::<f64>() {
+            Ok(v) => Ok(JsonValue::Number(v)),
+            Err(_) => {
+                self.pos = start;
+                self.error("无效的数字")
+            }
+        }
+    }
+}
+
+fn parse_json(text: &str) -> Result<JsonValue, SafetensorsError> {
+    let mut parser = JsonParser { bytes: text.as_bytes(), pos: 0, depth: 0 };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.pos != parser.bytes.len() {
+        return parser.error("JSON值之后还有多余的内容");
+    }
+    Ok(value)
+}
+
+// 把字符串写成JSON字符串字面量
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// 头部中一个张量的描述
+#[derive(Debug, Clone, PartialEq)]
+struct TensorInfo {
+    dtype: Dtype,
+    shape: Vec<usize>,
+    data_offsets: (usize, usize),
+}
+
+impl TensorInfo {
+    fn numel(&self) -> usize {
+        self.shape.iter().product()
+    }
+}
+
+// JSON数字转换为非负整数，不是整数或超出范围时返回None
+fn json_to_usize(value: &JsonValue) -> Option<usize> {
+    match value {
+        JsonValue::Number(n) if *n >= 0.0 && n.fract() == 0.0 && *n <= (1u64 << 53) as f64 => Some(*n as usize),
+        _ => None,
+    }
+}
+
+fn parse_tensor_info(name: &str, value: &JsonValue) -> Result<TensorInfo, SafetensorsError> {
+    let invalid = |msg: &str| SafetensorsError::InvalidHeader(format!("张量{}: {}", name, msg));
+    let fields = match value {
+        JsonValue::Object(fields) => fields,
+        _ => return Err(invalid("描述必须是JSON对象")),
+    };
+
+    let (mut dtype, mut shape, mut offsets) = (None, None, None);
+    for (key, v) in fields {
+        match (key.as_str(), v) {
+            ("dtype", JsonValue::String(s)) => {
+                dtype = Some(Dtype::from_name(s).ok_or_else(|| SafetensorsError::UnknownDtype(s.clone()))?);
+            }
+            ("shape", JsonValue::Array(dims)) => {
+                let dims: Option<Vec<usize>> = dims.iter().map(json_to_usize).collect();
+                shape = Some(dims.ok_or_else(|| invalid("shape必须是非负整数数组"))?);
+            }
+            ("data_offsets", JsonValue::Array(pair)) => {
+                let pair: Option<Vec<usize>> = pair.iter().map(json_to_usize).collect();
+                match pair.as_deref() {
+                    Some(&[begin, end]) => offsets = Some((begin, end)),
+                    _ => return Err(invalid("data_offsets必须是两个非负整数")),
+                }
+            }
+            ("dtype" | "shape" | "data_offsets", _) => return Err(invalid(&format!("字段{}的类型错误", key))),
+            _ => return Err(invalid(&format!("未知字段{}", key))),
+        }
+    }
+
+    Ok(TensorInfo {
+        dtype: dtype.ok_or_else(|| invalid("缺少dtype"))?,
+        shape: shape.ok_or_else(|| invalid("缺少shape"))?,
+        data_offsets: offsets.ok_or_else(|| invalid("缺少data_offsets"))?,
+    })
+}
+
+// 解析后的safetensors文件
+// 整个文件读入内存，张量数据通过TensorView按需借用和解码
+#[derive(Debug)]
+struct SafeTensors {
+    bytes: Vec<u8>,
+    data_start: usize,
+    tensors: Vec<(String, TensorInfo)>, // 按数据偏移排序
+    metadata: Vec<(String, String)>,
+}
+
+impl SafeTensors {
+    fn load<P: AsRef<Path>>(path: P) -> Result<Self, SafetensorsError> {
+        let bytes = fs::read(path).map_err(|e| SafetensorsError::Io(e.to_string()))?;
+        SafeTensors::from_bytes(bytes)
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, SafetensorsError> {
+        if bytes.len() < 8 {
+            return Err(SafetensorsError::HeaderTooShort);
+        }
+        let header_len = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+        if header_len > MAX_HEADER_SIZE || header_len > (bytes.len() - 8) as u64 {
+            return Err(SafetensorsError::HeaderTooLarge(header_len));
+        }
+        let data_start = 8 + header_len as usize;
+        let header = std::str::from_utf8(&bytes[8..data_start]).map_err(|_| SafetensorsError::InvalidUtf8)?;
+
+        let entries = match parse_json(header)? {
+            JsonValue::Object(entries) => entries,
+            _ => return Err(SafetensorsError::InvalidHeader("头部必须是JSON对象".to_string())),
+        };
+
+        let mut tensors: Vec<(String, TensorInfo)> = Vec::new();
+        let mut metadata = Vec::new();
+        for (name, value) in &entries {
+            if name == "__metadata__" {
+                let fields = match value {
+                    JsonValue::Object(fields) => fields,
+                    _ => return Err(SafetensorsError::InvalidHeader("__metadata__必须是JSON对象".to_string())),
+                };
+                for (k, v) in fields {
+                    match v {
+                        JsonValue::String(s) => metadata.push((k.clone(), s.clone())),
+                        _ => return Err(SafetensorsError::InvalidHeader(format!("__metadata__中{}的值必须是字符串", k))),
+                    }
+                }
+                continue;
+            }
+            if tensors.iter().any(|(n, _)| n == name) {
+                return Err(SafetensorsError::DuplicateTensor(name.clone()));
+            }
+            tensors.push((name.clone(), parse_tensor_info(name, value)?));
+        }
+
+        // 张量数据必须从0开始依次紧密排列，没有空隙和重叠，并且正好填满数据区
+        tensors.sort_by_key(|(_, info)| info.data_offsets);
+        let mut expected_begin = 0;
+        for (name, info) in &tensors {
+            let (begin, end) = info.data_offsets;
+            let invalid = |message: String| SafetensorsError::InvalidOffsets { name: name.clone(), message };
+            if begin != expected_begin {
+                return Err(invalid(format!("起始偏移应该是{}，实际是{}", expected_begin, begin)));
+            }
+            if end < begin {
+                return Err(invalid(format!("结束偏移{}小于起始偏移{}", end, begin)));
+            }
+            let size = info.shape.iter()
+                .try_fold(info.dtype.size(), |acc, &d| acc.checked_mul(d))
+                .ok_or_else(|| invalid("张量大小溢出".to_string()))?;
+            if end - begin != size {
+                return Err(invalid(format!(
+                    "形状{:?}的{}张量需要{}字节，实际是{}字节", info.shape, info.dtype.name(), size, end - begin
+                )));
+            }
+            expected_begin = end;
+        }
+        let data_len = bytes.len() - data_start;
+        if expected_begin != data_len {
+            return Err(SafetensorsError::DataLengthMismatch { expected: expected_begin, actual: data_len });
+        }
+
+        Ok(SafeTensors { bytes, data_start, tensors, metadata })
+    }
+
+    fn names(&self) -> Vec<&str> {
+        self.tensors.iter().map(|(n, _)| n.as_str()).collect()
+    }
+
+    fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    fn tensor(&self, name: &str) -> Result<TensorView<'_>, SafetensorsError> {
+        let (name, info) = self.tensors.iter()
+            .find(|(n, _)| n == name)
+            .ok_or_else(|| SafetensorsError::TensorNotFound(name.to_string()))?;
+        let (begin, end) = info.data_offsets;
+        Ok(TensorView {
+            name,
+            dtype: info.dtype,
+            shape: &info.shape,
+            data: &self.bytes[self.data_start + begin..self.data_start + end],
+        })
+    }
+}
+
+// 借用文件数据的张量视图
+#[derive(Debug, Clone, Copy)]
+struct TensorView<'a> {
+    name: &'a str,
+    dtype: Dtype,
+    shape: &'a [usize],
+    data: &'a [u8],
+}
+
+impl TensorView<'_> {
+    fn numel(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    // 解码为f32，F16和BF16使用项目自己的半精度转换函数
+    fn to_f32(self) -> Result<Vec<f32>, SafetensorsError> {
+        let values = match self.dtype {
+            Dtype::F32 => self.data.chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+            Dtype::F16 => self.data.chunks_exact(2)
+                .map(|b| f16_bits_to_f32(u16::from_le_bytes([b[0], b[1]])))
+                .collect(),
+            Dtype::BF16 => self.data.chunks_exact(2)
+                .map(|b| bf16_bits_to_f32(u16::from_le_bytes([b[0], b[1]])))
+                .collect(),
+            dtype => {
+                return Err(SafetensorsError::UnsupportedConversion { name: self.name.to_string(), dtype });
+            }
+        };
+        Ok(values)
+    }
+
+    // 解码为矩阵，例如线性层形状为 [out_features, in_features] 的权重
+    fn to_matrix(self) -> Result<Vec<Vec<f32>>, SafetensorsError> {
+        let cols = match self.shape {
+            [_, cols] => *cols,
+            _ => {
+                return Err(SafetensorsError::InvalidHeader(format!(
+                    "张量{}的形状是{:?}，不是矩阵", self.name, self.shape
+                )));
+            }
+        };
+        let values = self.to_f32()?;
+        if cols == 0 {
+            return Ok(vec![Vec::new(); self.shape[0]]);
+        }
+        Ok(values.chunks(cols).map(|r| r.to_vec()).collect())
+    }
+}
+
+// 待写入的张量
+#[derive(Debug, Clone, PartialEq)]
+struct TensorData {
+    dtype: Dtype,
+    shape: Vec<usize>,
+    data: Vec<u8>,
+}
+
+impl TensorData {
+    // 从f32数据创建张量，按dtype编码（只支持F32、F16和BF16）
+    fn from_f32(values: &[f32], shape: &[usize], dtype: Dtype) -> Result<Self, SafetensorsError> {
+        if shape.iter().try_fold(1usize, |acc, &d| acc.checked_mul(d)) != Some(values.len()) {
+            return Err(SafetensorsError::ShapeMismatch { shape: shape.to_vec(), len: values.len() });
+        }
+        let data: Vec<u8> = match dtype {
+            Dtype::F32 => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            Dtype::F16 => values.iter().flat_map(|&v| f32_to_f16_bits(v).to_le_bytes()).collect(),
+            Dtype::BF16 => values.iter().flat_map(|&v| f32_to_bf16_bits(v).to_le_bytes()).collect(),
+            dtype => {
+                return Err(SafetensorsError::UnsupportedConversion { name: String::new(), dtype });
+            }
+        };
+        Ok(TensorData { dtype, shape: shape.to_vec(), data })
+    }
+}
+
+// 序列化为safetensors格式
+// 张量按给定的顺序写入，头部用空格填充到8字节的整数倍，使数据区对齐
+fn serialize(tensors: &[(&str, &TensorData)], metadata: &[(&str, &str)]) -> Vec<u8> {
+    let mut header = String::from("{");
+    if !metadata.is_empty() {
+        header.push_str("\"__metadata__\":{");
+        for (i, (k, v)) in metadata.iter().enumerate() {
+            if i > 0 {
+                header.push(',');
+            }
+            write_json_string(&mut header, k);
+            header.push(':');
+            write_json_string(&mut header, v);
+        }
+        header.push('}');
+    }
+
+    let mut offset = 0;
+    for (i, (name, tensor)) in tensors.iter().enumerate() {
+        assert_eq!(
+            tensor.data.len(),
+            tensor.shape.iter().product::<usize>() * tensor.dtype.size(),
+            "张量{}的数据长度与形状不匹配", name
+        );
+        if i > 0 || !metadata.is_empty() {
+            header.push(',');
+        }
+        write_json_string(&mut header, name);
+        let shape: Vec<String> = tensor.shape.iter().map(|d| d.to_string()).collect();
+        header.push_str(&format!(
+            ":{{\"dtype\":\"{}\",\"shape\":[{}],\"data_offsets\":[{},{}]}}",
+            tensor.dtype.name(), shape.join(","), offset, offset + tensor.data.len()
+        ));
+        offset += tensor.data.len();
+    }
+    header.push('}');
+    while header.len() % 8 != 0 {
+        header.push(' ');
+    }
+
+    let mut out = Vec::with_capacity(8 + header.len() + offset);
+    out.extend_from_slice(&(header.len() as u64).to_le_bytes());
+    out.extend_from_slice(header.as_bytes());
+    for (_, tensor) in tensors {
+        out.extend_from_slice(&tensor.data);
+    }
+    out
+}
+
+fn save<P: AsRef<Path>>(
+    path: P,
+    tensors: &[(&str, &TensorData)],
+    metadata: &[(&str, &str)],
+) -> Result<(), SafetensorsError> {
+    fs::write(path, serialize(tensors, metadata)).map_err(|e| SafetensorsError::Io(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // 测试中生成的临时文件，离开作用域时删除
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            TempFile(std::env::temp_dir().join(format!("rustlings_llm_{}_{}", std::process::id(), name)))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    // 由头部字符串和数据区构造文件内容
+    fn raw_file(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let value = parse_json(r#" { "a" : [1, -2.5, 3e2, true, false, null], "b": {"c": "x\"y\\z\n\u00e9\ud83d\ude00"} } "#).unwrap();
+        assert_eq!(value, JsonValue::Object(vec![
+            ("a".to_string(), JsonValue::Array(vec![
+                JsonValue::Number(1.0), JsonValue::Number(-2.5), JsonValue::Number(300.0),
+                JsonValue::Bool(true), JsonValue::Bool(false), JsonValue::Null,
+            ])),
+            ("b".to_string(), JsonValue::Object(vec![
+                ("c".to_string(), JsonValue::String("x\"y\\z\né😀".to_string())),
+            ])),
+        ]));
+        assert_eq!(parse_json("\"中文\"").unwrap(), JsonValue::String("中文".to_string()));
+        assert_eq!(parse_json("[]").unwrap(), JsonValue::Array(vec![]));
+
+        for bad in ["{", "[1,]", "{\"a\" 1}", "tru", "\"abc", "{} x", "\"\\q\"", "-", "\"\\ud83d\""] {
+            assert!(matches!(parse_json(bad), Err(SafetensorsError::InvalidJson { .. })), "{}应该解析失败", bad);
+        }
+        match parse_json("[1, 2, @]") {
+            Err(SafetensorsError::InvalidJson { position, .. }) => assert_eq!(position, 7),
+            other => panic!("{:?}", other),
+        }
+
+        let mut s = String::new();
+        write_json_string(&mut s, "a\"b\\c\nd\u{1}");
+        assert_eq!(s, r#""a\"b\\c\nd\u0001""#);
+        assert_eq!(parse_json(&s).unwrap(), JsonValue::String("a\"b\\c\nd\u{1}".to_string()));
+    }
+
+    #[test]
+    fn test_read_known_bytes() {
+        let header = r#"{"__metadata__":{"format":"pt"},"w":{"dtype":"F16","shape":[2],"data_offsets":[0,4]},"b":{"dtype":"BF16","shape":[1,2],"data_offsets":[4,8]}}"#;
+        // f16: 1.0, -2.0；bf16: 0.5, 3.0
+        let data = [0x00, 0x3C, 0x00, 0xC0, 0x00, 0x3F, 0x40, 0x40];
+        let st = SafeTensors::from_bytes(raw_file(header, &data)).unwrap();
+
+        assert_eq!(st.names(), vec!["w", "b"]);
+        assert_eq!(st.metadata("format"), Some("pt"));
+        let w = st.tensor("w").unwrap();
+        assert_eq!(w.dtype, Dtype::F16);
+        assert_eq!(w.shape, &[2]);
+        assert_eq!(w.to_f32().unwrap(), vec![1.0, -2.0]);
+        let b = st.tensor("b").unwrap();
+        assert_eq!(b.to_matrix().unwrap(), vec![vec![0.5, 3.0]]);
+        assert_eq!(st.tensor("missing").unwrap_err(), SafetensorsError::TensorNotFound("missing".to_string()));
+    }
+
+    #[test]
+    fn test_round_trip_through_file() {
+        let weight = vec![vec![0.1, -0.2, 0.3], vec![1.5, -2.5, 65504.0]];
+        let flat: Vec<f32> = weight.iter().flatten().copied().collect();
+        let w32 = TensorData::from_f32(&flat, &[2, 3], Dtype::F32).unwrap();
+        let w16 = TensorData::from_f32(&flat, &[2, 3], Dtype::F16).unwrap();
+        let wbf = TensorData::from_f32(&flat, &[2, 3], Dtype::BF16).unwrap();
+        let scalar = TensorData::from_f32(&[7.0], &[], Dtype::F32).unwrap();
+        let empty = TensorData::from_f32(&[], &[0, 4], Dtype::F16).unwrap();
+        assert_eq!(
+            TensorData::from_f32(&flat, &[4, 2], Dtype::F32).unwrap_err(),
+            SafetensorsError::ShapeMismatch { shape: vec![4, 2], len: 6 }
+        );
+        assert!(TensorData::from_f32(&flat, &[usize::MAX, 2], Dtype::F16).is_err(), "形状的乘积溢出");
+        let ids = TensorData { dtype: Dtype::I64, shape: vec![2], data: [3i64, -1].iter().flat_map(|v| v.to_le_bytes()).collect() };
+
+        let file = TempFile::new("round_trip.safetensors");
+        save(
+            &file.0,
+            &[("w32", &w32), ("w16", &w16), ("wbf", &wbf), ("scalar", &scalar), ("empty", &empty), ("ids", &ids)],
+            &[("format", "pt"), ("note", "带\"引号\"的说明")],
+        ).unwrap();
+
+        let bytes = fs::read(&file.0).unwrap();
+        let header_len = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+        assert_eq!(header_len % 8, 0, "头部填充到8字节的整数倍");
+
+        let st = SafeTensors::load(&file.0).unwrap();
+        assert_eq!(st.metadata("note"), Some("带\"引号\"的说明"));
+        assert_eq!(st.tensor("w32").unwrap().to_matrix().unwrap(), weight, "f32是无损的");
+
+        // 半精度的结果与直接舍入一致
+        let w16_back = st.tensor("w16").unwrap().to_f32().unwrap();
+        let expected: Vec<f32> = flat.iter().map(|&v| f16_bits_to_f32(f32_to_f16_bits(v))).collect();
+        assert_eq!(w16_back, expected);
+        let wbf_back = st.tensor("wbf").unwrap().to_f32().unwrap();
+        let expected: Vec<f32> = flat.iter().map(|&v| bf16_bits_to_f32(f32_to_bf16_bits(v))).collect();
+        assert_eq!(wbf_back, expected);
+
+        let s = st.tensor("scalar").unwrap();
+        assert_eq!((s.numel(), s.to_f32().unwrap()), (1, vec![7.0]), "形状为[]的标量有1个元素");
+        assert_eq!(st.tensor("empty").unwrap().to_matrix().unwrap(), Vec::<Vec<f32>>::new());
+
+        let ids_view = st.tensor("ids").unwrap();
+        assert_eq!(ids_view.data, &ids.data[..]);
+        assert!(matches!(ids_view.to_f32(), Err(SafetensorsError::UnsupportedConversion { dtype: Dtype::I64, .. })));
+
+        // 再次写入得到完全相同的字节
+        let reloaded: Vec<(String, TensorData)> = st.tensors.iter()
+            .map(|(name, info)| {
+                let view = st.tensor(name).unwrap();
+                (name.clone(), TensorData { dtype: info.dtype, shape: info.shape.clone(), data: view.data.to_vec() })
+            })
+            .collect();
+        let refs: Vec<(&str, &TensorData)> = reloaded.iter().map(|(n, t)| (n.as_str(), t)).collect();
+        let again = SafeTensors::from_bytes(serialize(&refs, &[("format", "pt"), ("note", "带\"引号\"的说明")])).unwrap();
+        for name in st.names() {
+            assert_eq!(again.tensor(name).unwrap().data, st.tensor(name).unwrap().data);
+        }
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let t = |dtype: &str, shape: &str, offsets: &str| {
+            format!(r#"{{"w":{{"dtype":"{}","shape":{},"data_offsets":{}}}}}"#, dtype, shape, offsets)
+        };
+        let data = [0u8; 8];
+        let load = |header: &str, data: &[u8]| SafeTensors::from_bytes(raw_file(header, data)).unwrap_err();
+
+        assert_eq!(SafeTensors::from_bytes(vec![1, 2, 3]).unwrap_err(), SafetensorsError::HeaderTooShort);
+        let mut too_long = 1000u64.to_le_bytes().to_vec();
+        too_long.extend_from_slice(b"{}");
+        assert_eq!(SafeTensors::from_bytes(too_long).unwrap_err(), SafetensorsError::HeaderTooLarge(1000));
+        assert!(matches!(load("{\"w\":", &[]), SafetensorsError::InvalidJson { .. }));
+        // 嵌套过深的头部返回错误而不是栈溢出
+        assert!(matches!(load(&"[".repeat(200_000), &[]), SafetensorsError::InvalidJson { position: 64, .. }));
+        let nested = format!("{{\"a\":{}1{}}}", "[".repeat(62), "]".repeat(62));
+        assert!(matches!(load(&nested, &[]), SafetensorsError::InvalidHeader(_)), "最大深度以内可以正常解析");
+        assert!(matches!(load("[1, 2]", &[]), SafetensorsError::InvalidHeader(_)));
+        assert_eq!(load(&t("F8", "[2]", "[0,2]"), &data[..2]), SafetensorsError::UnknownDtype("F8".to_string()));
+        assert!(matches!(load(&t("F32", "[2, -1]", "[0,8]"), &data), SafetensorsError::InvalidHeader(_)));
+        assert!(matches!(load(&t("F32", "[2]", "[0]"), &data), SafetensorsError::InvalidHeader(_)));
+        assert!(matches!(load(r#"{"w":{"dtype":"F32","shape":[2]}}"#, &data), SafetensorsError::InvalidHeader(_)));
+        assert!(matches!(load(r#"{"__metadata__":{"a":1}}"#, &[]), SafetensorsError::InvalidHeader(_)));
+
+        // 形状与字节数不匹配
+        assert!(matches!(load(&t("F32", "[3]", "[0,8]"), &data), SafetensorsError::InvalidOffsets { .. }));
+        // 数据区比张量需要的长
+        assert_eq!(load(&t("F32", "[1]", "[0,4]"), &data), SafetensorsError::DataLengthMismatch { expected: 4, actual: 8 });
+        // 结束偏移超出数据区
+        assert_eq!(load(&t("F32", "[4]", "[0,16]"), &data), SafetensorsError::DataLengthMismatch { expected: 16, actual: 8 });
+
+        // 两个张量之间有空隙或重叠
+        let gap = r#"{"a":{"dtype":"U8","shape":[2],"data_offsets":[0,2]},"b":{"dtype":"U8","shape":[2],"data_offsets":[4,6]}}"#;
+        assert!(matches!(load(gap, &data[..6]), SafetensorsError::InvalidOffsets { name, .. } if name == "b"));
+        let overlap = r#"{"a":{"dtype":"U8","shape":[4],"data_offsets":[0,4]},"b":{"dtype":"U8","shape":[4],"data_offsets":[2,6]}}"#;
+        assert!(matches!(load(overlap, &data[..6]), SafetensorsError::InvalidOffsets { .. }));
+        let duplicate = r#"{"a":{"dtype":"U8","shape":[1],"data_offsets":[0,1]},"a":{"dtype":"U8","shape":[1],"data_offsets":[1,2]}}"#;
+        assert_eq!(load(duplicate, &data[..2]), SafetensorsError::DuplicateTensor("a".to_string()));
+
+        // 错误信息
+        let err = load(&t("F32", "[3]", "[0,8]"), &data);
+        assert!(err.to_string().contains("张量w"), "{}", err);
+        assert!(SafeTensors::load("/nonexistent/model.safetensors").unwrap_err().to_string().starts_with("IO错误"));
+    }
+}
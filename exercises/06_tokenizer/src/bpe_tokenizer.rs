@@ -0,0 +1,576 @@
+// 模型只能处理token ID，因此文本进入模型之前需要先分词。GPT-2使用字节级BPE（Byte Pair Encoding）：
+// 1. 预分词：用正则表达式把文本切成“单词”（字母串、数字串、标点串、空白），空格通常附着在下一个单词的开头
+// 2. 每个单词先拆成UTF-8字节，每个字节是一个初始token，所以任何文本都能被编码，不存在未知字符
+// 3. 按合并规则的优先级（rank越小越优先）反复合并相邻的token对，直到没有可以合并的对
+// 为了让词表文件可读，字节通过bytes_to_unicode映射为可见字符，例如空格映射为'Ġ'，换行映射为'Ċ'。
+// 在这个练习中，我们将实现词表文件的加载、编码、解码，以及BOS/EOS/PAD等特殊token的处理。
+//
+// 词表文件格式：
+// [vocab]            之后每行一个token，行号（从0开始）就是token ID
+// [merges]           之后每行一条合并规则"a b"，按优先级从高到低排列
+// [special]          之后每行"名称 token"，名称是bos、eos或pad，token必须在词表中
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// 分词器可能出现的错误
+#[derive(Debug, Clone, PartialEq)]
+enum TokenizerError {
+    Io(String),
+    Parse { line: usize, message: String },
+    UnknownId(u32),
+    MissingSpecialToken(&'static str),
+}
+
+impl fmt::Display for TokenizerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenizerError::Io(msg) => write!(f, "IO错误: {}", msg),
+            TokenizerError::Parse { line, message } => write!(f, "词表文件第{}行: {}", line, message),
+            TokenizerError::UnknownId(id) => write!(f, "token ID {}不在词表中", id),
+            TokenizerError::MissingSpecialToken(name) => write!(f, "词表中没有定义特殊token {}", name),
+        }
+    }
+}
+
+// GPT-2的字节到字符映射：可见的Latin-1字符映射为自身，其余字节（控制字符、空格等）依次映射到U+0100之后的字符
+fn bytes_to_unicode() -> [char; 256] {
+    let mut table = ['\0'; 256];
+    let mut next = 256u32;
+    for b in 0..256u32 {
+        let visible = (b'!' as u32..=b'~' as u32).contains(&b) || (0xA1..=0xAC).contains(&b) || (0xAE..=0xFF).contains(&b);
+        table[b as usize] = if visible {
+            char::from_u32(b).unwrap()
+        } else {
+            next += 1;
+            char::from_u32(next - 1).unwrap()
+        };
+    }
+    table
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CharClass {
+    Letter,
+    Number,
+    Whitespace,
+    Other,
+}
+
+// 用标准库近似Unicode通用类别：is_alphabetic比\p{L}多了部分组合标记和Nl类的字母数字（如罗马数字Ⅻ），
+// 这些字符会被归为字母而不是标点或数字；ASCII和常见文字中两者一致
+fn char_class(c: char) -> CharClass {
+    if c.is_alphabetic() {
+        CharClass::Letter
+    } else if c.is_numeric() {
+        CharClass::Number
+    } else if c.is_whitespace() {
+        CharClass::Whitespace
+    } else {
+        CharClass::Other
+    }
+}
+
+// GPT-2的预分词，对应正则表达式
+//   's|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+(?!\S)|\s+
+// 这里不依赖正则库，按同样的优先级手动匹配。所有片段拼接起来等于原文。
+// 字符类别由char_class近似判断，对少数组合标记等字符的切分可能与正则不同，是近似而不是完全等价。
+fn pre_tokenize(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let byte_pos = |i: usize| chars.get(i).map_or(text.len(), |&(p, _)| p);
+    let class_at = |i: usize| chars.get(i).map(|&(_, c)| char_class(c));
+    let mut pieces = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i].1;
+
+        // 英文缩写
+        if c == '\'' {
+            let rest = &text[byte_pos(i + 1)..];
+            if let Some(suffix) = ["s", "t", "re", "ve", "m", "ll", "d"].iter().find(|s| rest.starts_with(*s)) {
+                i += 1 + suffix.len();
+                pieces.push(&text[byte_pos(start)..byte_pos(i)]);
+                continue;
+            }
+        }
+
+        // 可选的一个空格 + 同一类字符（字母、数字或其他符号）组成的串
+        let word_start = if c == ' ' && matches!(class_at(i + 1), Some(cls) if cls != CharClass::Whitespace) {
+            i + 1
+        } else {
+            i
+        };
+        let class = class_at(word_start).unwrap();
+        if class != CharClass::Whitespace {
+            i = word_start;
+            while class_at(i) == Some(class) {
+                i += 1;
+            }
+        } else {
+            // 空白串：如果后面还有非空白字符，最后一个空白留给下一个单词（\s+(?!\S)），只有一个空白时单独成为一段（\s+）
+            while class_at(i) == Some(CharClass::Whitespace) {
+                i += 1;
+            }
+            if i < chars.len() && i - start > 1 {
+                i -= 1;
+            }
+        }
+        pieces.push(&text[byte_pos(start)..byte_pos(i)]);
+    }
+    pieces
+}
+
+// 填充后的token ID和对应的注意力掩码
+type PaddedBatch = (Vec<Vec<u32>>, Vec<Vec<bool>>);
+
+// 特殊token的ID
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct SpecialTokens {
+    bos: Option<u32>,
+    eos: Option<u32>,
+    pad: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+struct BpeTokenizer {
+    vocab: Vec<String>,
+    token_to_id: HashMap<String, u32>,
+    merges: Vec<(u32, u32)>,
+    merge_ranks: HashMap<(u32, u32), (usize, u32)>, // (左, 右) -> (优先级, 合并后的ID)
+    byte_ids: [u32; 256],
+    token_bytes: Vec<Vec<u8>>, // 每个token解码后的字节
+    special: SpecialTokens,
+    special_ids: Vec<u32>,     // 按文本长度从长到短排列，匹配时优先匹配更长的
+}
+
+impl BpeTokenizer {
+    fn load<P: AsRef<Path>>(path: P) -> Result<Self, TokenizerError> {
+        let text = fs::read_to_string(path).map_err(|e| TokenizerError::Io(e.to_string()))?;
+        BpeTokenizer::from_vocab_file(&text)
+    }
+
+    fn from_vocab_file(text: &str) -> Result<Self, TokenizerError> {
+        #[derive(PartialEq)]
+        enum Section {
+            None,
+            Vocab,
+            Merges,
+            Special,
+        }
+
+        let mut section = Section::None;
+        let mut vocab: Vec<String> = Vec::new();
+        let mut vocab_lines = Vec::new();
+        let mut token_to_id = HashMap::new();
+        let mut merge_lines = Vec::new();
+        let mut special_lines = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line_no = index + 1;
+            let err = |message: String| TokenizerError::Parse { line: line_no, message };
+            match line {
+                "[vocab]" => section = Section::Vocab,
+                "[merges]" => section = Section::Merges,
+                "[special]" => section = Section::Special,
+                _ => match section {
+                    // 第一个段落之前的内容是注释
+                    Section::None => {}
+                    Section::Vocab => {
+                        if line.is_empty() {
+                            return Err(err("token不能为空".to_string()));
+                        }
+                        if token_to_id.insert(line.to_string(), vocab.len() as u32).is_some() {
+                            return Err(err(format!("token {:?}重复出现", line)));
+                        }
+                        vocab.push(line.to_string());
+                        vocab_lines.push(line_no);
+                    }
+                    Section::Merges => merge_lines.push((line_no, line)),
+                    Section::Special => special_lines.push((line_no, line)),
+                },
+            }
+        }
+        if vocab.is_empty() {
+            return Err(TokenizerError::Parse { line: 0, message: "缺少[vocab]段落".to_string() });
+        }
+
+        let lookup = |line: usize, token: &str| {
+            token_to_id.get(token).copied().ok_or_else(|| TokenizerError::Parse {
+                line,
+                message: format!("token {:?}不在词表中", token),
+            })
+        };
+
+        let mut special = SpecialTokens::default();
+        let mut special_ids = Vec::new();
+        for (line, text) in special_lines {
+            let (name, token) = text.split_once(' ').ok_or_else(|| TokenizerError::Parse {
+                line,
+                message: "应该是\"名称 token\"".to_string(),
+            })?;
+            let id = lookup(line, token)?;
+            let slot = match name {
+                "bos" => &mut special.bos,
+                "eos" => &mut special.eos,
+                "pad" => &mut special.pad,
+                _ => return Err(TokenizerError::Parse { line, message: format!("未知的特殊token名称{}", name) }),
+            };
+            *slot = Some(id);
+            if !special_ids.contains(&id) {
+                special_ids.push(id);
+            }
+        }
+        special_ids.sort_by_key(|&id| std::cmp::Reverse(vocab[id as usize].len()));
+
+        // 普通token由映射后的字节字符组成，特殊token按原文解码
+        let byte_encoder = bytes_to_unicode();
+        let byte_decoder: HashMap<char, u8> = byte_encoder.iter().enumerate().map(|(b, &c)| (c, b as u8)).collect();
+        let mut token_bytes = Vec::with_capacity(vocab.len());
+        for (id, token) in vocab.iter().enumerate() {
+            if special_ids.contains(&(id as u32)) {
+                token_bytes.push(token.as_bytes().to_vec());
+                continue;
+            }
+            let bytes: Option<Vec<u8>> = token.chars().map(|c| byte_decoder.get(&c).copied()).collect();
+            token_bytes.push(bytes.ok_or_else(|| TokenizerError::Parse {
+                line: vocab_lines[id],
+                message: format!("token {:?}包含不属于字节映射的字符", token),
+            })?);
+        }
+
+        let mut byte_ids = [0u32; 256];
+        for (b, c) in byte_encoder.iter().enumerate() {
+            byte_ids[b] = token_to_id.get(&c.to_string()).copied().ok_or_else(|| TokenizerError::Parse {
+                line: 0,
+                message: format!("词表缺少字节0x{:02X}对应的token {:?}", b, c),
+            })?;
+        }
+
+        let mut merges = Vec::with_capacity(merge_lines.len());
+        let mut merge_ranks = HashMap::new();
+        for (rank, (line, text)) in merge_lines.into_iter().enumerate() {
+            let (a, b) = text.split_once(' ').ok_or_else(|| TokenizerError::Parse {
+                line,
+                message: "合并规则应该是\"a b\"".to_string(),
+            })?;
+            let pair = (lookup(line, a)?, lookup(line, b)?);
+            let merged = lookup(line, &format!("{}{}", a, b))?;
+            if merge_ranks.insert(pair, (rank, merged)).is_some() {
+                return Err(TokenizerError::Parse { line, message: format!("合并规则{:?}重复出现", text) });
+            }
+            merges.push(pair);
+        }
+
+        Ok(BpeTokenizer { vocab, token_to_id, merges, merge_ranks, byte_ids, token_bytes, special, special_ids })
+    }
+
+    // 生成词表文件，可以被from_vocab_file重新加载
+    fn to_vocab_file(&self) -> String {
+        let mut out = String::from("[vocab]\n");
+        for token in &self.vocab {
+            out.push_str(token);
+            out.push('\n');
+        }
+        out.push_str("[merges]\n");
+        for &(a, b) in &self.merges {
+            out.push_str(&format!("{} {}\n", self.vocab[a as usize], self.vocab[b as usize]));
+        }
+        out.push_str("[special]\n");
+        for (name, id) in [("bos", self.special.bos), ("eos", self.special.eos), ("pad", self.special.pad)] {
+            if let Some(id) = id {
+                out.push_str(&format!("{} {}\n", name, self.vocab[id as usize]));
+            }
+        }
+        out
+    }
+
+    fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), TokenizerError> {
+        fs::write(path, self.to_vocab_file()).map_err(|e| TokenizerError::Io(e.to_string()))
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.vocab.len()
+    }
+
+    fn token_to_id(&self, token: &str) -> Option<u32> {
+        self.token_to_id.get(token).copied()
+    }
+
+    fn id_to_token(&self, id: u32) -> Option<&str> {
+        self.vocab.get(id as usize).map(String::as_str)
+    }
+
+    // 对一个预分词片段做BPE：每次合并优先级最高的相邻token对（该对的所有出现位置一起合并）
+    fn bpe(&self, piece: &str) -> Vec<u32> {
+        let mut ids: Vec<u32> = piece.bytes().map(|b| self.byte_ids[b as usize]).collect();
+        while ids.len() > 1 {
+            let best = ids.windows(2)
+                .filter_map(|w| self.merge_ranks.get(&(w[0], w[1])).map(|&(rank, merged)| (rank, (w[0], w[1]), merged)))
+                .min_by_key(|&(rank, _, _)| rank);
+            let Some((_, pair, merged)) = best else { break };
+
+            let mut out = Vec::with_capacity(ids.len());
+            let mut i = 0;
+            while i < ids.len() {
+                if i + 1 < ids.len() && (ids[i], ids[i + 1]) == pair {
+                    out.push(merged);
+                    i += 2;
+                } else {
+                    out.push(ids[i]);
+                    i += 1;
+                }
+            }
+            ids = out;
+        }
+        ids
+    }
+
+    // 编码普通文本，文本中出现的特殊token字符串按普通文本处理
+    fn encode(&self, text: &str) -> Vec<u32> {
+        pre_tokenize(text).into_iter().flat_map(|piece| self.bpe(piece)).collect()
+    }
+
+    // 编码文本并识别其中的特殊token字符串，可选地在开头添加BOS、在结尾添加EOS
+    fn encode_with_special(&self, text: &str, add_bos: bool, add_eos: bool) -> Result<Vec<u32>, TokenizerError> {
+        let mut ids = Vec::new();
+        if add_bos {
+            ids.push(self.special.bos.ok_or(TokenizerError::MissingSpecialToken("bos"))?);
+        }
+
+        let mut rest = text;
+        while !rest.is_empty() {
+            // 找到最早出现的特殊token，位置相同时取更长的
+            let next = self.special_ids.iter()
+                .filter_map(|&id| rest.find(self.vocab[id as usize].as_str()).map(|pos| (pos, id)))
+                .min_by_key(|&(pos, _)| pos);
+            match next {
+                Some((pos, id)) => {
+                    ids.extend(self.encode(&rest[..pos]));
+                    ids.push(id);
+                    rest = &rest[pos + self.vocab[id as usize].len()..];
+                }
+                None => {
+                    ids.extend(self.encode(rest));
+                    break;
+                }
+            }
+        }
+
+        if add_eos {
+            ids.push(self.special.eos.ok_or(TokenizerError::MissingSpecialToken("eos"))?);
+        }
+        Ok(ids)
+    }
+
+    fn decode_bytes(&self, ids: &[u32], skip_special: bool) -> Result<Vec<u8>, TokenizerError> {
+        let mut bytes = Vec::new();
+        for &id in ids {
+            let token = self.token_bytes.get(id as usize).ok_or(TokenizerError::UnknownId(id))?;
+            if !(skip_special && self.special_ids.contains(&id)) {
+                bytes.extend_from_slice(token);
+            }
+        }
+        Ok(bytes)
+    }
+
+    // 解码为文本。单独的token可能只是一个多字节字符的一部分，不完整的UTF-8用U+FFFD代替
+    fn decode(&self, ids: &[u32], skip_special: bool) -> Result<String, TokenizerError> {
+        Ok(String::from_utf8_lossy(&self.decode_bytes(ids, skip_special)?).into_owned())
+    }
+
+    // 在右侧用PAD填充到批次中最长的序列，同时返回注意力掩码（true表示真实token）
+    fn pad_batch(&self, batch: &[Vec<u32>]) -> Result<PaddedBatch, TokenizerError> {
+        let pad = self.special.pad.ok_or(TokenizerError::MissingSpecialToken("pad"))?;
+        let max_len = batch.iter().map(Vec::len).max().unwrap_or(0);
+        let padded = batch.iter()
+            .map(|ids| {
+                let mut ids = ids.clone();
+                ids.resize(max_len, pad);
+                ids
+            })
+            .collect();
+        let mask = batch.iter()
+            .map(|ids| (0..max_len).map(|i| i < ids.len()).collect())
+            .collect();
+        Ok((padded, mask))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // 256个字节token（按字节顺序）+ 合并产生的token + 特殊token
+    fn vocab_file(merges: &[&str], special: &[(&str, &str)]) -> String {
+        let mut out = String::from("# 测试用词表\n[vocab]\n");
+        for c in bytes_to_unicode() {
+            out.push(c);
+            out.push('\n');
+        }
+        for m in merges {
+            out.push_str(&m.replace(' ', ""));
+            out.push('\n');
+        }
+        for (_, token) in special {
+            out.push_str(token);
+            out.push('\n');
+        }
+        out.push_str("[merges]\n");
+        for m in merges {
+            out.push_str(m);
+            out.push('\n');
+        }
+        out.push_str("[special]\n");
+        for (name, token) in special {
+            out.push_str(&format!("{} {}\n", name, token));
+        }
+        out
+    }
+
+    fn tokenizer() -> BpeTokenizer {
+        let merges = ["Ġ t", "h e", "Ġt he", "i n", "Ġ a", "Ġa n", "Ġan d", "ä ½", "ä½ ł"];
+        let special = [("bos", "<s>"), ("eos", "</s>"), ("pad", "<pad>")];
+        BpeTokenizer::from_vocab_file(&vocab_file(&merges, &special)).unwrap()
+    }
+
+    fn tokens(t: &BpeTokenizer, ids: &[u32]) -> Vec<String> {
+        ids.iter().map(|&id| t.id_to_token(id).unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn test_bytes_to_unicode() {
+        let table = bytes_to_unicode();
+        let mut unique = table.to_vec();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), 256);
+        assert_eq!((table[b'a' as usize], table[b'!' as usize], table[0xFF]), ('a', '!', 'ÿ'));
+        assert_eq!((table[b' ' as usize], table[b'\n' as usize], table[0]), ('Ġ', 'Ċ', 'Ā'));
+        assert!(table.iter().all(|c| !c.is_whitespace()), "映射后的字符都是可见字符");
+    }
+
+    #[test]
+    fn test_pre_tokenize() {
+        assert_eq!(pre_tokenize("Hello world"), vec!["Hello", " world"]);
+        assert_eq!(pre_tokenize("I'm  fine!\n\nOK"), vec!["I", "'m", " ", " fine", "!", "\n", "\n", "OK"]);
+        assert_eq!(pre_tokenize("they'll go,it's 2024 ok??"), vec!["they", "'ll", " go", ",", "it", "'s", " 2024", " ok", "??"]);
+        assert_eq!(pre_tokenize("abc123 456"), vec!["abc", "123", " 456"]);
+        assert_eq!(pre_tokenize("中文测试，很好"), vec!["中文测试", "，", "很好"]);
+        assert_eq!(pre_tokenize("a  "), vec!["a", "  "]);
+        assert_eq!(pre_tokenize("  \tx"), vec!["  ", "\t", "x"]);
+        assert_eq!(pre_tokenize(" ''s"), vec![" ''", "s"]);
+        assert_eq!(pre_tokenize("hi 😀😀 !"), vec!["hi", " 😀😀", " !"]);
+        assert_eq!(pre_tokenize(""), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_encode_applies_merges_by_rank() {
+        let t = tokenizer();
+        assert_eq!(t.vocab_size(), 256 + 9 + 3);
+
+        assert_eq!(tokens(&t, &t.encode(" the")), vec!["Ġthe"]);
+        assert_eq!(tokens(&t, &t.encode("the")), vec!["t", "he"]);
+        assert_eq!(tokens(&t, &t.encode("the cat and the hat")), vec![
+            "t", "he", "Ġ", "c", "a", "t", "Ġand", "Ġthe", "Ġ", "h", "a", "t",
+        ]);
+        // "你"的UTF-8是E4 BD A0，三个字节逐步合并成一个token
+        assert_eq!(tokens(&t, &t.encode("你你")), vec!["ä½ł", "ä½ł"]);
+
+        // 优先级：b c（rank 0）先于 a b（rank 1）
+        let t2 = BpeTokenizer::from_vocab_file(&vocab_file(&["b c", "a b"], &[])).unwrap();
+        assert_eq!(tokens(&t2, &t2.encode("abc")), vec!["a", "bc"]);
+        let t3 = BpeTokenizer::from_vocab_file(&vocab_file(&["a b", "b c"], &[])).unwrap();
+        assert_eq!(tokens(&t3, &t3.encode("abc")), vec!["ab", "c"]);
+    }
+
+    #[test]
+    fn test_round_trip_arbitrary_utf8() {
+        let t = tokenizer();
+        let samples = [
+            "", "Hello, world!", "你好，世界！我们在学习分词。", "emoji: 😀👍🏽👨‍👩‍👧 🇨🇳",
+            "  leading and trailing  \n\n", "tabs\tand\r\nnewlines", "mixed中文and English123数字",
+            "\u{0}\u{7f}\u{80}\u{ffff}\u{10ffff}", "I'm 'quoted' they'll",
+        ];
+        for s in samples {
+            assert_eq!(t.decode(&t.encode(s), false).unwrap(), s);
+        }
+
+        let ranges = [(0x20u32, 0x7F), (0x4E00, 0x9FFF), (0x1F300, 0x1FAFF), (0x0, 0x20), (0x80, 0x800), (0x3000, 0x303F)];
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..200 {
+            let len = rng.gen_range(0..40);
+            let s: String = (0..len)
+                .map(|_| {
+                    let (lo, hi) = ranges[rng.gen_range(0..ranges.len())];
+                    char::from_u32(rng.gen_range(lo..hi)).unwrap_or('?')
+                })
+                .collect();
+            let ids = t.encode(&s);
+            assert_eq!(t.decode(&ids, false).unwrap(), s);
+            assert!(ids.len() <= s.len(), "token数不超过字节数");
+        }
+    }
+
+    #[test]
+    fn test_special_tokens() {
+        let t = tokenizer();
+        let (bos, eos, pad) = (t.token_to_id("<s>").unwrap(), t.token_to_id("</s>").unwrap(), t.token_to_id("<pad>").unwrap());
+        assert_eq!(t.special, SpecialTokens { bos: Some(bos), eos: Some(eos), pad: Some(pad) });
+
+        // encode把特殊token当作普通文本
+        assert_eq!(tokens(&t, &t.encode("<s>")), vec!["<", "s", ">"]);
+        let ids = t.encode_with_special("<s> the</s>the", false, false).unwrap();
+        assert_eq!(tokens(&t, &ids), vec!["<s>", "Ġthe", "</s>", "t", "he"]);
+
+        let ids = t.encode_with_special(" the", true, true).unwrap();
+        assert_eq!(ids.first(), Some(&bos));
+        assert_eq!(ids.last(), Some(&eos));
+        assert_eq!(t.decode(&ids, false).unwrap(), "<s> the</s>");
+        assert_eq!(t.decode(&ids, true).unwrap(), " the");
+        assert_eq!(t.decode(&[9999], false).unwrap_err(), TokenizerError::UnknownId(9999));
+
+        let (padded, mask) = t.pad_batch(&[vec![1, 2, 3], vec![4]]).unwrap();
+        assert_eq!(padded, vec![vec![1, 2, 3], vec![4, pad, pad]]);
+        assert_eq!(mask, vec![vec![true, true, true], vec![true, false, false]]);
+
+        let plain = BpeTokenizer::from_vocab_file(&vocab_file(&[], &[])).unwrap();
+        assert_eq!(plain.encode_with_special("x", true, false).unwrap_err(), TokenizerError::MissingSpecialToken("bos"));
+        assert_eq!(plain.pad_batch(&[vec![1]]).unwrap_err(), TokenizerError::MissingSpecialToken("pad"));
+    }
+
+    #[test]
+    fn test_vocab_file_round_trip_and_errors() {
+        let t = tokenizer();
+        let path = std::env::temp_dir().join(format!("rustlings_llm_{}_bpe_vocab.txt", std::process::id()));
+        t.save(&path).unwrap();
+        let loaded = BpeTokenizer::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.vocab, t.vocab);
+        assert_eq!(loaded.merges, t.merges);
+        assert_eq!(loaded.special, t.special);
+        assert_eq!(loaded.encode("the cat and the hat"), t.encode("the cat and the hat"));
+
+        let parse_err = |text: &str| match BpeTokenizer::from_vocab_file(text) {
+            Err(TokenizerError::Parse { line, message }) => (line, message),
+            other => panic!("应该解析失败: {:?}", other),
+        };
+        assert_eq!(parse_err("").1, "缺少[vocab]段落");
+        assert!(parse_err("[vocab]\na\n").1.contains("缺少字节"));
+        let base = vocab_file(&[], &[]);
+        assert_eq!(parse_err(&format!("{}[merges]\na x\n", base.replace("[merges]\n[special]\n", ""))).1, "token \"ax\"不在词表中");
+        assert_eq!(parse_err(&base.replacen("a\n", "a\na\n", 1)).1, "token \"a\"重复出现");
+        assert!(parse_err(&format!("{}unk a\n", base)).1.contains("未知的特殊token名称"));
+        assert!(parse_err(&format!("{}bos <s>\n", base)).1.contains("<s>"));
+        assert!(parse_err(&format!("{}bos\n", base)).1.contains("名称 token"));
+        let (line, message) = parse_err(&base.replacen("[merges]", "普通\n[merges]", 1));
+        assert_eq!(line, 259);
+        assert!(message.contains("字节映射"), "{}", message);
+        assert!(BpeTokenizer::load("/nonexistent/vocab.txt").unwrap_err().to_string().starts_with("IO错误"));
+    }
+}
@@ -0,0 +1,399 @@
+// 预训练的BPE词表不一定适合我们的数据：在中文语料上，英文为主的词表会把每个汉字拆成2~3个字节token。
+// BPE的训练过程很直接：
+// 1. 用与编码时相同的预分词把语料切成单词，统计每个单词出现的次数
+// 2. 词表从256个字节token开始，每个单词表示为字节token序列
+// 3. 统计所有相邻token对的出现次数（按单词频率加权），把最常见的一对合并为新token，记录这条合并规则
+// 4. 重复第3步，直到词表达到目标大小，或者最常见的一对出现次数低于min_frequency
+// 合并规则的顺序就是编码时的优先级。训练结果写成与 bpe_tokenizer.rs 相同格式的词表文件，可以直接被编码器加载。
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// 分词器可能出现的错误
+// 注意：这个类型与 bpe_tokenizer.rs 中的定义相同，另外增加了训练时才会出现的SpecialTokenConflict
+#[derive(Debug, Clone, PartialEq)]
+enum TokenizerError {
+    Io(String),
+    Parse { line: usize, message: String },
+    UnknownId(u32),
+    MissingSpecialToken(&'static str),
+    SpecialTokenConflict(String),
+}
+
+impl fmt::Display for TokenizerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenizerError::Io(msg) => write!(f, "IO错误: {}", msg),
+            TokenizerError::Parse { line, message } => write!(f, "词表文件第{}行: {}", line, message),
+            TokenizerError::UnknownId(id) => write!(f, "token ID {}不在词表中", id),
+            TokenizerError::MissingSpecialToken(name) => write!(f, "词表中没有定义特殊token {}", name),
+            TokenizerError::SpecialTokenConflict(token) => write!(f, "特殊token {}与字节token或其他特殊token重复", token),
+        }
+    }
+}
+
+// 辅助函数：GPT-2的字节到字符映射
+// 注意：这个函数与 bpe_tokenizer.rs 中的实现相同，已经提供
+fn bytes_to_unicode() -> [char; 256] {
+    let mut table = ['\0'; 256];
+    let mut next = 256u32;
+    for b in 0..256u32 {
+        let visible = (b'!' as u32..=b'~' as u32).contains(&b) || (0xA1..=0xAC).contains(&b) || (0xAE..=0xFF).contains(&b);
+        table[b as usize] = if visible {
+            char::from_u32(b).unwrap()
+        } else {
+            next += 1;
+            char::from_u32(next - 1).unwrap()
+        };
+    }
+    table
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CharClass {
+    Letter,
+    Number,
+    Whitespace,
+    Other,
+}
+
+fn char_class(c: char) -> CharClass {
+    if c.is_alphabetic() {
+        CharClass::Letter
+    } else if c.is_numeric() {
+        CharClass::Number
+    } else if c.is_whitespace() {
+        CharClass::Whitespace
+    } else {
+        CharClass::Other
+    }
+}
+
+// 辅助函数：GPT-2的预分词
+// 注意：这个函数与 bpe_tokenizer.rs 中的实现相同，已经提供
+fn pre_tokenize(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let byte_pos = |i: usize| chars.get(i).map_or(text.len(), |&(p, _)| p);
+    let class_at = |i: usize| chars.get(i).map(|&(_, c)| char_class(c));
+    let mut pieces = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i].1;
+
+        // 英文缩写
+        if c == '\'' {
+            let rest = &text[byte_pos(i + 1)..];
+            if let Some(suffix) = ["s", "t", "re", "ve", "m", "ll", "d"].iter().find(|s| rest.starts_with(*s)) {
+                i += 1 + suffix.len();
+                pieces.push(&text[byte_pos(start)..byte_pos(i)]);
+                continue;
+            }
+        }
+
+        // 可选的一个空格 + 同一类字符（字母、数字或其他符号）组成的串
+        let word_start = if c == ' ' && matches!(class_at(i + 1), Some(cls) if cls != CharClass::Whitespace) {
+            i + 1
+        } else {
+            i
+        };
+        let class = class_at(word_start).unwrap();
+        if class != CharClass::Whitespace {
+            i = word_start;
+            while class_at(i) == Some(class) {
+                i += 1;
+            }
+        } else {
+            // 空白串：如果后面还有非空白字符，最后一个空白留给下一个单词（\s+(?!\S)），只有一个空白时单独成为一段（\s+）
+            while class_at(i) == Some(CharClass::Whitespace) {
+                i += 1;
+            }
+            if i < chars.len() && i - start > 1 {
+                i -= 1;
+            }
+        }
+        pieces.push(&text[byte_pos(start)..byte_pos(i)]);
+    }
+    pieces
+}
+
+// 训练得到的BPE词表
+#[derive(Debug, Clone, PartialEq)]
+struct TrainedBpe {
+    vocab: Vec<String>,              // token ID -> token，前256个是字节token
+    merges: Vec<(u32, u32)>,         // 按优先级从高到低
+    special: Vec<(String, String)>,  // (名称, token)
+}
+
+impl TrainedBpe {
+    // 生成词表文件，格式与 bpe_tokenizer.rs 的from_vocab_file相同
+    fn to_vocab_file(&self) -> String {
+        let mut out = String::from("[vocab]\n");
+        for token in &self.vocab {
+            out.push_str(token);
+            out.push('\n');
+        }
+        out.push_str("[merges]\n");
+        for &(a, b) in &self.merges {
+            out.push_str(&format!("{} {}\n", self.vocab[a as usize], self.vocab[b as usize]));
+        }
+        out.push_str("[special]\n");
+        for (name, token) in &self.special {
+            out.push_str(&format!("{} {}\n", name, token));
+        }
+        out
+    }
+
+    fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), TokenizerError> {
+        fs::write(path, self.to_vocab_file()).map_err(|e| TokenizerError::Io(e.to_string()))
+    }
+}
+
+// 把单词中所有相邻的pair替换为merged（从左到右，不重叠）
+fn merge_word(word: &[u32], pair: (u32, u32), merged: u32) -> Vec<u32> {
+    let mut out = Vec::with_capacity(word.len());
+    let mut i = 0;
+    while i < word.len() {
+        if i + 1 < word.len() && (word[i], word[i + 1]) == pair {
+            out.push(merged);
+            i += 2;
+        } else {
+            out.push(word[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+fn add_pairs(pair_counts: &mut HashMap<(u32, u32), usize>, word: &[u32], count: usize) {
+    for w in word.windows(2) {
+        *pair_counts.entry((w[0], w[1])).or_insert(0) += count;
+    }
+}
+
+fn remove_pairs(pair_counts: &mut HashMap<(u32, u32), usize>, word: &[u32], count: usize) {
+    for w in word.windows(2) {
+        let pair = (w[0], w[1]);
+        let c = pair_counts.get_mut(&pair).expect("pair已经统计过");
+        *c -= count;
+        if *c == 0 {
+            pair_counts.remove(&pair);
+        }
+    }
+}
+
+// 在语料上训练BPE
+// vocab_size包括256个字节token和特殊token；special是(名称, token)，名称为bos、eos或pad
+// 特殊token的ID是预留的：合并结果与特殊token相同的pair不会被合并，所以词表不会因为复用而变小
+fn train_bpe(
+    corpus: &[&str],
+    vocab_size: usize,
+    min_frequency: usize,
+    special: &[(&str, &str)],
+) -> Result<TrainedBpe, TokenizerError> {
+    let mut word_counts: HashMap<&str, usize> = HashMap::new();
+    for text in corpus {
+        for piece in pre_tokenize(text) {
+            *word_counts.entry(piece).or_insert(0) += 1;
+        }
+    }
+    // 排序使训练结果与HashMap的遍历顺序无关
+    let mut word_counts: Vec<(&str, usize)> = word_counts.into_iter().collect();
+    word_counts.sort();
+    let mut words: Vec<(Vec<u32>, usize)> = word_counts.into_iter()
+        .map(|(w, c)| (w.bytes().map(|b| b as u32).collect(), c))
+        .collect();
+
+    let mut vocab: Vec<String> = bytes_to_unicode().iter().map(|c| c.to_string()).collect();
+    let mut token_to_id: HashMap<String, u32> = vocab.iter().enumerate().map(|(i, t)| (t.clone(), i as u32)).collect();
+    let mut merges: Vec<(u32, u32)> = Vec::new();
+
+    let mut reserved: HashSet<&str> = HashSet::new();
+    for &(_, token) in special {
+        if token_to_id.contains_key(token) || !reserved.insert(token) {
+            return Err(TokenizerError::SpecialTokenConflict(token.to_string()));
+        }
+    }
+    // 合并结果是特殊token的pair，训练时跳过
+    let mut blocked: HashSet<(u32, u32)> = HashSet::new();
+
+    let mut pair_counts = HashMap::new();
+    for (word, count) in &words {
+        add_pairs(&mut pair_counts, word, *count);
+    }
+
+    let target = vocab_size.saturating_sub(special.len());
+    while vocab.len() < target {
+        // 出现次数最多的pair，次数相同时取ID最小的，保证结果确定
+        let best = pair_counts.iter()
+            .filter(|&(pair, &count)| count >= min_frequency.max(1) && !blocked.contains(pair))
+            .max_by(|(pa, ca), (pb, cb)| ca.cmp(cb).then(pb.cmp(pa)));
+        let Some((&pair, _)) = best else { break };
+
+        // 不同的合并路径可能得到相同的字符串（例如 a+bc 和 ab+c），这时复用已有的token
+        let merged = format!("{}{}", vocab[pair.0 as usize], vocab[pair.1 as usize]);
+        if reserved.contains(merged.as_str()) {
+            blocked.insert(pair);
+            continue;
+        }
+        let merged_id = *token_to_id.entry(merged.clone()).or_insert_with(|| {
+            vocab.push(merged);
+            (vocab.len() - 1) as u32
+        });
+        // 复用token后，已经合并过的pair可能重新出现，编码器会用已有的规则合并它，不需要重复记录
+        if !merges.contains(&pair) {
+            merges.push(pair);
+        }
+
+        for (word, count) in words.iter_mut() {
+            if !word.windows(2).any(|w| (w[0], w[1]) == pair) {
+                continue;
+            }
+            remove_pairs(&mut pair_counts, word, *count);
+            *word = merge_word(word, pair, merged_id);
+            add_pairs(&mut pair_counts, word, *count);
+        }
+    }
+
+    let mut special_tokens = Vec::new();
+    for &(name, token) in special {
+        vocab.push(token.to_string());
+        special_tokens.push((name.to_string(), token.to_string()));
+    }
+
+    Ok(TrainedBpe { vocab, merges, special: special_tokens })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 按合并规则的优先级编码一个预分词片段（与 bpe_tokenizer.rs 的编码过程相同）
+    fn encode_piece(bpe: &TrainedBpe, piece: &str) -> Vec<String> {
+        let ranks: HashMap<(u32, u32), usize> = bpe.merges.iter().enumerate().map(|(r, &p)| (p, r)).collect();
+        let ids: HashMap<&str, u32> = bpe.vocab.iter().enumerate().map(|(i, t)| (t.as_str(), i as u32)).collect();
+        let mut word: Vec<u32> = piece.bytes().map(|b| b as u32).collect();
+        while let Some((_, pair)) = word.windows(2)
+            .filter_map(|w| ranks.get(&(w[0], w[1])).map(|&r| (r, (w[0], w[1]))))
+            .min()
+        {
+            let merged = format!("{}{}", bpe.vocab[pair.0 as usize], bpe.vocab[pair.1 as usize]);
+            word = merge_word(&word, pair, ids[merged.as_str()]);
+        }
+        word.iter().map(|&id| bpe.vocab[id as usize].clone()).collect()
+    }
+
+    fn merge_strings(bpe: &TrainedBpe) -> Vec<String> {
+        bpe.merges.iter()
+            .map(|&(a, b)| format!("{} {}", bpe.vocab[a as usize], bpe.vocab[b as usize]))
+            .collect()
+    }
+
+    #[test]
+    fn test_classic_example() {
+        // Sennrich等人论文中的例子：low×5, lower×2, newest×6, widest×3
+        let mut corpus = Vec::new();
+        for (word, count) in [("low", 5), ("lower", 2), ("newest", 6), ("widest", 3)] {
+            corpus.extend(std::iter::repeat_n(word, count));
+        }
+        let bpe = train_bpe(&corpus, 256 + 5, 2, &[]).unwrap();
+
+        // e s和s t都出现9次，取ID较小的e s；l o和o w都出现7次，取l o
+        assert_eq!(merge_strings(&bpe), vec!["e s", "es t", "l o", "lo w", "e w"]);
+        assert_eq!(bpe.vocab[256..], ["es", "est", "lo", "low", "ew"]);
+        assert_eq!(encode_piece(&bpe, "lowest"), vec!["low", "est"]);
+        assert_eq!(encode_piece(&bpe, "newer"), vec!["n", "ew", "e", "r"]);
+
+        // min_frequency限制了合并：只有出现至少7次的pair会被合并
+        let bpe = train_bpe(&corpus, 1000, 7, &[]).unwrap();
+        assert_eq!(merge_strings(&bpe), vec!["e s", "es t", "l o", "lo w"]);
+    }
+
+    #[test]
+    fn test_train_on_chinese_corpus() {
+        let corpus = [
+            "张量是多维数组，张量计算是大模型的核心。",
+            "在这个练习中，我们将实现张量的矩阵乘法。",
+            "注意力机制使用张量计算注意力分数。",
+            "量化可以减少张量占用的内存。",
+            "The tensor is a multi-dimensional array.",
+        ];
+        let special = [("bos", "<s>"), ("eos", "</s>"), ("pad", "<pad>")];
+        let bpe = train_bpe(&corpus, 290, 2, &special).unwrap();
+
+        assert_eq!(bpe.vocab.len(), 290, "语料足够大时正好达到目标大小");
+        assert_eq!(bpe.vocab[287..], ["<s>", "</s>", "<pad>"]);
+        let unique: std::collections::HashSet<&String> = bpe.vocab.iter().collect();
+        assert_eq!(unique.len(), bpe.vocab.len(), "token不重复");
+
+        // "张量"的UTF-8是6个字节，训练后成为一个token
+        let byte_decoder: HashMap<char, u8> = bytes_to_unicode().iter().enumerate().map(|(b, &c)| (c, b as u8)).collect();
+        let tensor: String = "张量".bytes().map(|b| bytes_to_unicode()[b as usize]).collect();
+        assert!(bpe.vocab.contains(&tensor));
+        assert_eq!(encode_piece(&bpe, "张量"), vec![tensor]);
+
+        // 训练语料中的每个单词都能按合并规则编码，并且解码回原文
+        for text in corpus {
+            for piece in pre_tokenize(text) {
+                let tokens = encode_piece(&bpe, piece);
+                let bytes: Vec<u8> = tokens.concat().chars().map(|c| byte_decoder[&c]).collect();
+                assert_eq!(String::from_utf8(bytes).unwrap(), piece);
+            }
+        }
+        let total_bytes: usize = corpus.iter().map(|t| t.len()).sum();
+        let total_tokens: usize = corpus.iter().flat_map(|t| pre_tokenize(t)).map(|p| encode_piece(&bpe, p).len()).sum();
+        assert!(total_tokens * 3 < total_bytes * 2, "压缩率应该超过1.5倍: {} / {}", total_bytes, total_tokens);
+    }
+
+    #[test]
+    fn test_vocab_file() {
+        let bpe = train_bpe(&["aaabdaaabac"], 260, 2, &[("eos", "</s>")]).unwrap();
+        // aa出现4次 -> aa；aaab中aa+a、aa+b... 每一步都合并当前最常见的pair
+        assert_eq!(merge_strings(&bpe)[0], "a a");
+
+        let file = bpe.to_vocab_file();
+        let lines: Vec<&str> = file.lines().collect();
+        assert_eq!(lines[0], "[vocab]");
+        assert_eq!(lines[1 + b' ' as usize], "Ġ");
+        let merges_at = lines.iter().position(|&l| l == "[merges]").unwrap();
+        let special_at = lines.iter().position(|&l| l == "[special]").unwrap();
+        assert_eq!(merges_at - 1, bpe.vocab.len());
+        assert_eq!(lines[merges_at + 1..special_at], merge_strings(&bpe)[..]);
+        assert_eq!(lines[special_at + 1..], ["eos </s>"]);
+
+        // 每条合并规则的结果都在词表中，编码器加载时不会出错
+        for m in merge_strings(&bpe) {
+            assert!(bpe.vocab.contains(&m.replace(' ', "")), "{}", m);
+        }
+
+        let path = std::env::temp_dir().join(format!("rustlings_llm_{}_trained_bpe.txt", std::process::id()));
+        bpe.save(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), file);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_special_token_reserved() {
+        // 语料中的"low"会被合并成与特殊token相同的字符串，这个合并被跳过，特殊token仍然占用自己的ID
+        let corpus = ["low"; 10];
+        let bpe = train_bpe(&corpus, 258, 2, &[("eos", "low")]).unwrap();
+        assert_eq!(merge_strings(&bpe), vec!["l o"]);
+        assert_eq!(bpe.vocab.len(), 258);
+        assert_eq!(bpe.vocab[256..], ["lo", "low"]);
+        let unique: HashSet<&String> = bpe.vocab.iter().collect();
+        assert_eq!(unique.len(), bpe.vocab.len(), "token不重复");
+
+        // 与字节token相同或者重复的特殊token无法预留ID
+        assert_eq!(
+            train_bpe(&corpus, 260, 2, &[("pad", "a")]),
+            Err(TokenizerError::SpecialTokenConflict("a".to_string()))
+        );
+        assert_eq!(
+            train_bpe(&corpus, 260, 2, &[("bos", "<s>"), ("eos", "<s>")]),
+            Err(TokenizerError::SpecialTokenConflict("<s>".to_string()))
+        );
+    }
+}
@@ -0,0 +1,611 @@
+// SentencePiece的Unigram语言模型分词器把每个片段（piece）看作独立出现的“词”，片段有一个对数概率分数。
+// 一段文本有很多种切分方式，分词时选择分数之和最大的切分，这可以用Viterbi算法（动态规划）求出。
+// 训练过程（EM + 剪枝）：
+// 1. 种子词表：语料中的所有字符，加上最常见的子串
+// 2. E步：用前向-后向算法计算每个片段在所有可能切分下的期望出现次数；M步：分数更新为 ln(次数 / 总次数)
+// 3. 剪枝：删除一个片段后，它会被切成其他片段，似然会下降。保留下降最多的片段，删除其余的一部分
+// 4. 重复2~3直到词表达到目标大小。单个字符永远保留，保证训练语料总能被切分
+// 文本中的空格替换为'▁'（U+2581），并在开头加一个'▁'，这样解码时可以还原空格。
+// 词表中没有的字符使用字节回退：编码为它的UTF-8字节对应的<0xXX>片段，因此任何文本都能被编码。
+//
+// 词表文件每行是"片段\t分数\t类型"，类型是normal、byte、control或unknown，行号（从0开始）就是片段ID。
+// 片段中的\、制表符、换行符和回车符分别写成\\、\t、\n、\r，否则会破坏行和字段的划分。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+const SPACE_SYMBOL: char = '▁';
+// 每轮剪枝后保留的比例
+const SHRINKING_FACTOR: f32 = 0.75;
+// 每轮剪枝前的EM迭代次数
+const EM_ITERATIONS: usize = 2;
+
+// 分词器可能出现的错误
+#[derive(Debug, Clone, PartialEq)]
+enum TokenizerError {
+    Io(String),
+    Parse { line: usize, message: String },
+    UnknownId(u32),
+    InvalidConfig(String),
+}
+
+impl fmt::Display for TokenizerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenizerError::Io(msg) => write!(f, "IO错误: {}", msg),
+            TokenizerError::Parse { line, message } => write!(f, "词表文件第{}行: {}", line, message),
+            TokenizerError::UnknownId(id) => write!(f, "片段ID {}不在词表中", id),
+            TokenizerError::InvalidConfig(msg) => write!(f, "训练参数无效: {}", msg),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PieceKind {
+    Normal,
+    Byte(u8),
+    Control,
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Piece {
+    text: String,
+    score: f32,
+    kind: PieceKind,
+}
+
+// 文本规范化：开头加一个▁，空格替换为▁
+fn normalize(text: &str) -> Vec<char> {
+    std::iter::once(SPACE_SYMBOL)
+        .chain(text.chars().map(|c| if c == ' ' { SPACE_SYMBOL } else { c }))
+        .collect()
+}
+
+// 写入词表文件时转义片段中的\、\t、\n、\r
+fn escape_piece(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape_piece(text: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            other => return Err(format!("无效的转义序列\\{}", other.map(String::from).unwrap_or_default())),
+        }
+    }
+    Ok(out)
+}
+
+// 片段查找表：片段 -> (ID, 分数)
+type PieceIndex = HashMap<String, (usize, f32)>;
+
+// 切分结果中的一段：词表中的片段，或者词表中没有的字符
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Segment {
+    Piece(usize),
+    Unknown(char),
+}
+
+// 从位置i开始、在词表中的所有片段：(结束位置, ID, 分数)
+fn edges_from(chars: &[char], i: usize, index: &PieceIndex, max_chars: usize) -> Vec<(usize, usize, f32)> {
+    let mut edges = Vec::new();
+    let mut text = String::new();
+    for (j, &c) in chars.iter().enumerate().skip(i).take(max_chars) {
+        text.push(c);
+        if let Some(&(id, score)) = index.get(&text) {
+            edges.push((j + 1, id, score));
+        }
+    }
+    edges
+}
+
+// Viterbi：求分数之和最大的切分
+// 如果某个位置没有单字符的片段，允许用分数为fallback_score的Unknown跳过一个字符；exclude中的片段不参与切分
+fn viterbi(chars: &[char], index: &PieceIndex, max_chars: usize, fallback_score: f32, exclude: Option<usize>) -> (f32, Vec<Segment>) {
+    let n = chars.len();
+    let mut best = vec![f32::NEG_INFINITY; n + 1];
+    let mut back: Vec<(usize, Segment)> = vec![(0, Segment::Unknown('\0')); n + 1];
+    best[0] = 0.0;
+
+    for i in 0..n {
+        if best[i] == f32::NEG_INFINITY {
+            continue;
+        }
+        let edges = edges_from(chars, i, index, max_chars);
+        let mut has_single_char = false;
+        for (j, id, score) in edges {
+            if Some(id) == exclude {
+                continue;
+            }
+            has_single_char |= j == i + 1;
+            if best[i] + score > best[j] {
+                best[j] = best[i] + score;
+                back[j] = (i, Segment::Piece(id));
+            }
+        }
+        if !has_single_char && best[i] + fallback_score > best[i + 1] {
+            best[i + 1] = best[i] + fallback_score;
+            back[i + 1] = (i, Segment::Unknown(chars[i]));
+        }
+    }
+
+    let mut segments = Vec::new();
+    let mut j = n;
+    while j > 0 {
+        let (i, segment) = back[j];
+        segments.push(segment);
+        j = i;
+    }
+    segments.reverse();
+    (best[n], segments)
+}
+
+fn log_add(a: f64, b: f64) -> f64 {
+    if a == f64::NEG_INFINITY {
+        return b;
+    }
+    if b == f64::NEG_INFINITY {
+        return a;
+    }
+    let (hi, lo) = if a > b { (a, b) } else { (b, a) };
+    hi + (lo - hi).exp().ln_1p()
+}
+
+// 前向-后向算法：把每个片段在所有切分下的期望次数（乘以weight）加到counts中，返回 weight * ln(所有切分的概率之和)
+fn expected_counts(chars: &[char], index: &PieceIndex, max_chars: usize, weight: f64, counts: &mut [f64]) -> f64 {
+    let n = chars.len();
+    let edges: Vec<(usize, usize, usize, f64)> = (0..n)
+        .flat_map(|i| edges_from(chars, i, index, max_chars).into_iter().map(move |(j, id, s)| (i, j, id, s as f64)))
+        .collect();
+
+    let mut alpha = vec![f64::NEG_INFINITY; n + 1];
+    alpha[0] = 0.0;
+    for &(i, j, _, s) in &edges {
+        alpha[j] = log_add(alpha[j], alpha[i] + s);
+    }
+    let mut beta = vec![f64::NEG_INFINITY; n + 1];
+    beta[n] = 0.0;
+    for &(i, j, _, s) in edges.iter().rev() {
+        beta[i] = log_add(beta[i], beta[j] + s);
+    }
+
+    let z = alpha[n];
+    if z == f64::NEG_INFINITY {
+        return 0.0;
+    }
+    for &(i, j, id, s) in &edges {
+        counts[id] += weight * (alpha[i] + s + beta[j] - z).exp();
+    }
+    weight * z
+}
+
+fn build_index(pieces: &[(String, f32)]) -> PieceIndex {
+    pieces.iter().enumerate().map(|(id, (text, score))| (text.clone(), (id, *score))).collect()
+}
+
+#[derive(Debug, Clone)]
+struct UnigramTokenizer {
+    pieces: Vec<Piece>,
+    index: PieceIndex,              // 只包含Normal片段
+    max_chars: usize,
+    byte_ids: Option<[u32; 256]>,   // 256个字节片段都存在时启用字节回退
+    unk_id: u32,
+    bos_id: Option<u32>,
+    eos_id: Option<u32>,
+    fallback_score: f32,
+}
+
+impl UnigramTokenizer {
+    fn from_pieces(pieces: Vec<Piece>) -> Result<Self, TokenizerError> {
+        let err = |line: usize, message: String| TokenizerError::Parse { line, message };
+        let mut index = PieceIndex::new();
+        let mut byte_ids = [None; 256];
+        let mut unk_id = None;
+        let mut seen = std::collections::HashSet::new();
+
+        for (id, piece) in pieces.iter().enumerate() {
+            if piece.text.is_empty() {
+                return Err(err(id, "片段不能为空".to_string()));
+            }
+            if !seen.insert(piece.text.as_str()) {
+                return Err(err(id, format!("片段{:?}重复出现", piece.text)));
+            }
+            match piece.kind {
+                PieceKind::Normal => {
+                    index.insert(piece.text.clone(), (id, piece.score));
+                }
+                PieceKind::Byte(b) => byte_ids[b as usize] = Some(id as u32),
+                PieceKind::Unknown if unk_id.is_none() => unk_id = Some(id as u32),
+                PieceKind::Unknown => return Err(err(id, "只能有一个unknown片段".to_string())),
+                PieceKind::Control => {}
+            }
+        }
+
+        let unk_id = unk_id.ok_or_else(|| err(0, "缺少unknown片段".to_string()))?;
+        let control_id = |text: &str| {
+            pieces.iter().position(|p| p.kind == PieceKind::Control && p.text == text).map(|id| id as u32)
+        };
+        let byte_ids = if byte_ids.iter().all(Option::is_some) { Some(byte_ids.map(Option::unwrap)) } else { None };
+        let max_chars = index.keys().map(|t| t.chars().count()).max().unwrap_or(1);
+        // 回退的分数比任何正常片段都低，只有在没有其他选择时才使用
+        let min_score = index.values().map(|&(_, s)| s).fold(0.0f32, f32::min);
+
+        Ok(UnigramTokenizer {
+            bos_id: control_id("<s>"),
+            eos_id: control_id("</s>"),
+            pieces,
+            index,
+            max_chars,
+            byte_ids,
+            unk_id,
+            fallback_score: min_score - 10.0,
+        })
+    }
+
+    fn from_vocab_file(text: &str) -> Result<Self, TokenizerError> {
+        let mut pieces = Vec::new();
+        for (line_index, line) in text.lines().enumerate() {
+            let err = |message: String| TokenizerError::Parse { line: line_index + 1, message };
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [text, score, kind] = fields[..] else {
+                return Err(err("应该是\"片段\\t分数\\t类型\"".to_string()));
+            };
+            let text = unescape_piece(text).map_err(err)?;
+            let score: f32 = score.parse().map_err(|_| err(format!("无效的分数{:?}", score)))?;
+            let kind = match kind {
+                "normal" => PieceKind::Normal,
+                "control" => PieceKind::Control,
+                "unknown" => PieceKind::Unknown,
+                "byte" => {
+                    let value = text.strip_prefix("<0x")
+                        .and_then(|t| t.strip_suffix('>'))
+                        .filter(|hex| hex.len() == 2)
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                        .ok_or_else(|| err(format!("字节片段应该是<0xXX>，实际是{:?}", text)))?;
+                    PieceKind::Byte(value)
+                }
+                _ => return Err(err(format!("未知的片段类型{}", kind))),
+            };
+            pieces.push(Piece { text, score, kind });
+        }
+        // from_pieces报告的是片段ID，转换为行号
+        UnigramTokenizer::from_pieces(pieces).map_err(|e| match e {
+            TokenizerError::Parse { line, message } => TokenizerError::Parse { line: line + 1, message },
+            e => e,
+        })
+    }
+
+    fn load<P: AsRef<Path>>(path: P) -> Result<Self, TokenizerError> {
+        let text = fs::read_to_string(path).map_err(|e| TokenizerError::Io(e.to_string()))?;
+        UnigramTokenizer::from_vocab_file(&text)
+    }
+
+    fn to_vocab_file(&self) -> String {
+        let mut out = String::new();
+        for piece in &self.pieces {
+            let kind = match piece.kind {
+                PieceKind::Normal => "normal",
+                PieceKind::Byte(_) => "byte",
+                PieceKind::Control => "control",
+                PieceKind::Unknown => "unknown",
+            };
+            out.push_str(&format!("{}\t{}\t{}\n", escape_piece(&piece.text), piece.score, kind));
+        }
+        out
+    }
+
+    fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), TokenizerError> {
+        fs::write(path, self.to_vocab_file()).map_err(|e| TokenizerError::Io(e.to_string()))
+    }
+
+    fn vocab_size(&self) -> usize {
+        self.pieces.len()
+    }
+
+    fn piece_to_id(&self, text: &str) -> Option<u32> {
+        self.pieces.iter().position(|p| p.text == text).map(|id| id as u32)
+    }
+
+    fn encode(&self, text: &str) -> Vec<u32> {
+        let chars = normalize(text);
+        let (_, segments) = viterbi(&chars, &self.index, self.max_chars, self.fallback_score, None);
+        let mut ids = Vec::with_capacity(segments.len());
+        for segment in segments {
+            match (segment, self.byte_ids) {
+                (Segment::Piece(id), _) => ids.push(id as u32),
+                (Segment::Unknown(c), Some(byte_ids)) => {
+                    let mut buf = [0u8; 4];
+                    ids.extend(c.encode_utf8(&mut buf).bytes().map(|b| byte_ids[b as usize]));
+                }
+                (Segment::Unknown(_), None) => ids.push(self.unk_id),
+            }
+        }
+        ids
+    }
+
+    // 解码：控制片段被跳过，未知片段显示为⁇，▁还原为空格并去掉开头添加的那一个空格
+    fn decode(&self, ids: &[u32]) -> Result<String, TokenizerError> {
+        let mut bytes = Vec::new();
+        for &id in ids {
+            let piece = self.pieces.get(id as usize).ok_or(TokenizerError::UnknownId(id))?;
+            match piece.kind {
+                PieceKind::Normal => bytes.extend_from_slice(piece.text.as_bytes()),
+                PieceKind::Byte(b) => bytes.push(b),
+                PieceKind::Unknown => bytes.extend_from_slice("⁇".as_bytes()),
+                PieceKind::Control => {}
+            }
+        }
+        let text = String::from_utf8_lossy(&bytes).replace(SPACE_SYMBOL, " ");
+        Ok(text.strip_prefix(' ').map(str::to_string).unwrap_or(text))
+    }
+}
+
+// 在语料上训练Unigram分词器
+// vocab_size包括<unk>、<s>、</s>和256个字节片段；max_piece_chars是片段的最大字符数
+fn train_unigram(corpus: &[&str], vocab_size: usize, max_piece_chars: usize) -> Result<UnigramTokenizer, TokenizerError> {
+    const RESERVED: usize = 3 + 256;
+
+    // 按▁切分成单词并统计频率，片段不会跨越单词边界
+    let mut word_counts: HashMap<Vec<char>, usize> = HashMap::new();
+    for text in corpus {
+        let chars = normalize(text);
+        let mut start = 0;
+        for i in 1..=chars.len() {
+            if i == chars.len() || chars[i] == SPACE_SYMBOL {
+                *word_counts.entry(chars[start..i].to_vec()).or_insert(0) += 1;
+                start = i;
+            }
+        }
+    }
+    let mut words: Vec<(Vec<char>, usize)> = word_counts.into_iter().collect();
+    words.sort();
+
+    // 种子词表：所有字符（必须保留）+ 出现至少两次的子串，子串按 频率 * 长度 选出最多的
+    let mut char_counts: HashMap<String, usize> = HashMap::new();
+    let mut substring_counts: HashMap<String, usize> = HashMap::new();
+    for (word, count) in &words {
+        for i in 0..word.len() {
+            *char_counts.entry(word[i].to_string()).or_insert(0) += count;
+            for j in i + 2..=(i + max_piece_chars).min(word.len()) {
+                *substring_counts.entry(word[i..j].iter().collect()).or_insert(0) += count;
+            }
+        }
+    }
+    let target = vocab_size.saturating_sub(RESERVED);
+    if target < char_counts.len() {
+        return Err(TokenizerError::InvalidConfig(format!(
+            "vocab_size至少需要{}（{}个保留片段 + 语料中的{}个字符）", RESERVED + char_counts.len(), RESERVED, char_counts.len()
+        )));
+    }
+
+    let mut seed: Vec<(String, usize)> = substring_counts.into_iter().filter(|&(_, c)| c >= 2).collect();
+    seed.sort_by(|(a, ca), (b, cb)| (cb * b.chars().count()).cmp(&(ca * a.chars().count())).then(a.cmp(b)));
+    seed.truncate(target * 4);
+    let mut required: Vec<(String, usize)> = char_counts.into_iter().collect();
+    required.sort();
+    let required_count = required.len();
+
+    // 前required_count个片段是必须保留的单字符
+    let total: usize = required.iter().chain(&seed).map(|(_, c)| c).sum();
+    let mut pieces: Vec<(String, f32)> = required.into_iter()
+        .chain(seed)
+        .map(|(text, count)| (text, (count as f32 / total as f32).ln()))
+        .collect();
+
+    loop {
+        let mut counts = vec![0.0; pieces.len()];
+        for _ in 0..EM_ITERATIONS {
+            let index = build_index(&pieces);
+            counts = vec![0.0; pieces.len()];
+            for (word, count) in &words {
+                expected_counts(word, &index, max_piece_chars, *count as f64, &mut counts);
+            }
+            let sum: f64 = counts.iter().sum();
+            for (piece, &count) in pieces.iter_mut().zip(&counts) {
+                piece.1 = (count.max(0.1) / sum).ln() as f32;
+            }
+        }
+        if pieces.len() <= target {
+            break;
+        }
+
+        // 删除片段p后，p会被Viterbi切成其他片段。损失 = p的期望次数 * (p的分数 - 替代切分的分数)
+        let index = build_index(&pieces);
+        let mut candidates: Vec<(f64, usize)> = (required_count..pieces.len())
+            .map(|id| {
+                let chars: Vec<char> = pieces[id].0.chars().collect();
+                let (alternative, _) = viterbi(&chars, &index, max_piece_chars, f32::NEG_INFINITY, Some(id));
+                (counts[id] * (pieces[id].1 - alternative) as f64, id)
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        let keep = ((pieces.len() as f32 * SHRINKING_FACTOR) as usize).max(target) - required_count;
+        let mut kept: Vec<usize> = (0..required_count).chain(candidates.iter().take(keep).map(|&(_, id)| id)).collect();
+        kept.sort();
+        pieces = kept.into_iter().map(|id| pieces[id].clone()).collect();
+    }
+
+    // 词表：<unk>、<s>、</s>、256个字节片段、按分数从高到低排列的正常片段
+    pieces.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    let mut vocab = vec![
+        Piece { text: "<unk>".to_string(), score: 0.0, kind: PieceKind::Unknown },
+        Piece { text: "<s>".to_string(), score: 0.0, kind: PieceKind::Control },
+        Piece { text: "</s>".to_string(), score: 0.0, kind: PieceKind::Control },
+    ];
+    vocab.extend((0..=255u8).map(|b| Piece { text: format!("<0x{:02X}>", b), score: 0.0, kind: PieceKind::Byte(b) }));
+    vocab.extend(pieces.into_iter().map(|(text, score)| Piece { text, score, kind: PieceKind::Normal }));
+    UnigramTokenizer::from_pieces(vocab)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(pieces: &[(&str, f32)]) -> PieceIndex {
+        pieces.iter().enumerate().map(|(id, &(t, s))| (t.to_string(), (id, s))).collect()
+    }
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    fn pieces(t: &UnigramTokenizer, ids: &[u32]) -> Vec<String> {
+        ids.iter().map(|&id| t.pieces[id as usize].text.clone()).collect()
+    }
+
+    const CORPUS: [&str; 8] = [
+        "张量是多维数组，张量计算是大模型的核心。",
+        "在这个练习中，我们将实现张量的矩阵乘法。",
+        "注意力机制使用张量计算注意力分数。",
+        "量化可以减少张量占用的内存。",
+        "我们将实现分词器，分词器把文本转换为token。",
+        "the tensor is the core of the model",
+        "the attention uses the tensor",
+        "we will implement the tokenizer",
+    ];
+
+    #[test]
+    fn test_viterbi() {
+        let idx = index(&[("a", -1.0), ("b", -1.0), ("ab", -1.5), ("c", -2.0), ("abc", -4.5)]);
+        // a+b+c = -4，ab+c = -3.5，abc = -4.5
+        let (score, segments) = viterbi(&chars("abc"), &idx, 3, -100.0, None);
+        assert_eq!(score, -3.5);
+        assert_eq!(segments, vec![Segment::Piece(2), Segment::Piece(3)]);
+
+        // 排除ab之后
+        let (score, segments) = viterbi(&chars("abc"), &idx, 3, -100.0, Some(2));
+        assert_eq!(score, -4.0);
+        assert_eq!(segments, vec![Segment::Piece(0), Segment::Piece(1), Segment::Piece(3)]);
+
+        // 词表中没有的字符
+        let (score, segments) = viterbi(&chars("abx"), &idx, 3, -100.0, None);
+        assert_eq!(score, -101.5);
+        assert_eq!(segments, vec![Segment::Piece(2), Segment::Unknown('x')]);
+        let (score, _) = viterbi(&chars("x"), &idx, 3, f32::NEG_INFINITY, None);
+        assert_eq!(score, f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_expected_counts() {
+        // "ab"有两种切分：a+b（分数-2）和ab（分数-1.5）
+        let idx = index(&[("a", -1.0), ("b", -1.0), ("ab", -1.5)]);
+        let mut counts = vec![0.0; 3];
+        let log_z = expected_counts(&chars("ab"), &idx, 2, 2.0, &mut counts);
+
+        let p_ab = 1.0 / (1.0 + (-0.5f64).exp());
+        assert!((counts[2] - 2.0 * p_ab).abs() < 1e-9);
+        assert!((counts[0] - 2.0 * (1.0 - p_ab)).abs() < 1e-9);
+        assert!((counts[0] - counts[1]).abs() < 1e-12);
+        let expected_z = ((-2.0f64).exp() + (-1.5f64).exp()).ln();
+        assert!((log_z - 2.0 * expected_z).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_train_and_encode() {
+        let t = train_unigram(&CORPUS, 380, 8).unwrap();
+        assert_eq!(t.vocab_size(), 380);
+        assert_eq!(t.pieces[0].kind, PieceKind::Unknown);
+        assert_eq!((t.bos_id, t.eos_id), (Some(1), Some(2)));
+        assert_eq!(t.pieces[3 + 0x41].text, "<0x41>");
+        assert!(t.pieces[259..].windows(2).all(|w| w[0].score >= w[1].score), "正常片段按分数排列");
+
+        // 语料中的每个字符都在词表中
+        for c in CORPUS.concat().chars().filter(|&c| c != ' ') {
+            assert!(t.piece_to_id(&c.to_string()).is_some(), "{}", c);
+        }
+        // 高频词成为一个片段
+        assert!(t.piece_to_id("张量").is_some() || t.piece_to_id("▁张量").is_some());
+        assert!(t.piece_to_id("▁the").is_some());
+        let ids = t.encode("张量计算");
+        assert!(ids.len() < 5, "{:?}", pieces(&t, &ids));
+
+        // 训练语料编码后的片段数明显少于字符数
+        let total_chars: usize = CORPUS.iter().map(|s| s.chars().count()).sum();
+        let total_ids: usize = CORPUS.iter().map(|s| t.encode(s).len()).sum();
+        assert!(total_ids * 3 < total_chars * 2, "{} / {}", total_chars, total_ids);
+
+        // 往返：包括没见过的字符（字节回退）、连续空格和换行
+        for s in CORPUS.iter().copied().chain([" leading  spaces ", "新的文本😀，包含emoji\n和换行", "", "ü"]) {
+            assert_eq!(t.decode(&t.encode(s)).unwrap(), s);
+        }
+        let ids = t.encode("😀");
+        assert_eq!(pieces(&t, &ids[1..]), vec!["<0xF0>", "<0x9F>", "<0x98>", "<0x80>"]);
+        assert_eq!(t.decode(&[9999]).unwrap_err(), TokenizerError::UnknownId(9999));
+
+        assert!(matches!(train_unigram(&CORPUS, 300, 8), Err(TokenizerError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_vocab_file() {
+        let t = train_unigram(&CORPUS, 360, 6).unwrap();
+        let path = std::env::temp_dir().join(format!("rustlings_llm_{}_unigram.vocab", std::process::id()));
+        t.save(&path).unwrap();
+        let loaded = UnigramTokenizer::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.pieces, t.pieces, "分数的十进制表示可以精确还原");
+        for s in CORPUS {
+            assert_eq!(loaded.encode(s), t.encode(s));
+        }
+
+        // 没有字节片段时，未知字符编码为<unk>
+        let small = UnigramTokenizer::from_vocab_file("<unk>\t0\tunknown\n▁\t-1\tnormal\na\t-1\tnormal\nb\t-2\tnormal\nab\t-2.5\tnormal\n").unwrap();
+        assert_eq!(pieces(&small, &small.encode("ab a")), vec!["▁", "ab", "▁", "a"]);
+        assert_eq!(small.encode("axb"), vec![1, 2, 0, 3]);
+        assert_eq!(small.decode(&small.encode("axb")).unwrap(), "a⁇b");
+
+        let parse_err = |text: &str| match UnigramTokenizer::from_vocab_file(text) {
+            Err(TokenizerError::Parse { line, message }) => (line, message),
+            other => panic!("应该解析失败: {:?}", other),
+        };
+        assert_eq!(parse_err("a\t0\tnormal\n").1, "缺少unknown片段");
+        assert_eq!(parse_err("<unk>\t0\tunknown\na\t0\n").0, 2);
+        assert!(parse_err("<unk>\t0\tunknown\na\tx\tnormal\n").1.contains("无效的分数"));
+        assert!(parse_err("<unk>\t0\tunknown\n<0xZZ>\t0\tbyte\n").1.contains("<0xXX>"));
+        assert!(parse_err("<unk>\t0\tunknown\na\t0\tweird\n").1.contains("未知的片段类型"));
+        assert_eq!(parse_err("<unk>\t0\tunknown\na\t0\tnormal\na\t-1\tnormal\n"), (3, "片段\"a\"重复出现".to_string()));
+        assert!(parse_err("<unk>\t0\tunknown\na\\x\t0\tnormal\n").1.contains("无效的转义序列"));
+        assert!(UnigramTokenizer::load("/nonexistent/unigram.vocab").unwrap_err().to_string().starts_with("IO错误"));
+    }
+
+    #[test]
+    fn test_vocab_file_escapes_special_chars() {
+        let corpus = ["line one\nline two\tend", "path C:\\dir\r\n", "tab\tand\nnewline\n"];
+        let t = train_unigram(&corpus, 300, 4).unwrap();
+        for piece in ["\n", "\t", "\\", "\r"] {
+            assert!(t.piece_to_id(piece).is_some(), "单个字符{:?}应该被保留", piece);
+        }
+        let file = t.to_vocab_file();
+        assert_eq!(file.lines().count(), t.vocab_size(), "每个片段恰好占一行");
+        assert!(file.contains("\\n\t") && file.contains("\\t\t") && file.contains("\\\\\t"));
+
+        let loaded = UnigramTokenizer::from_vocab_file(&file).unwrap();
+        assert_eq!(loaded.pieces, t.pieces);
+        for s in corpus {
+            assert_eq!(loaded.encode(s), t.encode(s));
+            assert_eq!(loaded.decode(&loaded.encode(s)).unwrap(), s);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+// 贪心解码每一步只保留当前最好的token，可能错过整体概率更高的序列；随机采样又不够稳定。
+// 机器翻译等任务通常使用束搜索（beam search）：每一步保留num_beams个得分最高的候选序列。
+// 在这个练习中，我们将实现HuggingFace风格的束搜索：
+// - 长度惩罚：得分 = 累计对数概率 / 生成长度^length_penalty，
+//   length_penalty > 0 鼓励更长的序列，= 0 时直接比较累计对数概率
+// - 提前停止：early_stopping为true时，只要凑够num_beams个完成的假设就停止；
+//   为false时，还要等到存活的候选不可能再超过已完成的假设
+// - n-gram屏蔽：禁止生成已经出现过的n-gram，避免重复
+// 为了简单起见，这里每一步都对完整序列重新计算logits；
+// 实际实现中每个beam会有自己的KV Cache，并随着beam的重新排序一起复制或重排。
+
+// 束搜索需要的模型接口：给定完整的token序列，返回下一个token的logits
+trait ScoringModel {
+    fn vocab_size(&self) -> usize;
+
+    fn next_token_logits(&self, tokens: &[u32]) -> Vec<f32>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BeamSearchConfig {
+    num_beams: usize,
+    max_new_tokens: usize,
+    eos_token_id: u32,
+    length_penalty: f32,
+    early_stopping: bool,
+    // 为0时不屏蔽
+    no_repeat_ngram_size: usize,
+}
+
+impl BeamSearchConfig {
+    fn new(num_beams: usize, max_new_tokens: usize, eos_token_id: u32) -> Self {
+        BeamSearchConfig {
+            num_beams,
+            max_new_tokens,
+            eos_token_id,
+            length_penalty: 1.0,
+            early_stopping: false,
+            no_repeat_ngram_size: 0,
+        }
+    }
+
+    fn with_length_penalty(mut self, length_penalty: f32) -> Self {
+        self.length_penalty = length_penalty;
+        self
+    }
+
+    fn with_early_stopping(mut self, early_stopping: bool) -> Self {
+        self.early_stopping = early_stopping;
+        self
+    }
+
+    fn with_no_repeat_ngram_size(mut self, size: usize) -> Self {
+        self.no_repeat_ngram_size = size;
+        self
+    }
+
+    // 长度归一化后的得分，len为生成的token数（包括EOS）
+    fn score(&self, log_prob: f32, len: usize) -> f32 {
+        log_prob / (len.max(1) as f32).powf(self.length_penalty)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Hypothesis {
+    // 生成的token，不包括prompt；以EOS结束时包括EOS
+    tokens: Vec<u32>,
+    log_prob: f32,
+    score: f32,
+    // 为false表示到达max_new_tokens时仍未生成EOS
+    finished: bool,
+}
+
+fn log_softmax(x: &[f32]) -> Vec<f32> {
+    let max = x.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+    let log_sum = x.iter().map(|&v| (v - max).exp()).sum::<f32>().ln() + max;
+    x.iter().map(|&v| v - log_sum).collect()
+}
+
+// 如果下一个token会组成一个已经在seq中出现过的n-gram，就屏蔽它
+fn banned_ngram_tokens(seq: &[u32], n: usize) -> Vec<u32> {
+    if n == 0 || seq.len() < n {
+        return Vec::new();
+    }
+    let prefix = &seq[seq.len() - (n - 1)..];
+    seq.windows(n)
+        .filter(|window| &window[..n - 1] == prefix)
+        .map(|window| window[n - 1])
+        .collect()
+}
+
+// 已完成的假设，只保留得分最高的capacity个
+struct FinishedPool {
+    hyps: Vec<Hypothesis>,
+    capacity: usize,
+}
+
+impl FinishedPool {
+    fn add(&mut self, hyp: Hypothesis) {
+        self.hyps.push(hyp);
+        self.hyps.sort_by(|a, b| b.score.total_cmp(&a.score));
+        self.hyps.truncate(self.capacity);
+    }
+
+    fn is_full(&self) -> bool {
+        self.hyps.len() >= self.capacity
+    }
+
+    fn worst_score(&self) -> f32 {
+        self.hyps.last().map_or(f32::NEG_INFINITY, |hyp| hyp.score)
+    }
+}
+
+// 返回最多num_beams个假设，按得分从高到低排序
+fn beam_search(
+    model: &impl ScoringModel,
+    prompt: &[u32],
+    config: &BeamSearchConfig,
+) -> Result<Vec<Hypothesis>, String> {
+    if config.num_beams == 0 {
+        return Err("num_beams必须大于0".to_string());
+    }
+    if prompt.is_empty() {
+        return Err("prompt不能为空".to_string());
+    }
+    if config.eos_token_id as usize >= model.vocab_size() {
+        return Err(format!("EOS token ID {}超出词表大小{}", config.eos_token_id, model.vocab_size()));
+    }
+
+    let num_beams = config.num_beams;
+    // 存活的候选：(生成的token, 累计对数概率)，按累计对数概率从高到低排列
+    let mut beams: Vec<(Vec<u32>, f32)> = vec![(Vec::new(), 0.0)];
+    let mut finished = FinishedPool { hyps: Vec::new(), capacity: num_beams };
+    let mut done = false;
+
+    for step in 0..config.max_new_tokens {
+        let cur_len = step + 1;
+        let mut candidates = Vec::new();
+        for (beam, (tokens, log_prob)) in beams.iter().enumerate() {
+            let seq: Vec<u32> = prompt.iter().chain(tokens.iter()).copied().collect();
+            let mut log_probs = log_softmax(&model.next_token_logits(&seq));
+            for token in banned_ngram_tokens(&seq, config.no_repeat_ngram_size) {
+                log_probs[token as usize] = f32::NEG_INFINITY;
+            }
+            for (token, &lp) in log_probs.iter().enumerate() {
+                if lp.is_finite() {
+                    candidates.push((beam, token as u32, log_prob + lp));
+                }
+            }
+        }
+        // 分数相同时按beam和token ID排序，保证结果确定
+        candidates.sort_by(|a, b| b.2.total_cmp(&a.2).then((a.0, a.1).cmp(&(b.0, b.1))));
+
+        // 取前2*num_beams个候选，保证即使其中有num_beams个是EOS也还能凑够存活的候选
+        let mut next = Vec::new();
+        for (rank, (beam, token, log_prob)) in candidates.into_iter().take(2 * num_beams).enumerate() {
+            let mut tokens = beams[beam].0.clone();
+            tokens.push(token);
+            if token == config.eos_token_id {
+                // 只有排在前num_beams的EOS候选才算完成（与HuggingFace一致）
+                if rank < num_beams {
+                    let score = config.score(log_prob, cur_len);
+                    finished.add(Hypothesis { tokens, log_prob, score, finished: true });
+                }
+            } else {
+                next.push((tokens, log_prob));
+                if next.len() == num_beams {
+                    break;
+                }
+            }
+        }
+        beams = next;
+
+        if beams.is_empty() {
+            done = true;
+            break;
+        }
+        if finished.is_full() {
+            // 存活的最好候选如果在当前长度结束也超不过已完成中最差的，就认为搜索完成
+            let best_possible = config.score(beams[0].1, cur_len);
+            if config.early_stopping || best_possible <= finished.worst_score() {
+                done = true;
+                break;
+            }
+        }
+    }
+
+    // 到达max_new_tokens时仍然存活的候选也参与最终排序
+    if !done {
+        for (tokens, log_prob) in beams {
+            let score = config.score(log_prob, tokens.len());
+            finished.add(Hypothesis { tokens, log_prob, score, finished: false });
+        }
+    }
+    Ok(finished.hyps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    const EOS: u32 = 0;
+    const A: u32 = 1;
+    const B: u32 = 2;
+    const C: u32 = 3;
+    const START: u32 = 4;
+
+    // 玩具模型：下一个token的概率只取决于最后一个token
+    struct BigramModel {
+        probs: Vec<[f32; 5]>,
+        calls: Cell<usize>,
+    }
+
+    impl BigramModel {
+        fn new(rows: &[(u32, [f32; 5])]) -> Self {
+            let mut probs = vec![[1.0, 0.0, 0.0, 0.0, 0.0]; 5];
+            for &(token, row) in rows {
+                probs[token as usize] = row;
+            }
+            BigramModel { probs, calls: Cell::new(0) }
+        }
+    }
+
+    impl ScoringModel for BigramModel {
+        fn vocab_size(&self) -> usize {
+            5
+        }
+
+        fn next_token_logits(&self, tokens: &[u32]) -> Vec<f32> {
+            self.calls.set(self.calls.get() + 1);
+            self.probs[*tokens.last().unwrap() as usize].iter().map(|p| p.ln()).collect()
+        }
+    }
+
+    #[test]
+    fn test_beam_beats_greedy() {
+        let model = BigramModel::new(&[
+            (START, [0.0, 0.5, 0.4, 0.1, 0.0]),
+            (A, [0.3, 0.2, 0.2, 0.3, 0.0]),
+            (B, [0.9, 0.05, 0.05, 0.0, 0.0]),
+        ]);
+        // 贪心先选概率最大的A，整体概率只有0.5 * 0.3 = 0.15
+        let greedy = beam_search(&model, &[START], &BeamSearchConfig::new(1, 5, EOS)).unwrap();
+        assert_eq!(greedy.len(), 1);
+        assert_eq!(greedy[0].tokens, vec![A, EOS]);
+
+        // 束搜索找到概率为0.4 * 0.9 = 0.36的序列
+        let hyps = beam_search(&model, &[START], &BeamSearchConfig::new(2, 5, EOS)).unwrap();
+        assert_eq!(hyps.len(), 2);
+        assert_eq!(hyps[0].tokens, vec![B, EOS]);
+        assert!((hyps[0].log_prob - 0.36f32.ln()).abs() < 1e-5);
+        assert!((hyps[0].score - 0.36f32.ln() / 2.0).abs() < 1e-5);
+        assert!(hyps[0].finished);
+        assert_eq!(hyps[1].tokens, vec![A, EOS]);
+        assert!(hyps[0].score >= hyps[1].score);
+
+        assert!(beam_search(&model, &[START], &BeamSearchConfig::new(0, 5, EOS)).is_err());
+        assert!(beam_search(&model, &[], &BeamSearchConfig::new(2, 5, EOS)).is_err());
+        assert!(beam_search(&model, &[START], &BeamSearchConfig::new(2, 5, 9)).is_err());
+    }
+
+    fn length_model() -> BigramModel {
+        BigramModel::new(&[
+            (START, [0.4, 0.5, 0.1, 0.0, 0.0]),
+            (A, [0.2, 0.7, 0.1, 0.0, 0.0]),
+            (B, [0.9, 0.0, 0.1, 0.0, 0.0]),
+        ])
+    }
+
+    #[test]
+    fn test_length_penalty() {
+        let model = length_model();
+        // 不做长度归一化时，立即结束的序列概率最高
+        let config = BeamSearchConfig::new(2, 4, EOS).with_length_penalty(0.0);
+        let hyps = beam_search(&model, &[START], &config).unwrap();
+        assert_eq!(hyps[0].tokens, vec![EOS]);
+        assert!((hyps[0].score - 0.4f32.ln()).abs() < 1e-5);
+
+        // 按长度归一化后，更长的序列胜出；到达max_new_tokens的候选标记为未完成
+        let config = BeamSearchConfig::new(2, 4, EOS).with_length_penalty(1.0);
+        let hyps = beam_search(&model, &[START], &config).unwrap();
+        assert_eq!(hyps[0].tokens, vec![A, A, A, A]);
+        assert!(!hyps[0].finished);
+        assert!((hyps[0].score - (0.5f32 * 0.7 * 0.7 * 0.7).ln() / 4.0).abs() < 1e-5);
+        assert_eq!(hyps[1].tokens, vec![A, A, A, EOS]);
+        assert!(hyps[1].finished);
+    }
+
+    #[test]
+    fn test_early_stopping() {
+        let model = length_model();
+        let config = BeamSearchConfig::new(2, 10, EOS).with_early_stopping(true);
+        let hyps = beam_search(&model, &[START], &config).unwrap();
+        // 第1步得到[EOS]，第2步得到[A, EOS]，凑够2个立即停止：第1步1次forward，第2步2次
+        assert_eq!(model.calls.get(), 3);
+        assert_eq!(hyps.iter().map(|h| h.tokens.clone()).collect::<Vec<_>>(), vec![vec![EOS], vec![A, EOS]]);
+
+        // 不提前停止时，更长的候选归一化后得分更高，搜索会继续
+        let model = length_model();
+        let hyps = beam_search(&model, &[START], &config.with_early_stopping(false)).unwrap();
+        assert!(model.calls.get() > 3);
+        assert!(hyps[0].tokens.len() > 2);
+    }
+
+    #[test]
+    fn test_no_repeat_ngram() {
+        let model = BigramModel::new(&[
+            (START, [0.0, 0.9, 0.1, 0.0, 0.0]),
+            (A, [0.1, 0.3, 0.6, 0.0, 0.0]),
+            (B, [0.1, 0.6, 0.3, 0.0, 0.0]),
+        ]);
+        let config = BeamSearchConfig::new(1, 6, EOS);
+        let hyps = beam_search(&model, &[START], &config).unwrap();
+        assert_eq!(hyps[0].tokens, vec![A, B, A, B, A, B]);
+
+        // "A B"已经出现过，之后不能再生成B；"A A"也出现后只能生成EOS
+        let hyps = beam_search(&model, &[START], &config.with_no_repeat_ngram_size(2)).unwrap();
+        assert_eq!(hyps[0].tokens, vec![A, B, A, A, EOS]);
+        let seq: Vec<u32> = std::iter::once(START).chain(hyps[0].tokens.iter().copied()).collect();
+        let mut bigrams: Vec<&[u32]> = seq.windows(2).collect();
+        let total = bigrams.len();
+        bigrams.sort();
+        bigrams.dedup();
+        assert_eq!(bigrams.len(), total);
+
+        assert_eq!(banned_ngram_tokens(&[1, 2, 3, 1, 2], 3), vec![3]);
+        assert_eq!(banned_ngram_tokens(&[1, 2, 1, 3, 1], 2), vec![2, 3]);
+        assert!(banned_ngram_tokens(&[1, 2], 3).is_empty());
+    }
+}
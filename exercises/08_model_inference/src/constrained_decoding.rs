@@ -0,0 +1,694 @@
+// 很多场景要求模型的输出严格符合某种格式，例如只能输出JSON、只能输出几个分类标签之一。
+// 约束解码在每一步把会导致输出不合法的token的logit置为负无穷，模型只能在合法的token中选择。
+// 约束用一个按字节推进的自动机表示：从起始状态出发，每读入一个字节转移一次，走到死状态说明不合法。
+// 在这个练习中，我们将实现两种约束：
+// - TrieConstraint：输出必须是给定字符串集合中的一个，用前缀树实现
+// - RegexConstraint：输出必须完整匹配一个正则表达式，正则先编译为Thompson NFA，
+//   推进时同时跟踪所有可能到达的NFA状态
+// 一个token可能包含多个字节，甚至跨越正则的多个部分（例如 `", "`），
+// 所以判断一个token是否合法，要把它的所有字节依次推进，看最后是否还活着。
+// EOS只有在自动机处于接受状态时才允许生成。
+// 注意：正则只能描述嵌套深度有限的JSON，任意嵌套的JSON语法需要下推自动机。
+
+use std::collections::HashMap;
+
+// 约束解码需要的模型接口：给定完整的token序列，返回下一个token的logits
+// 注意：这个trait与 beam_search.rs 中的定义相同，已经提供
+trait ScoringModel {
+    fn vocab_size(&self) -> usize;
+
+    fn next_token_logits(&self, tokens: &[u32]) -> Vec<f32>;
+}
+
+// 按字节推进的自动机
+trait Automaton {
+    type State: Clone;
+
+    fn start(&self) -> Self::State;
+
+    // 读入一个字节，返回None表示进入死状态
+    fn step(&self, state: &Self::State, byte: u8) -> Option<Self::State>;
+
+    fn is_accepting(&self, state: &Self::State) -> bool;
+
+    fn advance(&self, state: &Self::State, bytes: &[u8]) -> Option<Self::State> {
+        bytes.iter().try_fold(state.clone(), |state, &byte| self.step(&state, byte))
+    }
+
+    // text是否被完整接受
+    fn matches(&self, text: &str) -> bool {
+        self.advance(&self.start(), text.as_bytes())
+            .is_some_and(|state| self.is_accepting(&state))
+    }
+}
+
+// 前缀树约束，状态为节点编号
+struct TrieConstraint {
+    children: Vec<HashMap<u8, usize>>,
+    terminal: Vec<bool>,
+}
+
+impl TrieConstraint {
+    fn new(words: &[&str]) -> Self {
+        let mut trie = TrieConstraint { children: vec![HashMap::new()], terminal: vec![false] };
+        for word in words {
+            let mut node = 0;
+            for &byte in word.as_bytes() {
+                node = match trie.children[node].get(&byte) {
+                    Some(&child) => child,
+                    None => {
+                        trie.children.push(HashMap::new());
+                        trie.terminal.push(false);
+                        let child = trie.children.len() - 1;
+                        trie.children[node].insert(byte, child);
+                        child
+                    }
+                };
+            }
+            trie.terminal[node] = true;
+        }
+        trie
+    }
+}
+
+impl Automaton for TrieConstraint {
+    type State = usize;
+
+    fn start(&self) -> usize {
+        0
+    }
+
+    fn step(&self, state: &usize, byte: u8) -> Option<usize> {
+        self.children[*state].get(&byte).copied()
+    }
+
+    fn is_accepting(&self, state: &usize) -> bool {
+        self.terminal[*state]
+    }
+}
+
+// 单个字节可以匹配的集合
+type ByteSet = [bool; 256];
+
+// {n,m}中允许的最大重复次数，避免NFA过大
+const MAX_REPEAT: usize = 1000;
+// 有上限的重复会把重复的部分复制多份，嵌套时大小相乘，所以还要限制整个NFA的节点数
+const MAX_NFA_NODES: usize = 100_000;
+// 括号和连续量词的最大嵌套深度，解析和编译都是递归的，避免栈溢出
+const MAX_NESTING_DEPTH: usize = 64;
+
+#[derive(Debug, Clone)]
+enum RegexAst {
+    Empty,
+    Bytes(Box<ByteSet>),
+    Concat(Vec<RegexAst>),
+    Alternate(Vec<RegexAst>),
+    Repeat { inner: Box<RegexAst>, min: usize, max: Option<usize> },
+}
+
+fn byte_set(pred: impl Fn(u8) -> bool) -> Box<ByteSet> {
+    let mut set = Box::new([false; 256]);
+    for (byte, slot) in set.iter_mut().enumerate() {
+        *slot = pred(byte as u8);
+    }
+    set
+}
+
+// 递归下降解析正则表达式，按字节匹配
+// 支持：字面量、.、[...]、[^...]、\d \w \s \D \W \S \n \t \r、(...)、|、* + ? {n} {n,} {n,m}
+struct RegexParser<'a> {
+    pattern: &'a [u8],
+    pos: usize,
+    depth: usize, // 当前所在的括号层数
+}
+
+impl<'a> RegexParser<'a> {
+    fn parse(pattern: &'a str) -> Result<RegexAst, String> {
+        let mut parser = RegexParser { pattern: pattern.as_bytes(), pos: 0, depth: 0 };
+        let ast = parser.parse_alternate()?;
+        if parser.pos < parser.pattern.len() {
+            return Err(parser.error("多余的')'"));
+        }
+        Ok(ast)
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("正则表达式第{}个字节处{}", self.pos, message)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.pattern.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<u8, String> {
+        let byte = self.peek().ok_or_else(|| self.error("意外结束"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn parse_alternate(&mut self) -> Result<RegexAst, String> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.peek() == Some(b'|') {
+            self.pos += 1;
+            branches.push(self.parse_concat()?);
+        }
+        Ok(if branches.len() == 1 { branches.pop().unwrap() } else { RegexAst::Alternate(branches) })
+    }
+
+    fn parse_concat(&mut self) -> Result<RegexAst, String> {
+        let mut items = Vec::new();
+        while let Some(byte) = self.peek() {
+            if byte == b'|' || byte == b')' {
+                break;
+            }
+            items.push(self.parse_repeat()?);
+        }
+        Ok(match items.len() {
+            0 => RegexAst::Empty,
+            1 => items.pop().unwrap(),
+            _ => RegexAst::Concat(items),
+        })
+    }
+
+    fn parse_repeat(&mut self) -> Result<RegexAst, String> {
+        let mut ast = self.parse_atom()?;
+        // 每个量词都会多包一层Repeat，与括号一起计入嵌套深度
+        let mut depth = self.depth;
+        while let Some(quantifier @ (b'*' | b'+' | b'?' | b'{')) = self.peek() {
+            depth += 1;
+            if depth > MAX_NESTING_DEPTH {
+                return Err(self.error(&format!("嵌套深度不能超过{}", MAX_NESTING_DEPTH)));
+            }
+            self.pos += 1;
+            let (min, max) = match quantifier {
+                b'*' => (0, None),
+                b'+' => (1, None),
+                b'?' => (0, Some(1)),
+                _ => self.parse_braces()?,
+            };
+            ast = RegexAst::Repeat { inner: Box::new(ast), min, max };
+        }
+        Ok(ast)
+    }
+
+    // 解析{n}、{n,}、{n,m}，调用时已经跳过了'{'
+    fn parse_braces(&mut self) -> Result<(usize, Option<usize>), String> {
+        let min = self.parse_number()?.ok_or_else(|| self.error("{}中缺少重复次数"))?;
+        let max = if self.peek() == Some(b',') {
+            self.pos += 1;
+            self.parse_number()?
+        } else {
+            Some(min)
+        };
+        if self.next()? != b'}' {
+            return Err(self.error("缺少'}'"));
+        }
+        if max.is_some_and(|max| max < min) {
+            return Err(self.error("{n,m}中m不能小于n"));
+        }
+        if min.max(max.unwrap_or(0)) > MAX_REPEAT {
+            return Err(self.error(&format!("重复次数不能超过{}", MAX_REPEAT)));
+        }
+        Ok((min, max))
+    }
+
+    fn parse_number(&mut self) -> Result<Option<usize>, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Ok(None);
+        }
+        std::str::from_utf8(&self.pattern[start..self.pos])
+            .unwrap()
+            .parse()
+            .map(Some)
+            .map_err(|_| self.error("重复次数过大"))
+    }
+
+    fn parse_atom(&mut self) -> Result<RegexAst, String> {
+        let byte = self.next()?;
+        match byte {
+            b'(' => {
+                if self.depth >= MAX_NESTING_DEPTH {
+                    return Err(self.error(&format!("嵌套深度不能超过{}", MAX_NESTING_DEPTH)));
+                }
+                self.depth += 1;
+                let inner = self.parse_alternate()?;
+                self.depth -= 1;
+                if self.peek() != Some(b')') {
+                    return Err(self.error("缺少')'"));
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            b'[' => self.parse_class().map(RegexAst::Bytes),
+            b'.' => Ok(RegexAst::Bytes(byte_set(|b| b != b'\n'))),
+            b'\\' => self.parse_escape().map(RegexAst::Bytes),
+            b'*' | b'+' | b'?' | b'{' => {
+                self.pos -= 1;
+                Err(self.error("量词前面没有内容"))
+            }
+            _ => Ok(RegexAst::Bytes(byte_set(|b| b == byte))),
+        }
+    }
+
+    // 解析转义，调用时已经跳过了'\'
+    fn parse_escape(&mut self) -> Result<Box<ByteSet>, String> {
+        let byte = self.next()?;
+        let is_word = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+        Ok(match byte {
+            b'd' => byte_set(|b| b.is_ascii_digit()),
+            b'D' => byte_set(|b| !b.is_ascii_digit()),
+            b'w' => byte_set(is_word),
+            b'W' => byte_set(|b| !is_word(b)),
+            b's' => byte_set(|b| b.is_ascii_whitespace()),
+            b'S' => byte_set(|b| !b.is_ascii_whitespace()),
+            b'n' => byte_set(|b| b == b'\n'),
+            b't' => byte_set(|b| b == b'\t'),
+            b'r' => byte_set(|b| b == b'\r'),
+            _ if byte.is_ascii_alphanumeric() => {
+                self.pos -= 1;
+                return Err(self.error(&format!("不支持的转义\\{}", byte as char)));
+            }
+            _ => byte_set(|b| b == byte),
+        })
+    }
+
+    // 解析字符类，调用时已经跳过了'['
+    fn parse_class(&mut self) -> Result<Box<ByteSet>, String> {
+        let negate = self.peek() == Some(b'^');
+        if negate {
+            self.pos += 1;
+        }
+        let mut set = Box::new([false; 256]);
+        let mut empty = true;
+        loop {
+            let byte = self.next().map_err(|_| self.error("缺少']'"))?;
+            if byte == b']' && !empty {
+                break;
+            }
+            empty = false;
+            if byte == b'\\' {
+                for (slot, &matched) in set.iter_mut().zip(self.parse_escape()?.iter()) {
+                    *slot |= matched;
+                }
+                continue;
+            }
+            // 范围a-z，'-'出现在末尾时按字面量处理
+            if self.peek() == Some(b'-') && self.pattern.get(self.pos + 1).is_some_and(|&b| b != b']') {
+                self.pos += 1;
+                let end = self.next()?;
+                if end < byte {
+                    return Err(self.error("字符范围的结尾小于开头"));
+                }
+                for slot in &mut set[byte as usize..=end as usize] {
+                    *slot = true;
+                }
+            } else {
+                set[byte as usize] = true;
+            }
+        }
+        if negate {
+            for slot in set.iter_mut() {
+                *slot = !*slot;
+            }
+        }
+        Ok(set)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum NfaNode {
+    // 读入集合中的一个字节后转移到目标节点
+    Bytes(Box<ByteSet>, usize),
+    // 不读入字节，同时转移到两个节点
+    Split(usize, usize),
+    Match,
+}
+
+// 正则约束，状态为当前可能所在的NFA节点集合（只保留Bytes和Match节点）
+struct RegexConstraint {
+    nodes: Vec<NfaNode>,
+    start: usize,
+}
+
+impl RegexConstraint {
+    fn new(pattern: &str) -> Result<Self, String> {
+        let ast = RegexParser::parse(pattern)?;
+        let mut nodes = vec![NfaNode::Match];
+        let start = Self::compile(&ast, 0, &mut nodes)?;
+        Ok(RegexConstraint { nodes, start })
+    }
+
+    // 把ast编译为NFA片段，匹配完成后转移到next，返回片段的入口
+    // 节点数超过MAX_NFA_NODES时返回错误
+    fn compile(ast: &RegexAst, next: usize, nodes: &mut Vec<NfaNode>) -> Result<usize, String> {
+        let push = |node: NfaNode, nodes: &mut Vec<NfaNode>| {
+            if nodes.len() >= MAX_NFA_NODES {
+                return Err(format!("正则表达式编译后的NFA超过{}个节点", MAX_NFA_NODES));
+            }
+            nodes.push(node);
+            Ok(nodes.len() - 1)
+        };
+        Ok(match ast {
+            RegexAst::Empty => next,
+            RegexAst::Bytes(set) => push(NfaNode::Bytes(set.clone(), next), nodes)?,
+            RegexAst::Concat(items) => {
+                items.iter().rev().try_fold(next, |next, item| Self::compile(item, next, nodes))?
+            }
+            RegexAst::Alternate(branches) => {
+                let starts = branches.iter()
+                    .map(|branch| Self::compile(branch, next, nodes))
+                    .collect::<Result<Vec<usize>, String>>()?;
+                let mut entry = *starts.last().unwrap_or(&next);
+                for &start in starts.iter().rev().skip(1) {
+                    entry = push(NfaNode::Split(start, entry), nodes)?;
+                }
+                entry
+            }
+            RegexAst::Repeat { inner, min, max } => {
+                let mut entry = match max {
+                    // 无上限：循环节点先占位，编译完inner后再填入
+                    None => {
+                        let loop_node = push(NfaNode::Match, nodes)?;
+                        let body = Self::compile(inner, loop_node, nodes)?;
+                        nodes[loop_node] = NfaNode::Split(body, next);
+                        loop_node
+                    }
+                    // 有上限：嵌套的可选部分 (x(x)?)?
+                    Some(max) => (0..max - min).try_fold(next, |rest, _| {
+                        let body = Self::compile(inner, rest, nodes)?;
+                        push(NfaNode::Split(body, next), nodes)
+                    })?,
+                };
+                for _ in 0..*min {
+                    entry = Self::compile(inner, entry, nodes)?;
+                }
+                entry
+            }
+        })
+    }
+
+    // 沿着Split展开，得到排好序的状态集合
+    fn closure(&self, roots: impl IntoIterator<Item = usize>) -> Vec<usize> {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut stack: Vec<usize> = roots.into_iter().collect();
+        let mut states = Vec::new();
+        while let Some(node) = stack.pop() {
+            if std::mem::replace(&mut visited[node], true) {
+                continue;
+            }
+            match &self.nodes[node] {
+                NfaNode::Split(a, b) => {
+                    stack.push(*a);
+                    stack.push(*b);
+                }
+                _ => states.push(node),
+            }
+        }
+        states.sort_unstable();
+        states
+    }
+}
+
+impl Automaton for RegexConstraint {
+    type State = Vec<usize>;
+
+    fn start(&self) -> Vec<usize> {
+        self.closure([self.start])
+    }
+
+    fn step(&self, state: &Vec<usize>, byte: u8) -> Option<Vec<usize>> {
+        let targets = state.iter().filter_map(|&node| match &self.nodes[node] {
+            NfaNode::Bytes(set, next) if set[byte as usize] => Some(*next),
+            _ => None,
+        });
+        let next = self.closure(targets);
+        if next.is_empty() { None } else { Some(next) }
+    }
+
+    fn is_accepting(&self, state: &Vec<usize>) -> bool {
+        state.iter().any(|&node| matches!(self.nodes[node], NfaNode::Match))
+    }
+}
+
+// 在自动机上跟踪已生成的输出，为每一步计算合法的token
+struct ConstrainedDecoder<'a, A: Automaton> {
+    automaton: &'a A,
+    // 每个token解码后的字节
+    token_bytes: &'a [Vec<u8>],
+    eos_token_id: u32,
+    state: A::State,
+}
+
+impl<'a, A: Automaton> ConstrainedDecoder<'a, A> {
+    fn new(automaton: &'a A, token_bytes: &'a [Vec<u8>], eos_token_id: u32) -> Self {
+        ConstrainedDecoder { automaton, token_bytes, eos_token_id, state: automaton.start() }
+    }
+
+    fn is_accepting(&self) -> bool {
+        self.automaton.is_accepting(&self.state)
+    }
+
+    // 每个token在当前状态下是否合法
+    // 不产生任何字节的token（EOS以外的特殊token）不会推进输出，一律不允许
+    fn allowed_tokens(&self) -> Vec<bool> {
+        self.token_bytes
+            .iter()
+            .enumerate()
+            .map(|(id, bytes)| {
+                if id as u32 == self.eos_token_id {
+                    self.is_accepting()
+                } else {
+                    !bytes.is_empty() && self.automaton.advance(&self.state, bytes).is_some()
+                }
+            })
+            .collect()
+    }
+
+    fn mask_logits(&self, logits: &mut [f32]) {
+        for (logit, allowed) in logits.iter_mut().zip(self.allowed_tokens()) {
+            if !allowed {
+                *logit = f32::NEG_INFINITY;
+            }
+        }
+    }
+
+    fn advance(&mut self, token: u32) -> Result<(), String> {
+        let bytes = self.token_bytes
+            .get(token as usize)
+            .ok_or_else(|| format!("token ID {}超出词表大小{}", token, self.token_bytes.len()))?;
+        if token == self.eos_token_id {
+            return if self.is_accepting() { Ok(()) } else { Err("输出尚未完整，不能生成EOS".to_string()) };
+        }
+        self.state = self.automaton
+            .advance(&self.state, bytes)
+            .ok_or_else(|| format!("token {}不满足约束", token))?;
+        Ok(())
+    }
+}
+
+// 带约束的贪心解码，返回生成的token（不包含EOS）
+fn generate_constrained<A: Automaton>(
+    model: &impl ScoringModel,
+    automaton: &A,
+    token_bytes: &[Vec<u8>],
+    eos_token_id: u32,
+    prompt: &[u32],
+    max_new_tokens: usize,
+) -> Result<Vec<u32>, String> {
+    if token_bytes.len() != model.vocab_size() {
+        return Err(format!("token_bytes长度{}与词表大小{}不一致", token_bytes.len(), model.vocab_size()));
+    }
+    let mut decoder = ConstrainedDecoder::new(automaton, token_bytes, eos_token_id);
+    let mut seq = prompt.to_vec();
+    let mut generated = Vec::new();
+    for _ in 0..max_new_tokens {
+        let mut logits = model.next_token_logits(&seq);
+        decoder.mask_logits(&mut logits);
+        let best = logits.iter()
+            .enumerate()
+            .filter(|(_, logit)| logit.is_finite())
+            .fold(None, |best: Option<(usize, f32)>, (i, &logit)| match best {
+                Some((_, best_logit)) if best_logit >= logit => best,
+                _ => Some((i, logit)),
+            });
+        let token = match best {
+            Some((token, _)) => token as u32,
+            None => return Err("没有满足约束的token可以继续生成".to_string()),
+        };
+        if token == eos_token_id {
+            return Ok(generated);
+        }
+        decoder.advance(token)?;
+        seq.push(token);
+        generated.push(token);
+    }
+    if decoder.is_accepting() {
+        Ok(generated)
+    } else {
+        Err(format!("生成{}个token后输出仍不完整", max_new_tokens))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 玩具模型：不看输入，总是给出固定的偏好
+    struct PreferenceModel {
+        logits: Vec<f32>,
+    }
+
+    impl ScoringModel for PreferenceModel {
+        fn vocab_size(&self) -> usize {
+            self.logits.len()
+        }
+
+        fn next_token_logits(&self, _tokens: &[u32]) -> Vec<f32> {
+            self.logits.clone()
+        }
+    }
+
+    fn vocab_and_model(tokens: &[(&str, f32)]) -> (Vec<Vec<u8>>, PreferenceModel) {
+        let vocab = tokens.iter()
+            .map(|&(text, _)| if text == "<eos>" { Vec::new() } else { text.as_bytes().to_vec() })
+            .collect();
+        let model = PreferenceModel { logits: tokens.iter().map(|&(_, logit)| logit).collect() };
+        (vocab, model)
+    }
+
+    fn decode(vocab: &[Vec<u8>], tokens: &[u32]) -> String {
+        String::from_utf8(tokens.iter().flat_map(|&t| vocab[t as usize].clone()).collect()).unwrap()
+    }
+
+    #[test]
+    fn test_regex_matching() {
+        let cases: &[(&str, &[&str], &[&str])] = &[
+            ("a(b|c)*d", &["ad", "abd", "abcbcd"], &["a", "abxd", "abdd"]),
+            ("colou?r", &["color", "colour"], &["colouur", "colr"]),
+            (r"\d{2,3}", &["12", "123"], &["1", "1234", "ab"]),
+            (r"x{2,}y{0,1}", &["xx", "xxxxy"], &["x", "xxyy"]),
+            (r#""[^"]*""#, &["\"\"", "\"hi there\""], &["\"a\"b\"", "\"abc"]),
+            (r"-?\d+(\.\d+)?", &["42", "-3.14", "0.5"], &["3.", ".5", "--1"]),
+            (r"[a-z_]\w*", &["foo", "_bar9"], &["9foo", "a-b"]),
+            ("(a|)b|c", &["ab", "b", "c"], &["a", "ac"]),
+            (r"\{\}\s[-+]", &["{} -", "{}\t+"], &["{}-", "{} *"]),
+            ("(a*)*b", &["b", "aaab"], &["aa"]),
+            ("", &[""], &["a"]),
+        ];
+        for &(pattern, accepted, rejected) in cases {
+            let regex = RegexConstraint::new(pattern).unwrap();
+            for text in accepted {
+                assert!(regex.matches(text), "{}应该匹配{:?}", pattern, text);
+            }
+            for text in rejected {
+                assert!(!regex.matches(text), "{}不应该匹配{:?}", pattern, text);
+            }
+        }
+
+        // 前缀仍然存活，但还没有到接受状态
+        let regex = RegexConstraint::new(r#"\{"a": \d+\}"#).unwrap();
+        let state = regex.advance(&regex.start(), b"{\"a\": 1").unwrap();
+        assert!(!regex.is_accepting(&state));
+        assert!(regex.advance(&regex.start(), b"{x").is_none());
+
+        for pattern in ["(ab", "ab)", "[a-", "*a", "a{3,1}", "a{", r"\q", "[z-a]", "a{2000}"] {
+            assert!(RegexConstraint::new(pattern).is_err(), "{}应该解析失败", pattern);
+        }
+        assert_eq!(RegexConstraint::new("a(b").err().unwrap(), "正则表达式第3个字节处缺少')'");
+
+        // 嵌套的有上限重复会让NFA的大小相乘，超过节点上限时返回错误而不是耗尽内存
+        assert!(RegexConstraint::new("(a{100}){100}").is_ok());
+        let err = RegexConstraint::new("((a{1000}){1000}){1000}").err().unwrap();
+        assert!(err.contains("NFA超过"), "{}", err);
+        assert!(RegexConstraint::new("(a{1000}){1000}").is_err());
+
+        // 嵌套过深的括号或连续量词返回错误而不是栈溢出
+        let deep = format!("{}a{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert!(RegexConstraint::new(&deep).err().unwrap().contains("嵌套深度"));
+        assert!(RegexConstraint::new(&format!("a{}", "*".repeat(100_000))).err().unwrap().contains("嵌套深度"));
+        let ok = format!("{}a{}", "(".repeat(MAX_NESTING_DEPTH), ")".repeat(MAX_NESTING_DEPTH));
+        assert!(RegexConstraint::new(&ok).unwrap().matches("a"));
+    }
+
+    #[test]
+    fn test_trie_constraint() {
+        let (vocab, model) = vocab_and_model(&[
+            ("<eos>", 9.0),
+            ("I think it is positive", 10.0),
+            ("pos", 1.0),
+            ("itive", 1.0),
+            ("neg", 1.0),
+            ("ative", 1.0),
+            ("neu", 1.0),
+            ("tral", 6.0),
+            ("n", 8.0),
+            ("e", 7.0),
+            ("u", 4.0),
+            ("g", 3.0),
+        ]);
+        let trie = TrieConstraint::new(&["positive", "negative", "neutral"]);
+        assert!(trie.matches("negative") && !trie.matches("neg") && !trie.matches("neutrals"));
+
+        let decoder = ConstrainedDecoder::new(&trie, &vocab, 0);
+        let allowed: Vec<usize> = (0..vocab.len()).filter(|&i| decoder.allowed_tokens()[i]).collect();
+        assert_eq!(allowed, vec![2, 4, 6, 8]);
+
+        let tokens = generate_constrained(&model, &trie, &vocab, 0, &[1], 10).unwrap();
+        assert_eq!(decode(&vocab, &tokens), "neutral");
+        assert_eq!(tokens, vec![8, 9, 10, 7]);
+    }
+
+    #[test]
+    fn test_json_regex_constraint() {
+        let (vocab, model) = vocab_and_model(&[
+            ("<eos>", 8.0),
+            ("Sure! Here is the JSON:", 10.0),
+            ("{", 4.5),
+            ("}", 4.0),
+            ("\"", 6.0),
+            ("name", 3.5),
+            ("\": ", 5.5),
+            (", ", 7.0),
+            ("age", 3.0),
+            ("{\"", 5.0),
+            ("\"}", 6.5),
+            ("bob", 2.5),
+            ("1", 1.5),
+            ("2", 1.0),
+            ("42", 2.0),
+            ("a", 0.5),
+            ("\n", 9.0),
+            ("\", \"", 7.0),
+            (":", 0.2),
+            (" ", 0.1),
+        ]);
+        let pattern = r#"\{"name": "[a-z]+", "age": [0-9]+\}"#;
+        let regex = RegexConstraint::new(pattern).unwrap();
+
+        // 不加约束时模型会先输出一段说明文字
+        assert!(model.logits.iter().all(|&logit| logit <= model.logits[1]));
+
+        let decoder = ConstrainedDecoder::new(&regex, &vocab, 0);
+        let allowed: Vec<usize> = (0..vocab.len()).filter(|&i| decoder.allowed_tokens()[i]).collect();
+        assert_eq!(allowed, vec![2, 9]);
+
+        let tokens = generate_constrained(&model, &regex, &vocab, 0, &[1], 30).unwrap();
+        let text = decode(&vocab, &tokens);
+        assert!(regex.matches(&text));
+        assert_eq!(text, r#"{"name": "name", "age": 42}"#);
+        // 跨越正则多个部分的token `", "` 被使用了
+        assert!(tokens.contains(&17));
+
+        // token数不够时报错，而不是返回不完整的JSON
+        assert!(generate_constrained(&model, &regex, &vocab, 0, &[1], 5).is_err());
+
+        let mut decoder = ConstrainedDecoder::new(&regex, &vocab, 0);
+        assert!(decoder.advance(1).is_err());
+        assert!(decoder.advance(0).is_err());
+        assert!(decoder.advance(9).is_ok());
+    }
+}
@@ -0,0 +1,515 @@
+// 有了模型和采样器，还需要一个生成循环把它们串起来：
+// 1. prefill：把整个prompt一次性送入模型，填充KV Cache，得到最后一个位置的logits
+// 2. decode：每一步采样一个token，再只把这个新token送入模型，利用KV Cache得到下一步的logits
+// 生成在以下情况停止：
+// - 采样到EOS token
+// - 生成的文本中出现了用户指定的停止字符串（停止字符串可能跨越多个token）
+// - 达到max_new_tokens，或者prompt加上已生成的token达到模型的最大长度
+// 每生成一个token都会调用一次回调函数，用于流式输出。为了不把停止字符串的一部分输出出去，
+// 可能是停止字符串前缀的文本以及不完整的UTF-8字符会先暂存，确认安全后再输出。
+// 生成结束后会报告首token延迟（TTFT）和生成速度（tokens/秒）。
+//
+// 为了让这个练习独立于具体的模型实现，生成循环只依赖下面几个trait：
+// transformer.rs中的DecoderModel加上KvCache、sampler.rs中的Sampler都可以很容易地实现它们。
+
+use std::time::{Duration, Instant};
+
+// 生成循环需要的模型接口，模型内部维护KV Cache
+trait LanguageModel {
+    fn vocab_size(&self) -> usize;
+
+    fn max_seq_len(&self) -> usize;
+
+    // 把tokens接在已缓存的序列之后，返回每个新位置的logits，形状为 [tokens.len(), vocab_size]
+    fn forward(&mut self, tokens: &[u32]) -> Result<Vec<Vec<f32>>, String>;
+
+    // 已缓存的token数
+    fn cache_len(&self) -> usize;
+
+    // 只保留前len个token的缓存
+    fn truncate_cache(&mut self, len: usize);
+}
+
+// 把token ID还原为字节，字节级BPE中一个token可能只包含半个UTF-8字符
+trait TokenDecoder {
+    fn token_bytes(&self, id: u32) -> Vec<u8>;
+}
+
+// 根据logits和已经出现的token（prompt + 已生成）选出下一个token
+trait TokenSampler {
+    fn sample(&mut self, logits: &[f32], history: &[u32]) -> u32;
+}
+
+// 最简单的采样器：贪心解码
+struct GreedySampler;
+
+impl TokenSampler for GreedySampler {
+    fn sample(&mut self, logits: &[f32], _history: &[u32]) -> u32 {
+        logits.iter()
+            .enumerate()
+            .fold(0, |best, (i, &v)| if v > logits[best] { i } else { best }) as u32
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct GenerationConfig {
+    max_new_tokens: usize,
+    eos_token_id: Option<u32>,
+    stop_strings: Vec<String>,
+}
+
+impl GenerationConfig {
+    fn new(max_new_tokens: usize) -> Self {
+        GenerationConfig { max_new_tokens, eos_token_id: None, stop_strings: Vec::new() }
+    }
+
+    fn with_eos(mut self, eos_token_id: u32) -> Self {
+        self.eos_token_id = Some(eos_token_id);
+        self
+    }
+
+    fn with_stop_string(mut self, stop: &str) -> Self {
+        self.stop_strings.push(stop.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FinishReason {
+    Eos,
+    // 命中的停止字符串
+    StopString(String),
+    MaxNewTokens,
+    // 序列长度达到模型的最大长度
+    MaxLength,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct GenerationStats {
+    prompt_tokens: usize,
+    generated_tokens: usize,
+    // 从开始prefill到采样出第一个token的时间，一个token都没有生成时为None
+    time_to_first_token: Option<Duration>,
+    total_time: Duration,
+}
+
+impl GenerationStats {
+    // 整体速度：生成的token数 / 总时间
+    fn tokens_per_second(&self) -> f64 {
+        let secs = self.total_time.as_secs_f64();
+        if secs > 0.0 { self.generated_tokens as f64 / secs } else { 0.0 }
+    }
+
+    // 解码速度：不计prefill和第一个token，只看之后每步增量解码的速度
+    fn decode_tokens_per_second(&self) -> f64 {
+        let ttft = match self.time_to_first_token {
+            Some(ttft) => ttft,
+            None => return 0.0,
+        };
+        let secs = self.total_time.saturating_sub(ttft).as_secs_f64();
+        if secs > 0.0 && self.generated_tokens > 1 {
+            (self.generated_tokens - 1) as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct GenerationOutput {
+    // 生成的token，不包含EOS；命中停止字符串时包含构成停止字符串的token
+    tokens: Vec<u32>,
+    // 生成的文本，不包含停止字符串及其之后的内容
+    text: String,
+    // 结束时仍被暂存、没有通过on_token输出的文本（例如最后可能是停止字符串开头的部分）
+    trailing_text: String,
+    finish_reason: FinishReason,
+    stats: GenerationStats,
+}
+
+// 流式输出的状态：已生成的全部字节以及已经输出的字节数
+struct TextStream<'a> {
+    bytes: Vec<u8>,
+    emitted: usize,
+    stop_strings: &'a [String],
+}
+
+impl<'a> TextStream<'a> {
+    fn new(stop_strings: &'a [String]) -> Self {
+        TextStream { bytes: Vec::new(), emitted: 0, stop_strings }
+    }
+
+    // 追加一个token的字节
+    // 命中停止字符串时截断到停止字符串之前，返回(可以输出的新文本, 命中的停止字符串)
+    fn push(&mut self, token_bytes: &[u8]) -> (String, Option<String>) {
+        self.bytes.extend_from_slice(token_bytes);
+
+        // 停止字符串可能从已输出部分之前开始，但不会早于已暂存部分的起点
+        let max_stop_len = self.stop_strings.iter().map(|s| s.len()).max().unwrap_or(0);
+        let search_from = self.emitted.saturating_sub(max_stop_len);
+        let hit = self.stop_strings.iter()
+            .filter(|stop| !stop.is_empty())
+            .filter_map(|stop| {
+                find_bytes(&self.bytes[search_from..], stop.as_bytes()).map(|pos| (search_from + pos, stop))
+            })
+            .min_by_key(|&(pos, _)| pos);
+        if let Some((pos, stop)) = hit {
+            self.bytes.truncate(pos);
+            return (self.flush(), Some(stop.clone()));
+        }
+
+        // 暂存可能是停止字符串前缀的后缀
+        let held = self.stop_strings.iter()
+            .map(|stop| longest_suffix_prefix(&self.bytes[self.emitted..], stop.as_bytes()))
+            .max()
+            .unwrap_or(0);
+        let safe = self.bytes.len() - held;
+        // 暂存不完整的UTF-8字符
+        let text = match std::str::from_utf8(&self.bytes[self.emitted..safe]) {
+            Ok(text) => text.to_string(),
+            Err(e) if e.error_len().is_none() => {
+                let end = self.emitted + e.valid_up_to();
+                let text = String::from_utf8_lossy(&self.bytes[self.emitted..end]).into_owned();
+                self.emitted = end;
+                return (text, None);
+            }
+            // 真正非法的字节序列不会再变得合法，直接替换输出
+            Err(_) => String::from_utf8_lossy(&self.bytes[self.emitted..safe]).into_owned(),
+        };
+        self.emitted = safe;
+        (text, None)
+    }
+
+    // 输出所有剩余的字节
+    fn flush(&mut self) -> String {
+        let text = String::from_utf8_lossy(&self.bytes[self.emitted.min(self.bytes.len())..]).into_owned();
+        self.emitted = self.bytes.len();
+        text
+    }
+
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.bytes).into_owned()
+    }
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+// text的后缀与pattern的前缀最长能重合多少（不包括整个pattern）
+fn longest_suffix_prefix(text: &[u8], pattern: &[u8]) -> usize {
+    let max = text.len().min(pattern.len().saturating_sub(1));
+    (1..=max)
+        .rev()
+        .find(|&n| text[text.len() - n..] == pattern[..n])
+        .unwrap_or(0)
+}
+
+struct Generator<M, D, S> {
+    model: M,
+    decoder: D,
+    sampler: S,
+}
+
+impl<M: LanguageModel, D: TokenDecoder, S: TokenSampler> Generator<M, D, S> {
+    fn new(model: M, decoder: D, sampler: S) -> Self {
+        Generator { model, decoder, sampler }
+    }
+
+    fn generate(&mut self, prompt: &[u32], config: &GenerationConfig) -> Result<GenerationOutput, String> {
+        self.generate_stream(prompt, config, |_, _| {})
+    }
+
+    // 每生成一个token恰好调用一次on_token(token, 新增的可输出文本)
+    // 新增文本可能为空（被暂存），所有新增文本拼起来再加上trailing_text等于最终的text
+    fn generate_stream(
+        &mut self,
+        prompt: &[u32],
+        config: &GenerationConfig,
+        mut on_token: impl FnMut(u32, &str),
+    ) -> Result<GenerationOutput, String> {
+        if prompt.is_empty() {
+            return Err("prompt不能为空".to_string());
+        }
+        let max_seq_len = self.model.max_seq_len();
+        if prompt.len() > max_seq_len {
+            return Err(format!("prompt长度{}超出模型最大长度{}", prompt.len(), max_seq_len));
+        }
+        let vocab_size = self.model.vocab_size();
+        if let Some(&id) = prompt.iter().find(|&&id| id as usize >= vocab_size) {
+            return Err(format!("token ID {}超出词表大小{}", id, vocab_size));
+        }
+
+        let start = Instant::now();
+        self.model.truncate_cache(0);
+        // prefill：只需要最后一个位置的logits
+        let mut logits = self.model
+            .forward(prompt)?
+            .pop()
+            .ok_or_else(|| "模型没有返回logits".to_string())?;
+
+        let mut history = prompt.to_vec();
+        let mut tokens = Vec::new();
+        let mut stream = TextStream::new(&config.stop_strings);
+        let mut time_to_first_token = None;
+
+        let finish_reason = loop {
+            if tokens.len() >= config.max_new_tokens {
+                break FinishReason::MaxNewTokens;
+            }
+            if history.len() >= max_seq_len {
+                break FinishReason::MaxLength;
+            }
+            // decode：只把上一步生成的新token送入模型
+            if let Some(&last) = tokens.last() {
+                logits = self.model
+                    .forward(&[last])?
+                    .pop()
+                    .ok_or_else(|| "模型没有返回logits".to_string())?;
+            }
+
+            let token = self.sampler.sample(&logits, &history);
+            time_to_first_token.get_or_insert_with(|| start.elapsed());
+            if Some(token) == config.eos_token_id {
+                break FinishReason::Eos;
+            }
+            tokens.push(token);
+            history.push(token);
+
+            let (text, stop) = stream.push(&self.decoder.token_bytes(token));
+            on_token(token, &text);
+            if let Some(stop) = stop {
+                break FinishReason::StopString(stop);
+            }
+        };
+
+        // 停止时暂存的文本放在trailing_text中返回，不再调用on_token
+        // 命中停止字符串时push已经输出了停止字符串之前的全部文本
+        let trailing_text = stream.flush();
+
+        Ok(GenerationOutput {
+            stats: GenerationStats {
+                prompt_tokens: prompt.len(),
+                generated_tokens: tokens.len(),
+                time_to_first_token,
+                total_time: start.elapsed(),
+            },
+            tokens,
+            text: stream.text(),
+            trailing_text,
+            finish_reason,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 玩具模型：第p个位置的logits是next[p]的one-hot，用来精确控制生成的序列
+    // 同时记录每次forward的输入长度，用于检查KV Cache的使用方式
+    struct ScriptedModel {
+        next: Vec<u32>,
+        vocab_size: usize,
+        max_seq_len: usize,
+        cache: Vec<u32>,
+        calls: Vec<usize>,
+        delay: Duration,
+    }
+
+    impl ScriptedModel {
+        fn new(next: Vec<u32>) -> Self {
+            ScriptedModel {
+                next,
+                vocab_size: VOCAB.len(),
+                max_seq_len: 64,
+                cache: Vec::new(),
+                calls: Vec::new(),
+                delay: Duration::ZERO,
+            }
+        }
+    }
+
+    impl LanguageModel for ScriptedModel {
+        fn vocab_size(&self) -> usize {
+            self.vocab_size
+        }
+
+        fn max_seq_len(&self) -> usize {
+            self.max_seq_len
+        }
+
+        fn forward(&mut self, tokens: &[u32]) -> Result<Vec<Vec<f32>>, String> {
+            std::thread::sleep(self.delay);
+            self.calls.push(tokens.len());
+            let start = self.cache.len();
+            self.cache.extend_from_slice(tokens);
+            Ok((start..self.cache.len())
+                .map(|p| {
+                    let mut logits = vec![0.0; self.vocab_size];
+                    logits[self.next[p] as usize] = 10.0;
+                    logits
+                })
+                .collect())
+        }
+
+        fn cache_len(&self) -> usize {
+            self.cache.len()
+        }
+
+        fn truncate_cache(&mut self, len: usize) {
+            self.cache.truncate(len);
+        }
+    }
+
+    const VOCAB: [&[u8]; 12] = [
+        b"<eos>", b"Hello", b",", b" wor", b"ld", b"!", b" ST", b"OP", b"\xC3", b"\xA9", b" caf", b"S",
+    ];
+
+    struct ToyDecoder;
+
+    impl TokenDecoder for ToyDecoder {
+        fn token_bytes(&self, id: u32) -> Vec<u8> {
+            VOCAB[id as usize].to_vec()
+        }
+    }
+
+    // prompt为两个token，之后依次生成continuation
+    fn scripted(continuation: &[u32]) -> ScriptedModel {
+        let mut next = vec![0, continuation[0]];
+        next.extend_from_slice(&continuation[1..]);
+        next.resize(64, 0);
+        ScriptedModel::new(next)
+    }
+
+    #[test]
+    fn test_eos_and_max_new_tokens() {
+        let model = scripted(&[1, 2, 3, 4, 5, 0, 1]);
+        let mut generator = Generator::new(model, ToyDecoder, GreedySampler);
+        let config = GenerationConfig::new(20).with_eos(0);
+        let out = generator.generate(&[5, 5], &config).unwrap();
+        assert_eq!(out.text, "Hello, world!");
+        assert_eq!(out.tokens, vec![1, 2, 3, 4, 5]);
+        assert_eq!(out.finish_reason, FinishReason::Eos);
+        // 先prefill整个prompt，之后每步只送入一个token
+        assert_eq!(generator.model.calls, vec![2, 1, 1, 1, 1, 1]);
+        assert_eq!(generator.model.cache_len(), 7);
+
+        // 重新生成时会清空KV Cache
+        generator.model.calls.clear();
+        let out = generator.generate(&[5, 5], &GenerationConfig::new(3).with_eos(0)).unwrap();
+        assert_eq!(out.text, "Hello, wor");
+        assert_eq!(out.finish_reason, FinishReason::MaxNewTokens);
+        assert_eq!(generator.model.calls, vec![2, 1, 1]);
+
+        // 没有设置EOS时EOS只是普通token
+        let out = generator.generate(&[5, 5], &GenerationConfig::new(7)).unwrap();
+        assert_eq!(out.tokens, vec![1, 2, 3, 4, 5, 0, 1]);
+        assert_eq!(out.text, "Hello, world!<eos>Hello");
+
+        let out = generator.generate(&[5, 5], &GenerationConfig::new(0)).unwrap();
+        assert!(out.tokens.is_empty() && out.stats.time_to_first_token.is_none());
+    }
+
+    #[test]
+    fn test_stop_string_across_tokens() {
+        // "Hello ST" + "OP" + "!"：停止字符串"STOP"跨越两个token
+        let model = scripted(&[1, 6, 7, 5, 0]);
+        let mut generator = Generator::new(model, ToyDecoder, GreedySampler);
+        let config = GenerationConfig::new(20).with_eos(0).with_stop_string("STOP");
+        let mut chunks = Vec::new();
+        let out = generator
+            .generate_stream(&[5, 5], &config, |token, text| chunks.push((token, text.to_string())))
+            .unwrap();
+        assert_eq!(out.text, "Hello ");
+        assert_eq!(out.tokens, vec![1, 6, 7]);
+        assert_eq!(out.finish_reason, FinishReason::StopString("STOP".to_string()));
+        // "ST"可能是停止字符串的开头，先暂存不输出
+        assert_eq!(chunks, vec![(1, "Hello".to_string()), (6, " ".to_string()), (7, String::new())]);
+        assert!(out.trailing_text.is_empty(), "停止字符串之前的文本已经全部输出");
+
+        // 暂存的前缀最终没有构成停止字符串时会被正常输出
+        let model = scripted(&[6, 11, 6, 5, 0]);
+        let mut generator = Generator::new(model, ToyDecoder, GreedySampler);
+        let config = GenerationConfig::new(20).with_eos(0).with_stop_string("STOP").with_stop_string("!!");
+        let mut streamed = String::new();
+        let mut calls = 0;
+        let out = generator
+            .generate_stream(&[5, 5], &config, |_, text| {
+                streamed.push_str(text);
+                calls += 1;
+            })
+            .unwrap();
+        assert_eq!(out.text, " STS ST!");
+        // 最后的"!"可能是"!!"的开头，结束时还没有输出，每个token只回调一次
+        assert_eq!(streamed, " STS ST");
+        assert_eq!(out.trailing_text, "!");
+        assert_eq!(streamed + &out.trailing_text, out.text);
+        assert_eq!(calls, out.tokens.len());
+        assert_eq!(out.finish_reason, FinishReason::Eos);
+
+        // 多个停止字符串取最先出现的
+        let model = scripted(&[1, 2, 3, 4, 5]);
+        let mut generator = Generator::new(model, ToyDecoder, GreedySampler);
+        let config = GenerationConfig::new(20).with_stop_string("ld").with_stop_string(",");
+        let out = generator.generate(&[5, 5], &config).unwrap();
+        assert_eq!(out.text, "Hello");
+        assert_eq!(out.finish_reason, FinishReason::StopString(",".to_string()));
+    }
+
+    #[test]
+    fn test_streaming_utf8() {
+        // "é"的两个字节分属两个token，第一个字节到达时不能输出
+        let model = scripted(&[10, 8, 9, 5, 0]);
+        let mut generator = Generator::new(model, ToyDecoder, GreedySampler);
+        let mut chunks = Vec::new();
+        let out = generator
+            .generate_stream(&[5, 5], &GenerationConfig::new(20).with_eos(0), |_, text| {
+                chunks.push(text.to_string())
+            })
+            .unwrap();
+        assert_eq!(out.text, " café!");
+        assert_eq!(chunks, vec![" caf", "", "é", "!"]);
+
+        // 在半个字符处停止时，剩余字节按替换字符输出
+        let model = scripted(&[10, 8, 9]);
+        let mut generator = Generator::new(model, ToyDecoder, GreedySampler);
+        let mut streamed = String::new();
+        let out = generator
+            .generate_stream(&[5, 5], &GenerationConfig::new(2), |_, text| streamed.push_str(text))
+            .unwrap();
+        assert_eq!(out.text, " caf\u{FFFD}");
+        assert_eq!(streamed, " caf");
+        assert_eq!(out.trailing_text, "\u{FFFD}");
+    }
+
+    #[test]
+    fn test_stats_and_limits() {
+        let mut model = scripted(&[1, 2, 3, 4, 5, 0]);
+        model.delay = Duration::from_millis(2);
+        let mut generator = Generator::new(model, ToyDecoder, GreedySampler);
+        let out = generator.generate(&[5, 5], &GenerationConfig::new(20).with_eos(0)).unwrap();
+        let stats = &out.stats;
+        assert_eq!(stats.prompt_tokens, 2);
+        assert_eq!(stats.generated_tokens, 5);
+        let ttft = stats.time_to_first_token.unwrap();
+        // TTFT至少包含一次prefill，总时间至少包含6次forward
+        assert!(ttft >= Duration::from_millis(2));
+        assert!(stats.total_time >= Duration::from_millis(12));
+        assert!(ttft < stats.total_time);
+        assert!(stats.tokens_per_second() > 0.0 && stats.tokens_per_second() < 5.0 / 0.012);
+        assert!(stats.decode_tokens_per_second() > 0.0);
+
+        // 达到模型最大长度时停止
+        let mut model = scripted(&[1, 2, 3, 4, 5, 0]);
+        model.max_seq_len = 4;
+        let mut generator = Generator::new(model, ToyDecoder, GreedySampler);
+        let out = generator.generate(&[5, 5], &GenerationConfig::new(20)).unwrap();
+        assert_eq!(out.tokens, vec![1, 2]);
+        assert_eq!(out.finish_reason, FinishReason::MaxLength);
+
+        assert!(generator.generate(&[], &GenerationConfig::new(5)).is_err());
+        assert!(generator.generate(&[1, 2, 3, 4, 5], &GenerationConfig::new(5)).is_err());
+        assert!(generator.generate(&[1, 12], &GenerationConfig::new(5)).is_err());
+    }
+}
@@ -0,0 +1,345 @@
+// 模型输出的是每个位置在词表上的logits，要得到下一个token还需要一个采样器。
+// 在这个练习中，我们将实现常见的采样策略：
+// - 贪心解码：直接取logit最大的token
+// - 温度（temperature）：logits除以温度，温度越高分布越平坦
+// - Top-K：只保留概率最大的k个token
+// - Top-P（nucleus）：按概率从大到小累加，只保留累计概率刚好达到p的最小集合
+// - Min-P：只保留概率不低于 p * 最大概率 的token
+// - 典型采样（typical）：保留信息量 -log(p) 最接近分布熵的token，直到累计概率达到p
+// - 重复惩罚、频率惩罚、存在惩罚：根据已经生成的token压低它们的logit
+// 每种策略都是流水线中的一步（SamplingStep），按配置的顺序依次作用在logits上，
+// 被过滤掉的token的logit会被置为负无穷，之后永远不会被选中。
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+
+// 辅助函数：数值稳定的softmax
+// 注意：这个函数与 mixed_precision_tensor.rs 中的实现相同，已经提供
+fn softmax(x: &[f32]) -> Vec<f32> {
+    let max = x.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+    let exps: Vec<f32> = x.iter().map(|&v| (v - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.iter().map(|&e| e / sum).collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SamplingStep {
+    // CTRL论文中的重复惩罚：出现过的token，logit为正时除以penalty，为负时乘以penalty
+    RepetitionPenalty(f32),
+    // OpenAI API中的惩罚：logit -= count * frequency + (count > 0 ? presence : 0)
+    FrequencyPresencePenalty { frequency: f32, presence: f32 },
+    Temperature(f32),
+    TopK(usize),
+    TopP(f32),
+    MinP(f32),
+    Typical(f32),
+}
+
+impl SamplingStep {
+    fn validate(&self) -> Result<(), String> {
+        match *self {
+            SamplingStep::RepetitionPenalty(penalty) if penalty <= 0.0 || !penalty.is_finite() => {
+                Err(format!("重复惩罚必须为正数，实际为{}", penalty))
+            }
+            SamplingStep::FrequencyPresencePenalty { frequency, presence }
+                if !frequency.is_finite() || !presence.is_finite() =>
+            {
+                Err("频率惩罚和存在惩罚必须是有限值".to_string())
+            }
+            SamplingStep::Temperature(t) if t <= 0.0 || !t.is_finite() => {
+                Err(format!("温度必须大于0，实际为{}，贪心解码请使用Sampler::greedy", t))
+            }
+            SamplingStep::TopK(0) => Err("top_k必须大于0".to_string()),
+            SamplingStep::TopP(p) | SamplingStep::Typical(p) if !(p > 0.0 && p <= 1.0) => {
+                Err(format!("累计概率阈值必须在(0, 1]之间，实际为{}", p))
+            }
+            SamplingStep::MinP(p) if !(0.0..=1.0).contains(&p) => {
+                Err(format!("min_p必须在[0, 1]之间，实际为{}", p))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // 原地修改logits，history为之前已经出现的token
+    fn apply(&self, logits: &mut [f32], history: &[u32]) {
+        match *self {
+            SamplingStep::RepetitionPenalty(penalty) => {
+                for (id, _) in token_counts(history, logits.len()) {
+                    let logit = &mut logits[id];
+                    *logit = if *logit > 0.0 { *logit / penalty } else { *logit * penalty };
+                }
+            }
+            SamplingStep::FrequencyPresencePenalty { frequency, presence } => {
+                for (id, count) in token_counts(history, logits.len()) {
+                    logits[id] -= count as f32 * frequency + presence;
+                }
+            }
+            SamplingStep::Temperature(t) => {
+                for logit in logits.iter_mut() {
+                    *logit /= t;
+                }
+            }
+            SamplingStep::TopK(k) => {
+                let order = sorted_by_logit(logits);
+                mask_all_except(logits, &order[..k.min(order.len())]);
+            }
+            SamplingStep::TopP(p) => {
+                let probs = softmax(logits);
+                let order = sorted_by_logit(logits);
+                let keep = cumulative_prefix(&order, &probs, p);
+                mask_all_except(logits, &order[..keep]);
+            }
+            SamplingStep::MinP(p) => {
+                let probs = softmax(logits);
+                let threshold = p * probs.iter().fold(0.0f32, |a, &b| a.max(b));
+                for (logit, &prob) in logits.iter_mut().zip(probs.iter()) {
+                    if prob < threshold {
+                        *logit = f32::NEG_INFINITY;
+                    }
+                }
+            }
+            SamplingStep::Typical(p) => {
+                let probs = softmax(logits);
+                // 分布的熵，即信息量的期望值
+                let entropy: f32 = probs.iter()
+                    .filter(|&&prob| prob > 0.0)
+                    .map(|&prob| -prob * prob.ln())
+                    .sum();
+                let mut order: Vec<usize> = (0..logits.len()).filter(|&i| probs[i] > 0.0).collect();
+                let deviation = |i: usize| (-probs[i].ln() - entropy).abs();
+                order.sort_by(|&a, &b| deviation(a).total_cmp(&deviation(b)).then(a.cmp(&b)));
+                let keep = cumulative_prefix(&order, &probs, p);
+                mask_all_except(logits, &order[..keep]);
+            }
+        }
+    }
+}
+
+// 统计history中每个token出现的次数，忽略超出词表的ID
+fn token_counts(history: &[u32], vocab_size: usize) -> HashMap<usize, usize> {
+    let mut counts = HashMap::new();
+    for &id in history.iter().filter(|&&id| (id as usize) < vocab_size) {
+        *counts.entry(id as usize).or_insert(0) += 1;
+    }
+    counts
+}
+
+// 未被过滤的token按logit从大到小排序，相同logit时ID小的在前
+fn sorted_by_logit(logits: &[f32]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..logits.len()).filter(|&i| logits[i] > f32::NEG_INFINITY).collect();
+    order.sort_by(|&a, &b| logits[b].total_cmp(&logits[a]).then(a.cmp(&b)));
+    order
+}
+
+// 按order的顺序累加概率，返回累计概率首次达到p时的前缀长度（至少为1）
+fn cumulative_prefix(order: &[usize], probs: &[f32], p: f32) -> usize {
+    let mut cumulative = 0.0;
+    for (n, &i) in order.iter().enumerate() {
+        cumulative += probs[i];
+        if cumulative >= p {
+            return n + 1;
+        }
+    }
+    order.len()
+}
+
+fn mask_all_except(logits: &mut [f32], keep: &[usize]) {
+    let mut kept = vec![false; logits.len()];
+    for &i in keep {
+        kept[i] = true;
+    }
+    for (logit, kept) in logits.iter_mut().zip(kept) {
+        if !kept {
+            *logit = f32::NEG_INFINITY;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Sampler {
+    steps: Vec<SamplingStep>,
+    // 为None时进行贪心解码
+    rng: Option<StdRng>,
+}
+
+impl Sampler {
+    // 按steps的顺序处理logits，然后从得到的分布中随机采样
+    fn new(steps: Vec<SamplingStep>, seed: u64) -> Result<Self, String> {
+        for step in &steps {
+            step.validate()?;
+        }
+        Ok(Sampler { steps, rng: Some(StdRng::seed_from_u64(seed)) })
+    }
+
+    // 贪心解码，steps中仍然可以包含各种惩罚
+    fn greedy(steps: Vec<SamplingStep>) -> Result<Self, String> {
+        for step in &steps {
+            step.validate()?;
+        }
+        Ok(Sampler { steps, rng: None })
+    }
+
+    fn is_greedy(&self) -> bool {
+        self.rng.is_none()
+    }
+
+    // 依次应用每一步，返回处理后的logits，被过滤的token为负无穷
+    fn process(&self, logits: &[f32], history: &[u32]) -> Vec<f32> {
+        let mut logits = logits.to_vec();
+        for step in &self.steps {
+            step.apply(&mut logits, history);
+        }
+        logits
+    }
+
+    // 最终用于采样的概率分布
+    fn probabilities(&self, logits: &[f32], history: &[u32]) -> Vec<f32> {
+        softmax(&self.process(logits, history))
+    }
+
+    // 根据logits和已经出现的token选出下一个token
+    fn sample(&mut self, logits: &[f32], history: &[u32]) -> u32 {
+        assert!(!logits.is_empty(), "logits不能为空");
+        let processed = self.process(logits, history);
+        let argmax = processed.iter()
+            .enumerate()
+            .fold(0, |best, (i, &v)| if v > processed[best] { i } else { best });
+        let rng = match self.rng.as_mut() {
+            Some(rng) => rng,
+            None => return argmax as u32,
+        };
+        // WeightedIndex不会选中权重为0的token
+        match WeightedIndex::new(softmax(&processed)) {
+            Ok(dist) => dist.sample(rng) as u32,
+            // 所有logit都是非有限值时退化为贪心
+            Err(_) => argmax as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGITS: [f32; 6] = [2.0, 1.0, 0.5, 3.0, -1.0, 0.0];
+
+    fn kept(logits: &[f32]) -> Vec<usize> {
+        (0..logits.len()).filter(|&i| logits[i] > f32::NEG_INFINITY).collect()
+    }
+
+    #[test]
+    fn test_greedy_and_penalties() {
+        let mut greedy = Sampler::greedy(vec![]).unwrap();
+        assert!(greedy.is_greedy());
+        assert_eq!(greedy.sample(&LOGITS, &[]), 3);
+
+        let mut logits = LOGITS.to_vec();
+        SamplingStep::RepetitionPenalty(2.0).apply(&mut logits, &[3, 4, 3]);
+        assert_eq!(logits, vec![2.0, 1.0, 0.5, 1.5, -2.0, 0.0]);
+
+        let mut logits = LOGITS.to_vec();
+        let step = SamplingStep::FrequencyPresencePenalty { frequency: 0.5, presence: 0.25 };
+        // 超出词表的ID被忽略
+        step.apply(&mut logits, &[3, 3, 0, 100]);
+        assert_eq!(logits, vec![1.25, 1.0, 0.5, 1.75, -1.0, 0.0]);
+
+        // 惩罚之后贪心解码不再重复同一个token
+        let mut greedy = Sampler::greedy(vec![SamplingStep::RepetitionPenalty(2.0)]).unwrap();
+        assert_eq!(greedy.sample(&LOGITS, &[3]), 0);
+    }
+
+    #[test]
+    fn test_filters() {
+        let process = |step: SamplingStep| {
+            let mut logits = LOGITS.to_vec();
+            step.apply(&mut logits, &[]);
+            logits
+        };
+        // 概率约为 [0.2225, 0.0818, 0.0496, 0.6048, 0.0111, 0.0301]
+        assert_eq!(kept(&process(SamplingStep::TopK(2))), vec![0, 3]);
+        assert_eq!(kept(&process(SamplingStep::TopK(100))), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(kept(&process(SamplingStep::TopP(0.5))), vec![3]);
+        assert_eq!(kept(&process(SamplingStep::TopP(0.9))), vec![0, 1, 3]);
+        assert_eq!(kept(&process(SamplingStep::TopP(1.0))).len(), 6);
+        assert_eq!(kept(&process(SamplingStep::MinP(0.1))), vec![0, 1, 3]);
+        assert_eq!(kept(&process(SamplingStep::MinP(0.0))).len(), 6);
+        // 熵约为1.15，最典型的token是0（-ln p约为1.50），其次是3和1
+        assert_eq!(kept(&process(SamplingStep::Typical(0.2))), vec![0]);
+        assert_eq!(kept(&process(SamplingStep::Typical(0.9))), vec![0, 1, 3]);
+
+        let scaled = process(SamplingStep::Temperature(0.5));
+        assert_eq!(scaled, LOGITS.iter().map(|&x| x * 2.0).collect::<Vec<_>>());
+
+        // 过滤后概率重新归一化，被过滤的token概率为0
+        let sampler = Sampler::new(vec![SamplingStep::TopK(2)], 0).unwrap();
+        let probs = sampler.probabilities(&LOGITS, &[]);
+        assert!((probs[0] + probs[3] - 1.0).abs() < 1e-6);
+        assert_eq!(probs[1], 0.0);
+    }
+
+    #[test]
+    fn test_step_order_matters() {
+        let kept_with = |steps: Vec<SamplingStep>| {
+            kept(&Sampler::new(steps, 0).unwrap().process(&LOGITS, &[]))
+        };
+        // 先升温再做Top-P，分布更平坦，保留的token更多
+        let temperature_first = kept_with(vec![SamplingStep::Temperature(3.0), SamplingStep::TopP(0.6)]);
+        let top_p_first = kept_with(vec![SamplingStep::TopP(0.6), SamplingStep::Temperature(3.0)]);
+        assert_eq!(temperature_first, vec![0, 1, 3]);
+        assert_eq!(top_p_first, vec![3]);
+
+        // 过滤是逐步叠加的
+        let combined = kept_with(vec![SamplingStep::TopK(4), SamplingStep::MinP(0.1), SamplingStep::TopK(2)]);
+        assert_eq!(combined, vec![0, 3]);
+    }
+
+    #[test]
+    fn test_sampling_distribution() {
+        let configs = vec![
+            vec![SamplingStep::TopK(3)],
+            vec![SamplingStep::Temperature(1.5), SamplingStep::TopP(0.8)],
+            vec![SamplingStep::MinP(0.05), SamplingStep::Temperature(0.7)],
+            vec![SamplingStep::Typical(0.95)],
+            vec![SamplingStep::RepetitionPenalty(1.3), SamplingStep::TopK(4), SamplingStep::Temperature(2.0)],
+        ];
+        let history = [3, 0];
+        let trials = 20000;
+        for steps in configs {
+            let mut sampler = Sampler::new(steps.clone(), 1234).unwrap();
+            let expected = sampler.probabilities(&LOGITS, &history);
+            let mut counts = [0usize; 6];
+            for _ in 0..trials {
+                counts[sampler.sample(&LOGITS, &history) as usize] += 1;
+            }
+            for (i, (&count, &p)) in counts.iter().zip(expected.iter()).enumerate() {
+                if p == 0.0 {
+                    assert_eq!(count, 0, "{:?}: 被过滤的token {}被选中", steps, i);
+                }
+                let freq = count as f32 / trials as f32;
+                assert!((freq - p).abs() < 0.015, "{:?}: token {}频率{}，期望{}", steps, i, freq, p);
+            }
+        }
+    }
+
+    #[test]
+    fn test_seed_and_validation() {
+        let steps = vec![SamplingStep::Temperature(1.0)];
+        let mut a = Sampler::new(steps.clone(), 42).unwrap();
+        let mut b = Sampler::new(steps.clone(), 42).unwrap();
+        let seq_a: Vec<u32> = (0..50).map(|_| a.sample(&LOGITS, &[])).collect();
+        let seq_b: Vec<u32> = (0..50).map(|_| b.sample(&LOGITS, &[])).collect();
+        assert_eq!(seq_a, seq_b);
+        let mut c = Sampler::new(steps, 43).unwrap();
+        let seq_c: Vec<u32> = (0..50).map(|_| c.sample(&LOGITS, &[])).collect();
+        assert_ne!(seq_a, seq_c);
+
+        assert!(Sampler::new(vec![SamplingStep::Temperature(0.0)], 0).is_err());
+        assert!(Sampler::new(vec![SamplingStep::TopK(0)], 0).is_err());
+        assert!(Sampler::new(vec![SamplingStep::TopP(1.5)], 0).is_err());
+        assert!(Sampler::new(vec![SamplingStep::Typical(0.0)], 0).is_err());
+        assert!(Sampler::new(vec![SamplingStep::MinP(-0.1)], 0).is_err());
+        assert!(Sampler::greedy(vec![SamplingStep::RepetitionPenalty(0.0)]).is_err());
+    }
+}
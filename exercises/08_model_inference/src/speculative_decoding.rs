@@ -0,0 +1,371 @@
+// 自回归解码每一步都要跑一次大模型，在CPU上非常慢。投机解码（speculative decoding）的思路是：
+// 1. 用一个小的草稿模型（draft）快速地逐个猜出k个token：x_1..x_k，并记下草稿分布q_1..q_k
+// 2. 大的目标模型（target）一次前向就能同时算出这k个位置以及之后一个位置的分布p_1..p_{k+1}
+// 3. 依次验证每个草稿token：以 min(1, p_i(x_i) / q_i(x_i)) 的概率接受；
+//    一旦拒绝，就从残差分布 norm(max(0, p_i - q_i)) 中重新采样一个token，并丢弃之后的草稿；
+//    如果k个都被接受，再从p_{k+1}中额外采样一个token
+// 这种拒绝采样保证了输出的分布与直接从目标模型采样完全相同，而每次目标模型前向最多能产出k+1个token。
+// 被拒绝的草稿token已经写入了两个模型的KV Cache，需要回退（truncate）到最后一个被接受的位置。
+// 温度为0时两个分布都退化为one-hot，同一套规则就变成了"草稿与目标的贪心结果一致才接受"。
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// 辅助函数：数值稳定的softmax
+// 注意：这个函数与 mixed_precision_tensor.rs 中的实现相同，已经提供
+fn softmax(x: &[f32]) -> Vec<f32> {
+    let max = x.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+    let exps: Vec<f32> = x.iter().map(|&v| (v - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.iter().map(|&e| e / sum).collect()
+}
+
+// 生成循环需要的模型接口，模型内部维护KV Cache
+// 注意：这个trait与 generation.rs 中的定义相同，已经提供
+trait LanguageModel {
+    fn vocab_size(&self) -> usize;
+
+    fn max_seq_len(&self) -> usize;
+
+    // 把tokens接在已缓存的序列之后，返回每个新位置的logits，形状为 [tokens.len(), vocab_size]
+    fn forward(&mut self, tokens: &[u32]) -> Result<Vec<Vec<f32>>, String>;
+
+    // 已缓存的token数
+    fn cache_len(&self) -> usize;
+
+    // 只保留前len个token的缓存
+    fn truncate_cache(&mut self, len: usize);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SpeculativeConfig {
+    // 每轮草稿模型猜测的token数k
+    num_draft_tokens: usize,
+    max_new_tokens: usize,
+    // 为0时进行贪心解码
+    temperature: f32,
+    eos_token_id: Option<u32>,
+    seed: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct SpeculativeStats {
+    target_forwards: usize,
+    draft_forwards: usize,
+    drafted_tokens: usize,
+    accepted_tokens: usize,
+}
+
+impl SpeculativeStats {
+    // 草稿token被目标模型接受的比例
+    fn acceptance_rate(&self) -> f64 {
+        if self.drafted_tokens == 0 {
+            0.0
+        } else {
+            self.accepted_tokens as f64 / self.drafted_tokens as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SpeculativeOutput {
+    // 生成的token，不包含EOS
+    tokens: Vec<u32>,
+    stats: SpeculativeStats,
+}
+
+// 按温度把logits转换为概率分布，温度为0时为argmax的one-hot
+fn probabilities(logits: &[f32], temperature: f32) -> Vec<f32> {
+    if temperature == 0.0 {
+        let argmax = logits.iter()
+            .enumerate()
+            .fold(0, |best, (i, &v)| if v > logits[best] { i } else { best });
+        let mut probs = vec![0.0; logits.len()];
+        probs[argmax] = 1.0;
+        probs
+    } else {
+        softmax(&logits.iter().map(|&x| x / temperature).collect::<Vec<_>>())
+    }
+}
+
+// 从（未必归一化的）分布中采样，权重全为0时返回None
+fn sample_from(weights: &[f32], rng: &mut impl Rng) -> Option<u32> {
+    WeightedIndex::new(weights).ok().map(|dist| dist.sample(rng) as u32)
+}
+
+fn last_logits(mut logits: Vec<Vec<f32>>) -> Result<Vec<f32>, String> {
+    logits.pop().ok_or_else(|| "模型没有返回logits".to_string())
+}
+
+fn speculative_generate(
+    target: &mut impl LanguageModel,
+    draft: &mut impl LanguageModel,
+    prompt: &[u32],
+    config: &SpeculativeConfig,
+) -> Result<SpeculativeOutput, String> {
+    if prompt.is_empty() {
+        return Err("prompt不能为空".to_string());
+    }
+    if target.vocab_size() != draft.vocab_size() {
+        return Err(format!(
+            "草稿模型词表大小{}与目标模型{}不一致",
+            draft.vocab_size(),
+            target.vocab_size()
+        ));
+    }
+    if !(config.temperature >= 0.0 && config.temperature.is_finite()) {
+        return Err(format!("温度必须是非负数，实际为{}", config.temperature));
+    }
+    let max_seq_len = target.max_seq_len().min(draft.max_seq_len());
+    if prompt.len() > max_seq_len {
+        return Err(format!("prompt长度{}超出模型最大长度{}", prompt.len(), max_seq_len));
+    }
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut stats = SpeculativeStats::default();
+    target.truncate_cache(0);
+    draft.truncate_cache(0);
+
+    // 约定：两个模型的KV Cache里都只有seq的前缀，每轮开始时把还没送入的token补上
+    // 第一轮补上的就是整个prompt，相当于prefill
+    let mut seq = prompt.to_vec();
+    let mut finished = false;
+    while !finished && seq.len() - prompt.len() < config.max_new_tokens && seq.len() < max_seq_len {
+        // 本轮最多产出k+1个token，不能超过max_new_tokens和最大长度
+        let remaining = config.max_new_tokens - (seq.len() - prompt.len());
+        let k = config.num_draft_tokens.min(remaining - 1).min(max_seq_len - seq.len() - 1);
+
+        // 1. 草稿模型逐个猜测k个token
+        let mut drafts = Vec::with_capacity(k);
+        let mut draft_probs = Vec::with_capacity(k);
+        if k > 0 {
+            let mut logits = last_logits(draft.forward(&seq[draft.cache_len()..])?)?;
+            stats.draft_forwards += 1;
+            for i in 0..k {
+                let q = probabilities(&logits, config.temperature);
+                let x = sample_from(&q, &mut rng).ok_or_else(|| "草稿分布无效".to_string())?;
+                drafts.push(x);
+                draft_probs.push(q);
+                if i + 1 < k {
+                    logits = last_logits(draft.forward(&[x])?)?;
+                    stats.draft_forwards += 1;
+                }
+            }
+        }
+        stats.drafted_tokens += k;
+
+        // 2. 目标模型一次前向验证所有草稿，最后k+1行就是p_1..p_{k+1}
+        let mut input = seq[target.cache_len()..].to_vec();
+        input.extend_from_slice(&drafts);
+        let logits = target.forward(&input)?;
+        stats.target_forwards += 1;
+        let target_probs: Vec<Vec<f32>> = logits[logits.len() - (k + 1)..]
+            .iter()
+            .map(|row| probabilities(row, config.temperature))
+            .collect();
+
+        // 3. 逐个验证草稿token
+        let mut accepted = 0;
+        let mut next_token = None;
+        for ((&x, q), p) in drafts.iter().zip(draft_probs.iter()).zip(target_probs.iter()) {
+            let (p_x, q_x) = (p[x as usize], q[x as usize]);
+            if rng.gen::<f32>() * q_x < p_x {
+                accepted += 1;
+                continue;
+            }
+            // 拒绝：从残差分布 max(0, p - q) 中重新采样
+            let residual: Vec<f32> = p.iter().zip(q.iter()).map(|(&p, &q)| (p - q).max(0.0)).collect();
+            let token = sample_from(&residual, &mut rng)
+                .or_else(|| sample_from(p, &mut rng))
+                .ok_or_else(|| "目标分布无效".to_string())?;
+            next_token = Some(token);
+            break;
+        }
+        // 所有草稿都被接受时，从p_{k+1}中额外采样一个token
+        let next_token = match next_token {
+            Some(token) => token,
+            None => sample_from(&target_probs[k], &mut rng).ok_or_else(|| "目标分布无效".to_string())?,
+        };
+        stats.accepted_tokens += accepted;
+
+        // 4. 回退KV Cache：只保留已接受的token，新采样的token下一轮再送入
+        let keep = seq.len() + accepted;
+        target.truncate_cache(keep);
+        draft.truncate_cache(keep);
+
+        for &token in drafts[..accepted].iter().chain(std::iter::once(&next_token)) {
+            if Some(token) == config.eos_token_id {
+                finished = true;
+                break;
+            }
+            seq.push(token);
+        }
+    }
+
+    Ok(SpeculativeOutput { tokens: seq[prompt.len()..].to_vec(), stats })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const VOCAB_SIZE: usize = 3;
+
+    // 玩具模型的logits只取决于最后两个token，salt不同则分布不同
+    fn toy_logits(salt: u32, context: &[u32]) -> Vec<f32> {
+        let a = context[context.len().saturating_sub(2)];
+        let b = context[context.len() - 1];
+        (0..VOCAB_SIZE as u32)
+            .map(|i| ((a * 31 + b * 7 + salt * 13 + i * 17) % 11) as f32 * 0.3)
+            .collect()
+    }
+
+    // 用KV Cache的方式实现：每个位置的logits依赖缓存中的上下文，回退错了结果就会错
+    struct ToyModel {
+        salt: u32,
+        cache: Vec<u32>,
+        max_seq_len: usize,
+    }
+
+    impl ToyModel {
+        fn new(salt: u32) -> Self {
+            ToyModel { salt, cache: Vec::new(), max_seq_len: 64 }
+        }
+    }
+
+    impl LanguageModel for ToyModel {
+        fn vocab_size(&self) -> usize {
+            VOCAB_SIZE
+        }
+
+        fn max_seq_len(&self) -> usize {
+            self.max_seq_len
+        }
+
+        fn forward(&mut self, tokens: &[u32]) -> Result<Vec<Vec<f32>>, String> {
+            if self.cache.len() + tokens.len() > self.max_seq_len {
+                return Err("超出最大长度".to_string());
+            }
+            let start = self.cache.len();
+            self.cache.extend_from_slice(tokens);
+            Ok((start..self.cache.len()).map(|p| toy_logits(self.salt, &self.cache[..=p])).collect())
+        }
+
+        fn cache_len(&self) -> usize {
+            self.cache.len()
+        }
+
+        fn truncate_cache(&mut self, len: usize) {
+            self.cache.truncate(len);
+        }
+    }
+
+    fn config(num_draft_tokens: usize, max_new_tokens: usize, temperature: f32, seed: u64) -> SpeculativeConfig {
+        SpeculativeConfig { num_draft_tokens, max_new_tokens, temperature, eos_token_id: None, seed }
+    }
+
+    // 直接从模型的条件分布计算出某个续写序列的精确概率
+    fn sequence_prob(salt: u32, prompt: &[u32], tokens: &[u32]) -> f32 {
+        let mut context = prompt.to_vec();
+        let mut prob = 1.0;
+        for &token in tokens {
+            prob *= softmax(&toy_logits(salt, &context))[token as usize];
+            context.push(token);
+        }
+        prob
+    }
+
+    #[test]
+    fn test_greedy_matches_target() {
+        let prompt = [1, 2];
+        // 目标模型自己的贪心解码
+        let mut expected = prompt.to_vec();
+        for _ in 0..12 {
+            let probs = probabilities(&toy_logits(0, &expected), 0.0);
+            expected.push(probs.iter().position(|&p| p == 1.0).unwrap() as u32);
+        }
+
+        for k in [1, 3, 5] {
+            let (mut target, mut draft) = (ToyModel::new(0), ToyModel::new(5));
+            let out = speculative_generate(&mut target, &mut draft, &prompt, &config(k, 12, 0.0, 0)).unwrap();
+            assert_eq!(out.tokens, expected[2..]);
+            assert!(out.stats.acceptance_rate() < 1.0);
+            // 缓存里只有已接受的token，最后一个token没有送入
+            assert!(target.cache_len() < prompt.len() + 12);
+            assert_eq!(target.cache, expected[..target.cache_len()]);
+        }
+
+        // 草稿模型与目标相同时全部接受，每次目标前向产出k+1个token
+        let (mut target, mut draft) = (ToyModel::new(0), ToyModel::new(0));
+        let out = speculative_generate(&mut target, &mut draft, &prompt, &config(3, 12, 0.0, 0)).unwrap();
+        assert_eq!(out.tokens, expected[2..]);
+        assert_eq!(out.stats.acceptance_rate(), 1.0);
+        assert_eq!(out.stats.target_forwards, 3);
+    }
+
+    #[test]
+    fn test_matches_target_distribution() {
+        let prompt = [0, 1];
+        let (target_salt, draft_salt) = (0, 4);
+        let trials = 20000;
+        let mut counts: HashMap<Vec<u32>, usize> = HashMap::new();
+        let mut stats = SpeculativeStats::default();
+        let (mut target, mut draft) = (ToyModel::new(target_salt), ToyModel::new(draft_salt));
+        for seed in 0..trials {
+            let out = speculative_generate(&mut target, &mut draft, &prompt, &config(2, 3, 1.0, seed)).unwrap();
+            assert_eq!(out.tokens.len(), 3);
+            *counts.entry(out.tokens).or_insert(0) += 1;
+            stats.drafted_tokens += out.stats.drafted_tokens;
+            stats.accepted_tokens += out.stats.accepted_tokens;
+        }
+        // 既有接受也有拒绝，两条路径都被覆盖
+        assert!(stats.acceptance_rate() > 0.3 && stats.acceptance_rate() < 0.95);
+
+        // 枚举所有27种续写，比较经验分布和目标模型的精确分布（总变差距离）
+        let mut tv_target = 0.0;
+        let mut tv_draft = 0.0;
+        for a in 0..3 {
+            for b in 0..3 {
+                for c in 0..3 {
+                    let tokens = vec![a, b, c];
+                    let freq = *counts.get(&tokens).unwrap_or(&0) as f32 / trials as f32;
+                    tv_target += (freq - sequence_prob(target_salt, &prompt, &tokens)).abs() / 2.0;
+                    tv_draft += (freq - sequence_prob(draft_salt, &prompt, &tokens)).abs() / 2.0;
+                }
+            }
+        }
+        assert!(tv_target < 0.02, "与目标分布的总变差距离为{}", tv_target);
+        // 检验有足够的区分度：草稿模型的分布明显不同
+        assert!(tv_draft > 0.1, "与草稿分布的总变差距离为{}", tv_draft);
+    }
+
+    #[test]
+    fn test_stop_conditions() {
+        let prompt = [1, 2];
+        let (mut target, mut draft) = (ToyModel::new(0), ToyModel::new(0));
+        let full = speculative_generate(&mut target, &mut draft, &prompt, &config(4, 10, 0.0, 0)).unwrap();
+        assert_eq!(full.tokens.len(), 10);
+
+        // 遇到EOS停止，即使EOS位于一批被接受的草稿中间
+        // 贪心结果为[1, 1, 2, ...]，第一轮被接受的4个草稿中第3个就是EOS
+        assert_eq!(full.tokens[..3], [1, 1, 2]);
+        let cfg = SpeculativeConfig { eos_token_id: Some(2), ..config(4, 10, 0.0, 0) };
+        let out = speculative_generate(&mut target, &mut draft, &prompt, &cfg).unwrap();
+        assert_eq!(out.tokens, vec![1, 1]);
+        assert_eq!(out.stats.target_forwards, 1);
+
+        // k大于剩余的token数时不会超出max_new_tokens
+        let out = speculative_generate(&mut target, &mut draft, &prompt, &config(8, 3, 1.0, 7)).unwrap();
+        assert_eq!(out.tokens.len(), 3);
+
+        // 序列长度受模型最大长度限制，不会触发forward的越界错误
+        target.max_seq_len = 7;
+        let out = speculative_generate(&mut target, &mut draft, &prompt, &config(4, 10, 1.0, 3)).unwrap();
+        assert_eq!(out.tokens.len(), 5);
+
+        assert!(speculative_generate(&mut target, &mut draft, &[], &config(4, 10, 1.0, 0)).is_err());
+        assert!(speculative_generate(&mut target, &mut draft, &prompt, &config(4, 10, -1.0, 0)).is_err());
+    }
+}
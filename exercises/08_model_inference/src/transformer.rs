@@ -0,0 +1,1047 @@
+// 前面的练习中我们分别实现了多头注意力的张量运算、层归一化、前馈网络和嵌入，
+// 在这个练习中，我们把它们组装成一个完整的decoder-only Transformer：
+// - TransformerBlock：因果自注意力 + 前馈网络，通过残差连接串起来，
+//   支持Pre-Norm（GPT-2、Llama）和Post-Norm（原始Transformer）两种接法
+// - DecoderModel：词嵌入 -> N个TransformerBlock -> 最终归一化 -> LM Head，
+//   输出每个位置在整个词表上的logits，形状为 [batch_size, seq_len, vocab_size]
+// ModelConfig可以在两种常见的架构之间选择：
+// - GPT-2：可学习的位置嵌入、带偏置的LayerNorm、GELU MLP、线性层带偏置、LM Head与词嵌入共享权重
+// - Llama：RoPE旋转位置编码、RMSNorm、SwiGLU、线性层无偏置、分组查询注意力（GQA）、独立的LM Head
+// 为了后续的文本生成练习，模型还支持KV Cache：已经处理过的token的key/value会被缓存下来，
+// 增量解码时每一步只需要计算新的token。
+
+use rand::Rng;
+
+// 3D张量的简写，形状为 [batch_size, seq_len, hidden_size]
+type Tensor3D = Vec<Vec<Vec<f32>>>;
+
+// 4D张量的简写，形状为 [batch_size, num_heads, rows, cols]
+// 注意：这个类型与 multi_head_attention.rs 中的定义相同，已经提供
+type Tensor4D = Vec<Vec<Vec<Vec<f32>>>>;
+
+// 辅助函数：把 [batch_size, seq_len, hidden_size] 切分为 [batch_size, num_heads, seq_len, head_size]
+// 注意：这个函数与 multi_head_attention.rs 中的实现相同，已经提供
+fn reshape_for_attention(
+    input: &[Vec<Vec<f32>>],
+    num_heads: usize
+) -> Vec<Vec<Vec<Vec<f32>>>> {
+    let seq_len = input[0].len();
+    let hidden_size = input[0][0].len();
+    let head_size = hidden_size / num_heads;
+
+    input.iter()
+        .map(|batch| {
+            batch.iter()
+                .map(|seq| {
+                    seq.chunks(head_size)
+                        .map(|chunk| chunk.to_vec())
+                        .collect::<Vec<Vec<f32>>>()
+                })
+                .collect::<Vec<Vec<Vec<f32>>>>()
+        })
+        .map(|batch_heads| {
+            (0..num_heads)
+                .map(|h| {
+                    (0..seq_len)
+                        .map(|s| batch_heads[s][h].clone())
+                        .collect::<Vec<Vec<f32>>>()
+                })
+                .collect::<Vec<Vec<Vec<f32>>>>()
+        })
+        .collect()
+}
+
+// 辅助函数：多头合并，即reshape_for_attention的逆变换
+// 注意：这个函数与 multi_head_attention.rs 中的实现相同，已经提供
+fn merge_heads(
+    x: &[Vec<Vec<Vec<f32>>>]
+) -> Vec<Vec<Vec<f32>>> {
+    x.iter()
+        .map(|batch| {
+            let seq_len = batch[0].len();
+            (0..seq_len)
+                .map(|s| {
+                    batch.iter()
+                        .flat_map(|head| head[s].iter().copied())
+                        .collect::<Vec<f32>>()
+                })
+                .collect::<Vec<Vec<f32>>>()
+        })
+        .collect()
+}
+
+// 辅助函数：均值
+// 注意：这个函数与 layer_norm.rs 中的实现相同，已经提供
+fn compute_mean(x: &[f32]) -> f32 {
+    x.iter().sum::<f32>() / x.len() as f32
+}
+
+// 辅助函数：数值稳定的softmax
+// 注意：这个函数与 mixed_precision_tensor.rs 中的实现相同，已经提供
+fn softmax(x: &[f32]) -> Vec<f32> {
+    let max = x.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+    let exps: Vec<f32> = x.iter().map(|&v| (v - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.iter().map(|&e| e / sum).collect()
+}
+
+// 辅助函数：激活函数的前向传播
+// 注意：这些函数与 feed_forward.rs 中的实现相同，已经提供
+fn relu_forward(x: &[f32]) -> Vec<f32> {
+    x.iter()
+        .map(|&x| x.max(0.0))
+        .collect()
+}
+
+fn sigmoid_forward(x: &[f32]) -> Vec<f32> {
+    x.iter()
+        .map(|&x| 1.0 / (1.0 + (-x).exp()))
+        .collect()
+}
+
+fn silu_forward(x: &[f32]) -> Vec<f32> {
+    x.iter()
+        .zip(sigmoid_forward(x).iter())
+        .map(|(&x, &s)| x * s)
+        .collect()
+}
+
+fn gelu_forward(x: &[f32]) -> Vec<f32> {
+    let k = (2.0 / std::f32::consts::PI).sqrt();
+    x.iter()
+        .map(|&x| 0.5 * x * (1.0 + (k * (x + 0.044715 * x.powi(3))).tanh()))
+        .collect()
+}
+
+// 辅助函数：计算FFN中间层维度
+// 注意：这个函数与 feed_forward.rs 中的实现相同，已经提供
+fn intermediate_size(hidden_size: usize, multiplier: f32, multiple_of: usize, gated: bool) -> usize {
+    assert!(multiple_of > 0, "multiple_of必须大于0");
+    let mut size = (hidden_size as f32 * multiplier) as usize;
+    if gated {
+        size = 2 * size / 3;
+    }
+    size.div_ceil(multiple_of) * multiple_of
+}
+
+// 辅助函数：计算 x * W^T
+// 注意：这个函数与 feed_forward.rs 中的实现相同，已经提供
+fn matmul_transposed(x: &[Vec<f32>], w: &[Vec<f32>]) -> Vec<Vec<f32>> {
+    x.iter()
+        .map(|row| {
+            w.iter()
+                .map(|w_row| row.iter().zip(w_row.iter()).map(|(&a, &b)| a * b).sum())
+                .collect()
+        })
+        .collect()
+}
+
+// 辅助函数：使用Box-Muller变换生成标准正态分布的随机数
+// 注意：这个函数与 linear.rs 中的实现相同，已经提供
+fn sample_standard_normal(rng: &mut impl Rng) -> f32 {
+    let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+    let u2: f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+// GPT-2和Llama都使用标准差为0.02的正态分布初始化权重
+const INIT_STD: f32 = 0.02;
+
+fn init_normal(rows: usize, cols: usize, rng: &mut impl Rng) -> Vec<Vec<f32>> {
+    (0..rows)
+        .map(|_| (0..cols).map(|_| INIT_STD * sample_standard_normal(rng)).collect())
+        .collect()
+}
+
+// 架构变体，决定位置编码、归一化类型以及线性层是否带偏置
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Architecture {
+    Gpt2,
+    Llama,
+}
+
+// 归一化相对于残差连接的位置
+// Pre:  x = x + f(norm(x))   训练更稳定，GPT-2之后的模型基本都采用
+// Post: x = norm(x + f(x))   原始Transformer的接法
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NormPosition {
+    Pre,
+    Post,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Activation {
+    Relu,
+    Gelu,
+    Silu,
+}
+
+impl Activation {
+    fn forward(&self, x: &[f32]) -> Vec<f32> {
+        match self {
+            Activation::Relu => relu_forward(x),
+            Activation::Gelu => gelu_forward(x),
+            Activation::Silu => silu_forward(x),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ModelConfig {
+    architecture: Architecture,
+    vocab_size: usize,
+    hidden_size: usize,
+    num_layers: usize,
+    num_heads: usize,
+    // 等于num_heads时为标准多头注意力，小于num_heads时为分组查询注意力（GQA）
+    num_kv_heads: usize,
+    intermediate_size: usize,
+    max_seq_len: usize,
+    activation: Activation,
+    // 为true时FFN为门控结构：down(act(gate(x)) * up(x))
+    gated_ffn: bool,
+    norm_position: NormPosition,
+    norm_eps: f32,
+    // 只有Llama（RoPE）使用
+    rope_theta: f32,
+    tie_word_embeddings: bool,
+}
+
+impl ModelConfig {
+    // GPT-2风格的配置
+    // 例如GPT-2 small: vocab_size=50257, hidden_size=768, num_layers=12, num_heads=12, max_seq_len=1024
+    fn gpt2(
+        vocab_size: usize,
+        hidden_size: usize,
+        num_layers: usize,
+        num_heads: usize,
+        max_seq_len: usize,
+    ) -> Self {
+        ModelConfig {
+            architecture: Architecture::Gpt2,
+            vocab_size,
+            hidden_size,
+            num_layers,
+            num_heads,
+            num_kv_heads: num_heads,
+            intermediate_size: intermediate_size(hidden_size, 4.0, 1, false),
+            max_seq_len,
+            activation: Activation::Gelu,
+            gated_ffn: false,
+            norm_position: NormPosition::Pre,
+            norm_eps: 1e-5,
+            rope_theta: 10000.0,
+            tie_word_embeddings: true,
+        }
+    }
+
+    // Llama风格的配置
+    // 例如Llama-7B: vocab_size=32000, hidden_size=4096, num_layers=32, num_heads=32, num_kv_heads=32
+    fn llama(
+        vocab_size: usize,
+        hidden_size: usize,
+        num_layers: usize,
+        num_heads: usize,
+        num_kv_heads: usize,
+        max_seq_len: usize,
+    ) -> Self {
+        ModelConfig {
+            architecture: Architecture::Llama,
+            vocab_size,
+            hidden_size,
+            num_layers,
+            num_heads,
+            num_kv_heads,
+            intermediate_size: intermediate_size(hidden_size, 4.0, 256, true),
+            max_seq_len,
+            activation: Activation::Silu,
+            gated_ffn: true,
+            norm_position: NormPosition::Pre,
+            norm_eps: 1e-6,
+            rope_theta: 10000.0,
+            tie_word_embeddings: false,
+        }
+    }
+
+    fn head_size(&self) -> usize {
+        self.hidden_size / self.num_heads
+    }
+
+    fn has_bias(&self) -> bool {
+        self.architecture == Architecture::Gpt2
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let sizes = [
+            ("vocab_size", self.vocab_size),
+            ("hidden_size", self.hidden_size),
+            ("num_layers", self.num_layers),
+            ("num_heads", self.num_heads),
+            ("num_kv_heads", self.num_kv_heads),
+            ("intermediate_size", self.intermediate_size),
+            ("max_seq_len", self.max_seq_len),
+        ];
+        if let Some((name, _)) = sizes.iter().find(|(_, size)| *size == 0) {
+            return Err(format!("{}必须大于0", name));
+        }
+        if !self.hidden_size.is_multiple_of(self.num_heads) {
+            return Err(format!(
+                "hidden_size {}必须能被num_heads {}整除",
+                self.hidden_size, self.num_heads
+            ));
+        }
+        if !self.num_heads.is_multiple_of(self.num_kv_heads) {
+            return Err(format!(
+                "num_heads {}必须能被num_kv_heads {}整除",
+                self.num_heads, self.num_kv_heads
+            ));
+        }
+        if self.architecture == Architecture::Llama && !self.head_size().is_multiple_of(2) {
+            return Err(format!("RoPE要求head_size为偶数，实际为{}", self.head_size()));
+        }
+        Ok(())
+    }
+
+    // 根据配置计算模型的参数量，不需要真正分配权重
+    fn num_parameters(&self) -> usize {
+        let h = self.hidden_size;
+        let kv_dim = self.num_kv_heads * self.head_size();
+        let linear = |in_features: usize, out_features: usize| {
+            in_features * out_features + if self.has_bias() { out_features } else { 0 }
+        };
+        let norm = if self.architecture == Architecture::Gpt2 { 2 * h } else { h };
+
+        let attention = 2 * linear(h, h) + 2 * linear(h, kv_dim);
+        let ffn_inputs = if self.gated_ffn { 2 } else { 1 };
+        let ffn = ffn_inputs * linear(h, self.intermediate_size) + linear(self.intermediate_size, h);
+        let per_layer = 2 * norm + attention + ffn;
+
+        let mut total = self.vocab_size * h + self.num_layers * per_layer;
+        if self.architecture == Architecture::Gpt2 {
+            total += self.max_seq_len * h;
+        }
+        if self.norm_position == NormPosition::Pre {
+            total += norm;
+        }
+        if !self.tie_word_embeddings {
+            total += self.vocab_size * h;
+        }
+        total
+    }
+}
+
+// 线性层
+// weight形状: [out_features, in_features]
+#[derive(Debug, Clone)]
+struct Linear {
+    weight: Vec<Vec<f32>>,
+    bias: Option<Vec<f32>>,
+}
+
+impl Linear {
+    fn new(in_features: usize, out_features: usize, bias: bool, rng: &mut impl Rng) -> Self {
+        Linear {
+            weight: init_normal(out_features, in_features, rng),
+            bias: if bias { Some(vec![0.0; out_features]) } else { None },
+        }
+    }
+
+    // 输入形状: [batch_size, seq_len, in_features]
+    // 输出形状: [batch_size, seq_len, out_features]
+    fn forward(&self, x: &[Vec<Vec<f32>>]) -> Tensor3D {
+        x.iter()
+            .map(|seq| {
+                let mut out = matmul_transposed(seq, &self.weight);
+                if let Some(bias) = &self.bias {
+                    for row in out.iter_mut() {
+                        for (o, &b) in row.iter_mut().zip(bias.iter()) {
+                            *o += b;
+                        }
+                    }
+                }
+                out
+            })
+            .collect()
+    }
+}
+
+// 归一化层
+// 与 layer_norm.rs 中的简化版不同，这里的gamma/beta是逐维度的向量，
+// 并且和PyTorch一样把eps放在根号里：(x - mean) / sqrt(var + eps)
+#[derive(Debug, Clone)]
+enum Norm {
+    LayerNorm { gamma: Vec<f32>, beta: Vec<f32>, eps: f32 },
+    // RMSNorm省去了减均值和偏置：x / sqrt(mean(x^2) + eps) * weight
+    RmsNorm { weight: Vec<f32>, eps: f32 },
+}
+
+impl Norm {
+    fn new(config: &ModelConfig) -> Self {
+        let h = config.hidden_size;
+        match config.architecture {
+            Architecture::Gpt2 => Norm::LayerNorm {
+                gamma: vec![1.0; h],
+                beta: vec![0.0; h],
+                eps: config.norm_eps,
+            },
+            Architecture::Llama => Norm::RmsNorm { weight: vec![1.0; h], eps: config.norm_eps },
+        }
+    }
+
+    fn forward(&self, x: &[f32]) -> Vec<f32> {
+        match self {
+            Norm::LayerNorm { gamma, beta, eps } => {
+                let mean = compute_mean(x);
+                let variance = x.iter().map(|&v| (v - mean).powi(2)).sum::<f32>() / x.len() as f32;
+                let inv_std = 1.0 / (variance + eps).sqrt();
+                x.iter()
+                    .zip(gamma.iter().zip(beta.iter()))
+                    .map(|(&v, (&g, &b))| (v - mean) * inv_std * g + b)
+                    .collect()
+            }
+            Norm::RmsNorm { weight, eps } => {
+                let mean_square = x.iter().map(|&v| v * v).sum::<f32>() / x.len() as f32;
+                let inv_rms = 1.0 / (mean_square + eps).sqrt();
+                x.iter().zip(weight.iter()).map(|(&v, &w)| v * inv_rms * w).collect()
+            }
+        }
+    }
+
+    fn forward_3d(&self, x: &[Vec<Vec<f32>>]) -> Tensor3D {
+        x.iter()
+            .map(|seq| seq.iter().map(|row| self.forward(row)).collect())
+            .collect()
+    }
+}
+
+// 对形状为 [batch_size, num_heads, seq_len, head_size] 的张量施加旋转位置编码（RoPE）
+// 第s行的绝对位置为 start_pos + s，维度(2i, 2i+1)组成一对，旋转角度为 pos * theta^(-2i/head_size)
+// 这样旋转后 q·k 只依赖于两个token的相对位置
+// 注意：HuggingFace的Llama实现把前后两半配对（rotate_half），与这里的相邻配对只差一个权重的重排
+fn apply_rope(x: &mut Tensor4D, start_pos: usize, theta: f32) {
+    for heads in x.iter_mut() {
+        for rows in heads.iter_mut() {
+            for (s, row) in rows.iter_mut().enumerate() {
+                let head_size = row.len();
+                let pos = (start_pos + s) as f32;
+                for (i, pair) in row.chunks_exact_mut(2).enumerate() {
+                    let freq = theta.powf(-((2 * i) as f32) / head_size as f32);
+                    let (sin, cos) = (pos * freq).sin_cos();
+                    let (x0, x1) = (pair[0], pair[1]);
+                    pair[0] = x0 * cos - x1 * sin;
+                    pair[1] = x0 * sin + x1 * cos;
+                }
+            }
+        }
+    }
+}
+
+// 一层的KV Cache
+// k、v形状: [batch_size, num_kv_heads, cached_len, head_size]
+#[derive(Debug, Clone, Default)]
+struct LayerCache {
+    k: Tensor4D,
+    v: Tensor4D,
+}
+
+impl LayerCache {
+    // 沿seq_len维度追加新token的key/value
+    fn append(&mut self, k: Tensor4D, v: Tensor4D) {
+        if self.k.is_empty() {
+            self.k = k;
+            self.v = v;
+            return;
+        }
+        for (cached, new) in self.k.iter_mut().zip(k).chain(self.v.iter_mut().zip(v)) {
+            for (cached_head, new_head) in cached.iter_mut().zip(new) {
+                cached_head.extend(new_head);
+            }
+        }
+    }
+
+    fn truncate(&mut self, len: usize) {
+        for heads in self.k.iter_mut().chain(self.v.iter_mut()) {
+            for rows in heads.iter_mut() {
+                rows.truncate(len);
+            }
+        }
+    }
+}
+
+// 整个模型的KV Cache，每层一个LayerCache
+#[derive(Debug, Clone)]
+struct KvCache {
+    layers: Vec<LayerCache>,
+    len: usize,
+}
+
+impl KvCache {
+    fn new(num_layers: usize) -> Self {
+        KvCache { layers: vec![LayerCache::default(); num_layers], len: 0 }
+    }
+
+    // 已缓存的token数，也是下一个token的位置
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn batch_size(&self) -> Option<usize> {
+        self.layers.first().filter(|_| self.len > 0).map(|layer| layer.k.len())
+    }
+
+    // 回退到只保留前len个token，用于丢弃被拒绝的token（例如投机解码）
+    // 回退到0时重置每一层，之后可以用不同的batch_size重新开始
+    fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        for layer in self.layers.iter_mut() {
+            if len == 0 {
+                *layer = LayerCache::default();
+            } else {
+                layer.truncate(len);
+            }
+        }
+        self.len = len;
+    }
+
+    fn clear(&mut self) {
+        self.truncate(0);
+    }
+}
+
+// 带因果掩码的多头自注意力
+#[derive(Debug, Clone)]
+struct Attention {
+    q_proj: Linear,
+    k_proj: Linear,
+    v_proj: Linear,
+    o_proj: Linear,
+    num_heads: usize,
+    num_kv_heads: usize,
+    // 为Some时对q、k施加RoPE
+    rope_theta: Option<f32>,
+}
+
+impl Attention {
+    fn new(config: &ModelConfig, rng: &mut impl Rng) -> Self {
+        let h = config.hidden_size;
+        let kv_dim = config.num_kv_heads * config.head_size();
+        let bias = config.has_bias();
+        Attention {
+            q_proj: Linear::new(h, h, bias, rng),
+            k_proj: Linear::new(h, kv_dim, bias, rng),
+            v_proj: Linear::new(h, kv_dim, bias, rng),
+            o_proj: Linear::new(h, h, bias, rng),
+            num_heads: config.num_heads,
+            num_kv_heads: config.num_kv_heads,
+            rope_theta: match config.architecture {
+                Architecture::Gpt2 => None,
+                Architecture::Llama => Some(config.rope_theta),
+            },
+        }
+    }
+
+    // 输入形状: [batch_size, seq_len, hidden_size]，第s个token的绝对位置为 start_pos + s
+    // 传入cache时，新的key/value会被追加进去，注意力覆盖全部历史token
+    fn forward(&self, x: &[Vec<Vec<f32>>], start_pos: usize, cache: Option<&mut LayerCache>) -> Tensor3D {
+        let mut q = reshape_for_attention(&self.q_proj.forward(x), self.num_heads);
+        let mut k = reshape_for_attention(&self.k_proj.forward(x), self.num_kv_heads);
+        let v = reshape_for_attention(&self.v_proj.forward(x), self.num_kv_heads);
+        if let Some(theta) = self.rope_theta {
+            apply_rope(&mut q, start_pos, theta);
+            apply_rope(&mut k, start_pos, theta);
+        }
+
+        let (keys, values) = match cache {
+            Some(cache) => {
+                cache.append(k, v);
+                (&cache.k, &cache.v)
+            }
+            None => (&k, &v),
+        };
+
+        // GQA：每group个查询头共享同一个key/value头
+        let group = self.num_heads / self.num_kv_heads;
+        let seq_len = x[0].len();
+        let out: Tensor4D = q.iter()
+            .enumerate()
+            .map(|(b, heads)| {
+                heads.iter()
+                    .enumerate()
+                    .map(|(h, rows)| {
+                        let head_keys = &keys[b][h / group];
+                        let head_values = &values[b][h / group];
+                        let past = head_keys.len() - seq_len;
+                        let scale = 1.0 / (rows[0].len() as f32).sqrt();
+                        rows.iter()
+                            .enumerate()
+                            .map(|(s, q_row)| {
+                                // 因果掩码：第s个token只能看到它自己以及之前的token
+                                let visible = past + s + 1;
+                                let scores: Vec<f32> = head_keys[..visible]
+                                    .iter()
+                                    .map(|k_row| {
+                                        q_row.iter().zip(k_row.iter()).map(|(&a, &b)| a * b).sum::<f32>()
+                                            * scale
+                                    })
+                                    .collect();
+                                let mut row = vec![0.0; q_row.len()];
+                                for (p, v_row) in softmax(&scores).iter().zip(head_values.iter()) {
+                                    for (o, &v) in row.iter_mut().zip(v_row.iter()) {
+                                        *o += p * v;
+                                    }
+                                }
+                                row
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        self.o_proj.forward(&merge_heads(&out))
+    }
+}
+
+// 前馈网络
+// 普通MLP: down(act(up(x)))
+// 门控结构: down(act(gate(x)) * up(x))
+#[derive(Debug, Clone)]
+struct FeedForward {
+    up: Linear,
+    gate: Option<Linear>,
+    down: Linear,
+    activation: Activation,
+}
+
+impl FeedForward {
+    fn new(config: &ModelConfig, rng: &mut impl Rng) -> Self {
+        let (h, inter, bias) = (config.hidden_size, config.intermediate_size, config.has_bias());
+        FeedForward {
+            up: Linear::new(h, inter, bias, rng),
+            gate: if config.gated_ffn { Some(Linear::new(h, inter, bias, rng)) } else { None },
+            down: Linear::new(inter, h, bias, rng),
+            activation: config.activation,
+        }
+    }
+
+    fn forward(&self, x: &[Vec<Vec<f32>>]) -> Tensor3D {
+        let up = self.up.forward(x);
+        let hidden: Tensor3D = match &self.gate {
+            Some(gate) => gate.forward(x)
+                .iter()
+                .zip(up.iter())
+                .map(|(gate_seq, up_seq)| {
+                    gate_seq.iter()
+                        .zip(up_seq.iter())
+                        .map(|(g, u)| {
+                            self.activation.forward(g).iter().zip(u.iter()).map(|(&a, &b)| a * b).collect()
+                        })
+                        .collect()
+                })
+                .collect(),
+            None => up.iter()
+                .map(|seq| seq.iter().map(|row| self.activation.forward(row)).collect())
+                .collect(),
+        };
+        self.down.forward(&hidden)
+    }
+}
+
+fn residual_add(x: &[Vec<Vec<f32>>], y: &[Vec<Vec<f32>>]) -> Tensor3D {
+    x.iter()
+        .zip(y.iter())
+        .map(|(x_seq, y_seq)| {
+            x_seq.iter()
+                .zip(y_seq.iter())
+                .map(|(a, b)| a.iter().zip(b.iter()).map(|(&a, &b)| a + b).collect())
+                .collect()
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+struct TransformerBlock {
+    attn_norm: Norm,
+    attention: Attention,
+    ffn_norm: Norm,
+    ffn: FeedForward,
+    norm_position: NormPosition,
+}
+
+impl TransformerBlock {
+    fn new(config: &ModelConfig, rng: &mut impl Rng) -> Self {
+        TransformerBlock {
+            attn_norm: Norm::new(config),
+            attention: Attention::new(config, rng),
+            ffn_norm: Norm::new(config),
+            ffn: FeedForward::new(config, rng),
+            norm_position: config.norm_position,
+        }
+    }
+
+    // 输入输出形状: [batch_size, seq_len, hidden_size]
+    fn forward(&self, x: &[Vec<Vec<f32>>], start_pos: usize, cache: Option<&mut LayerCache>) -> Tensor3D {
+        match self.norm_position {
+            NormPosition::Pre => {
+                let attn_out = self.attention.forward(&self.attn_norm.forward_3d(x), start_pos, cache);
+                let h = residual_add(x, &attn_out);
+                let ffn_out = self.ffn.forward(&self.ffn_norm.forward_3d(&h));
+                residual_add(&h, &ffn_out)
+            }
+            NormPosition::Post => {
+                let attn_out = self.attention.forward(x, start_pos, cache);
+                let h = self.attn_norm.forward_3d(&residual_add(x, &attn_out));
+                let ffn_out = self.ffn.forward(&h);
+                self.ffn_norm.forward_3d(&residual_add(&h, &ffn_out))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DecoderModel {
+    config: ModelConfig,
+    // token_embedding形状: [vocab_size, hidden_size]
+    token_embedding: Vec<Vec<f32>>,
+    // position_embedding形状: [max_seq_len, hidden_size]，只有GPT-2使用
+    position_embedding: Option<Vec<Vec<f32>>>,
+    blocks: Vec<TransformerBlock>,
+    // Post-Norm的每个Block输出已经归一化，不再需要最终的归一化
+    final_norm: Option<Norm>,
+    // 为None时与token_embedding共享权重
+    lm_head: Option<Linear>,
+}
+
+impl DecoderModel {
+    fn new(config: ModelConfig, rng: &mut impl Rng) -> Result<Self, String> {
+        config.validate()?;
+        let token_embedding = init_normal(config.vocab_size, config.hidden_size, rng);
+        let position_embedding = match config.architecture {
+            Architecture::Gpt2 => Some(init_normal(config.max_seq_len, config.hidden_size, rng)),
+            Architecture::Llama => None,
+        };
+        let blocks = (0..config.num_layers).map(|_| TransformerBlock::new(&config, rng)).collect();
+        let final_norm = match config.norm_position {
+            NormPosition::Pre => Some(Norm::new(&config)),
+            NormPosition::Post => None,
+        };
+        let lm_head = if config.tie_word_embeddings {
+            None
+        } else {
+            Some(Linear::new(config.hidden_size, config.vocab_size, false, rng))
+        };
+        Ok(DecoderModel { config, token_embedding, position_embedding, blocks, final_norm, lm_head })
+    }
+
+    fn config(&self) -> &ModelConfig {
+        &self.config
+    }
+
+    fn new_cache(&self) -> KvCache {
+        KvCache::new(self.config.num_layers)
+    }
+
+    // 不使用缓存，一次性计算整个序列
+    // 输入形状: [batch_size, seq_len]，输出形状: [batch_size, seq_len, vocab_size]
+    fn forward(&self, ids: &[Vec<u32>]) -> Result<Tensor3D, String> {
+        let mut hidden = self.embed(ids, 0)?;
+        for block in &self.blocks {
+            hidden = block.forward(&hidden, 0, None);
+        }
+        Ok(self.lm_logits(&hidden))
+    }
+
+    // 使用KV Cache，ids是接在已缓存token之后的新token
+    // 第一次调用相当于prefill，之后每次传入一个token即为增量解码
+    fn forward_with_cache(&self, ids: &[Vec<u32>], cache: &mut KvCache) -> Result<Tensor3D, String> {
+        if cache.layers.len() != self.blocks.len() {
+            return Err(format!(
+                "KV Cache的层数{}与模型层数{}不一致",
+                cache.layers.len(),
+                self.blocks.len()
+            ));
+        }
+        if let Some(batch_size) = cache.batch_size() {
+            if batch_size != ids.len() {
+                return Err(format!("batch大小{}与KV Cache中的{}不一致", ids.len(), batch_size));
+            }
+        }
+        let start_pos = cache.len();
+        let mut hidden = self.embed(ids, start_pos)?;
+        for (block, layer) in self.blocks.iter().zip(cache.layers.iter_mut()) {
+            hidden = block.forward(&hidden, start_pos, Some(layer));
+        }
+        cache.len += ids[0].len();
+        Ok(self.lm_logits(&hidden))
+    }
+
+    // 词嵌入查表，GPT-2再加上start_pos开始的位置嵌入
+    fn embed(&self, ids: &[Vec<u32>], start_pos: usize) -> Result<Tensor3D, String> {
+        let seq_len = ids.first().map_or(0, |seq| seq.len());
+        if seq_len == 0 {
+            return Err("输入序列不能为空".to_string());
+        }
+        if let Some(b) = ids.iter().position(|seq| seq.len() != seq_len) {
+            return Err(format!("第{}个序列长度{}与第一个序列长度{}不一致", b, ids[b].len(), seq_len));
+        }
+        if start_pos + seq_len > self.config.max_seq_len {
+            return Err(format!(
+                "位置{}超出最大长度{}",
+                start_pos + seq_len - 1,
+                self.config.max_seq_len
+            ));
+        }
+
+        ids.iter()
+            .enumerate()
+            .map(|(b, seq)| {
+                seq.iter()
+                    .enumerate()
+                    .map(|(s, &id)| {
+                        let mut row = self.token_embedding
+                            .get(id as usize)
+                            .ok_or_else(|| {
+                                format!("位置[{}][{}]的token ID {}超出词表大小{}", b, s, id, self.config.vocab_size)
+                            })?
+                            .clone();
+                        if let Some(position_embedding) = &self.position_embedding {
+                            for (x, &p) in row.iter_mut().zip(position_embedding[start_pos + s].iter()) {
+                                *x += p;
+                            }
+                        }
+                        Ok(row)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn lm_logits(&self, hidden: &[Vec<Vec<f32>>]) -> Tensor3D {
+        let hidden = match &self.final_norm {
+            Some(norm) => norm.forward_3d(hidden),
+            None => hidden.to_vec(),
+        };
+        match &self.lm_head {
+            Some(lm_head) => lm_head.forward(&hidden),
+            None => hidden.iter().map(|seq| matmul_transposed(seq, &self.token_embedding)).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn tiny_configs() -> Vec<ModelConfig> {
+        let gpt2 = ModelConfig::gpt2(32, 16, 2, 4, 16);
+        // 原始Transformer风格：Post-Norm + ReLU
+        let post_norm = ModelConfig {
+            norm_position: NormPosition::Post,
+            activation: Activation::Relu,
+            ..gpt2.clone()
+        };
+        let llama = ModelConfig::llama(32, 16, 2, 4, 2, 16);
+        vec![gpt2, post_norm, llama]
+    }
+
+    fn assert_close(a: &[Vec<Vec<f32>>], b: &[Vec<Vec<f32>>], tol: f32) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().flatten().flatten().zip(b.iter().flatten().flatten()) {
+            assert!((x - y).abs() < tol, "{} != {}", x, y);
+        }
+    }
+
+    #[test]
+    fn test_config_presets() {
+        // 用真实模型的参数量验证各组件的连接方式
+        let gpt2_small = ModelConfig::gpt2(50257, 768, 12, 12, 1024);
+        assert_eq!(gpt2_small.intermediate_size, 3072);
+        assert_eq!(gpt2_small.num_parameters(), 124_439_808);
+
+        let llama_7b = ModelConfig::llama(32000, 4096, 32, 32, 32, 2048);
+        assert_eq!(llama_7b.intermediate_size, 11008);
+        assert_eq!(llama_7b.num_parameters(), 6_738_415_616);
+
+        assert!(ModelConfig::gpt2(32, 16, 2, 3, 16).validate().is_err());
+        assert!(ModelConfig::llama(32, 16, 2, 4, 3, 16).validate().is_err());
+        assert!(ModelConfig::llama(32, 12, 2, 4, 4, 16).validate().is_err());
+        assert!(ModelConfig { num_layers: 0, ..ModelConfig::gpt2(32, 16, 2, 4, 16) }.validate().is_err());
+        assert!(ModelConfig::llama(32, 16, 2, 4, 2, 16).validate().is_ok());
+    }
+
+    #[test]
+    fn test_norms_and_rope() {
+        let x = vec![1.0, -2.0, 3.0, 0.5, 4.0, -1.5];
+        let config = ModelConfig::gpt2(8, 6, 1, 1, 4);
+        let y = Norm::new(&config).forward(&x);
+        let mean = compute_mean(&y);
+        let variance = y.iter().map(|&v| (v - mean).powi(2)).sum::<f32>() / y.len() as f32;
+        assert!(mean.abs() < 1e-5);
+        assert!((variance - 1.0).abs() < 1e-3);
+
+        let config = ModelConfig::llama(8, 6, 1, 1, 1, 4);
+        let y = Norm::new(&config).forward(&x);
+        let rms = (y.iter().map(|&v| v * v).sum::<f32>() / y.len() as f32).sqrt();
+        assert!((rms - 1.0).abs() < 1e-3);
+        // RMSNorm不减均值，符号和比例保持不变
+        assert!((y[4] / y[0] - 4.0).abs() < 1e-5);
+
+        // RoPE之后q·k只依赖相对位置
+        let q = vec![0.3, -1.2, 0.7, 0.5];
+        let k = vec![-0.4, 0.9, 1.1, -0.2];
+        let rotated_dot = |q_pos: usize, k_pos: usize| {
+            let mut q4 = vec![vec![vec![q.clone()]]];
+            let mut k4 = vec![vec![vec![k.clone()]]];
+            apply_rope(&mut q4, q_pos, 10000.0);
+            apply_rope(&mut k4, k_pos, 10000.0);
+            // 旋转不改变向量长度
+            let norm: f32 = q4[0][0][0].iter().map(|v| v * v).sum();
+            assert!((norm - q.iter().map(|v| v * v).sum::<f32>()).abs() < 1e-5);
+            q4[0][0][0].iter().zip(k4[0][0][0].iter()).map(|(a, b)| a * b).sum::<f32>()
+        };
+        assert!((rotated_dot(5, 2) - rotated_dot(13, 10)).abs() < 1e-4);
+        assert!((rotated_dot(5, 2) - rotated_dot(5, 4)).abs() > 1e-3);
+    }
+
+    #[test]
+    fn test_output_shape() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for config in tiny_configs() {
+            let model = DecoderModel::new(config.clone(), &mut rng).unwrap();
+            let ids = vec![vec![1, 5, 7, 2, 9], vec![3, 3, 0, 31, 4]];
+            let logits = model.forward(&ids).unwrap();
+            assert_eq!(logits.len(), 2);
+            for seq in &logits {
+                assert_eq!(seq.len(), 5);
+                for row in seq {
+                    assert_eq!(row.len(), config.vocab_size);
+                    assert!(row.iter().all(|v| v.is_finite()));
+                }
+            }
+            // 两个序列互不影响：单独计算第二个序列得到相同的logits
+            let single = model.forward(&ids[1..]).unwrap();
+            assert_close(&single, &logits[1..], 1e-6);
+
+            assert_eq!(
+                model.forward(&[vec![1, 32]]).unwrap_err(),
+                "位置[0][1]的token ID 32超出词表大小32"
+            );
+            assert_eq!(model.forward(&[vec![1; 17]]).unwrap_err(), "位置16超出最大长度16");
+            assert!(model.forward(&[vec![1, 2], vec![3]]).is_err());
+            assert!(model.forward(&[vec![]]).is_err());
+        }
+
+        // GPT-2的LM Head与词嵌入共享权重，Llama的是独立的
+        let gpt2 = DecoderModel::new(ModelConfig::gpt2(32, 16, 1, 4, 16), &mut rng).unwrap();
+        assert!(gpt2.lm_head.is_none() && gpt2.position_embedding.is_some());
+        let llama = DecoderModel::new(ModelConfig::llama(32, 16, 1, 4, 2, 16), &mut rng).unwrap();
+        assert!(llama.lm_head.is_some() && llama.position_embedding.is_none());
+    }
+
+    #[test]
+    fn test_causal_property() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for config in tiny_configs() {
+            let model = DecoderModel::new(config, &mut rng).unwrap();
+            let ids = vec![vec![4, 8, 15, 16, 23, 30]];
+            let base = model.forward(&ids).unwrap();
+            for changed in 1..6 {
+                let mut other = ids.clone();
+                other[0][changed] = (other[0][changed] + 1) % 32;
+                let logits = model.forward(&other).unwrap();
+                // 修改第changed个token不会影响它之前位置的logits
+                for s in 0..changed {
+                    assert_eq!(logits[0][s], base[0][s], "位置{}受到了未来token的影响", s);
+                }
+                assert_ne!(logits[0][changed], base[0][changed]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_post_norm_block_output() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let config = ModelConfig { norm_position: NormPosition::Post, ..ModelConfig::gpt2(32, 16, 1, 4, 16) };
+        let block = TransformerBlock::new(&config, &mut rng);
+        let x: Tensor3D = vec![(0..3).map(|_| init_normal(1, 16, &mut rng).remove(0)).collect()];
+        let post = block.forward(&x, 0, None);
+        // Post-Norm的输出经过LayerNorm（gamma=1, beta=0），每个token均值为0、方差为1
+        for row in &post[0] {
+            let mean = compute_mean(row);
+            let variance = row.iter().map(|&v| (v - mean).powi(2)).sum::<f32>() / row.len() as f32;
+            assert!(mean.abs() < 1e-4);
+            assert!((variance - 1.0).abs() < 1e-2);
+        }
+
+        // 同样的权重换成Pre-Norm，输出是残差相加的结果，不再归一化
+        let pre_block = TransformerBlock { norm_position: NormPosition::Pre, ..block };
+        let pre = pre_block.forward(&x, 0, None);
+        assert_ne!(pre, post);
+    }
+
+    #[test]
+    fn test_kv_cache_matches_full_forward() {
+        let mut rng = StdRng::seed_from_u64(11);
+        for config in tiny_configs() {
+            let model = DecoderModel::new(config, &mut rng).unwrap();
+            let ids = vec![vec![2, 7, 1, 8, 28, 18, 28, 4], vec![3, 14, 15, 9, 26, 5, 3, 5]];
+            let full = model.forward(&ids).unwrap();
+
+            // prefill前4个token，然后逐个增量解码
+            let mut cache = model.new_cache();
+            assert!(cache.is_empty());
+            let prefix: Vec<Vec<u32>> = ids.iter().map(|seq| seq[..4].to_vec()).collect();
+            let mut logits = model.forward_with_cache(&prefix, &mut cache).unwrap();
+            for s in 4..8 {
+                let step: Vec<Vec<u32>> = ids.iter().map(|seq| vec![seq[s]]).collect();
+                let out = model.forward_with_cache(&step, &mut cache).unwrap();
+                for (seq, new) in logits.iter_mut().zip(out) {
+                    seq.extend(new);
+                }
+            }
+            assert_eq!(cache.len(), 8);
+            assert_close(&logits, &full, 1e-5);
+
+            // 回退到前5个token后接上不同的后缀，结果与直接计算新序列一致
+            cache.truncate(5);
+            let suffix = vec![vec![0, 1, 2], vec![31, 30, 29]];
+            let out = model.forward_with_cache(&suffix, &mut cache).unwrap();
+            let mut new_ids = ids.clone();
+            for (seq, tail) in new_ids.iter_mut().zip(suffix.iter()) {
+                seq.truncate(5);
+                seq.extend(tail);
+            }
+            let expected = model.forward(&new_ids).unwrap();
+            let expected_tail: Tensor3D = expected.iter().map(|seq| seq[5..].to_vec()).collect();
+            assert_close(&out, &expected_tail, 1e-5);
+
+            assert!(model.forward_with_cache(&[vec![1]], &mut cache).is_err());
+            assert_eq!(
+                model.forward_with_cache(&[vec![1; 9], vec![1; 9]], &mut cache).unwrap_err(),
+                "位置16超出最大长度16"
+            );
+            cache.clear();
+            assert!(cache.is_empty());
+            assert!(model.forward_with_cache(&[vec![1]], &mut cache).is_ok());
+
+            // 清空后可以换成更大的batch_size
+            cache.clear();
+            assert_eq!(cache.batch_size(), None);
+            let out = model.forward_with_cache(&ids, &mut cache).unwrap();
+            assert_eq!(cache.batch_size(), Some(2));
+            assert_close(&out, &full, 1e-5);
+            cache.truncate(0);
+            let out = model.forward_with_cache(&prefix[..1], &mut cache).unwrap();
+            assert_eq!(cache.batch_size(), Some(1));
+            assert_close(&out, &full[..1].iter().map(|seq| seq[..4].to_vec()).collect::<Tensor3D>(), 1e-5);
+        }
+    }
+}